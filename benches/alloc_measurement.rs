@@ -0,0 +1,39 @@
+//! Reports the bytes allocated per iteration of a few routines, with criterion's
+//! statistics:
+//!
+//! ```sh
+//! cargo bench --bench alloc_measurement --features criterion
+//! ```
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use interloc::criterion::AllocMeasurement;
+use interloc::{InterAlloc, ThreadMonitor};
+use std::alloc::System;
+use std::collections::HashMap;
+
+static MONITOR: ThreadMonitor = ThreadMonitor::new();
+
+#[global_allocator]
+static GLOBAL: InterAlloc<System, ThreadMonitor> = InterAlloc {
+    inner: System,
+    monitor: &MONITOR,
+};
+
+fn collect(c: &mut Criterion<AllocMeasurement>) {
+    let mut group = c.benchmark_group("collect");
+    for len in [16, 256, 4096] {
+        group.bench_with_input(BenchmarkId::new("vec", len), &len, |b, len| {
+            b.iter(|| (0..*len).collect::<Vec<u32>>())
+        });
+        group.bench_with_input(BenchmarkId::new("hash_map", len), &len, |b, len| {
+            b.iter(|| (0..*len).map(|i| (i, i)).collect::<HashMap<u32, u32>>())
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_measurement(AllocMeasurement::bytes());
+    targets = collect
+}
+criterion_main!(benches);
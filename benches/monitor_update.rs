@@ -0,0 +1,62 @@
+//! Times updating statistics for one call to the allocator, copying the
+//! statistics out and back with `after_call` against updating them in place
+//! with `apply`, and through the monitors that use `apply`:
+//!
+//! ```sh
+//! cargo bench --bench monitor_update --features criterion
+//! ```
+use core::alloc::Layout;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use interloc::{AllocAction, AllocInfo, AllocMonitor, StatsMonitor, ThreadMonitor};
+
+const CALLS: [AllocAction; 3] = [
+    AllocAction::Alloc,
+    AllocAction::Realloc {
+        ptr: core::ptr::null_mut(),
+        new_size: 128,
+    },
+    AllocAction::Dealloc {
+        ptr: core::ptr::null_mut(),
+    },
+];
+
+fn update(c: &mut Criterion) {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let mut group = c.benchmark_group("update");
+    group.bench_function("after_call", |b| {
+        let mut info = AllocInfo::new();
+        b.iter(|| {
+            for action in CALLS {
+                info = black_box(&info).after_call(layout, action);
+            }
+        })
+    });
+    group.bench_function("apply", |b| {
+        let mut info = AllocInfo::new();
+        b.iter(|| {
+            for action in CALLS {
+                black_box(&mut info).apply(layout, action);
+            }
+        })
+    });
+    group.bench_function("stats_monitor", |b| {
+        let monitor = StatsMonitor::new();
+        b.iter(|| {
+            for action in CALLS {
+                black_box(&monitor).monitor(layout, action);
+            }
+        })
+    });
+    group.bench_function("thread_monitor", |b| {
+        let monitor = ThreadMonitor::new();
+        b.iter(|| {
+            for action in CALLS {
+                black_box(&monitor).monitor(layout, action);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, update);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+//! Measures allocation throughput through `InterAlloc` with a `StatsMonitor`.
+//! Compare the fences around the inner allocator by running it both ways:
+//!
+//! ```sh
+//! cargo run --release --example alloc_throughput
+//! cargo run --release --example alloc_throughput --features strict-ordering
+//! ```
+use interloc::{InterAlloc, StatsMonitor};
+use std::alloc::System;
+use std::time::Instant;
+
+static MONITOR: StatsMonitor = StatsMonitor::new();
+
+#[global_allocator]
+static GLOBAL: InterAlloc<System, StatsMonitor> = InterAlloc {
+    inner: System,
+    monitor: &MONITOR,
+};
+
+const ALLOCS: usize = 10_000_000;
+
+fn main() {
+    let start = Instant::now();
+    for i in 0..ALLOCS {
+        let v: Vec<u8> = Vec::with_capacity(16 + i % 64);
+        std::hint::black_box(&v);
+    }
+    let elapsed = start.elapsed();
+    println!("strict-ordering: {}", cfg!(feature = "strict-ordering"));
+    println!(
+        "{} allocations in {:?}, {:.1} ns per allocation and free",
+        ALLOCS,
+        elapsed,
+        elapsed.as_nanos() as f64 / ALLOCS as f64
+    );
+}
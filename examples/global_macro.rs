@@ -0,0 +1,31 @@
+//! Declares the global allocator with `interloc::global!`, and checks that
+//! the monitor it declares sees the program's allocations, and that library
+//! code can read them through `interloc::global_info`.
+//!
+//! ```sh
+//! cargo run --example global_macro
+//! ```
+
+interloc::global!(
+    pub fn stats: std::alloc::System,
+    interloc::StatsMonitor::new(),
+);
+
+fn main() {
+    let before = stats().info();
+    let blocks: Vec<Vec<u8>> = (0..100).map(|i| vec![0; 1000 + i]).collect();
+    let during = stats().info().relative_to(&before);
+    drop(blocks);
+    let after = stats().info().relative_to(&before);
+
+    assert_eq!(during.alloc, 101);
+    assert!(during.bytes_alloc >= 100 * 1000);
+    assert_eq!(after.live_bytes(), 0);
+
+    // What library code sees, without knowing the monitor's type.
+    let global = interloc::global_info().expect("registered by global!");
+    assert_eq!(global, stats().info());
+    assert!(global.alloc > 100);
+    assert!(global.peak_bytes >= 100 * 1000);
+    println!("{:#?}", after);
+}
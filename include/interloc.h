@@ -0,0 +1,31 @@
+#ifndef INTERLOC_H
+#define INTERLOC_H
+
+/* Generated with cbindgen from src/ffi.rs, see cbindgen.toml. Don't edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct AllocInfoC {
+  uint64_t alloc;
+  uint64_t dealloc;
+  uint64_t realloc;
+  uint64_t bytes_alloc;
+  uint64_t bytes_dealloc;
+  uint64_t peak_bytes;
+  uint64_t baseline_bytes;
+} AllocInfoC;
+
+#ifdef __cplusplus
+extern "C" {
+#endif  // __cplusplus
+
+int32_t interloc_global_info(AllocInfoC *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* INTERLOC_H */
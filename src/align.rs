@@ -0,0 +1,192 @@
+use crate::alloc::{suppress, AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::keys::{pack_layout, unpack_layout, Inserted, KeySet};
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Called the first time an over-aligned layout is seen, with that layout.
+pub type OverAlignedHandler = fn(Layout);
+
+/// A monitor that watches the alignments allocations ask for. Over-aligned
+/// allocations, with an alignment above `threshold`, 16 bytes by default, are
+/// slow in many allocators, which only hand out blocks aligned that much on
+/// their slow paths.
+///
+/// It keeps the highest alignment seen and counts over-aligned allocations,
+/// reallocations included. Each distinct over-aligned layout is recorded in a
+/// table of up to `LAYOUTS` layouts, and the handler set with `on_new_layout`,
+/// if any, is called the first time it's recorded, with monitoring suppressed,
+/// e.g. to capture a backtrace that points at the type. Layouts that find the
+/// table full are counted in `untracked`, and don't call the handler.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use interloc::{AlignMonitor, InterAlloc};
+/// use std::alloc::System;
+///
+/// static NEW_LAYOUTS: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn new_layout(layout: Layout) {
+///     assert_eq!(layout.align(), 64);
+///     NEW_LAYOUTS.fetch_add(1, Ordering::Relaxed);
+/// }
+///
+/// static MONITOR: AlignMonitor = AlignMonitor::new().on_new_layout(new_layout);
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, AlignMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// #[repr(align(64))]
+/// struct Line([u8; 64]);
+///
+/// let over_aligned = MONITOR.over_aligned();
+/// for _ in 0..3 {
+///     drop(std::hint::black_box(Box::new(Line([0; 64]))));
+///     drop(std::hint::black_box(Box::new([Line([0; 64]), Line([0; 64])])));
+/// }
+/// assert_eq!(NEW_LAYOUTS.load(Ordering::Relaxed), 2);
+/// assert_eq!(MONITOR.over_aligned() - over_aligned, 6);
+/// assert_eq!(MONITOR.max_align_seen(), 64);
+/// let layouts: Vec<Layout> = MONITOR.layouts().collect();
+/// assert!(layouts.contains(&Layout::new::<Line>()));
+/// assert!(layouts.contains(&Layout::new::<[Line; 2]>()));
+/// ```
+///
+/// With a threshold of 0, every allocation is over-aligned, zero-sized ones
+/// included:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use interloc::{AlignMonitor, AllocAction, AllocMonitor};
+///
+/// static NEW_LAYOUTS: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn new_layout(_layout: Layout) {
+///     NEW_LAYOUTS.fetch_add(1, Ordering::Relaxed);
+/// }
+///
+/// let monitor = AlignMonitor::<8>::new().threshold(0).on_new_layout(new_layout);
+/// for _ in 0..3 {
+///     monitor.monitor(Layout::new::<()>(), AllocAction::Alloc);
+///     monitor.monitor(Layout::new::<u8>(), AllocAction::Alloc);
+/// }
+/// assert_eq!(monitor.over_aligned(), 6);
+/// assert_eq!(NEW_LAYOUTS.load(Ordering::Relaxed), 2);
+/// assert_eq!(monitor.layouts().count(), 2);
+/// ```
+pub struct AlignMonitor<const LAYOUTS: usize = 64> {
+    threshold: usize,
+    handler: Option<OverAlignedHandler>,
+    max_align: AtomicUsize,
+    over_aligned: AtomicU64,
+    /// The over-aligned layouts seen
+    layouts: KeySet<LAYOUTS>,
+    untracked: AtomicU64,
+}
+
+impl<const LAYOUTS: usize> AlignMonitor<LAYOUTS> {
+    pub const fn new() -> Self {
+        Self {
+            threshold: 16,
+            handler: None,
+            max_align: AtomicUsize::new(0),
+            over_aligned: AtomicU64::new(0),
+            layouts: KeySet::new(),
+            untracked: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts allocations aligned to more than `align` bytes as over-aligned.
+    pub const fn threshold(mut self, align: usize) -> Self {
+        self.threshold = align;
+        self
+    }
+
+    /// Calls `handler` the first time each over-aligned layout is seen.
+    pub const fn on_new_layout(mut self, handler: OverAlignedHandler) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    /// The highest alignment any allocation asked for, or 0 before the first.
+    pub fn max_align_seen(&self) -> usize {
+        self.max_align.load(Ordering::Relaxed)
+    }
+
+    /// How many allocations and reallocations were over-aligned.
+    pub fn over_aligned(&self) -> u64 {
+        self.over_aligned.load(Ordering::Relaxed)
+    }
+
+    /// The distinct over-aligned layouts seen, in no particular order.
+    pub fn layouts(&self) -> impl Iterator<Item = Layout> + '_ {
+        self.layouts.iter().map(unpack_layout)
+    }
+
+    /// How many over-aligned allocations had a layout that wasn't in the table,
+    /// and found it full.
+    pub fn untracked(&self) -> u64 {
+        self.untracked.load(Ordering::Relaxed)
+    }
+
+    /// Records the layout, returning whether it's the first time it was seen.
+    fn record(&self, layout: Layout) -> bool {
+        let inserted = pack_layout(layout).map_or(Inserted::Full, |key| self.layouts.insert(key));
+        match inserted {
+            Inserted::New(_) => true,
+            Inserted::Seen(_) => false,
+            Inserted::Full => {
+                self.untracked.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+}
+
+impl<const LAYOUTS: usize> Default for AlignMonitor<LAYOUTS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const LAYOUTS: usize> AllocMonitor for AlignMonitor<LAYOUTS> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let layout = match action {
+            AllocAction::Alloc | AllocAction::AllocZeroed => layout,
+            AllocAction::Realloc { new_size, .. } => {
+                match Layout::from_size_align(new_size, layout.align()) {
+                    Ok(layout) => layout,
+                    Err(_) => return,
+                }
+            }
+            _ => return,
+        };
+        let align = layout.align();
+        if align > self.max_align.load(Ordering::Relaxed) {
+            self.max_align.fetch_max(align, Ordering::Relaxed);
+        }
+        if align <= self.threshold {
+            return;
+        }
+        self.over_aligned.fetch_add(1, Ordering::Relaxed);
+        if self.record(layout) {
+            if let Some(handler) = self.handler {
+                suppress(|| handler(layout));
+            }
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("AlignMonitor", Overhead::Table)
+            .param("threshold", self.threshold as u64)
+            .param("layouts", LAYOUTS as u64)
+    }
+}
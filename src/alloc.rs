@@ -1,9 +1,183 @@
+use crate::counters::Counters;
+use crate::describe::{ConfigReport, MonitorDesc, Overhead};
+use crate::monitor::AllocInfo;
 use core::alloc::GlobalAlloc;
 pub use core::alloc::Layout;
+use core::cell::Cell;
+#[cfg(not(feature = "disabled"))]
+use core::panic::AssertUnwindSafe;
+#[cfg(all(feature = "strict-ordering", not(feature = "disabled")))]
 use core::sync::atomic::{fence, Ordering};
 
+/// Events go to the monitor.
+const MONITORED: u8 = 0;
+/// Events are dropped.
+const SUPPRESSED: u8 = 1;
+/// Events are counted in `internal_overhead`.
+const INTERNAL: u8 = 2;
+
+thread_local! {
+    static MODE: Cell<u8> = const { Cell::new(MONITORED) };
+}
+
+/// What interloc itself allocated, with `internal`.
+static INTERNAL_INFO: Counters = Counters::new();
+
+/// Whether monitoring is currently suppressed on this thread, by `suppress` or
+/// `internal`.
+#[inline]
+pub fn is_suppressed() -> bool {
+    MODE.with(Cell::get) != MONITORED
+}
+
+/// Restores the previous suppression state when dropped, even on panic.
+struct SuppressGuard(u8);
+
+impl Drop for SuppressGuard {
+    fn drop(&mut self) {
+        MODE.with(|s| s.set(self.0));
+    }
+}
+
+/// Runs `f` with monitoring suppressed on the current thread: allocations made by
+/// `f` still go to the inner allocator, but `InterAlloc` doesn't tell its monitor
+/// about them. Monitors use this around code that may allocate, so that they
+/// don't end up monitoring themselves.
+#[inline]
+pub fn suppress<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = SuppressGuard(MODE.with(|s| s.replace(SUPPRESSED)));
+    f()
+}
+
+/// Like `suppress`, but counts what `f` allocates in `internal_overhead`
+/// instead of dropping it. Interloc runs its own bookkeeping this way, like
+/// capturing backtraces or registering threads, so that its memory overhead can
+/// be checked without it showing up in the monitor's statistics. Inside
+/// `suppress`, allocations stay dropped.
+#[inline]
+pub fn internal<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = SuppressGuard(MODE.with(|s| {
+        let mode = s.get();
+        if mode == MONITORED {
+            s.set(INTERNAL);
+        }
+        mode
+    }));
+    f()
+}
+
+/// What was allocated with `internal`, by every `InterAlloc` in the process,
+/// which none of their monitors saw. The fields are read one at a time, and
+/// the peak is only tracked roughly, when allocations from several threads
+/// overlap.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{internal, internal_overhead, suppress, InterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, StatsMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// let (before, user) = (internal_overhead(), MONITOR.info());
+/// let block = internal(|| std::hint::black_box(vec![0u8; 1000]));
+/// let overhead = internal_overhead().relative_to(&before);
+/// assert_eq!((overhead.alloc, overhead.bytes_alloc), (1, 1000));
+/// assert_eq!(overhead.live_bytes(), 1000);
+/// internal(|| drop(block));
+/// assert_eq!(internal_overhead().relative_to(&before).live_bytes(), 0);
+///
+/// // Suppressed allocations aren't counted anywhere.
+/// suppress(|| internal(|| drop(std::hint::black_box(vec![0u8; 1000]))));
+/// assert_eq!(internal_overhead().relative_to(&before).bytes_alloc, 1000);
+/// // The monitor saw none of it.
+/// assert_eq!(MONITOR.info().relative_to(&user).bytes_alloc, 0);
+/// ```
+pub fn internal_overhead() -> AllocInfo {
+    INTERNAL_INFO.read()
+}
+
 /// An action that an allocator can take, either right before, or right after it
 /// happens.
+///
+/// Every call to an `InterAlloc` gives its monitor exactly two events, one right
+/// before the call to the inner allocator and one right after it, unless
+/// monitoring is suppressed on the calling thread, in which case it gives none:
+///
+/// | Call           | Before        | After                                   |
+/// |----------------|---------------|-----------------------------------------|
+/// | `alloc`        | `Alloc`       | `AllocResult`, null if it failed        |
+/// | `alloc_zeroed` | `AllocZeroed` | `AllocZeroedResult`, null if it failed  |
+/// | `dealloc`      | `Dealloc`     | `DeallocResult`                         |
+/// | `realloc`      | `Realloc`     | `ReallocResult`, null if it failed      |
+///
+/// That holds whichever `GlobalAlloc` methods the inner allocator implements.
+/// When it relies on the default `alloc_zeroed` or `realloc`, those call its
+/// own `alloc` and `dealloc` rather than the `InterAlloc`'s, so a reallocation
+/// is still one `Realloc` and one `ReallocResult`, never an allocation and a
+/// free of their own. The layout of every event is the one the call was made
+/// with, so the `Realloc` events of a block have its old layout. Calls that the
+/// inner allocator, or the monitor, makes to the global allocator are separate
+/// calls, and give events of their own in between, if it's an `InterAlloc`.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::testing::{FakeAlloc, RecordedEvent, RecordingMonitor};
+/// use interloc::{ActionKind, AllocAction, ArenaAlloc, ScopedInterAlloc};
+/// use std::alloc::System;
+///
+/// fn record<A: GlobalAlloc>(inner: A, calls: impl FnOnce(&dyn GlobalAlloc)) -> Vec<RecordedEvent> {
+///     let monitor = RecordingMonitor::<16>::new();
+///     calls(&ScopedInterAlloc { inner, monitor: &monitor });
+///     monitor.events().to_vec()
+/// }
+///
+/// fn kinds(events: &[RecordedEvent]) -> Vec<ActionKind> {
+///     events.iter().map(|event| event.action.kind()).collect()
+/// }
+///
+/// let layout = Layout::from_size_align(64, 8).unwrap();
+/// let calls = |alloc: &dyn GlobalAlloc| unsafe {
+///     let a = alloc.alloc(layout);
+///     let b = alloc.alloc_zeroed(layout);
+///     let a = alloc.realloc(a, layout, 128);
+///     alloc.dealloc(a, Layout::from_size_align(128, 8).unwrap());
+///     alloc.dealloc(b, layout);
+/// };
+///
+/// use ActionKind::*;
+/// let expected = [
+///     Alloc, AllocResult, AllocZeroed, AllocZeroedResult, Realloc, ReallocResult,
+///     Dealloc, DeallocResult, Dealloc, DeallocResult,
+/// ];
+/// // Only `alloc` and `dealloc`, with the default `alloc_zeroed` and `realloc`.
+/// assert_eq!(kinds(&record(FakeAlloc::<4096>::new(), calls)), expected);
+/// // `realloc` of its own.
+/// assert_eq!(kinds(&record(ArenaAlloc::<4096>::new(), calls)), expected);
+/// // Every method of its own.
+/// assert_eq!(kinds(&record(System, calls)), expected);
+///
+/// // Failed calls give the same events, with a null pointer after.
+/// let events = record(FakeAlloc::<128>::new(), |alloc| unsafe {
+///     let a = alloc.alloc(layout);
+///     assert!(alloc.realloc(a, layout, 1024).is_null());
+///     assert!(alloc.alloc_zeroed(Layout::new::<[u8; 1024]>()).is_null());
+/// });
+/// assert_eq!(
+///     kinds(&events),
+///     [Alloc, AllocResult, Realloc, ReallocResult, AllocZeroed, AllocZeroedResult]
+/// );
+/// let null = core::ptr::null_mut();
+/// assert_eq!(events[3].layout, layout);
+/// assert_eq!(events[3].action, AllocAction::ReallocResult { ptr: null, new_size: 1024 });
+/// assert_eq!(events[5].action, AllocAction::AllocZeroedResult { ptr: null });
+/// ```
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum AllocAction {
     /// alloc was called
@@ -24,6 +198,50 @@ pub enum AllocAction {
     ReallocResult { ptr: *mut u8, new_size: usize },
 }
 
+/// The kind of an `AllocAction`, without the data attached to it.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum ActionKind {
+    Alloc = 0,
+    AllocResult = 1,
+    AllocZeroed = 2,
+    AllocZeroedResult = 3,
+    Dealloc = 4,
+    DeallocResult = 5,
+    Realloc = 6,
+    ReallocResult = 7,
+}
+
+impl ActionKind {
+    /// All kinds, in the order of their discriminants.
+    pub const ALL: [ActionKind; 8] = [
+        ActionKind::Alloc,
+        ActionKind::AllocResult,
+        ActionKind::AllocZeroed,
+        ActionKind::AllocZeroedResult,
+        ActionKind::Dealloc,
+        ActionKind::DeallocResult,
+        ActionKind::Realloc,
+        ActionKind::ReallocResult,
+    ];
+
+    /// The kind with the discriminant `tag`, if there is one.
+    #[inline]
+    pub fn from_u8(tag: u8) -> Option<Self> {
+        Self::ALL.get(tag as usize).copied()
+    }
+
+    /// Whether actions of this kind are before or after the action itself.
+    #[inline]
+    pub fn relation(self) -> AllocRel {
+        if self as u8 % 2 == 0 {
+            AllocRel::Before
+        } else {
+            AllocRel::After
+        }
+    }
+}
+
 /// Before or after an allocation call is executed.
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum AllocRel {
@@ -31,6 +249,94 @@ pub enum AllocRel {
     After,
 }
 
+/// A set of `ActionKind`s, used by monitors that record events to pick which
+/// ones they record. Since every kind is either before or after its call, this
+/// covers relations too.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub struct EventMask(u8);
+
+impl EventMask {
+    /// No kinds at all.
+    pub const NONE: Self = Self(0);
+    /// Every kind.
+    pub const ALL: Self = Self(0xff);
+    /// The kinds that come before their call.
+    pub const BEFORE_ONLY: Self = Self(0x55);
+    /// The kinds that come after their call, and carry its result.
+    pub const AFTER_ONLY: Self = Self(0xaa);
+    /// `Dealloc` and `DeallocResult`.
+    pub const DEALLOC_ONLY: Self = Self::of(ActionKind::Dealloc).with(ActionKind::DeallocResult);
+
+    /// The set of just `kind`.
+    pub const fn of(kind: ActionKind) -> Self {
+        Self(1 << kind as u8)
+    }
+
+    /// This set with `kind` added.
+    pub const fn with(self, kind: ActionKind) -> Self {
+        Self(self.0 | 1 << kind as u8)
+    }
+
+    /// This set with `kind` removed.
+    pub const fn without(self, kind: ActionKind) -> Self {
+        Self(self.0 & !(1 << kind as u8))
+    }
+
+    /// The kinds in either set.
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// The kinds in both sets.
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Whether `kind` is in the set.
+    #[inline]
+    pub const fn contains(self, kind: ActionKind) -> bool {
+        self.0 & (1 << kind as u8) != 0
+    }
+
+    /// Whether `action` is of a kind in the set.
+    #[inline]
+    pub fn matches(self, action: &AllocAction) -> bool {
+        self.contains(action.kind())
+    }
+
+    /// The set as bits, where bit `n` is the kind with discriminant `n`.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// The set with the given bits, as returned by `bits`.
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+}
+
+impl Default for EventMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl core::ops::BitOr for EventMask {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+impl core::ops::BitAnd for EventMask {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        self.intersection(other)
+    }
+}
+
 impl AllocAction {
     /// Whether the action is before or after the action itself.
     #[inline]
@@ -46,6 +352,46 @@ impl AllocAction {
             _ => AllocRel::After,
         }
     }
+
+    /// The kind of this action.
+    #[inline]
+    pub fn kind(&self) -> ActionKind {
+        use AllocAction::*;
+        match self {
+            Alloc => ActionKind::Alloc,
+            AllocResult { .. } => ActionKind::AllocResult,
+            AllocZeroed => ActionKind::AllocZeroed,
+            AllocZeroedResult { .. } => ActionKind::AllocZeroedResult,
+            Dealloc { .. } => ActionKind::Dealloc,
+            DeallocResult => ActionKind::DeallocResult,
+            Realloc { .. } => ActionKind::Realloc,
+            ReallocResult { .. } => ActionKind::ReallocResult,
+        }
+    }
+
+    /// The pointer attached to this action, if it has one.
+    #[inline]
+    pub fn ptr(&self) -> Option<*mut u8> {
+        use AllocAction::*;
+        match *self {
+            AllocResult { ptr }
+            | AllocZeroedResult { ptr }
+            | Dealloc { ptr }
+            | Realloc { ptr, .. }
+            | ReallocResult { ptr, .. } => Some(ptr),
+            _ => None,
+        }
+    }
+
+    /// The new size attached to this action, if it's part of a realloc.
+    #[inline]
+    pub fn new_size(&self) -> Option<usize> {
+        use AllocAction::*;
+        match *self {
+            Realloc { new_size, .. } | ReallocResult { new_size, .. } => Some(new_size),
+            _ => None,
+        }
+    }
 }
 
 /// An allocator that watches the calls to its API, sends them to a struct, and
@@ -54,7 +400,86 @@ impl AllocAction {
 /// To use this struct, use the `#[global_allocator]` compiler directive and
 /// construct the allocator with your own custom monitor, or one of the builtins.
 /// Note that the new method of `interloc::StatsMonitor` is a `const fn`.
-pub struct InterAlloc<'a, T, F>
+///
+/// With the `disabled` feature, this forwards every call straight to the inner
+/// allocator without calling the monitor, so it costs nothing over using the
+/// inner allocator directly. `StatsMonitor` also becomes zero-sized and always
+/// reports zeroes, so its statics take no space. The monitors built on fixed
+/// tables, like `TrackingMonitor`, `SiteTable`, `HistogramMonitor` and the
+/// event logs and queues, drop their tables and keep only a few counters and
+/// their configuration. Called directly, they count nothing into the table, and
+/// report it empty.
+///
+/// # Lifetimes
+/// The allocator borrows its monitor for `'static`, since a global allocator
+/// outlives everything else in the program, and `#[global_allocator]` statics
+/// are what it's for. Even used directly, it can't borrow a local monitor:
+///
+/// ```rust,compile_fail,E0597
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::{InterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// let monitor = StatsMonitor::new();
+/// let alloc = InterAlloc {
+///     inner: System,
+///     monitor: &monitor,
+/// };
+/// unsafe { alloc.alloc(Layout::new::<u64>()) };
+/// ```
+///
+/// Nor can it be returned from the function that owns the monitor:
+///
+/// ```rust,compile_fail,E0515
+/// use interloc::{InterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// fn counted() -> InterAlloc<System, StatsMonitor> {
+///     let monitor = StatsMonitor::new();
+///     InterAlloc {
+///         inner: System,
+///         monitor: &monitor,
+///     }
+/// }
+/// ```
+///
+/// `ScopedInterAlloc` is the same allocator over a monitor borrowed for
+/// shorter, for those uses.
+pub type InterAlloc<T, F> = ScopedInterAlloc<'static, T, F>;
+
+/// An `InterAlloc` that borrows its monitor for `'a` rather than for
+/// `'static`, to call directly over a monitor that doesn't live for the whole
+/// program, like one made for a test or a benchmark. It can't be used once the
+/// monitor is gone:
+///
+/// ```rust,compile_fail,E0597
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::{ScopedInterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// let alloc;
+/// {
+///     let monitor = StatsMonitor::new();
+///     alloc = ScopedInterAlloc::new(System, &monitor);
+/// }
+/// unsafe { alloc.alloc(Layout::new::<u64>()) };
+/// ```
+///
+/// Within the monitor's scope, it's an `InterAlloc` in every other way:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::{ScopedInterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// let monitor = StatsMonitor::new();
+/// let alloc = ScopedInterAlloc::new(System, &monitor);
+/// let layout = Layout::new::<u64>();
+/// unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+/// assert_eq!(monitor.info().alloc, 1);
+/// ```
+pub struct ScopedInterAlloc<'a, T, F>
 where
     T: GlobalAlloc,
     F: AllocMonitor,
@@ -68,7 +493,7 @@ where
     pub monitor: &'a F,
 }
 
-impl<'a, T, F> InterAlloc<'a, T, F>
+impl<'a, T, F> ScopedInterAlloc<'a, T, F>
 where
     T: GlobalAlloc,
     F: AllocMonitor,
@@ -78,18 +503,75 @@ where
     pub fn new(base: T, monitor: &'a F) -> Self {
         Self {
             inner: base,
-            monitor: monitor,
+            monitor,
         }
     }
 
-    /// Call the monitor function.
+    /// Call the monitor function, unless monitoring is suppressed.
+    #[cfg(not(feature = "disabled"))]
     #[inline]
     fn monitor_(&self, layout: Layout, act: AllocAction) {
-        self.monitor.monitor(layout, act);
+        match MODE.with(Cell::get) {
+            MONITORED => {}
+            INTERNAL => return INTERNAL_INFO.add_with_peak(layout, act),
+            _ => return,
+        }
+        let call = AssertUnwindSafe(|| {
+            crate::first_event::first_event(self.monitor);
+            self.monitor.monitor(layout, act);
+        });
+        if let Err(payload) = std::panic::catch_unwind(call) {
+            crate::monitor_panic::monitor_panicked::<F>(payload);
+        }
     }
+
+    #[cfg(feature = "disabled")]
+    #[inline(always)]
+    fn monitor_(&self, _: Layout, _: AllocAction) {}
 }
 
-unsafe impl<'a, T, F> GlobalAlloc for InterAlloc<'a, T, F>
+/// A `SeqCst` fence with the `strict-ordering` feature, and nothing otherwise, or
+/// with the `disabled` feature.
+///
+/// The monitor is called on the thread making the allocator call, so program
+/// order already puts the before action ahead of the call and the after action
+/// behind it, as far as that thread can tell. Other threads can't observe the
+/// inner allocator's memory accesses in any meaningful way, and monitors that
+/// share state between threads synchronize it with their own atomics and locks.
+/// So the fences only ever cost time; they're kept behind the feature for
+/// anyone relying on their exact old behavior.
+#[inline(always)]
+fn strict_fence() {
+    #[cfg(all(feature = "strict-ordering", not(feature = "disabled")))]
+    fence(Ordering::SeqCst);
+}
+
+/// Reallocates by allocating a new block from `alloc`, copying the contents up
+/// to the smaller of the two sizes, and freeing the old block, for allocators
+/// that can't resize blocks themselves. If the new block can't be allocated,
+/// null is returned and the old block is left as it was.
+///
+/// # Safety
+/// The same as for `GlobalAlloc::realloc`.
+pub(crate) unsafe fn realloc_via_alloc_copy<A: GlobalAlloc + ?Sized>(
+    alloc: &A,
+    ptr: *mut u8,
+    layout: Layout,
+    new_size: usize,
+) -> *mut u8 {
+    let new_layout = match Layout::from_size_align(new_size, layout.align()) {
+        Ok(new_layout) => new_layout,
+        Err(_) => return core::ptr::null_mut(),
+    };
+    let new_ptr = alloc.alloc(new_layout);
+    if !new_ptr.is_null() {
+        core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+        alloc.dealloc(ptr, layout);
+    }
+    new_ptr
+}
+
+unsafe impl<'a, T, F> GlobalAlloc for ScopedInterAlloc<'a, T, F>
 where
     T: GlobalAlloc,
     F: AllocMonitor,
@@ -97,9 +579,9 @@ where
     #[inline]
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         self.monitor_(layout, AllocAction::Alloc);
-        fence(Ordering::SeqCst);
+        strict_fence();
         let ptr = self.inner.alloc(layout);
-        fence(Ordering::SeqCst);
+        strict_fence();
         self.monitor_(layout, AllocAction::AllocResult { ptr });
         ptr
     }
@@ -107,18 +589,18 @@ where
     #[inline]
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         self.monitor_(layout, AllocAction::Dealloc { ptr });
-        fence(Ordering::SeqCst);
+        strict_fence();
         self.inner.dealloc(ptr, layout);
-        fence(Ordering::SeqCst);
+        strict_fence();
         self.monitor_(layout, AllocAction::DeallocResult);
     }
 
     #[inline]
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
         self.monitor_(layout, AllocAction::AllocZeroed);
-        fence(Ordering::SeqCst);
+        strict_fence();
         let ptr = self.inner.alloc_zeroed(layout);
-        fence(Ordering::SeqCst);
+        strict_fence();
         self.monitor_(layout, AllocAction::AllocZeroedResult { ptr });
         ptr
     }
@@ -126,20 +608,100 @@ where
     #[inline]
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
         self.monitor_(layout, AllocAction::Realloc { ptr, new_size });
-        fence(Ordering::SeqCst);
+        strict_fence();
         let ptr = self.inner.realloc(ptr, layout, new_size);
-        fence(Ordering::SeqCst);
+        strict_fence();
         self.monitor_(layout, AllocAction::ReallocResult { ptr, new_size });
         ptr
     }
 }
 
 /// When attached to an `InterAlloc` instance, this struct's `monitor` method
-/// is called before and after calls to the inner allocator. The ordering of
-/// these method calls is enforced by `std::sync::atomic::fence`
-/// with `std::sync::atomic::Ordering::SeqCst`.
+/// is called before and after calls to the inner allocator, on the thread making
+/// the call. With the `strict-ordering` feature, the calls are also separated
+/// from the inner allocator's by `std::sync::atomic::fence` with
+/// `std::sync::atomic::Ordering::SeqCst`.
 pub trait AllocMonitor {
     /// The api to the monitor. This method is called right before and right after
     /// allocations happen.
     fn monitor(&self, layout: Layout, act: AllocAction);
+
+    /// Called by `InterAlloc` exactly once per monitor, before the first event
+    /// is passed to `monitor`, for setup that can't happen in a `const`
+    /// constructor, like opening files or warming up caches. It runs with
+    /// monitoring suppressed, so it may allocate. Events from other threads wait
+    /// until it returns. Does nothing by default.
+    ///
+    /// Monitors are told apart by their address and type, and `InterAlloc`
+    /// keeps track of up to 64 of them per process; the hook isn't called for
+    /// any after that, which `first_event_overflowed` reports. A monitor at the
+    /// address of one of the same type that was dropped counts as the same
+    /// monitor. The hook isn't called with the `disabled` feature, or when the
+    /// monitor is called directly rather than through an `InterAlloc`.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use core::alloc::Layout;
+    /// use core::sync::atomic::{AtomicUsize, Ordering};
+    /// use interloc::{AllocAction, AllocMonitor, InterAlloc};
+    /// use std::alloc::System;
+    ///
+    /// struct Lazy {
+    ///     setups: AtomicUsize,
+    /// }
+    ///
+    /// impl AllocMonitor for Lazy {
+    ///     fn monitor(&self, _layout: Layout, _action: AllocAction) {}
+    ///
+    ///     fn on_first_event(&self) {
+    ///         // Allocating here isn't monitored.
+    ///         let buffer = vec![0u8; 4096];
+    ///         drop(buffer);
+    ///         self.setups.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// }
+    ///
+    /// static MONITOR: Lazy = Lazy {
+    ///     setups: AtomicUsize::new(0),
+    /// };
+    ///
+    /// #[global_allocator]
+    /// static GLOBAL: InterAlloc<System, Lazy> = InterAlloc {
+    ///     inner: System,
+    ///     monitor: &MONITOR,
+    /// };
+    ///
+    /// let threads: Vec<_> = (0..8)
+    ///     .map(|_| std::thread::spawn(|| drop(vec![1u8; 100])))
+    ///     .collect();
+    /// for thread in threads {
+    ///     thread.join().unwrap();
+    /// }
+    /// assert_eq!(MONITOR.setups.load(Ordering::Relaxed), 1);
+    /// ```
+    fn on_first_event(&self) {}
+
+    /// A short description of the monitor, for `ConfigReport`. By default, its
+    /// type name and an unknown overhead.
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new(core::any::type_name::<Self>(), Overhead::Unknown)
+    }
+
+    /// Adds the monitors that this one passes events to, if any, with
+    /// `ConfigReport::add`, for wrappers and lists of monitors. Does nothing by
+    /// default.
+    fn describe_inner(&self, report: &mut ConfigReport) {
+        let _ = report;
+    }
+}
+
+/// A monitor that decides whether an event should go any further, for putting
+/// cheap checks in front of expensive monitors in a `PipelineMonitor`.
+///
+/// `accepts` is called exactly once per event, so gates may keep state, e.g. to
+/// sample, and must make the same decision for the before and after actions of
+/// a call if the monitors behind them pair the two up.
+pub trait GatingMonitor {
+    /// Whether the event should be passed on.
+    fn accepts(&self, layout: Layout, act: AllocAction) -> bool;
 }
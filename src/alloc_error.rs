@@ -0,0 +1,103 @@
+use crate::alloc::suppress;
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// What to run when an allocation fails for good, e.g. to flush a ring buffer or
+/// write a crash report. The process is about to abort, and the heap can't be
+/// relied on, so it mustn't allocate; formatting into a `FmtBuffer` and writing
+/// it to a raw file descriptor is fine.
+pub type AllocErrorHandler = fn(Layout);
+
+/// The installed handler, or null, which it is swapped for when it runs.
+static HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Installs `handler` to run once, right before the process aborts on a failed
+/// allocation. Installing another replaces it.
+///
+/// With the `nightly` feature, this chains `std::alloc::set_alloc_error_hook`,
+/// so the handler runs when `handle_alloc_error` is called from anywhere, for
+/// example by `Vec` when the allocator returns null. On stable there's no such
+/// hook, so the handler only runs when an allocator that knows a failure is
+/// fatal calls `report_alloc_error`, or when code calls this crate's
+/// `handle_alloc_error` instead of the one in `std`.
+///
+/// ```rust
+/// use core::alloc::Layout;
+/// use interloc::{handle_alloc_error, install_alloc_error_hook, FmtBuffer};
+/// use std::io::Write;
+/// use std::process::Command;
+///
+/// fn flush(layout: Layout) {
+///     use core::fmt::Write;
+///     let mut buf = FmtBuffer::<64>::new();
+///     let _ = writeln!(buf, "flushed for {} bytes", layout.size());
+///     let _ = std::io::stderr().write_all(buf.as_str().as_bytes());
+/// }
+///
+/// if std::env::var_os("ALLOC_ERROR_CHILD").is_some() {
+///     install_alloc_error_hook(flush);
+///     handle_alloc_error(Layout::from_size_align(1 << 40, 8).unwrap());
+/// }
+///
+/// // The failure aborts the process, so it happens in a child. Miri can't start
+/// // one.
+/// if cfg!(miri) {
+///     return;
+/// }
+/// let output = Command::new(std::env::current_exe().unwrap())
+///     .env("ALLOC_ERROR_CHILD", "1")
+///     .output()
+///     .unwrap();
+/// let stderr = String::from_utf8_lossy(&output.stderr);
+/// assert!(!output.status.success());
+/// let flushed = stderr.find("flushed for 1099511627776 bytes").unwrap();
+/// let aborted = stderr.find("memory allocation of").unwrap();
+/// assert!(flushed < aborted);
+/// ```
+pub fn install_alloc_error_hook(handler: AllocErrorHandler) {
+    HANDLER.store(handler as *mut (), Ordering::Release);
+    #[cfg(feature = "nightly")]
+    chain_std_hook();
+}
+
+/// Runs the installed handler, unless it already ran, with monitoring
+/// suppressed. For allocators that are about to return null for a failure the
+/// caller can't recover from, like a hard limit, right before they do.
+///
+/// The handler runs at most once, even if it fails an allocation itself.
+pub fn report_alloc_error(layout: Layout) {
+    let handler = HANDLER.swap(core::ptr::null_mut(), Ordering::AcqRel);
+    if !handler.is_null() {
+        let handler: AllocErrorHandler = unsafe { core::mem::transmute(handler) };
+        suppress(|| handler(layout));
+    }
+}
+
+/// Runs the installed handler, then `std::alloc::handle_alloc_error`, which
+/// aborts the process. For code that would call the one in `std`, so the
+/// handler runs on stable too.
+pub fn handle_alloc_error(layout: Layout) -> ! {
+    report_alloc_error(layout);
+    std::alloc::handle_alloc_error(layout)
+}
+
+/// Puts a hook in front of `std`'s alloc error hook that runs the handler, once.
+#[cfg(feature = "nightly")]
+fn chain_std_hook() {
+    static PREVIOUS: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+    fn hook(layout: Layout) {
+        report_alloc_error(layout);
+        let previous = PREVIOUS.load(Ordering::Acquire);
+        if !previous.is_null() {
+            let previous: fn(Layout) = unsafe { core::mem::transmute(previous) };
+            previous(layout);
+        }
+    }
+
+    let previous = std::alloc::take_alloc_error_hook();
+    if previous as usize != hook as fn(Layout) as usize {
+        PREVIOUS.store(previous as *mut (), Ordering::Release);
+    }
+    std::alloc::set_alloc_error_hook(hook);
+}
@@ -0,0 +1,349 @@
+use crate::alloc::realloc_via_alloc_copy;
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::mem::{size_of, MaybeUninit};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Every block's size and offset in an `ArenaAlloc` is a multiple of this,
+/// so that a free block can always hold a free list node.
+const GRANULE: usize = 16;
+
+const _: () = assert!(GRANULE >= 2 * size_of::<usize>());
+
+/// Marks the end of the free list.
+const NIL: usize = usize::MAX;
+
+#[repr(C, align(4096))]
+struct Bytes<const N: usize>([MaybeUninit<u8>; N]);
+
+/// The bookkeeping of an `ArenaAlloc`, only touched with its lock held.
+struct State {
+    /// Offset of the end of the bumped part of the arena
+    top: usize,
+    /// Offset of the free block with the lowest address, or `NIL`
+    head: usize,
+    /// Bytes in allocated blocks, rounded up to the granule
+    used: usize,
+}
+
+/// An allocator over a fixed array of `N` bytes, for targets without a heap,
+/// and for tests that need the same addresses, and the same decisions about
+/// moving reallocated blocks, every time.
+///
+/// Blocks are carved off the end of the used part of the arena, and freed
+/// blocks go on a free list that's searched first-fit, in address order, before
+/// the arena grows. Adjacent free blocks are merged, and a block freed at the
+/// end of the used part shrinks it. Sizes are rounded up to 16 bytes.
+/// Reallocation works in place when the block shrinks, or when it can grow into
+/// free space right after it; otherwise the block moves. When the arena runs
+/// out, allocations return null, which `InterAlloc` counts as a failure.
+///
+/// The arena is aligned to 4096 bytes, so where each block goes only depends on
+/// the calls made so far. Calls are serialized by a spinlock, so the arena must
+/// not be used from a signal handler that may interrupt one.
+///
+/// ```rust
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::ArenaAlloc;
+///
+/// let arena = ArenaAlloc::<4096>::new();
+/// let small = Layout::from_size_align(24, 8).unwrap();
+/// let aligned = Layout::from_size_align(64, 256).unwrap();
+/// unsafe {
+///     let a = arena.alloc(small);
+///     let b = arena.alloc(small);
+///     let c = arena.alloc(aligned);
+///     assert_eq!(arena.offset_of(b), Some(32));
+///     assert_eq!(arena.offset_of(c), Some(256));
+///
+///     // Freed blocks are reused first.
+///     arena.dealloc(b, small);
+///     assert_eq!(arena.alloc(small), b);
+///
+///     // Blocks grow in place into free space after them, and move otherwise.
+///     assert_eq!(arena.realloc(b, small, 200), b);
+///     assert_eq!(arena.realloc(c, aligned, 1024), c);
+///     assert_ne!(arena.realloc(a, small, 64), a);
+///
+///     assert!(arena.alloc(Layout::new::<[u8; 4096]>()).is_null());
+/// }
+/// ```
+pub struct ArenaAlloc<const N: usize> {
+    bytes: UnsafeCell<Bytes<N>>,
+    state: UnsafeCell<State>,
+    locked: AtomicBool,
+}
+
+// The arena and its bookkeeping are only touched with the lock held, apart
+// from the blocks given out, which belong to their callers.
+unsafe impl<const N: usize> Sync for ArenaAlloc<N> {}
+
+unsafe impl<const N: usize> Send for ArenaAlloc<N> {}
+
+/// Releases the lock of an `ArenaAlloc` when dropped.
+struct Locked<'a, const N: usize>(&'a ArenaAlloc<N>);
+
+impl<'a, const N: usize> Drop for Locked<'a, N> {
+    fn drop(&mut self) {
+        self.0.locked.store(false, Ordering::Release);
+    }
+}
+
+impl<const N: usize> ArenaAlloc<N> {
+    pub const fn new() -> Self {
+        Self {
+            bytes: UnsafeCell::new(Bytes([MaybeUninit::uninit(); N])),
+            state: UnsafeCell::new(State {
+                top: 0,
+                head: NIL,
+                used: 0,
+            }),
+            locked: AtomicBool::new(false),
+        }
+    }
+
+    /// The start of the arena.
+    pub fn base(&self) -> *mut u8 {
+        self.bytes.get() as *mut u8
+    }
+
+    /// The size of the arena.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Bytes in allocated blocks, with each rounded up to 16 bytes.
+    pub fn used(&self) -> usize {
+        let _lock = self.lock();
+        unsafe { (*self.state.get()).used }
+    }
+
+    /// The offset of `ptr` from the start of the arena, or `None` if it isn't in
+    /// the arena.
+    pub fn offset_of(&self, ptr: *const u8) -> Option<usize> {
+        let offset = (ptr as usize).checked_sub(self.base() as usize)?;
+        (offset < N).then_some(offset)
+    }
+
+    fn lock(&self) -> Locked<'_, N> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        Locked(self)
+    }
+
+    /// The size of a block holding `size` bytes, or `None` if it's too big for
+    /// the arena.
+    fn block_size(size: usize) -> Option<usize> {
+        let size = size.max(1).checked_next_multiple_of(GRANULE)?;
+        (size <= N).then_some(size)
+    }
+
+    /// The lowest offset from `offset` up that's aligned to `align`.
+    fn align_offset(&self, offset: usize, align: usize) -> Option<usize> {
+        let base = self.base() as usize;
+        let start = (base + offset).checked_next_multiple_of(align)?;
+        Some(start - base)
+    }
+
+    /// Reads the free list node at `offset`: the offset of the next free block,
+    /// and the length of this one.
+    unsafe fn node(&self, offset: usize) -> (usize, usize) {
+        *(self.base().add(offset) as *const (usize, usize))
+    }
+
+    unsafe fn set_node(&self, offset: usize, next: usize, len: usize) {
+        *(self.base().add(offset) as *mut (usize, usize)) = (next, len);
+    }
+
+    /// Points the free block before `next` at it, or the head if there isn't one.
+    unsafe fn link(&self, state: &mut State, prev: usize, next: usize) {
+        if prev == NIL {
+            state.head = next;
+        } else {
+            let (_, len) = self.node(prev);
+            self.set_node(prev, next, len);
+        }
+    }
+
+    /// Adds `len` bytes at `offset` to the free list, merging them with the free
+    /// blocks next to them.
+    unsafe fn insert_free(&self, state: &mut State, offset: usize, len: usize) {
+        let mut prev = NIL;
+        let mut cur = state.head;
+        while cur != NIL && cur < offset {
+            prev = cur;
+            cur = self.node(cur).0;
+        }
+        let merge_prev = prev != NIL && prev + self.node(prev).1 == offset;
+        let merge_next = cur != NIL && offset + len == cur;
+        match (merge_prev, merge_next) {
+            (true, true) => {
+                let (next, cur_len) = self.node(cur);
+                self.set_node(prev, next, self.node(prev).1 + len + cur_len);
+            }
+            (true, false) => self.set_node(prev, cur, self.node(prev).1 + len),
+            (false, true) => {
+                let (next, cur_len) = self.node(cur);
+                self.set_node(offset, next, len + cur_len);
+                self.link(state, prev, offset);
+            }
+            (false, false) => {
+                self.set_node(offset, cur, len);
+                self.link(state, prev, offset);
+            }
+        }
+    }
+
+    /// Gives `len` bytes at `offset` back, shrinking the bumped part of the
+    /// arena if they're at its end.
+    unsafe fn release(&self, state: &mut State, offset: usize, len: usize) {
+        if offset + len != state.top {
+            self.insert_free(state, offset, len);
+            return;
+        }
+        state.top = offset;
+        // Free blocks are merged, so only the last one can end at the top.
+        let mut prev = NIL;
+        let mut cur = state.head;
+        while cur != NIL {
+            let (next, cur_len) = self.node(cur);
+            if next == NIL && cur + cur_len == state.top {
+                self.link(state, prev, NIL);
+                state.top = cur;
+                break;
+            }
+            prev = cur;
+            cur = next;
+        }
+    }
+
+    unsafe fn alloc_block(&self, state: &mut State, size: usize, align: usize) -> *mut u8 {
+        let mut prev = NIL;
+        let mut cur = state.head;
+        while cur != NIL {
+            let (next, len) = self.node(cur);
+            if let Some(start) = self.align_offset(cur, align) {
+                if start + size <= cur + len {
+                    self.link(state, prev, next);
+                    if start > cur {
+                        self.insert_free(state, cur, start - cur);
+                    }
+                    if start + size < cur + len {
+                        self.insert_free(state, start + size, cur + len - start - size);
+                    }
+                    state.used += size;
+                    return self.base().add(start);
+                }
+            }
+            prev = cur;
+            cur = next;
+        }
+
+        let start = match self.align_offset(state.top, align) {
+            Some(start) if start <= N && size <= N - start => start,
+            _ => return core::ptr::null_mut(),
+        };
+        if start > state.top {
+            self.insert_free(state, state.top, start - state.top);
+        }
+        state.top = start + size;
+        state.used += size;
+        self.base().add(start)
+    }
+
+    /// Resizes the block at `offset` from `old` to `new` bytes without moving
+    /// it, if there's room.
+    unsafe fn resize_in_place(
+        &self,
+        state: &mut State,
+        offset: usize,
+        old: usize,
+        new: usize,
+    ) -> bool {
+        if new <= old {
+            if new < old {
+                self.release(state, offset + new, old - new);
+                state.used -= old - new;
+            }
+            return true;
+        }
+        let end = offset + old;
+        let extra = new - old;
+        if end == state.top {
+            if new > N - offset {
+                return false;
+            }
+            state.top = offset + new;
+            state.used += extra;
+            return true;
+        }
+        let mut prev = NIL;
+        let mut cur = state.head;
+        while cur != NIL && cur < end {
+            prev = cur;
+            cur = self.node(cur).0;
+        }
+        if cur != end {
+            return false;
+        }
+        let (next, len) = self.node(cur);
+        if len < extra {
+            return false;
+        }
+        if len == extra {
+            self.link(state, prev, next);
+        } else {
+            self.set_node(end + extra, next, len - extra);
+            self.link(state, prev, end + extra);
+        }
+        state.used += extra;
+        true
+    }
+}
+
+impl<const N: usize> Default for ArenaAlloc<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const N: usize> GlobalAlloc for ArenaAlloc<N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let size = match Self::block_size(layout.size()) {
+            Some(size) => size,
+            None => return core::ptr::null_mut(),
+        };
+        let _lock = self.lock();
+        self.alloc_block(&mut *self.state.get(), size, layout.align())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let size = Self::block_size(layout.size()).unwrap_or(N);
+        let offset = ptr as usize - self.base() as usize;
+        let _lock = self.lock();
+        let state = &mut *self.state.get();
+        state.used -= size;
+        self.release(state, offset, size);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = match Self::block_size(new_size) {
+            Some(new) => new,
+            None => return core::ptr::null_mut(),
+        };
+        let old = Self::block_size(layout.size()).unwrap_or(N);
+        let offset = ptr as usize - self.base() as usize;
+        {
+            let _lock = self.lock();
+            if self.resize_in_place(&mut *self.state.get(), offset, old, new) {
+                return ptr;
+            }
+        }
+        realloc_via_alloc_copy(self, ptr, layout, new_size)
+    }
+}
@@ -0,0 +1,158 @@
+use crate::alloc::{internal, AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::sites::{AllocSite, SiteTable};
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Captures the stack of allocations and accumulates them by site, so that you
+/// can find out where allocations come from.
+///
+/// Stacks are captured as raw return addresses with `backtrace::trace`, and are
+/// never symbolized while allocating. Capturing is expensive, so it can be
+/// limited to allocations of at least `min_size` bytes, and to one in every
+/// `sample_every` of those. Reallocations are recorded with their new size.
+///
+/// Frames up to and including the monitor's own are always dropped, found by
+/// comparing the start address of each frame's function with the addresses of
+/// the monitor's functions, so the same user frame always ends up as the same
+/// site. `skip_frames` drops further frames past those, like the allocator
+/// shims, and at most `max_depth` frames are kept after that.
+///
+/// The stack is captured with monitoring suppressed, since the unwinder may
+/// allocate the first time it's used. Calling `warm_up` once at startup gets that
+/// out of the way before the first capture.
+pub struct BacktraceMonitor<const SITES: usize = 1024, const DEPTH: usize = 32> {
+    sites: SiteTable<SITES, DEPTH>,
+    min_size: usize,
+    sample_every: usize,
+    max_depth: usize,
+    skip_frames: usize,
+    seen: AtomicUsize,
+}
+
+/// How many frames are searched for the monitor's own frames before giving up,
+/// in case the platform can't tell the function a frame is in.
+const INTERNAL_SEARCH: usize = 32;
+
+impl<const SITES: usize, const DEPTH: usize> BacktraceMonitor<SITES, DEPTH> {
+    /// A monitor that captures the stack of every allocation.
+    pub const fn new() -> Self {
+        Self {
+            sites: SiteTable::new(),
+            min_size: 0,
+            sample_every: 1,
+            max_depth: DEPTH,
+            skip_frames: 0,
+            seen: AtomicUsize::new(0),
+        }
+    }
+
+    /// Only capture stacks for allocations of at least `min_size` bytes.
+    pub const fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Only capture the stack of one in every `n` allocations that are large
+    /// enough to be captured.
+    pub const fn sample_every(mut self, n: usize) -> Self {
+        self.sample_every = if n == 0 { 1 } else { n };
+        self
+    }
+
+    /// Keeps at most `max_depth` frames of each stack, which can't be more than
+    /// `DEPTH`.
+    pub const fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = if max_depth < DEPTH { max_depth } else { DEPTH };
+        self
+    }
+
+    /// Drops `n` more frames from the innermost end of each stack, after the
+    /// monitor's own frames.
+    pub const fn skip_frames(mut self, n: usize) -> Self {
+        self.skip_frames = n;
+        self
+    }
+
+    /// Initializes the unwinder, so that its first-use allocations happen now
+    /// rather than during the first capture.
+    pub fn warm_up() {
+        internal(|| backtrace::trace(|_| false));
+    }
+
+    /// The table of sites recorded so far.
+    pub fn sites(&self) -> &SiteTable<SITES, DEPTH> {
+        &self.sites
+    }
+
+    /// The `n` sites with the most bytes allocated, most first.
+    pub fn top_sites(&self, n: usize) -> Vec<AllocSite> {
+        internal(|| self.sites.top_sites(n))
+    }
+
+    /// Captures the current stack and records an allocation of `size` bytes at it.
+    #[inline(never)]
+    fn capture(&self, size: usize) {
+        let own = [
+            Self::capture as fn(&Self, usize) as usize,
+            <Self as AllocMonitor>::monitor as fn(&Self, Layout, AllocAction) as usize,
+        ];
+        let mut frames = [0usize; DEPTH];
+        let mut len = 0;
+        let mut skip = self.skip_frames;
+        let mut found = false;
+        let mut searched = 0;
+        internal(|| {
+            backtrace::trace(|frame| {
+                searched += 1;
+                if own.contains(&(frame.symbol_address() as usize)) {
+                    found = true;
+                    len = 0;
+                    skip = self.skip_frames;
+                    return true;
+                }
+                if skip > 0 {
+                    skip -= 1;
+                } else if len < self.max_depth {
+                    frames[len] = frame.ip() as usize;
+                    len += 1;
+                }
+                len < self.max_depth || (!found && searched < INTERNAL_SEARCH)
+            })
+        });
+        self.sites.record(&frames[..len], size);
+    }
+}
+
+impl<const SITES: usize, const DEPTH: usize> Default for BacktraceMonitor<SITES, DEPTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SITES: usize, const DEPTH: usize> AllocMonitor for BacktraceMonitor<SITES, DEPTH> {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let size = match action {
+            AllocAction::Alloc | AllocAction::AllocZeroed => layout.size(),
+            AllocAction::Realloc { new_size, .. } => new_size,
+            _ => return,
+        };
+        if size < self.min_size {
+            return;
+        }
+        if self.sample_every > 1
+            && self.seen.fetch_add(1, Ordering::Relaxed) % self.sample_every != 0
+        {
+            return;
+        }
+        self.capture(size);
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("BacktraceMonitor", Overhead::Heavy)
+            .param("sites", SITES as u64)
+            .param("depth", self.max_depth as u64)
+            .param("min_size", self.min_size as u64)
+            .param("sample_every", self.sample_every as u64)
+    }
+}
@@ -0,0 +1,149 @@
+//! Allocation profiles of functions, for benchmarks and tests.
+//!
+#![cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#![cfg_attr(feature = "disabled", doc = "```ignore")]
+//! use interloc::{bench, InterAlloc, ThreadMonitor};
+//! use std::alloc::System;
+//!
+//! static MONITOR: ThreadMonitor = ThreadMonitor::new();
+//!
+//! #[global_allocator]
+//! static GLOBAL: InterAlloc<System, ThreadMonitor> = InterAlloc {
+//!     inner: System,
+//!     monitor: &MONITOR,
+//! };
+//!
+//! let profile = bench::profile(10, || vec![0u8; 100]);
+//! assert!(profile.identical);
+//! assert_eq!(profile.max.alloc, 1);
+//! assert_eq!(profile.max.bytes_alloc, 100);
+//! ```
+//!
+//! The counts come from `ThreadMonitor`, so the global allocator has to be an
+//! `InterAlloc` whose monitor includes one, and only allocations made on the
+//! calling thread are counted.
+use crate::monitor::{AllocInfo, ThreadMonitor};
+
+/// The allocations made by each run of a function, as measured by `profile`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllocProfile {
+    /// What each run allocated, in order. `peak_bytes` is the most bytes that
+    /// were live at once during the run, on top of those live when it started.
+    pub runs: Vec<AllocInfo>,
+    /// The smallest value of each field over the runs
+    pub min: AllocInfo,
+    /// The median value of each field over the runs, or the higher of the two
+    /// middle values for an even number of runs
+    pub median: AllocInfo,
+    /// The largest value of each field over the runs
+    pub max: AllocInfo,
+    /// Whether every run allocated exactly the same way
+    pub identical: bool,
+}
+
+impl AllocProfile {
+    fn new(runs: Vec<AllocInfo>) -> Self {
+        let field = |pick: fn(&AllocInfo) -> u64| {
+            let mut values: Vec<u64> = runs.iter().map(pick).collect();
+            values.sort_unstable();
+            let min = values.first().copied().unwrap_or(0);
+            let median = values.get(values.len() / 2).copied().unwrap_or(0);
+            let max = values.last().copied().unwrap_or(0);
+            (min, median, max)
+        };
+        let alloc = field(|i| i.alloc);
+        let dealloc = field(|i| i.dealloc);
+        let realloc = field(|i| i.realloc);
+        let bytes_alloc = field(|i| i.bytes_alloc);
+        let bytes_dealloc = field(|i| i.bytes_dealloc);
+        let peak_bytes = field(|i| i.peak_bytes);
+        let baseline_bytes = field(|i| i.baseline_bytes);
+        let info = |pick: fn((u64, u64, u64)) -> u64| AllocInfo {
+            alloc: pick(alloc),
+            dealloc: pick(dealloc),
+            realloc: pick(realloc),
+            bytes_alloc: pick(bytes_alloc),
+            bytes_dealloc: pick(bytes_dealloc),
+            peak_bytes: pick(peak_bytes),
+            baseline_bytes: pick(baseline_bytes),
+        };
+        Self {
+            min: info(|f| f.0),
+            median: info(|f| f.1),
+            max: info(|f| f.2),
+            identical: runs.windows(2).all(|w| w[0] == w[1]),
+            runs,
+        }
+    }
+}
+
+/// Measures the allocations made by runs of a function.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Profiler {
+    warmup: usize,
+}
+
+impl Profiler {
+    pub const fn new() -> Self {
+        Self { warmup: 0 }
+    }
+
+    /// Runs the function `n` more times before measuring, to leave out the
+    /// allocations of lazy initialization on the first runs.
+    pub const fn warmup(mut self, n: usize) -> Self {
+        self.warmup = n;
+        self
+    }
+
+    /// Runs `f` `iters` times on the current thread, after the warmup runs, and
+    /// returns what each run allocated. What `f` returns is dropped within the
+    /// run.
+    pub fn profile<R>(&self, iters: usize, mut f: impl FnMut() -> R) -> AllocProfile {
+        for _ in 0..self.warmup {
+            drop(f());
+        }
+        let mut runs = Vec::with_capacity(iters);
+        for _ in 0..iters {
+            runs.push(measure(|| drop(f())).1);
+        }
+        AllocProfile::new(runs)
+    }
+}
+
+/// Runs `f` `iters` times on the current thread and returns what each run
+/// allocated, without any warmup runs.
+pub fn profile<R>(iters: usize, f: impl FnMut() -> R) -> AllocProfile {
+    Profiler::new().profile(iters, f)
+}
+
+/// Calls `f` and returns what it allocated on the current thread, according to
+/// `ThreadMonitor`. `peak_bytes` is the most bytes that were live at once during
+/// the call, on top of those live when it started.
+pub(crate) fn measure<R>(f: impl FnOnce() -> R) -> (R, AllocInfo) {
+    let start = start_measuring();
+    let result = f();
+    (result, stop_measuring(&start))
+}
+
+/// Starts measuring the allocations of the current thread, returning what
+/// `stop_measuring` needs to finish.
+pub(crate) fn start_measuring() -> AllocInfo {
+    let monitor = ThreadMonitor::new();
+    let start = monitor.info();
+    // Peaks can't be subtracted, so the peak is reset to what's live now while
+    // measuring, then put back.
+    monitor.set_peak(start.live_bytes());
+    start
+}
+
+/// The allocations of the current thread since `start_measuring` returned
+/// `start`.
+pub(crate) fn stop_measuring(start: &AllocInfo) -> AllocInfo {
+    let monitor = ThreadMonitor::new();
+    let end = monitor.info();
+    monitor.set_peak(end.peak_bytes.max(start.peak_bytes));
+    AllocInfo {
+        peak_bytes: end.peak_bytes - start.live_bytes(),
+        ..end.relative_to(start)
+    }
+}
@@ -0,0 +1,307 @@
+use crate::alloc::{AllocAction, AllocMonitor, GatingMonitor};
+use crate::describe::{ConfigReport, MonitorDesc, Overhead};
+use core::alloc::Layout;
+
+/// Forwards every event to each monitor of a static slice, in order, for monitor
+/// lists that are put together at build time, e.g. from feature flags, without
+/// nesting generic wrappers.
+///
+/// Every event costs one dynamic call per element, which can't be inlined, on
+/// top of what the elements themselves do. The elements are shared by every
+/// thread that allocates, so they have to be `Sync`.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, EventRecord, RecentEventsMonitor};
+/// use interloc::{SliceMonitor, StatsMonitor, ThreadMonitor};
+///
+/// static STATS: StatsMonitor = StatsMonitor::new();
+/// static RECENT: RecentEventsMonitor<8> = RecentEventsMonitor::new();
+/// static MONITORS: SliceMonitor = SliceMonitor::new(&[&STATS, &ThreadMonitor::new(), &RECENT]);
+///
+/// let layout = Layout::new::<u64>();
+/// MONITORS.monitor(layout, AllocAction::Alloc);
+/// MONITORS.monitor(layout, AllocAction::Dealloc { ptr: core::ptr::null_mut() });
+///
+/// assert_eq!(STATS.info().alloc, 1);
+/// assert_eq!(ThreadMonitor::new().info().dealloc, 1);
+/// let mut events = [EventRecord::new(layout, AllocAction::Alloc); 8];
+/// assert_eq!(RECENT.snapshot(&mut events), 2);
+/// assert!(events[0].serial < events[1].serial);
+/// ```
+#[derive(Clone, Copy)]
+pub struct SliceMonitor {
+    monitors: &'static [&'static (dyn AllocMonitor + Sync)],
+}
+
+impl SliceMonitor {
+    pub const fn new(monitors: &'static [&'static (dyn AllocMonitor + Sync)]) -> Self {
+        Self { monitors }
+    }
+
+    /// The monitors that events are forwarded to.
+    pub fn monitors(&self) -> &'static [&'static (dyn AllocMonitor + Sync)] {
+        self.monitors
+    }
+}
+
+impl AllocMonitor for SliceMonitor {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        for monitor in self.monitors {
+            monitor.monitor(layout, action);
+        }
+    }
+
+    fn on_first_event(&self) {
+        for monitor in self.monitors {
+            monitor.on_first_event();
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("SliceMonitor", Overhead::Free)
+    }
+
+    fn describe_inner(&self, report: &mut ConfigReport) {
+        for monitor in self.monitors {
+            report.add(*monitor);
+        }
+    }
+}
+
+/// A stage of a `PipelineMonitor`.
+#[derive(Clone, Copy)]
+pub enum PipelineStage {
+    /// Stops the event from reaching the stages after it unless it accepts it
+    Gate(&'static (dyn GatingMonitor + Sync)),
+    /// Gets every event that makes it past the gates before it
+    Monitor(&'static (dyn AllocMonitor + Sync)),
+}
+
+/// Passes every event through a static slice of stages, in order: monitors see
+/// the event, and gates decide whether the stages after them do. Once a gate
+/// turns an event down, no later stage, gate or monitor, is called, so cheap
+/// gates like a `ThreadFilterMonitor` should go before expensive monitors.
+///
+/// Like `SliceMonitor`, every stage costs a dynamic call, and stages have to be
+/// `Sync`.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, NoopMonitor, PipelineMonitor, PipelineStage};
+/// use interloc::{StatsMonitor, ThreadFilterMonitor};
+///
+/// static ALL: StatsMonitor = StatsMonitor::new();
+/// static FILTER: ThreadFilterMonitor = ThreadFilterMonitor::new(NoopMonitor);
+/// static ENABLED: StatsMonitor = StatsMonitor::new();
+/// static PIPELINE: PipelineMonitor = PipelineMonitor::new(&[
+///     PipelineStage::Monitor(&ALL),
+///     PipelineStage::Gate(&FILTER),
+///     PipelineStage::Monitor(&ENABLED),
+/// ]);
+///
+/// let layout = Layout::new::<u64>();
+/// PIPELINE.monitor(layout, AllocAction::Alloc);
+/// FILTER.enable_current_thread();
+/// PIPELINE.monitor(layout, AllocAction::Alloc);
+/// assert_eq!(ALL.info().alloc, 2);
+/// assert_eq!(ENABLED.info().alloc, 1);
+/// ```
+#[derive(Clone, Copy)]
+pub struct PipelineMonitor {
+    stages: &'static [PipelineStage],
+}
+
+impl PipelineMonitor {
+    pub const fn new(stages: &'static [PipelineStage]) -> Self {
+        Self { stages }
+    }
+
+    /// The stages that events pass through.
+    pub fn stages(&self) -> &'static [PipelineStage] {
+        self.stages
+    }
+}
+
+impl AllocMonitor for PipelineMonitor {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        for stage in self.stages {
+            match stage {
+                PipelineStage::Gate(gate) => {
+                    if !gate.accepts(layout, action) {
+                        return;
+                    }
+                }
+                PipelineStage::Monitor(monitor) => monitor.monitor(layout, action),
+            }
+        }
+    }
+
+    /// Calls the hooks of all the monitors, whatever the gates before them.
+    fn on_first_event(&self) {
+        for stage in self.stages {
+            if let PipelineStage::Monitor(monitor) = stage {
+                monitor.on_first_event();
+            }
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        let gates = self
+            .stages
+            .iter()
+            .filter(|stage| matches!(stage, PipelineStage::Gate(_)));
+        MonitorDesc::new("PipelineMonitor", Overhead::Free).param("gates", gates.count() as u64)
+    }
+
+    /// Adds the monitors, whatever the gates before them.
+    fn describe_inner(&self, report: &mut ConfigReport) {
+        for stage in self.stages {
+            if let PipelineStage::Monitor(monitor) = stage {
+                report.add(*monitor);
+            }
+        }
+    }
+}
+
+/// The monitors of a `RouterMonitor`, one per size band, smallest first.
+/// Implemented for tuples of up to 4 monitors.
+pub trait MonitorBands {
+    /// Number of bands.
+    const BANDS: usize;
+
+    /// Passes the event to the monitor of band `band`.
+    fn monitor_band(&self, band: usize, layout: Layout, action: AllocAction);
+
+    /// Calls `on_first_event` on the monitors of every band.
+    fn on_first_event(&self) {}
+
+    /// Adds the monitors of every band to `report`.
+    fn describe_bands(&self, report: &mut ConfigReport) {
+        let _ = report;
+    }
+}
+
+macro_rules! monitor_bands {
+    ($len:expr; $($index:tt $name:ident),+) => {
+        impl<$($name: AllocMonitor),+> MonitorBands for ($($name,)+) {
+            const BANDS: usize = $len;
+
+            #[inline]
+            fn monitor_band(&self, band: usize, layout: Layout, action: AllocAction) {
+                match band {
+                    $($index => self.$index.monitor(layout, action),)+
+                    _ => {}
+                }
+            }
+
+            fn on_first_event(&self) {
+                $(self.$index.on_first_event();)+
+            }
+
+            fn describe_bands(&self, report: &mut ConfigReport) {
+                $(report.add(&self.$index);)+
+            }
+        }
+    };
+}
+
+monitor_bands!(1; 0 A);
+monitor_bands!(2; 0 A, 1 B);
+monitor_bands!(3; 0 A, 1 B, 2 C);
+monitor_bands!(4; 0 A, 1 B, 2 C, 3 D);
+
+/// Sends each event to one of several monitors depending on the size of the
+/// block, e.g. small allocations to a cheap counter and large ones to a
+/// `BacktraceMonitor`.
+///
+/// The boundaries split sizes into bands: band 0 is below the first boundary,
+/// band `i` is from boundary `i - 1` up to but not including boundary `i`, and
+/// the last band is everything from the last boundary up. Events are routed by
+/// the size of the layout the allocator was called with, which for
+/// deallocations and reallocations is the old size of the block, so both
+/// actions of a call always go to the same monitor.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, RouterMonitor, StatsMonitor};
+///
+/// static ROUTER: RouterMonitor<(StatsMonitor, StatsMonitor, StatsMonitor)> = RouterMonitor::new(
+///     &[4096, 1 << 20],
+///     (StatsMonitor::new(), StatsMonitor::new(), StatsMonitor::new()),
+/// );
+///
+/// for size in [4095, 4096, (1 << 20) - 1, 1 << 20] {
+///     let layout = Layout::from_size_align(size, 1).unwrap();
+///     ROUTER.monitor(layout, AllocAction::Alloc);
+/// }
+/// let (small, medium, large) = ROUTER.bands();
+/// assert_eq!(small.info().bytes_alloc, 4095);
+/// assert_eq!(medium.info().bytes_alloc, 4096 + (1 << 20) - 1);
+/// assert_eq!(large.info().bytes_alloc, 1 << 20);
+/// ```
+pub struct RouterMonitor<B> {
+    bounds: &'static [usize],
+    bands: B,
+}
+
+impl<B: MonitorBands> RouterMonitor<B> {
+    /// Routes events between `bands` by the sizes in `bounds`.
+    ///
+    /// # Panics
+    /// Panics, at compile time if used in a `static`, unless there's one more
+    /// band than boundaries and the boundaries are in increasing order.
+    pub const fn new(bounds: &'static [usize], bands: B) -> Self {
+        assert!(
+            bounds.len() + 1 == B::BANDS,
+            "a router needs one more band than boundaries"
+        );
+        let mut i = 1;
+        while i < bounds.len() {
+            assert!(
+                bounds[i - 1] < bounds[i],
+                "router boundaries must be increasing"
+            );
+            i += 1;
+        }
+        Self { bounds, bands }
+    }
+
+    /// The monitors of each band.
+    pub fn bands(&self) -> &B {
+        &self.bands
+    }
+
+    /// The band that a block of `size` bytes falls in.
+    pub fn band(&self, size: usize) -> usize {
+        self.bounds
+            .iter()
+            .take_while(|&&bound| size >= bound)
+            .count()
+    }
+}
+
+impl<B: MonitorBands> AllocMonitor for RouterMonitor<B> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        self.bands
+            .monitor_band(self.band(layout.size()), layout, action);
+    }
+
+    fn on_first_event(&self) {
+        self.bands.on_first_event();
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("RouterMonitor", Overhead::Free).param("bands", B::BANDS as u64)
+    }
+
+    fn describe_inner(&self, report: &mut ConfigReport) {
+        self.bands.describe_bands(report);
+    }
+}
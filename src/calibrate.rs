@@ -0,0 +1,110 @@
+use crate::alloc::{AllocAction, AllocMonitor, ScopedInterAlloc};
+use crate::monitor::NoopMonitor;
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use std::alloc::System;
+use std::time::Instant;
+
+/// How many times each loop is timed. The report has the median of the runs.
+const RUNS: usize = 11;
+/// Iterations of each loop per run.
+const ITERATIONS: usize = 10_000;
+
+/// The per-event and per-allocation overhead of a monitor, measured by
+/// `calibrate`. All times are medians, in nanoseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CalibrationReport {
+    /// Time for one call to the monitor
+    pub ns_per_event: f64,
+    /// Time for an allocation and deallocation through `InterAlloc` with the
+    /// monitor
+    pub ns_per_alloc: f64,
+    /// Time for an allocation and deallocation through `InterAlloc` with a
+    /// `NoopMonitor`
+    pub baseline_ns_per_alloc: f64,
+}
+
+impl CalibrationReport {
+    /// The time the monitor adds to each allocation and deallocation.
+    pub fn overhead_ns_per_alloc(&self) -> f64 {
+        self.ns_per_alloc - self.baseline_ns_per_alloc
+    }
+}
+
+impl fmt::Display for CalibrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "monitor call:       {:>8.1} ns/event", self.ns_per_event)?;
+        writeln!(f, "alloc + dealloc:    {:>8.1} ns", self.ns_per_alloc)?;
+        writeln!(
+            f,
+            "  without monitor:  {:>8.1} ns",
+            self.baseline_ns_per_alloc
+        )?;
+        write!(
+            f,
+            "  overhead:         {:>8.1} ns",
+            self.overhead_ns_per_alloc()
+        )
+    }
+}
+
+/// Times `run` `RUNS` times, returning the median time per unit of work, where
+/// a run does `units` units.
+fn median_ns(units: usize, mut run: impl FnMut()) -> f64 {
+    let mut times = [0f64; RUNS];
+    for time in times.iter_mut() {
+        let start = Instant::now();
+        run();
+        *time = start.elapsed().as_nanos() as f64 / units as f64;
+    }
+    times.sort_unstable_by(f64::total_cmp);
+    times[RUNS / 2]
+}
+
+/// Times allocations and deallocations made directly through a `ScopedInterAlloc`
+/// over `System` with `monitor`, without it being the global allocator.
+fn alloc_ns<M: AllocMonitor>(monitor: &M) -> f64 {
+    let alloc = ScopedInterAlloc::new(System, monitor);
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    median_ns(ITERATIONS, || {
+        for _ in 0..ITERATIONS {
+            unsafe {
+                let ptr = alloc.alloc(layout);
+                core::hint::black_box(ptr);
+                alloc.dealloc(ptr, layout);
+            }
+        }
+    })
+}
+
+/// Measures the overhead of `monitor`, in process, by timing a loop of
+/// synthetic calls to it, and a loop of real allocations through `InterAlloc`
+/// with it and with a `NoopMonitor`.
+///
+/// The synthetic calls describe allocations of a dangling pointer, and the real
+/// allocations are real, so `monitor` records both; calibrate an instance that
+/// isn't otherwise in use. This takes a few tens of milliseconds with cheap
+/// monitors.
+pub fn calibrate<M: AllocMonitor>(monitor: &M) -> CalibrationReport {
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let ptr = core::ptr::NonNull::<u64>::dangling().as_ptr() as *mut u8;
+    let events = [
+        AllocAction::Alloc,
+        AllocAction::AllocResult { ptr },
+        AllocAction::Dealloc { ptr },
+        AllocAction::DeallocResult,
+    ];
+    let ns_per_event = median_ns(ITERATIONS * events.len(), || {
+        for _ in 0..ITERATIONS {
+            for event in &events {
+                monitor.monitor(core::hint::black_box(layout), *event);
+            }
+        }
+    });
+
+    CalibrationReport {
+        ns_per_event,
+        ns_per_alloc: alloc_ns(monitor),
+        baseline_ns_per_alloc: alloc_ns(&NoopMonitor::new()),
+    }
+}
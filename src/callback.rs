@@ -0,0 +1,65 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use core::alloc::Layout;
+
+/// A monitor that calls a function with a shared context on every event, for
+/// stateful handlers that don't need an `AllocMonitor` impl of their own. Unlike
+/// a closure, it can be made in a `static`.
+///
+/// ```rust
+/// use core::alloc::Layout;
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use interloc::{AllocAction, AllocMonitor, CallbackMonitor};
+///
+/// static DEALLOCS: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn count_deallocs(count: &AtomicUsize, _: Layout, action: AllocAction) {
+///     if let AllocAction::Dealloc { .. } = action {
+///         count.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// static MONITOR: CallbackMonitor<AtomicUsize> = CallbackMonitor::new(&DEALLOCS, count_deallocs);
+///
+/// let layout = Layout::new::<u64>();
+/// let ptr = core::ptr::null_mut();
+/// MONITOR.monitor(layout, AllocAction::Alloc);
+/// MONITOR.monitor(layout, AllocAction::Dealloc { ptr });
+/// MONITOR.monitor(layout, AllocAction::DeallocResult);
+/// assert_eq!(DEALLOCS.load(Ordering::Relaxed), 1);
+/// assert!(core::ptr::eq(MONITOR.context(), &DEALLOCS));
+/// ```
+pub struct CallbackMonitor<C: Sync + 'static> {
+    context: &'static C,
+    callback: fn(&C, Layout, AllocAction),
+}
+
+impl<C: Sync + 'static> CallbackMonitor<C> {
+    pub const fn new(context: &'static C, callback: fn(&C, Layout, AllocAction)) -> Self {
+        Self { context, callback }
+    }
+
+    /// The context passed to the callback.
+    pub fn context(&self) -> &'static C {
+        self.context
+    }
+}
+
+impl<C: Sync + 'static> Clone for CallbackMonitor<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C: Sync + 'static> Copy for CallbackMonitor<C> {}
+
+impl<C: Sync + 'static> AllocMonitor for CallbackMonitor<C> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        (self.callback)(self.context, layout, action);
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("CallbackMonitor", Overhead::Heavy)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::keys::{Inserted, LocationSet};
+use crate::sites::{fnv1a, SiteTable, FNV_OFFSET};
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::panic::Location;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+thread_local! {
+    static LOCATION: Cell<Option<&'static Location<'static>>> = const { Cell::new(None) };
+}
+
+/// The location allocations on this thread are currently attributed to, if any.
+pub fn current_location() -> Option<&'static Location<'static>> {
+    LOCATION.try_with(|l| l.get()).ok().flatten()
+}
+
+/// Hashes the file, line and column of `location` with FNV-1a, so that copies
+/// of the same location hash the same. Never returns zero.
+pub(crate) fn hash_location(location: &Location) -> u64 {
+    let hash = fnv1a(FNV_OFFSET, location.file().as_bytes());
+    let hash = fnv1a(hash, &location.line().to_le_bytes());
+    fnv1a(hash, &location.column().to_le_bytes()).max(1)
+}
+
+/// The hash of `current_location`, or zero if there's none.
+pub(crate) fn current_site() -> u64 {
+    current_location().map_or(0, hash_location)
+}
+
+/// Restores the previously set location on drop, so that attribution is undone
+/// even if the attributed code panics.
+struct LocationGuard(Option<&'static Location<'static>>);
+
+impl Drop for LocationGuard {
+    fn drop(&mut self) {
+        LOCATION.with(|l| l.set(self.0));
+    }
+}
+
+/// Runs `f`, attributing the allocations it makes on this thread to the
+/// caller's location. When nested, the innermost location wins.
+#[track_caller]
+pub fn attributed<R>(f: impl FnOnce() -> R) -> R {
+    let location = Location::caller();
+    let _guard = LocationGuard(LOCATION.with(|l| l.replace(Some(location))));
+    f()
+}
+
+/// Evaluates the body, attributing the allocations it makes on this thread to
+/// the location of the macro call, for `CallsiteMonitor`. The body is run in a
+/// closure, so `return` and `?` apply to the body rather than the enclosing
+/// function.
+///
+/// ```rust
+/// let v = interloc::trace_alloc! { vec![0u8; 100] };
+/// # assert_eq!(v.len(), 100);
+/// ```
+#[macro_export]
+macro_rules! trace_alloc {
+    ($($body:tt)*) => {
+        $crate::attributed(|| { $($body)* })
+    };
+}
+
+/// Allocations attributed to one location, from `CallsiteMonitor::callsites`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Callsite {
+    pub location: &'static Location<'static>,
+    /// Number of allocations attributed to the location
+    pub count: usize,
+    /// Bytes allocated at the location
+    pub bytes: u64,
+}
+
+/// Attributes allocations to the location set with `attributed` or
+/// `trace_alloc!`, which is much cheaper than capturing a backtrace.
+///
+/// Locations are compared by file, line and column, so copies of one count as
+/// one, and each distinct location is given an index in a table of up to
+/// `LOCATIONS`. Their counts are kept in a fixed-capacity table of `SITES`
+/// entries, keyed by that index, which evicts the locations with the fewest
+/// bytes when it fills up. Allocations made without a location set are only
+/// counted, in `unattributed`. Reallocations are recorded with their new size.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, CallsiteMonitor};
+///
+/// let monitor = CallsiteMonitor::<16>::new();
+/// let layout = Layout::from_size_align(64, 8).unwrap();
+/// for _ in 0..3 {
+///     interloc::trace_alloc! { monitor.monitor(layout, AllocAction::Alloc) };
+///     monitor.monitor(layout, AllocAction::Alloc);
+/// }
+/// let callsites = monitor.callsites();
+/// assert_eq!(callsites.len(), 1);
+/// assert_eq!(callsites[0].location.file(), file!());
+/// assert_eq!((callsites[0].count, callsites[0].bytes), (3, 192));
+/// assert_eq!(monitor.unattributed(), 3);
+/// ```
+pub struct CallsiteMonitor<const SITES: usize = 256, const LOCATIONS: usize = 1024> {
+    locations: LocationSet<LOCATIONS>,
+    /// Counts of the locations, by their index in `locations`
+    sites: SiteTable<SITES, 1>,
+    unattributed: AtomicUsize,
+    /// Allocations at locations that found `locations` full
+    unindexed: AtomicUsize,
+}
+
+impl<const SITES: usize, const LOCATIONS: usize> CallsiteMonitor<SITES, LOCATIONS> {
+    pub const fn new() -> Self {
+        Self {
+            locations: LocationSet::new(),
+            sites: SiteTable::new(),
+            unattributed: AtomicUsize::new(0),
+            unindexed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of allocations made while no location was set.
+    pub fn unattributed(&self) -> usize {
+        self.unattributed.load(Ordering::Relaxed)
+    }
+
+    /// Number of attributed allocations that weren't recorded because no slot
+    /// could be claimed for their location, in either table.
+    pub fn dropped(&self) -> usize {
+        self.sites.dropped() + self.unindexed.load(Ordering::Relaxed)
+    }
+
+    /// Number of locations evicted from the table to make room for new ones.
+    pub fn evictions(&self) -> usize {
+        self.sites.evictions()
+    }
+
+    /// Bytes allocated at evicted locations, which are missing from `callsites`.
+    pub fn evicted_bytes(&self) -> u64 {
+        self.sites.evicted_bytes()
+    }
+
+    /// Every location allocated at so far, most bytes allocated first.
+    pub fn callsites(&self) -> Vec<Callsite> {
+        self.sites
+            .top_sites(SITES)
+            .into_iter()
+            .filter_map(|site| {
+                Some(Callsite {
+                    location: self.locations.get(site.frames[0])?,
+                    count: site.count,
+                    bytes: site.bytes,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<const SITES: usize, const LOCATIONS: usize> Default for CallsiteMonitor<SITES, LOCATIONS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SITES: usize, const LOCATIONS: usize> AllocMonitor
+    for CallsiteMonitor<SITES, LOCATIONS>
+{
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let size = match action {
+            AllocAction::Alloc | AllocAction::AllocZeroed => layout.size(),
+            AllocAction::Realloc { new_size, .. } => new_size,
+            _ => return,
+        };
+        let location = match current_location() {
+            Some(location) => location,
+            None => {
+                self.unattributed.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        match self.locations.insert(location) {
+            Inserted::New(index) | Inserted::Seen(index) => self.sites.record(&[index], size),
+            Inserted::Full => {
+                self.unindexed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("CallsiteMonitor", Overhead::Table)
+            .param("sites", SITES as u64)
+            .param("locations", LOCATIONS as u64)
+    }
+}
@@ -0,0 +1,219 @@
+use crate::clock::Clock;
+use crate::histogram::{Bucketing, LogBuckets};
+use crate::tracking::TrackingMonitor;
+use core::cmp::Reverse;
+use core::fmt;
+
+/// How many of the most common sizes a `CensusClass` lists.
+pub const CENSUS_TOP: usize = 3;
+
+/// How many sizes of each class are counted while scanning for the most common
+/// ones.
+const CANDIDATES: usize = 8;
+
+/// A block size and how many live blocks have it, in a `CensusClass`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SizeCount {
+    pub size: usize,
+    pub blocks: u64,
+}
+
+/// The live blocks of a size class, from `TrackingMonitor::census`: those with
+/// a size from `start` up to but not including `end`, or with no upper bound if
+/// `end` is `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CensusClass {
+    pub start: usize,
+    pub end: Option<usize>,
+    pub blocks: u64,
+    pub bytes: u64,
+    /// The most common exact sizes in the class, most blocks first, then
+    /// smallest first. Slots past the sizes the class has are left with no
+    /// blocks.
+    pub top: [SizeCount; CENSUS_TOP],
+}
+
+impl CensusClass {
+    /// The most common sizes that the class has blocks of.
+    pub fn top_sizes(&self) -> &[SizeCount] {
+        let len = self.top.iter().take_while(|top| top.blocks != 0).count();
+        &self.top[..len]
+    }
+}
+
+/// The live blocks of a `TrackingMonitor` by size class, in the classes of a
+/// histogram's `Bucketing`, for sizing the slabs of an allocator at steady
+/// state. See `TrackingMonitor::census`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Census<const N: usize = 64> {
+    classes: [CensusClass; N],
+}
+
+impl<const N: usize> Census<N> {
+    /// Every class, smallest sizes first, including empty ones.
+    pub fn classes(&self) -> &[CensusClass; N] {
+        &self.classes
+    }
+
+    /// The classes that have live blocks, smallest sizes first.
+    pub fn nonempty(&self) -> impl Iterator<Item = &CensusClass> {
+        self.classes.iter().filter(|class| class.blocks != 0)
+    }
+
+    /// How many live blocks were counted, in every class.
+    pub fn blocks(&self) -> u64 {
+        self.classes.iter().map(|class| class.blocks).sum()
+    }
+
+    /// The sizes of the live blocks counted, added up.
+    pub fn bytes(&self) -> u64 {
+        self.classes.iter().map(|class| class.bytes).sum()
+    }
+}
+
+/// A table of the classes that have live blocks, with their ranges, blocks,
+/// bytes and most common sizes.
+impl<const N: usize> fmt::Display for Census<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<28} {:>12} {:>16}  most common",
+            "size", "blocks", "bytes"
+        )?;
+        for class in self.nonempty() {
+            // Formatted first, since ranges don't pad.
+            let range = match class.end {
+                Some(end) => format!("[{}, {})", class.start, end),
+                None => format!("[{}, inf)", class.start),
+            };
+            write!(f, "{:<28} {:>12} {:>16} ", range, class.blocks, class.bytes)?;
+            for top in class.top_sizes() {
+                write!(f, " {} x{}", top.size, top.blocks)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<const CAPACITY: usize, C: Clock> TrackingMonitor<CAPACITY, C> {
+    /// Counts the live blocks and their bytes in each size class of a
+    /// `HistogramMonitor` with the default `LogBuckets`, along with the most
+    /// common exact sizes in each. See `census_with` for other classes.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, SizeCount, TrackingMonitor};
+    ///
+    /// let tracking = TrackingMonitor::<256>::new();
+    /// let mut blocks = Vec::new();
+    /// let mixture = [(24, 10), (32, 5), (40, 3), (48, 2), (56, 1), (60, 1), (1000, 2)];
+    /// for (size, count) in mixture {
+    ///     for _ in 0..count {
+    ///         let ptr = (0x1000 + blocks.len() * 0x400) as *mut u8;
+    ///         let layout = Layout::from_size_align(size, 8).unwrap();
+    ///         tracking.monitor(layout, AllocAction::AllocResult { ptr });
+    ///         blocks.push((ptr, layout));
+    ///     }
+    /// }
+    /// // Frees 4 blocks of 24 bytes, 1 of 40 and the one of 56.
+    /// for (i, size) in [(0, 24), (1, 24), (2, 24), (3, 24), (16, 40), (20, 56)] {
+    ///     let (ptr, layout) = blocks[i];
+    ///     assert_eq!(layout.size(), size);
+    ///     tracking.monitor(layout, AllocAction::Dealloc { ptr });
+    /// }
+    ///
+    /// let census = tracking.census();
+    /// let classes: Vec<_> = census.nonempty().collect();
+    /// assert_eq!(classes.len(), 3);
+    ///
+    /// assert_eq!((classes[0].start, classes[0].end), (16, Some(32)));
+    /// assert_eq!((classes[0].blocks, classes[0].bytes), (6, 6 * 24));
+    /// assert_eq!(classes[0].top_sizes(), [SizeCount { size: 24, blocks: 6 }]);
+    ///
+    /// assert_eq!((classes[1].start, classes[1].end), (32, Some(64)));
+    /// assert_eq!((classes[1].blocks, classes[1].bytes), (10, 5 * 32 + 2 * 40 + 2 * 48 + 60));
+    /// let top: Vec<_> = classes[1].top_sizes().iter().map(|t| (t.size, t.blocks)).collect();
+    /// assert_eq!(top, [(32, 5), (40, 2), (48, 2)]);
+    ///
+    /// assert_eq!((classes[2].start, classes[2].blocks), (512, 2));
+    /// assert_eq!((census.blocks(), census.bytes()), (18, 6 * 24 + 396 + 2000));
+    /// assert_eq!(census.blocks(), tracking.live_blocks() as u64);
+    /// print!("{}", census);
+    /// ```
+    pub fn census(&self) -> Census {
+        self.census_with::<LogBuckets, 64>()
+    }
+
+    /// Like `census`, in the size classes of `B`, which has to have `N` of them.
+    ///
+    /// The census scans the whole table, without stopping other threads, so
+    /// it's approximate while they allocate and free: blocks allocated or freed
+    /// during the scan may or may not be counted. It doesn't allocate. The most
+    /// common sizes are exact for classes with up to 8 distinct sizes. Past
+    /// that, they're estimated while scanning: a size that makes up more than
+    /// an eighth of its class's blocks is always found, but it may be counted
+    /// with more blocks than it has, and less common sizes may be left out or
+    /// listed in the wrong order.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, LinearBuckets, TrackingMonitor};
+    ///
+    /// let tracking = TrackingMonitor::<64>::new();
+    /// for (i, &size) in [8, 16, 16, 40, 100].iter().enumerate() {
+    ///     let ptr = (0x1000 + i * 0x100) as *mut u8;
+    ///     let layout = Layout::from_size_align(size, 8).unwrap();
+    ///     tracking.monitor(layout, AllocAction::AllocResult { ptr });
+    /// }
+    ///
+    /// // Slabs of 0-32, 32-48 and 48-64 bytes, and the rest.
+    /// let census = tracking.census_with::<LinearBuckets<16, 16, 4>, 4>();
+    /// let blocks: Vec<u64> = census.classes().iter().map(|class| class.blocks).collect();
+    /// assert_eq!(blocks, [3, 1, 0, 1]);
+    /// assert_eq!(census.classes()[0].top[0].size, 16);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics unless `B` has `N` buckets.
+    pub fn census_with<B: Bucketing, const N: usize>(&self) -> Census<N> {
+        assert!(B::BUCKETS == N, "a census needs a class per bucket");
+        let mut classes = [CensusClass::default(); N];
+        let mut candidates = [[SizeCount::default(); CANDIDATES]; N];
+        self.for_each_size(|size| {
+            let bucket = B::bucket(size).min(N - 1);
+            let class = &mut classes[bucket];
+            class.blocks += 1;
+            class.bytes += size as u64;
+            count_size(&mut candidates[bucket], size);
+        });
+        for (i, (class, candidates)) in classes.iter_mut().zip(&mut candidates).enumerate() {
+            class.start = B::start(i);
+            class.end = (i + 1 < N).then(|| B::start(i + 1));
+            candidates.sort_unstable_by_key(|top| (Reverse(top.blocks), top.size));
+            class.top.copy_from_slice(&candidates[..CENSUS_TOP]);
+        }
+        Census { classes }
+    }
+}
+
+/// Counts a block of `size` among the most common sizes of its class, with
+/// the space-saving algorithm: a size that isn't counted yet takes a free slot,
+/// or else the slot with the fewest blocks, and its count.
+fn count_size(candidates: &mut [SizeCount; CANDIDATES], size: usize) {
+    if let Some(top) = candidates
+        .iter_mut()
+        .find(|top| top.blocks != 0 && top.size == size)
+    {
+        top.blocks += 1;
+        return;
+    }
+    let fewest = candidates
+        .iter_mut()
+        .min_by_key(|top| top.blocks)
+        .expect("there are candidates");
+    fewest.size = size;
+    fewest.blocks += 1;
+}
@@ -0,0 +1,272 @@
+use crate::alloc::suppress;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Once, OnceLock};
+use std::time::Instant;
+
+/// A source of time for monitors that need one, so that tests can control it.
+pub trait Clock {
+    /// Nanoseconds since some fixed point in the past. This must never go
+    /// backwards.
+    fn now_nanos(&self) -> u64;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    #[inline]
+    fn now_nanos(&self) -> u64 {
+        (**self).now_nanos()
+    }
+}
+
+/// The monotonic clock of the system, counting from the first time it's read.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    #[inline]
+    fn now_nanos(&self) -> u64 {
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+    }
+}
+
+/// The default clock of the monitors that need one. It reads the cheapest
+/// counter the platform has: the time stamp counter on x86, the virtual counter
+/// on ARM, `QueryPerformanceCounter` on Windows, `CLOCK_MONOTONIC_COARSE` on
+/// other Linux targets, and `Instant` anywhere else, including under Miri.
+/// None of them take a lock or allocate, and most never leave user space.
+///
+/// Counter ticks are converted to nanoseconds at a rate that's calibrated once,
+/// the first time the clock is read or when `calibrate` is called, with
+/// monitoring suppressed. On x86 that means timing the counter against
+/// `Instant` for about a millisecond, which assumes an invariant counter, as
+/// on every x86 CPU of the last decade. Like `MonotonicClock`, it counts from
+/// the first time it's read.
+///
+/// ```rust
+/// use interloc::{Clock, CoarseClock};
+///
+/// CoarseClock::calibrate();
+/// let mut last = CoarseClock.now_nanos();
+/// for _ in 0..10_000 {
+///     let now = CoarseClock.now_nanos();
+///     assert!(now >= last);
+///     last = now;
+/// }
+///
+/// let start = CoarseClock.now_nanos();
+/// std::thread::sleep(std::time::Duration::from_millis(20));
+/// let slept = CoarseClock.now_nanos() - start;
+/// assert!(slept >= 15_000_000, "{}", slept);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CoarseClock;
+
+/// Counter ticks at calibration.
+static BASE_TICKS: AtomicU64 = AtomicU64::new(0);
+/// Nanoseconds per tick in 32.32 fixed point, or 0 before calibration.
+static SCALE: AtomicU64 = AtomicU64::new(0);
+static CALIBRATION: Once = Once::new();
+
+impl CoarseClock {
+    /// Calibrates the clock now, if it isn't already, instead of the first time
+    /// it's read, e.g. during startup, to keep the time it takes out of the
+    /// first monitored allocation.
+    pub fn calibrate() {
+        CALIBRATION.call_once(|| {
+            suppress(|| {
+                let (base, scale) = counter::calibrate();
+                BASE_TICKS.store(base, Ordering::Relaxed);
+                SCALE.store(scale.max(1), Ordering::Release);
+            })
+        });
+    }
+
+    /// How many nanoseconds a tick of the counter was calibrated to.
+    pub fn nanos_per_tick() -> f64 {
+        Self::scale() as f64 / (1u64 << 32) as f64
+    }
+
+    #[inline]
+    fn scale() -> u64 {
+        match SCALE.load(Ordering::Acquire) {
+            0 => {
+                Self::calibrate();
+                SCALE.load(Ordering::Acquire)
+            }
+            scale => scale,
+        }
+    }
+}
+
+impl Clock for CoarseClock {
+    #[inline]
+    fn now_nanos(&self) -> u64 {
+        let scale = Self::scale();
+        let ticks = counter::ticks().saturating_sub(BASE_TICKS.load(Ordering::Relaxed));
+        ((ticks as u128 * scale as u128) >> 32) as u64
+    }
+}
+
+/// The 32.32 fixed-point scale of a counter that ticks `frequency` times a second.
+#[allow(dead_code)]
+fn scale_of(frequency: u64) -> u64 {
+    ((1_000_000_000u128 << 32) / frequency.max(1) as u128) as u64
+}
+
+#[cfg(all(
+    any(target_arch = "x86_64", target_arch = "x86"),
+    not(windows),
+    not(miri)
+))]
+mod counter {
+    use std::time::{Duration, Instant};
+
+    #[inline]
+    pub fn ticks() -> u64 {
+        #[cfg(target_arch = "x86")]
+        use core::arch::x86::_rdtsc;
+        #[cfg(target_arch = "x86_64")]
+        use core::arch::x86_64::_rdtsc;
+        unsafe { _rdtsc() }
+    }
+
+    /// The counter's frequency isn't architectural, so it's timed against
+    /// `Instant`.
+    pub fn calibrate() -> (u64, u64) {
+        let (start, base) = (Instant::now(), ticks());
+        let mut elapsed;
+        loop {
+            elapsed = start.elapsed();
+            if elapsed >= Duration::from_millis(1) {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        let ticks = (ticks() - base).max(1);
+        (base, ((elapsed.as_nanos() << 32) / ticks as u128) as u64)
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", not(windows), not(miri)))]
+mod counter {
+    #[inline]
+    pub fn ticks() -> u64 {
+        let ticks: u64;
+        unsafe { core::arch::asm!("mrs {}, cntvct_el0", out(reg) ticks, options(nomem, nostack)) };
+        ticks
+    }
+
+    pub fn calibrate() -> (u64, u64) {
+        let frequency: u64;
+        unsafe {
+            core::arch::asm!("mrs {}, cntfrq_el0", out(reg) frequency, options(nomem, nostack))
+        };
+        (ticks(), super::scale_of(frequency))
+    }
+}
+
+#[cfg(all(windows, not(miri)))]
+mod counter {
+    extern "system" {
+        fn QueryPerformanceCounter(count: *mut i64) -> i32;
+        fn QueryPerformanceFrequency(frequency: *mut i64) -> i32;
+    }
+
+    #[inline]
+    pub fn ticks() -> u64 {
+        let mut count = 0;
+        unsafe { QueryPerformanceCounter(&mut count) };
+        count as u64
+    }
+
+    pub fn calibrate() -> (u64, u64) {
+        let mut frequency = 0;
+        unsafe { QueryPerformanceFrequency(&mut frequency) };
+        (ticks(), super::scale_of(frequency as u64))
+    }
+}
+
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    not(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64")),
+    not(miri)
+))]
+mod counter {
+    use std::os::raw::{c_int, c_long};
+
+    #[repr(C)]
+    struct Timespec {
+        tv_sec: c_long,
+        tv_nsec: c_long,
+    }
+
+    extern "C" {
+        fn clock_gettime(clock: c_int, time: *mut Timespec) -> c_int;
+    }
+
+    const CLOCK_MONOTONIC_COARSE: c_int = 6;
+
+    #[inline]
+    pub fn ticks() -> u64 {
+        let mut time = Timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { clock_gettime(CLOCK_MONOTONIC_COARSE, &mut time) };
+        time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+    }
+
+    pub fn calibrate() -> (u64, u64) {
+        (ticks(), 1 << 32)
+    }
+}
+
+#[cfg(any(
+    miri,
+    not(any(
+        windows,
+        target_arch = "x86_64",
+        target_arch = "x86",
+        target_arch = "aarch64",
+        target_os = "linux",
+        target_os = "android"
+    ))
+))]
+mod counter {
+    use crate::clock::{Clock, MonotonicClock};
+
+    #[inline]
+    pub fn ticks() -> u64 {
+        MonotonicClock.now_nanos()
+    }
+
+    pub fn calibrate() -> (u64, u64) {
+        (ticks(), 1 << 32)
+    }
+}
+
+/// A clock that only moves when it's told to.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    nanos: AtomicU64,
+}
+
+impl ManualClock {
+    pub const fn new() -> Self {
+        Self {
+            nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Moves the clock forward by `nanos` nanoseconds.
+    pub fn advance(&self, nanos: u64) {
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    #[inline]
+    fn now_nanos(&self) -> u64 {
+        self.nanos.load(Ordering::Relaxed)
+    }
+}
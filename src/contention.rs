@@ -0,0 +1,89 @@
+//! Counters of how often the locks behind `StatsMonitor` and `MirrorMonitor`
+//! had to wait, kept with the `self-metrics` feature. Without it, `Contention`
+//! is zero-sized and counting compiles to nothing.
+
+#[cfg(feature = "self-metrics")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// How contended a monitor's lock has been, as returned by
+/// `StatsMonitor::contention`. Needs the `self-metrics` feature.
+#[cfg(feature = "self-metrics")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ContentionStats {
+    /// Acquisitions of the write side that found it taken, and had to wait
+    pub contended: u64,
+    /// Times a waiting writer spun before trying again. Waits that park, like
+    /// parking_lot's, don't count any.
+    pub wait_loops: u64,
+    /// Reads that had to start over, because a write was in progress or
+    /// happened while copying, or, for a read-write lock, that had to wait
+    pub read_retries: u64,
+}
+
+#[cfg(feature = "self-metrics")]
+impl ContentionStats {
+    pub const fn new() -> Self {
+        Self {
+            contended: 0,
+            wait_loops: 0,
+            read_retries: 0,
+        }
+    }
+}
+
+/// The counters behind `ContentionStats`, updated with relaxed atomics only
+/// when a lock had to wait.
+#[cfg(feature = "self-metrics")]
+pub(crate) struct Contention {
+    contended: AtomicU64,
+    wait_loops: AtomicU64,
+    read_retries: AtomicU64,
+}
+
+#[cfg(feature = "self-metrics")]
+impl Contention {
+    pub(crate) const fn new() -> Self {
+        Self {
+            contended: AtomicU64::new(0),
+            wait_loops: AtomicU64::new(0),
+            read_retries: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a write that had to wait, spinning `loops` times.
+    #[inline]
+    pub(crate) fn waited(&self, loops: u64) {
+        self.contended.fetch_add(1, Ordering::Relaxed);
+        self.wait_loops.fetch_add(loops, Ordering::Relaxed);
+    }
+
+    /// Counts `retries` retried reads.
+    #[inline]
+    pub(crate) fn retried(&self, retries: u64) {
+        self.read_retries.fetch_add(retries, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> ContentionStats {
+        ContentionStats {
+            contended: self.contended.load(Ordering::Relaxed),
+            wait_loops: self.wait_loops.load(Ordering::Relaxed),
+            read_retries: self.read_retries.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(not(feature = "self-metrics"))]
+pub(crate) struct Contention;
+
+#[cfg(not(feature = "self-metrics"))]
+impl Contention {
+    pub(crate) const fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn waited(&self, _loops: u64) {}
+
+    #[inline(always)]
+    pub(crate) fn retried(&self, _retries: u64) {}
+}
@@ -0,0 +1,148 @@
+use crate::bench::measure;
+use crate::monitor::{AllocInfo, InfoSource, StatsMonitor};
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+/// Statistics for one kind of value, added up over every `Counted` that's
+/// built and dropped with it.
+///
+/// `peak_bytes` is the most bytes that were live at once while building or
+/// dropping any single value, on top of those live when it started.
+pub struct TypedStats {
+    stats: StatsMonitor,
+}
+
+impl TypedStats {
+    /// New statistics, labeled `name` in their output.
+    #[cfg(not(loom))]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            stats: StatsMonitor::named(name),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            stats: StatsMonitor::named(name),
+        }
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        self.stats.name()
+    }
+
+    pub fn info(&self) -> AllocInfo {
+        self.stats.info()
+    }
+}
+
+impl InfoSource for TypedStats {
+    fn info(&self) -> AllocInfo {
+        TypedStats::info(self)
+    }
+}
+
+/// A one-line summary, like `StatsMonitor`'s.
+impl fmt::Display for TypedStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.stats, f)
+    }
+}
+
+/// A value whose allocations are counted in a `TypedStats`: what the current
+/// thread allocates while building it, and what it frees while dropping it.
+///
+/// The counts come from `ThreadMonitor`, so the global allocator has to be an
+/// `InterAlloc` whose monitor includes one. Everything the thread does in the
+/// meantime is counted, so when one `Counted` is built or dropped as part of
+/// another, its allocations are counted in both.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{Counted, InterAlloc, ThreadMonitor, TypedStats};
+/// use std::alloc::System;
+///
+/// static MONITOR: ThreadMonitor = ThreadMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, ThreadMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// static TREE: TypedStats = TypedStats::new("tree");
+/// static LEAF: TypedStats = TypedStats::new("leaf");
+///
+/// let tree = Counted::new_in(&TREE, || {
+///     let leaves: Vec<_> = (0..3)
+///         .map(|_| Counted::new_in(&LEAF, || vec![0u8; 100]))
+///         .collect();
+///     leaves
+/// });
+/// assert_eq!(LEAF.info().alloc, 3);
+/// assert_eq!(LEAF.info().bytes_alloc, 300);
+/// // The tree's own vector, and the leaves built inside it
+/// assert_eq!(TREE.info().alloc, 4);
+/// assert_eq!(tree.len(), 3);
+///
+/// drop(tree);
+/// assert_eq!(LEAF.info().dealloc, 3);
+/// assert_eq!(TREE.info().dealloc, 4);
+/// assert_eq!(TREE.info().live_bytes(), 0);
+/// ```
+pub struct Counted<T> {
+    value: ManuallyDrop<T>,
+    stats: &'static TypedStats,
+}
+
+impl<T> Counted<T> {
+    /// Calls `build`, counting what it allocates in `stats`.
+    pub fn new_in(stats: &'static TypedStats, build: impl FnOnce() -> T) -> Self {
+        let (value, info) = measure(build);
+        stats.stats.add(&info);
+        Self {
+            value: ManuallyDrop::new(value),
+            stats,
+        }
+    }
+
+    /// The statistics the value is counted in.
+    pub fn stats(&self) -> &'static TypedStats {
+        self.stats
+    }
+
+    /// Takes the value out. What it frees when it's dropped isn't counted.
+    pub fn into_inner(self) -> T {
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.value) }
+    }
+}
+
+impl<T> Deref for Counted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Counted<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Counted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for Counted<T> {
+    fn drop(&mut self) {
+        let ((), info) = measure(|| unsafe { ManuallyDrop::drop(&mut self.value) });
+        self.stats.stats.add(&info);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::alloc::AllocAction;
+use crate::monitor::AllocInfo;
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters for `AllocInfo`'s fields, read one at a time.
+pub(crate) struct Counters {
+    alloc: AtomicU64,
+    dealloc: AtomicU64,
+    realloc: AtomicU64,
+    bytes_alloc: AtomicU64,
+    bytes_dealloc: AtomicU64,
+    peak_bytes: AtomicU64,
+    baseline_bytes: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            alloc: AtomicU64::new(0),
+            dealloc: AtomicU64::new(0),
+            realloc: AtomicU64::new(0),
+            bytes_alloc: AtomicU64::new(0),
+            bytes_dealloc: AtomicU64::new(0),
+            peak_bytes: AtomicU64::new(0),
+            baseline_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts the event like `AllocInfo::apply`. Only the thread owning the
+    /// counters writes them, so this doesn't need read-modify-write atomics.
+    #[inline]
+    pub(crate) fn apply(&self, layout: Layout, action: AllocAction) {
+        let bump = |counter: &AtomicU64, by: u64| {
+            counter.store(counter.load(Ordering::Relaxed) + by, Ordering::Relaxed)
+        };
+        let size = layout.size() as u64;
+        match action {
+            AllocAction::Alloc | AllocAction::AllocZeroed => {
+                bump(&self.alloc, 1);
+                bump(&self.bytes_alloc, size);
+            }
+            AllocAction::Dealloc { .. } => {
+                bump(&self.dealloc, 1);
+                bump(&self.bytes_dealloc, size);
+                return;
+            }
+            AllocAction::Realloc { new_size, .. } => {
+                bump(&self.realloc, 1);
+                bump(&self.bytes_alloc, new_size as u64);
+                bump(&self.bytes_dealloc, size);
+            }
+            _ => return,
+        }
+        let live = self.read().live_bytes();
+        if live > self.peak_bytes.load(Ordering::Relaxed) {
+            self.peak_bytes.store(live, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts the event from any thread, apart from the peak.
+    pub(crate) fn add(&self, layout: Layout, action: AllocAction) {
+        let mut info = AllocInfo::new();
+        info.apply(layout, action);
+        info.peak_bytes = 0;
+        self.merge(&info);
+    }
+
+    /// Counts the event from any thread, and raises the peak to the live bytes
+    /// after it, which other threads' events may race with.
+    #[cfg(not(feature = "disabled"))]
+    pub(crate) fn add_with_peak(&self, layout: Layout, action: AllocAction) {
+        self.add(layout, action);
+        let live = self.read().live_bytes();
+        self.peak_bytes.fetch_max(live, Ordering::Relaxed);
+    }
+
+    /// Adds `info` to the counters, from any thread.
+    pub(crate) fn merge(&self, info: &AllocInfo) {
+        self.alloc.fetch_add(info.alloc, Ordering::Relaxed);
+        self.dealloc.fetch_add(info.dealloc, Ordering::Relaxed);
+        self.realloc.fetch_add(info.realloc, Ordering::Relaxed);
+        self.bytes_alloc
+            .fetch_add(info.bytes_alloc, Ordering::Relaxed);
+        self.bytes_dealloc
+            .fetch_add(info.bytes_dealloc, Ordering::Relaxed);
+        self.peak_bytes
+            .fetch_max(info.peak_bytes, Ordering::Relaxed);
+        self.baseline_bytes
+            .fetch_add(info.baseline_bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn read(&self) -> AllocInfo {
+        AllocInfo {
+            alloc: self.alloc.load(Ordering::Relaxed),
+            dealloc: self.dealloc.load(Ordering::Relaxed),
+            realloc: self.realloc.load(Ordering::Relaxed),
+            bytes_alloc: self.bytes_alloc.load(Ordering::Relaxed),
+            bytes_dealloc: self.bytes_dealloc.load(Ordering::Relaxed),
+            peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+            baseline_bytes: self.baseline_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Overwrites the counters with `info`, one at a time.
+    pub(crate) fn store(&self, info: &AllocInfo) {
+        self.alloc.store(info.alloc, Ordering::Relaxed);
+        self.dealloc.store(info.dealloc, Ordering::Relaxed);
+        self.realloc.store(info.realloc, Ordering::Relaxed);
+        self.bytes_alloc.store(info.bytes_alloc, Ordering::Relaxed);
+        self.bytes_dealloc
+            .store(info.bytes_dealloc, Ordering::Relaxed);
+        self.peak_bytes.store(info.peak_bytes, Ordering::Relaxed);
+        self.baseline_bytes
+            .store(info.baseline_bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the counts and zeroes them. Only for the owning thread.
+    pub(crate) fn take(&self) -> AllocInfo {
+        let info = self.read();
+        for counter in [
+            &self.alloc,
+            &self.dealloc,
+            &self.realloc,
+            &self.bytes_alloc,
+            &self.bytes_dealloc,
+            &self.peak_bytes,
+            &self.baseline_bytes,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        info
+    }
+}
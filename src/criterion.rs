@@ -0,0 +1,182 @@
+//! Measuring allocations in criterion benchmarks.
+//!
+//! `AllocMeasurement` is a criterion `Measurement` that counts what the
+//! benchmarked routine allocates instead of timing it, so that criterion's
+//! statistics and regression detection apply to allocations:
+//!
+//! ```rust,ignore
+//! use criterion::{criterion_group, criterion_main, Criterion};
+//! use interloc::criterion::AllocMeasurement;
+//!
+//! fn bench(c: &mut Criterion<AllocMeasurement>) {
+//!     c.bench_function("collect", |b| b.iter(|| (0..100).collect::<Vec<u32>>()));
+//! }
+//!
+//! criterion_group! {
+//!     name = benches;
+//!     config = Criterion::default().with_measurement(AllocMeasurement::bytes());
+//!     targets = bench
+//! }
+//! criterion_main!(benches);
+//! ```
+//!
+//! The counts come from `ThreadMonitor`, so the global allocator has to be an
+//! `InterAlloc` whose monitor includes one. Criterion only takes measurements
+//! right before and after the loop that runs the routine, so its own
+//! bookkeeping isn't counted; work set up by `iter_batched` is counted, since
+//! it happens on the same thread.
+use crate::monitor::{AllocInfo, ThreadMonitor};
+use ::criterion::measurement::{Measurement, ValueFormatter};
+use ::criterion::Throughput;
+
+/// What an `AllocMeasurement` counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocMetric {
+    /// Number of allocations, not counting reallocations
+    Allocations,
+    /// Bytes allocated, including by reallocations that grow
+    Bytes,
+}
+
+/// A criterion `Measurement` of the allocations made by the current thread.
+#[derive(Clone, Copy, Debug)]
+pub struct AllocMeasurement {
+    metric: AllocMetric,
+}
+
+impl AllocMeasurement {
+    pub const fn new(metric: AllocMetric) -> Self {
+        Self { metric }
+    }
+
+    /// Measures the number of allocations per iteration.
+    pub const fn allocations() -> Self {
+        Self::new(AllocMetric::Allocations)
+    }
+
+    /// Measures the bytes allocated per iteration.
+    pub const fn bytes() -> Self {
+        Self::new(AllocMetric::Bytes)
+    }
+
+    pub fn metric(&self) -> AllocMetric {
+        self.metric
+    }
+}
+
+impl Default for AllocMeasurement {
+    fn default() -> Self {
+        Self::allocations()
+    }
+}
+
+impl Measurement for AllocMeasurement {
+    type Intermediate = AllocInfo;
+    type Value = u64;
+
+    fn start(&self) -> AllocInfo {
+        ThreadMonitor::new().info()
+    }
+
+    fn end(&self, start: AllocInfo) -> u64 {
+        let delta = ThreadMonitor::new().info().relative_to(&start);
+        match self.metric {
+            AllocMetric::Allocations => delta.alloc,
+            AllocMetric::Bytes => delta.bytes_alloc,
+        }
+    }
+
+    fn add(&self, v1: &u64, v2: &u64) -> u64 {
+        v1 + v2
+    }
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn to_f64(&self, value: &u64) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        match self.metric {
+            AllocMetric::Allocations => &CountFormatter,
+            AllocMetric::Bytes => &BytesFormatter,
+        }
+    }
+}
+
+/// Divides `values` by the amount of work in `throughput`, returning whether the
+/// work is counted in bytes rather than elements.
+fn divide_by_work(throughput: &Throughput, values: &mut [f64]) -> bool {
+    let (n, bytes) = match *throughput {
+        Throughput::Bytes(n) | Throughput::BytesDecimal(n) => (n, true),
+        Throughput::Elements(n) => (n, false),
+    };
+    for v in values.iter_mut() {
+        *v /= n.max(1) as f64;
+    }
+    bytes
+}
+
+struct CountFormatter;
+
+impl ValueFormatter for CountFormatter {
+    fn scale_values(&self, _: f64, _: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        if divide_by_work(throughput, values) {
+            "allocs/byte"
+        } else {
+            "allocs/elem"
+        }
+    }
+
+    fn scale_for_machines(&self, _: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+}
+
+struct BytesFormatter;
+
+impl ValueFormatter for BytesFormatter {
+    fn scale_values(&self, typical: f64, values: &mut [f64]) -> &'static str {
+        let (factor, unit) = if typical < 1024.0 {
+            (1.0, "B")
+        } else if typical < 1024.0 * 1024.0 {
+            (1024.0, "KiB")
+        } else if typical < 1024.0 * 1024.0 * 1024.0 {
+            (1024.0 * 1024.0, "MiB")
+        } else {
+            (1024.0 * 1024.0 * 1024.0, "GiB")
+        };
+        for v in values.iter_mut() {
+            *v /= factor;
+        }
+        unit
+    }
+
+    fn scale_throughputs(
+        &self,
+        _: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        if divide_by_work(throughput, values) {
+            "B/byte"
+        } else {
+            "B/elem"
+        }
+    }
+
+    fn scale_for_machines(&self, _: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
@@ -0,0 +1,187 @@
+use crate::monitor::AllocInfo;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// The columns written by `CsvWriter`, in order. The first column is the
+/// timestamp in milliseconds, and the rest are the fields of `AllocInfo`.
+///
+/// Columns for new `AllocInfo` fields are only ever appended to the end of this
+/// list, so readers that look columns up by position keep working.
+pub const CSV_COLUMNS: &[&str] = &[
+    "timestamp_ms",
+    "alloc",
+    "dealloc",
+    "realloc",
+    "bytes_alloc",
+    "bytes_dealloc",
+    "peak_bytes",
+    "baseline_bytes",
+];
+
+/// The column added after `CSV_COLUMNS` by a `CsvWriter` with a name.
+pub const CSV_MONITOR_COLUMN: &str = "monitor";
+
+/// Writes `AllocInfo` snapshots as rows of comma-separated values, with the
+/// columns described by `CSV_COLUMNS`.
+///
+/// ```rust
+/// use interloc::{AllocInfo, CsvWriter};
+/// use std::time::Duration;
+///
+/// let info = AllocInfo {
+///     alloc: 5,
+///     dealloc: 3,
+///     realloc: 1,
+///     bytes_alloc: 640,
+///     bytes_dealloc: 512,
+///     peak_bytes: 600,
+///     baseline_bytes: 0,
+/// };
+/// let mut out = Vec::new();
+/// let writer = CsvWriter::new();
+/// writer.write_header(&mut out).unwrap();
+/// writer.write_row(&mut out, Duration::from_micros(1500), &AllocInfo::new()).unwrap();
+/// writer.write_row(&mut out, Duration::from_secs(2), &info).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "\
+/// timestamp_ms,alloc,dealloc,realloc,bytes_alloc,bytes_dealloc,peak_bytes,baseline_bytes
+/// 1,0,0,0,0,0,0,0
+/// 2000,5,3,1,640,512,600,0
+/// "
+/// );
+///
+/// // Names with commas, quotes or line breaks are quoted, with quotes doubled.
+/// let mut out = Vec::new();
+/// CsvWriter::named("arena").write_header(&mut out).unwrap();
+/// CsvWriter::named("arena").write_row(&mut out, Duration::ZERO, &info).unwrap();
+/// CsvWriter::named("a, \"b\"").write_row(&mut out, Duration::ZERO, &info).unwrap();
+/// CsvWriter::named("two\nlines").write_row(&mut out, Duration::ZERO, &info).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "\
+/// timestamp_ms,alloc,dealloc,realloc,bytes_alloc,bytes_dealloc,peak_bytes,baseline_bytes,monitor
+/// 0,5,3,1,640,512,600,0,arena
+/// 0,5,3,1,640,512,600,0,\"a, \"\"b\"\"\"
+/// 0,5,3,1,640,512,600,0,\"two
+/// lines\"
+/// "
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CsvWriter {
+    name: Option<&'static str>,
+}
+
+impl CsvWriter {
+    pub const fn new() -> Self {
+        Self { name: None }
+    }
+
+    /// A writer that adds a `monitor` column holding `name` to every row, so
+    /// that the rows of several monitors can be written to the same file.
+    pub const fn named(name: &'static str) -> Self {
+        Self { name: Some(name) }
+    }
+
+    /// Writes the header line naming each column.
+    pub fn write_header(&self, out: &mut impl io::Write) -> io::Result<()> {
+        match self.name {
+            Some(_) => writeln!(out, "{},{}", CSV_COLUMNS.join(","), CSV_MONITOR_COLUMN),
+            None => writeln!(out, "{}", CSV_COLUMNS.join(",")),
+        }
+    }
+
+    /// Writes a single row for `info`, taken at `timestamp`.
+    pub fn write_row(
+        &self,
+        out: &mut impl io::Write,
+        timestamp: Duration,
+        info: &AllocInfo,
+    ) -> io::Result<()> {
+        write!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            timestamp.as_millis(),
+            info.alloc,
+            info.dealloc,
+            info.realloc,
+            info.bytes_alloc,
+            info.bytes_dealloc,
+            info.peak_bytes,
+            info.baseline_bytes,
+        )?;
+        match self.name {
+            Some(name) if name.contains(&[',', '"', '\n', '\r'][..]) => {
+                writeln!(out, ",\"{}\"", name.replace('"', "\"\""))
+            }
+            Some(name) => writeln!(out, ",{}", name),
+            None => writeln!(out),
+        }
+    }
+}
+
+/// Records a time series of snapshots to a CSV file. The user is expected to call
+/// `record` from their own timer loop; timestamps are measured from when the
+/// recorder was created, and the header is written before the first row.
+///
+/// ```rust
+/// use interloc::{AllocInfo, SnapshotRecorder};
+///
+/// let mut recorder = SnapshotRecorder::named(Vec::new(), "main");
+/// recorder.record(&AllocInfo::new()).unwrap();
+/// recorder.record(&AllocInfo::new()).unwrap();
+/// let csv = String::from_utf8(recorder.into_inner().unwrap()).unwrap();
+/// let lines: Vec<&str> = csv.lines().collect();
+/// assert_eq!(lines.len(), 3);
+/// assert!(lines[0].ends_with(",baseline_bytes,monitor"));
+/// for row in &lines[1..] {
+///     let (timestamp, rest) = row.split_once(',').unwrap();
+///     assert!(timestamp.parse::<u64>().is_ok());
+///     assert_eq!(rest, "0,0,0,0,0,0,0,main");
+/// }
+/// ```
+pub struct SnapshotRecorder<W: io::Write> {
+    out: W,
+    writer: CsvWriter,
+    start: Instant,
+    wrote_header: bool,
+}
+
+impl<W: io::Write> SnapshotRecorder<W> {
+    pub fn new(out: W) -> Self {
+        Self::with_writer(out, CsvWriter::new())
+    }
+
+    /// A recorder whose rows have a `monitor` column holding `name`, like
+    /// `CsvWriter::named`.
+    pub fn named(out: W, name: &'static str) -> Self {
+        Self::with_writer(out, CsvWriter::named(name))
+    }
+
+    fn with_writer(out: W, writer: CsvWriter) -> Self {
+        Self {
+            out,
+            writer,
+            start: Instant::now(),
+            wrote_header: false,
+        }
+    }
+
+    /// Writes a row for `info`, stamped with the time since this recorder was
+    /// created.
+    pub fn record(&mut self, info: &AllocInfo) -> io::Result<()> {
+        if !self.wrote_header {
+            self.writer.write_header(&mut self.out)?;
+            self.wrote_header = true;
+        }
+        self.writer
+            .write_row(&mut self.out, self.start.elapsed(), info)
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
@@ -0,0 +1,392 @@
+use crate::alloc::AllocMonitor;
+use crate::fmt::FmtBuffer;
+use core::fmt;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use std::io::Write as _;
+
+/// How much work a monitor adds to every event it sees, roughly, from least
+/// to most. See `MonitorDesc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Overhead {
+    /// Nothing at all
+    Free,
+    /// A few updates to thread-local state
+    ThreadLocal,
+    /// A few atomic operations, or a short critical section, on state shared by
+    /// every thread
+    Atomic,
+    /// A lookup in a fixed-size table, with hashing or probing
+    Table,
+    /// A lock that other threads may hold for a while, a system call, a stack
+    /// walk, or a call into other code
+    Heavy,
+    /// Not described, so assumed to be the worst
+    Unknown,
+}
+
+impl Overhead {
+    pub fn name(self) -> &'static str {
+        match self {
+            Overhead::Free => "free",
+            Overhead::ThreadLocal => "thread-local",
+            Overhead::Atomic => "atomic",
+            Overhead::Table => "table",
+            Overhead::Heavy => "heavy",
+            Overhead::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for Overhead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// A parameter of a monitor, in a `MonitorDesc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MonitorParam {
+    pub name: &'static str,
+    pub value: u64,
+}
+
+/// How many parameters a `MonitorDesc` holds.
+pub const MAX_PARAMS: usize = 4;
+
+/// A short description of a monitor, from `AllocMonitor::describe`: its name,
+/// the parameters it was configured with, like sampling rates, limits and
+/// capacities, and how much it adds to each event. Built without allocating,
+/// so it can be printed from inside the allocator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MonitorDesc {
+    pub name: &'static str,
+    pub overhead: Overhead,
+    params: [MonitorParam; MAX_PARAMS],
+    len: usize,
+}
+
+impl MonitorDesc {
+    pub const fn new(name: &'static str, overhead: Overhead) -> Self {
+        Self {
+            name,
+            overhead,
+            params: [MonitorParam { name: "", value: 0 }; MAX_PARAMS],
+            len: 0,
+        }
+    }
+
+    /// Adds a parameter. Parameters after the first `MAX_PARAMS` are left out.
+    pub const fn param(mut self, name: &'static str, value: u64) -> Self {
+        if self.len < MAX_PARAMS {
+            self.params[self.len] = MonitorParam { name, value };
+            self.len += 1;
+        }
+        self
+    }
+
+    pub fn params(&self) -> &[MonitorParam] {
+        &self.params[..self.len]
+    }
+
+    /// The value of the parameter `name`, if it has one.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        let param = self.params().iter().find(|param| param.name == name)?;
+        Some(param.value)
+    }
+}
+
+/// Shows the name, the parameters in brackets, and the overhead in
+/// parentheses, like `SampleMonitor[every=100] (atomic)`.
+impl fmt::Display for MonitorDesc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)?;
+        for (i, param) in self.params().iter().enumerate() {
+            let sep = if i == 0 { "[" } else { ", " };
+            write!(f, "{}{}={}", sep, param.name, param.value)?;
+        }
+        if self.len > 0 {
+            f.write_str("]")?;
+        }
+        write!(f, " ({})", self.overhead)
+    }
+}
+
+/// A monitor in a `ConfigReport`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReportEntry {
+    /// How many monitors it's nested in: 0 for the monitor the report is of, 1
+    /// for the monitors that one passes events to, and so on
+    pub depth: usize,
+    pub monitor: MonitorDesc,
+}
+
+/// How many monitors a `ConfigReport` holds.
+pub const REPORT_CAPACITY: usize = 32;
+
+/// Cargo features of interloc, to list the enabled ones in a `ConfigReport`.
+const FEATURES: &[(&str, bool)] = &[
+    ("parking_lot", cfg!(feature = "parking_lot")),
+    ("strict-ordering", cfg!(feature = "strict-ordering")),
+    ("self-metrics", cfg!(feature = "self-metrics")),
+    ("disabled", cfg!(feature = "disabled")),
+    ("nightly", cfg!(feature = "nightly")),
+    ("deterministic", cfg!(feature = "deterministic")),
+    ("statsd", cfg!(feature = "statsd")),
+    ("otel", cfg!(feature = "otel")),
+    ("signal", cfg!(feature = "signal")),
+    ("mirror", cfg!(feature = "mirror")),
+    ("backtrace", cfg!(feature = "backtrace")),
+    ("ffi", cfg!(feature = "ffi")),
+    ("pprof", cfg!(feature = "pprof")),
+    ("futures", cfg!(feature = "futures")),
+    ("rayon", cfg!(feature = "rayon")),
+    ("plot", cfg!(feature = "plot")),
+    ("puffin", cfg!(feature = "puffin")),
+    ("tracy", cfg!(feature = "tracy")),
+    ("etw", cfg!(feature = "etw")),
+    ("usable-size", cfg!(feature = "usable-size")),
+    ("usdt", cfg!(feature = "usdt")),
+    ("criterion", cfg!(feature = "criterion")),
+    ("jemalloc", cfg!(feature = "jemalloc")),
+    ("mimalloc", cfg!(feature = "mimalloc")),
+];
+
+/// What a stack of monitors is made of and how it's configured, along with the
+/// features interloc was built with, for logging once when a service starts.
+///
+/// `of` describes a monitor with `AllocMonitor::describe`, then the monitors it
+/// passes events to, as it adds them with `AllocMonitor::describe_inner`, and
+/// so on down. Wrappers and lists built into interloc add the monitors they
+/// hold, and monitors of other crates that don't describe themselves show up
+/// by their type name, with an unknown overhead. Monitors after the first
+/// `REPORT_CAPACITY` are only counted. It displays as a single line:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{ConfigReport, HistogramMonitor, Overhead, SampleMode, SampleMonitor};
+/// use interloc::{SliceMonitor, StatsMonitor, TrackingMonitor};
+///
+/// static STATS: StatsMonitor = StatsMonitor::new();
+/// static SAMPLED: SampleMonitor<HistogramMonitor> =
+///     SampleMonitor::new(HistogramMonitor::new(), SampleMode::Every(100));
+/// static TRACKING: TrackingMonitor<256> = TrackingMonitor::new();
+/// static MONITORS: SliceMonitor = SliceMonitor::new(&[&STATS, &SAMPLED, &TRACKING]);
+///
+/// let report = ConfigReport::of(&MONITORS);
+/// let names: Vec<_> = report
+///     .entries()
+///     .iter()
+///     .map(|entry| (entry.depth, entry.monitor.name))
+///     .collect();
+/// assert_eq!(
+///     names,
+///     [
+///         (0, "SliceMonitor"),
+///         (1, "StatsMonitor"),
+///         (1, "SampleMonitor"),
+///         (2, "HistogramMonitor"),
+///         (1, "TrackingMonitor"),
+///     ]
+/// );
+/// assert_eq!(report.entries()[2].monitor.get("every"), Some(100));
+/// assert_eq!(report.entries()[3].monitor.get("buckets"), Some(64));
+/// assert_eq!(report.entries()[4].monitor.get("capacity"), Some(256));
+/// assert_eq!(report.overhead(), Overhead::Table);
+///
+/// let line = report.to_string();
+/// assert!(line.starts_with("interloc "));
+/// assert!(line.contains(
+///     "SliceMonitor (free) { StatsMonitor (atomic), \
+///      SampleMonitor[every=100] (atomic) { HistogramMonitor[buckets=64] (atomic) }, \
+///      TrackingMonitor[capacity=256] (table) }; overhead: table; features: "
+/// ));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConfigReport {
+    entries: [ReportEntry; REPORT_CAPACITY],
+    len: usize,
+    omitted: usize,
+    depth: usize,
+}
+
+impl ConfigReport {
+    /// A report of no monitors.
+    pub const fn new() -> Self {
+        Self {
+            entries: [ReportEntry {
+                depth: 0,
+                monitor: MonitorDesc::new("", Overhead::Free),
+            }; REPORT_CAPACITY],
+            len: 0,
+            omitted: 0,
+            depth: 0,
+        }
+    }
+
+    /// A report of `monitor` and the monitors it passes events to.
+    pub fn of<M: AllocMonitor + ?Sized>(monitor: &M) -> Self {
+        let mut report = Self::new();
+        report.add(monitor);
+        report
+    }
+
+    /// Adds `monitor`, nested in the monitor being described, if any, and then
+    /// the monitors it passes events to. For `AllocMonitor::describe_inner`.
+    pub fn add<M: AllocMonitor + ?Sized>(&mut self, monitor: &M) {
+        if self.len < REPORT_CAPACITY {
+            self.entries[self.len] = ReportEntry {
+                depth: self.depth,
+                monitor: monitor.describe(),
+            };
+            self.len += 1;
+        } else {
+            self.omitted += 1;
+        }
+        self.depth += 1;
+        monitor.describe_inner(self);
+        self.depth -= 1;
+    }
+
+    /// The monitors, each one followed by the monitors it passes events to.
+    pub fn entries(&self) -> &[ReportEntry] {
+        &self.entries[..self.len]
+    }
+
+    /// How many monitors didn't fit.
+    pub fn omitted(&self) -> usize {
+        self.omitted
+    }
+
+    /// The highest overhead of any of the monitors, which is about what each
+    /// event costs on top of the inner allocator. `Unknown` if any didn't fit.
+    pub fn overhead(&self) -> Overhead {
+        let worst = self.entries().iter().map(|entry| entry.monitor.overhead);
+        let worst = worst.max().unwrap_or(Overhead::Free);
+        if self.omitted > 0 {
+            Overhead::Unknown
+        } else {
+            worst
+        }
+    }
+
+    /// The Cargo features interloc was built with.
+    pub fn features(&self) -> impl Iterator<Item = &'static str> {
+        FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+    }
+}
+
+impl Default for ConfigReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lists the monitors, with the ones each passes events to in braces after
+/// it, then the overhead and the features, on one line without a newline.
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "interloc {}: ", env!("CARGO_PKG_VERSION"))?;
+        let entries = self.entries();
+        if entries.is_empty() {
+            f.write_str("no monitors")?;
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            write!(f, "{}", entry.monitor)?;
+            let next = entries.get(i + 1).map_or(0, |next| next.depth);
+            if next > entry.depth {
+                f.write_str(" { ")?;
+                continue;
+            }
+            for _ in next..entry.depth {
+                f.write_str(" }")?;
+            }
+            if i + 1 < entries.len() {
+                f.write_str(", ")?;
+            }
+        }
+        if self.omitted > 0 {
+            write!(f, " and {} more", self.omitted)?;
+        }
+        write!(f, "; overhead: {}; features: ", self.overhead())?;
+        let mut features = self.features().peekable();
+        if features.peek().is_none() {
+            f.write_str("none")?;
+        }
+        for (i, feature) in features.enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{}{}", sep, feature)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns a report of the monitor of the global allocator declared with
+/// `global!`.
+pub type GlobalReport = fn() -> ConfigReport;
+
+/// The `GlobalReport` of the program, or null until it's registered.
+static REPORT: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+pub(crate) fn register_global_report(report: GlobalReport) {
+    REPORT.store(report as *mut (), Ordering::Release);
+}
+
+/// A report of the monitors of the global allocator, if it was declared with
+/// `global!`, or of no monitors. The monitor is registered when the allocator
+/// sees its first event, like for `global_info`, and `global!` can also print
+/// the report to stderr then, as a banner:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{SampleMode, SampleMonitor, StatsMonitor};
+/// use std::alloc::System;
+/// use std::process::Command;
+///
+/// interloc::global!(
+///     banner,
+///     System,
+///     SampleMonitor<StatsMonitor> = SampleMonitor::new(StatsMonitor::new(), SampleMode::Every(10))
+/// );
+///
+/// // The monitor is registered by the first allocation, which the runtime may
+/// // not have made before `main`.
+/// std::hint::black_box(Box::new(0));
+/// let report = interloc::describe();
+/// assert_eq!(report.entries()[0].monitor.get("every"), Some(10));
+/// assert_eq!(report.entries()[1].monitor.name, "StatsMonitor");
+///
+/// // The banner is printed by the first allocation, maybe before `main`, so
+/// // it's checked in a child. Miri can't start one.
+/// if std::env::var_os("BANNER_CHILD").is_some() || cfg!(miri) {
+///     return;
+/// }
+/// let output = Command::new(std::env::current_exe().unwrap())
+///     .env("BANNER_CHILD", "1")
+///     .output()
+///     .unwrap();
+/// assert!(output.status.success());
+/// let stderr = String::from_utf8_lossy(&output.stderr);
+/// assert_eq!(stderr.lines().next(), Some(&*report.to_string()));
+/// ```
+pub fn describe() -> ConfigReport {
+    let report = REPORT.load(Ordering::Acquire);
+    if report.is_null() {
+        return ConfigReport::new();
+    }
+    let report: GlobalReport = unsafe { core::mem::transmute(report) };
+    report()
+}
+
+/// Prints `report` to stderr on a line of its own, without allocating, cut
+/// short if it doesn't fit in 1 KiB.
+pub(crate) fn print_banner(report: &ConfigReport) {
+    use core::fmt::Write as _;
+    let mut buf = FmtBuffer::<1024>::new();
+    let _ = write!(buf, "{}", report);
+    let mut stderr = std::io::stderr().lock();
+    let _ = stderr.write_all(buf.as_bytes());
+    let _ = stderr.write_all(b"\n");
+}
@@ -0,0 +1,135 @@
+use crate::json::write_json_str;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io;
+
+/// The statistics of a single program point, i.e. a distinct allocation stack,
+/// for `DhatWriter`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DhatProgramPoint<'a> {
+    /// Bytes allocated over the whole run
+    pub total_bytes: u64,
+    /// Blocks allocated over the whole run
+    pub total_blocks: u64,
+    /// Most bytes live at once
+    pub max_bytes: u64,
+    /// Most blocks live at once
+    pub max_blocks: u64,
+    /// Bytes live when the heap as a whole peaked
+    pub gmax_bytes: u64,
+    /// Blocks live when the heap as a whole peaked
+    pub gmax_blocks: u64,
+    /// Bytes live at the end of the run
+    pub end_bytes: u64,
+    /// Blocks live at the end of the run
+    pub end_blocks: u64,
+    /// The stack of the allocation, innermost frame first
+    pub frames: &'a [&'a str],
+}
+
+/// Writes heap profiles in the JSON format read by DHAT's viewer,
+/// `dh_view.html`. Frames shared between program points are only written once,
+/// in the frame table.
+///
+/// Not everything DHAT records can be filled in from interloc's data:
+///
+/// | DHAT field          | Source                                          |
+/// |---------------------|-------------------------------------------------|
+/// | `tb`, `tbk`         | `total_bytes`, `total_blocks`                   |
+/// | `mb`, `mbk`         | `max_bytes`, `max_blocks`                       |
+/// | `gb`, `gbk`         | `gmax_bytes`, `gmax_blocks`                     |
+/// | `eb`, `ebk`         | `end_bytes`, `end_blocks`                       |
+/// | `tl` (lifetimes)    | not tracked, always 0                           |
+/// | `tg`, `te`          | the times passed to `write`                     |
+/// | access counts       | not tracked, omitted (`bkacc` is false)         |
+#[derive(Clone, Copy, Debug)]
+pub struct DhatWriter<'a> {
+    cmd: &'a str,
+    pid: u32,
+}
+
+impl<'a> DhatWriter<'a> {
+    /// A writer for a profile of the command `cmd`, attributed to the current
+    /// process.
+    pub fn new(cmd: &'a str) -> Self {
+        Self {
+            cmd,
+            pid: std::process::id(),
+        }
+    }
+
+    /// Sets the process id recorded in the profile.
+    pub fn pid(mut self, pid: u32) -> Self {
+        self.pid = pid;
+        self
+    }
+
+    /// Writes a profile of `points` to `out`. `t_gmax` is the time at which the
+    /// heap peaked and `t_end` the time at which the run ended, both in
+    /// microseconds since the start of the run.
+    pub fn write(
+        &self,
+        out: &mut impl io::Write,
+        points: &[DhatProgramPoint],
+        t_gmax: u64,
+        t_end: u64,
+    ) -> io::Result<()> {
+        let mut frames: HashMap<&str, usize> = HashMap::new();
+        let mut table = vec!["[root]"];
+        let mut json = String::new();
+        let to_io = |_| io::Error::other("formatting failed");
+
+        json.push_str("{\"dhatFileVersion\":2,\"mode\":\"rust-heap\",\"verb\":\"Allocated\",");
+        json.push_str("\"bklt\":true,\"bkacc\":false,\"tu\":\"µs\",\"Mtu\":\"s\",\"tuth\":10,");
+        json.push_str("\"cmd\":");
+        write_json_str(&mut json, self.cmd).map_err(to_io)?;
+        write!(
+            json,
+            ",\"pid\":{},\"tg\":{},\"te\":{},\"pps\":[",
+            self.pid, t_gmax, t_end
+        )
+        .map_err(to_io)?;
+
+        for (i, pp) in points.iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "{{\"tb\":{},\"tbk\":{},\"tl\":0,\"mb\":{},\"mbk\":{},\"gb\":{},\"gbk\":{},\
+                 \"eb\":{},\"ebk\":{},\"fs\":[",
+                pp.total_bytes,
+                pp.total_blocks,
+                pp.max_bytes,
+                pp.max_blocks,
+                pp.gmax_bytes,
+                pp.gmax_blocks,
+                pp.end_bytes,
+                pp.end_blocks,
+            )
+            .map_err(to_io)?;
+            for (j, frame) in pp.frames.iter().enumerate() {
+                let index = *frames.entry(frame).or_insert_with(|| {
+                    table.push(frame);
+                    table.len() - 1
+                });
+                if j != 0 {
+                    json.push(',');
+                }
+                write!(json, "{}", index).map_err(to_io)?;
+            }
+            json.push_str("]}");
+        }
+
+        json.push_str("],\"ftbl\":[");
+        for (i, frame) in table.iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+            write_json_str(&mut json, frame).map_err(to_io)?;
+        }
+        json.push_str("]}");
+
+        out.write_all(json.as_bytes())
+    }
+}
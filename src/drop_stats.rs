@@ -0,0 +1,155 @@
+use crate::bench::{start_measuring, stop_measuring};
+use crate::monitor::AllocInfo;
+use core::any::Any;
+use core::panic::AssertUnwindSafe;
+
+/// Drops `value` and returns what dropping it allocated on the current thread,
+/// according to `ThreadMonitor`, for checking that a `Drop` impl doesn't
+/// allocate, e.g. to format a message. `peak_bytes` is the most bytes that were
+/// live at once during the drop, on top of those live before it.
+///
+/// Only the drop is measured: reading the thread's statistics before and after
+/// it doesn't allocate. As with `bench`, the global allocator has to be an
+/// `InterAlloc` whose monitor includes a `ThreadMonitor`, or this always
+/// returns zeroes.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{drop_with_stats, InterAlloc, ThreadMonitor};
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, ThreadMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &ThreadMonitor::new(),
+/// };
+///
+/// struct Chatty(Vec<u8>);
+///
+/// impl Drop for Chatty {
+///     fn drop(&mut self) {
+///         let message = format!("dropping {} bytes", self.0.len());
+///         std::hint::black_box(message);
+///     }
+/// }
+///
+/// let stats = drop_with_stats(Chatty(vec![0; 100]));
+/// assert_eq!(stats.alloc, 1);
+/// // The message, then the Vec.
+/// assert_eq!(stats.dealloc, 2);
+/// assert_eq!(stats.bytes_dealloc - stats.bytes_alloc, 100);
+///
+/// let quiet = drop_with_stats(vec![0u8; 100]);
+/// assert_eq!((quiet.alloc, quiet.dealloc, quiet.bytes_dealloc), (0, 1, 100));
+/// ```
+///
+/// If the drop panics, the panic goes on with its payload wrapped in a
+/// `DropPanic`, along with what was allocated up to the panic, including the
+/// payload itself:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{drop_with_stats, DropPanic, InterAlloc, ThreadMonitor};
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, ThreadMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &ThreadMonitor::new(),
+/// };
+///
+/// struct Fails(u32);
+///
+/// impl Drop for Fails {
+///     fn drop(&mut self) {
+///         panic!("failed to drop {}", self.0);
+///     }
+/// }
+///
+/// std::panic::set_hook(Box::new(|_| {}));
+/// let payload = std::panic::catch_unwind(|| drop_with_stats(Fails(7))).unwrap_err();
+/// let panic = payload.downcast::<DropPanic>().unwrap();
+/// assert!(panic.stats.alloc >= 1);
+/// assert_eq!(panic.payload.downcast_ref::<String>().unwrap(), "failed to drop 7");
+/// ```
+pub fn drop_with_stats<T>(value: T) -> AllocInfo {
+    let start = start_measuring();
+    let dropped = std::panic::catch_unwind(AssertUnwindSafe(move || drop(value)));
+    let stats = stop_measuring(&start);
+    if let Err(payload) = dropped {
+        std::panic::resume_unwind(Box::new(DropPanic { stats, payload }));
+    }
+    stats
+}
+
+/// The payload of a panic in a drop measured by `drop_with_stats`.
+pub struct DropPanic {
+    /// What the drop allocated before it panicked
+    pub stats: AllocInfo,
+    /// The payload of the panic
+    pub payload: Box<dyn Any + Send>,
+}
+
+impl core::fmt::Debug for DropPanic {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("DropPanic")
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Drops a value with `drop_with_stats` and panics if that allocated more than
+/// `max_allocs` times, 0 by default, counting reallocations. Evaluates to the
+/// `AllocInfo` of the drop.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{assert_drop_allocs, InterAlloc, ThreadMonitor};
+/// use std::alloc::System;
+/// use std::collections::HashMap;
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, ThreadMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &ThreadMonitor::new(),
+/// };
+///
+/// struct Logged(u32);
+///
+/// impl Drop for Logged {
+///     fn drop(&mut self) {
+///         std::hint::black_box(self.0.to_string());
+///     }
+/// }
+///
+/// let cache: HashMap<u32, Vec<u8>> = (0..10).map(|i| (i, vec![0; 10])).collect();
+/// let stats = assert_drop_allocs!(cache);
+/// assert_eq!(stats.dealloc, 11);
+///
+/// let logged: Vec<_> = (0..3).map(Logged).collect();
+/// assert_drop_allocs!(logged, max_allocs = 3);
+///
+/// let logged = Logged(1);
+/// let failed = std::panic::catch_unwind(move || assert_drop_allocs!(logged));
+/// assert!(failed.is_err());
+/// ```
+#[macro_export]
+macro_rules! assert_drop_allocs {
+    ($value:expr $(,)?) => {
+        $crate::assert_drop_allocs!($value, max_allocs = 0)
+    };
+    ($value:expr, max_allocs = $max:expr $(,)?) => {{
+        let stats = $crate::drop_with_stats($value);
+        let max: u64 = $max;
+        if stats.alloc + stats.realloc > max {
+            panic!(
+                "dropping `{}` allocated {} times, more than {}: {:?}",
+                stringify!($value),
+                stats.alloc + stats.realloc,
+                max,
+                stats,
+            );
+        }
+        stats
+    }};
+}
@@ -0,0 +1,151 @@
+use crate::alloc::{internal, AllocAction, AllocMonitor, EventMask};
+use crate::callsite::current_location;
+use crate::describe::{MonitorDesc, Overhead};
+use crate::event::thread_token;
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use tracelogging as tlg;
+
+tlg::define_provider!(PROVIDER, "Interloc.Allocations");
+
+/// Name of the TraceLogging provider that `EtwMonitor` writes to.
+pub const ETW_PROVIDER_NAME: &str = "Interloc.Allocations";
+
+/// GUID of the provider, derived from its name in the standard way, so tools
+/// that take a provider name find it too.
+pub const ETW_PROVIDER_GUID: &str = "26810dc6-dae6-52b7-13a2-2fba80115d4d";
+
+const UNREGISTERED: u8 = 0;
+const REGISTERING: u8 = 1;
+const REGISTERED: u8 = 2;
+const FAILED: u8 = 3;
+
+static STATE: AtomicU8 = AtomicU8::new(UNREGISTERED);
+
+/// Writes allocator calls as ETW events, through a TraceLogging provider, so
+/// that they can be recorded with `wpr` or `tracelog` and looked at in WPA
+/// next to CPU traces.
+///
+/// Every call is written as an `Allocation` event at the verbose level, with
+/// keyword 1, and these fields:
+///
+/// | Field     | Value                                                       |
+/// |-----------|-------------------------------------------------------------|
+/// | `Kind`    | the discriminant of the call's `ActionKind`                 |
+/// | `Size`    | size of the layout                                          |
+/// | `Align`   | alignment of the layout                                     |
+/// | `Ptr`     | the address attached to the call, or 0                      |
+/// | `NewSize` | the new size of reallocations, or 0                         |
+/// | `Thread`  | the `thread_token` of the calling thread                    |
+/// | `Site`    | the address of the enclosing `trace_alloc!` location, or 0  |
+///
+/// The provider is registered on the first event, and writing is skipped
+/// unless a session is listening to it. If registering or writing fails, the
+/// failure is counted in `failures` and the event is dropped. Writing every
+/// call is expensive when a session is listening, so wrapping this in a
+/// `SampleMonitor` or picking kinds with `mask` is a good idea.
+pub struct EtwMonitor {
+    mask: EventMask,
+    failures: AtomicU64,
+}
+
+impl EtwMonitor {
+    pub const fn new() -> Self {
+        Self {
+            mask: EventMask::ALL,
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Only writes events of the kinds in `mask`.
+    pub const fn mask(mut self, mask: EventMask) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Number of times registering the provider or writing an event failed.
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Whether the provider is registered, registering it if nobody has tried
+    /// yet.
+    fn registered(&self) -> bool {
+        match STATE.load(Ordering::Acquire) {
+            REGISTERED => return true,
+            UNREGISTERED => {}
+            // Another thread is registering, or it failed.
+            _ => return false,
+        }
+        if STATE
+            .compare_exchange(
+                UNREGISTERED,
+                REGISTERING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            return STATE.load(Ordering::Acquire) == REGISTERED;
+        }
+        // The provider is never unregistered, which is only a problem if
+        // interloc is loaded from a DLL that gets unloaded.
+        let result = internal(|| unsafe { PROVIDER.register() });
+        if result == 0 {
+            STATE.store(REGISTERED, Ordering::Release);
+            true
+        } else {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            STATE.store(FAILED, Ordering::Release);
+            false
+        }
+    }
+}
+
+impl Default for EtwMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AllocMonitor for EtwMonitor {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        if !self.mask.matches(&action) || !self.registered() {
+            return;
+        }
+        if !PROVIDER.enabled(tlg::Level::Verbose, 1) {
+            return;
+        }
+        let kind = action.kind() as u8;
+        let size = layout.size();
+        let align = layout.align();
+        let ptr = action.ptr().map(|p| p as usize).unwrap_or(0);
+        let new_size = action.new_size().unwrap_or(0);
+        let thread = thread_token();
+        let site = current_location()
+            .map(|l| l as *const _ as usize)
+            .unwrap_or(0);
+        let result = internal(|| {
+            tlg::write_event!(
+                PROVIDER,
+                "Allocation",
+                level(Verbose),
+                keyword(0x1),
+                u8("Kind", &kind),
+                usize("Size", &size),
+                usize("Align", &align),
+                pointer("Ptr", &ptr),
+                usize("NewSize", &new_size),
+                usize("Thread", &thread),
+                pointer("Site", &site),
+            )
+        });
+        if result != 0 {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("EtwMonitor", Overhead::Heavy).param("mask", self.mask.bits() as u64)
+    }
+}
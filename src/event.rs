@@ -0,0 +1,210 @@
+use crate::alloc::{ActionKind, AllocAction};
+use crate::clock::{Clock, CoarseClock};
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::io;
+
+static NEXT_THREAD_TOKEN: AtomicUsize = AtomicUsize::new(1);
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static THREAD_TOKEN: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cfg(feature = "deterministic")]
+thread_local! {
+    /// The next serial of the current thread, while it's isolated by
+    /// `testing::isolate`.
+    static ISOLATED_SERIAL: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// A small nonzero number identifying the current thread, handed out in the order
+/// that threads first ask for one. Unlike `std::thread::ThreadId`, getting it
+/// never allocates.
+#[inline]
+pub fn thread_token() -> usize {
+    THREAD_TOKEN.with(|t| {
+        let token = t.get();
+        if token != 0 {
+            return token;
+        }
+        let token = NEXT_THREAD_TOKEN.fetch_add(1, Ordering::Relaxed);
+        t.set(token);
+        token
+    })
+}
+
+/// The next number in a process-wide sequence, used to order events recorded
+/// on different threads.
+#[inline]
+#[cfg(not(feature = "deterministic"))]
+pub(crate) fn next_serial() -> u64 {
+    NEXT_SERIAL.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The next number in a process-wide sequence, used to order events recorded
+/// on different threads, or in the sequence of the current thread if it's
+/// isolated.
+#[inline]
+#[cfg(feature = "deterministic")]
+pub(crate) fn next_serial() -> u64 {
+    let isolated = ISOLATED_SERIAL
+        .try_with(|s| {
+            let serial = s.get()?;
+            s.set(Some(serial + 1));
+            Some(serial)
+        })
+        .ok()
+        .flatten();
+    isolated.unwrap_or_else(|| NEXT_SERIAL.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Sets the next serial of the current thread, or makes it use the process-wide
+/// sequence again if `serial` is `None`, and returns what it was.
+#[cfg(feature = "deterministic")]
+pub(crate) fn swap_isolated_serial(serial: Option<u64>) -> Option<u64> {
+    ISOLATED_SERIAL
+        .try_with(|s| s.replace(serial))
+        .ok()
+        .flatten()
+}
+
+/// Nanoseconds on `CoarseClock`, which is cheap enough to read on every event.
+#[inline]
+pub(crate) fn now_nanos() -> u64 {
+    CoarseClock.now_nanos()
+}
+
+/// A single call to the monitor, flattened into plain integers so that it can be
+/// copied around and encoded without holding on to any pointers.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub struct EventRecord {
+    /// The kind of action
+    pub kind: ActionKind,
+    /// Size of the layout passed to the allocator
+    pub size: usize,
+    /// Alignment of the layout passed to the allocator
+    pub align: usize,
+    /// Address attached to the action, or zero if there wasn't one
+    pub ptr: usize,
+    /// New size for realloc actions, or zero otherwise
+    pub new_size: usize,
+    /// The `thread_token` of the thread the action happened on
+    pub thread: usize,
+    /// Position of this event in the process-wide ordering of events
+    pub serial: u64,
+    /// Nanoseconds on `CoarseClock`, counting from the first time it was read
+    /// in the process
+    pub timestamp: u64,
+}
+
+/// Size in bytes of an encoded `EventRecord`.
+pub const RECORD_SIZE: usize = 64;
+
+impl EventRecord {
+    /// A record with every field zeroed, suitable for filling fixed buffers.
+    pub const EMPTY: Self = Self {
+        kind: ActionKind::Alloc,
+        size: 0,
+        align: 0,
+        ptr: 0,
+        new_size: 0,
+        thread: 0,
+        serial: 0,
+        timestamp: 0,
+    };
+
+    /// Captures an event on the current thread.
+    #[inline]
+    pub fn new(layout: Layout, action: AllocAction) -> Self {
+        Self {
+            kind: action.kind(),
+            size: layout.size(),
+            align: layout.align(),
+            ptr: action.ptr().map(|p| p as usize).unwrap_or(0),
+            new_size: action.new_size().unwrap_or(0),
+            thread: thread_token(),
+            serial: next_serial(),
+            timestamp: now_nanos(),
+        }
+    }
+
+    /// The layout passed to the allocator.
+    pub fn layout(&self) -> Option<Layout> {
+        Layout::from_size_align(self.size, self.align).ok()
+    }
+
+    /// The action this record was made from.
+    pub fn action(&self) -> AllocAction {
+        let ptr = self.ptr as *mut u8;
+        let new_size = self.new_size;
+        match self.kind {
+            ActionKind::Alloc => AllocAction::Alloc,
+            ActionKind::AllocResult => AllocAction::AllocResult { ptr },
+            ActionKind::AllocZeroed => AllocAction::AllocZeroed,
+            ActionKind::AllocZeroedResult => AllocAction::AllocZeroedResult { ptr },
+            ActionKind::Dealloc => AllocAction::Dealloc { ptr },
+            ActionKind::DeallocResult => AllocAction::DeallocResult,
+            ActionKind::Realloc => AllocAction::Realloc { ptr, new_size },
+            ActionKind::ReallocResult => AllocAction::ReallocResult { ptr, new_size },
+        }
+    }
+
+    /// The fields of this record as eight 64-bit words: the kind tag, size,
+    /// align, ptr, new size, thread, serial and timestamp.
+    pub(crate) fn words(&self) -> [u64; 8] {
+        [
+            self.kind as u64,
+            self.size as u64,
+            self.align as u64,
+            self.ptr as u64,
+            self.new_size as u64,
+            self.thread as u64,
+            self.serial,
+            self.timestamp,
+        ]
+    }
+
+    /// The record made of `words` from `words`, or `None` if the kind tag is
+    /// invalid.
+    pub(crate) fn from_words(words: &[u64; 8]) -> Option<Self> {
+        let kind = if words[0] <= u8::MAX as u64 {
+            ActionKind::from_u8(words[0] as u8)
+        } else {
+            None
+        }?;
+        Some(Self {
+            kind,
+            size: words[1] as usize,
+            align: words[2] as usize,
+            ptr: words[3] as usize,
+            new_size: words[4] as usize,
+            thread: words[5] as usize,
+            serial: words[6],
+            timestamp: words[7],
+        })
+    }
+
+    /// Encodes this record as eight little-endian 64-bit words: the kind tag,
+    /// size, align, ptr, new size, thread, serial and timestamp.
+    pub fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut out = [0; RECORD_SIZE];
+        for (chunk, word) in out.chunks_exact_mut(8).zip(self.words().iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    /// Decodes a record produced by `encode`.
+    pub fn decode(bytes: &[u8; RECORD_SIZE]) -> io::Result<Self> {
+        let mut words = [0u64; 8];
+        for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(8)) {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(chunk);
+            *word = u64::from_le_bytes(buf);
+        }
+        Self::from_words(&words)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid action tag"))
+    }
+}
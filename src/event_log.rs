@@ -0,0 +1,326 @@
+use crate::alloc::{AllocAction, AllocMonitor, EventMask};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::event::{thread_token, EventRecord, RECORD_SIZE};
+use crate::slots::Slots;
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::io;
+
+/// Bytes at the start of every event log.
+pub const LOG_MAGIC: [u8; 8] = *b"ILOGv001";
+
+/// How many records a flush encodes for each write.
+const BATCH: usize = 16;
+
+/// A buffer of records owned by a single thread. The owning thread is the only
+/// one that advances `head`, and only the flusher advances `tail`.
+struct ThreadBuffer<const RECORDS: usize> {
+    owner: AtomicUsize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    /// Bytes of the record at `tail` that a failed flush did write, which
+    /// only the flusher uses
+    written: AtomicUsize,
+    records: UnsafeCell<[EventRecord; RECORDS]>,
+}
+
+impl<const RECORDS: usize> ThreadBuffer<RECORDS> {
+    const fn new() -> Self {
+        Self {
+            owner: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            written: AtomicUsize::new(0),
+            records: UnsafeCell::new([EventRecord::EMPTY; RECORDS]),
+        }
+    }
+}
+
+/// Records every event into per-thread buffers of fixed size, which are drained
+/// to a writer by calls to `flush`.
+///
+/// Each of up to `THREADS` threads claims a buffer of `RECORDS` records the first
+/// time it sees an event. Buffers aren't given back when threads exit, and
+/// threads that can't claim one have their events dropped. When a thread's
+/// buffer fills up between flushes, new events are dropped rather than waiting
+/// for the flusher. Every dropped event increments `dropped`. Events outside the
+/// monitor's `EventMask` aren't recorded, and don't count as dropped.
+///
+/// Note that the buffers are stored inline, so this struct is
+/// `THREADS * RECORDS * 64` bytes large; it's meant to be put in a static.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{ActionKind, AllocAction, AllocMonitor, EventLogMonitor, LogReader};
+///
+/// static LOG: EventLogMonitor<2, 8> = EventLogMonitor::new();
+///
+/// let layout = Layout::from_size_align(24, 8).unwrap();
+/// let ptr = 0x1000 as *mut u8;
+/// LOG.monitor(layout, AllocAction::Alloc);
+/// LOG.monitor(layout, AllocAction::AllocResult { ptr });
+/// LOG.monitor(layout, AllocAction::Realloc { ptr, new_size: 48 });
+///
+/// let mut file = Vec::new();
+/// LOG.write_header(&mut file).unwrap();
+/// assert_eq!(LOG.flush(&mut file).unwrap(), 3);
+/// // Flushed records aren't written again.
+/// assert_eq!(LOG.flush(&mut file).unwrap(), 0);
+///
+/// let records: Vec<_> = LogReader::new(&file[..])
+///     .unwrap()
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// let kinds: Vec<ActionKind> = records.iter().map(|r| r.kind).collect();
+/// assert_eq!(kinds, [ActionKind::Alloc, ActionKind::AllocResult, ActionKind::Realloc]);
+/// assert!(records.iter().all(|r| r.layout() == Some(layout)));
+/// assert_eq!(records[1].action(), AllocAction::AllocResult { ptr });
+/// assert_eq!(records[2].action(), AllocAction::Realloc { ptr, new_size: 48 });
+/// assert!(records.windows(2).all(|w| w[0].serial < w[1].serial));
+/// assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+///
+/// // Records that weren't written in full are kept for the next flush, which
+/// // carries on from the last byte written.
+/// struct Short<'a>(&'a mut Vec<u8>, usize);
+///
+/// impl std::io::Write for Short<'_> {
+///     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+///         let n = buf.len().min(self.1);
+///         if n == 0 {
+///             return Err(std::io::ErrorKind::StorageFull.into());
+///         }
+///         self.0.extend_from_slice(&buf[..n]);
+///         self.1 -= n;
+///         Ok(n)
+///     }
+///     fn flush(&mut self) -> std::io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// LOG.monitor(layout, AllocAction::Dealloc { ptr });
+/// LOG.monitor(layout, AllocAction::Alloc);
+/// let mut file = Vec::new();
+/// LOG.write_header(&mut file).unwrap();
+/// assert!(LOG.flush(&mut Short(&mut file, 100)).is_err());
+/// assert_eq!(LOG.flush(&mut file).unwrap(), 1);
+/// let kinds: Vec<ActionKind> = LogReader::new(&file[..])
+///     .unwrap()
+///     .map(|r| r.unwrap().kind)
+///     .collect();
+/// assert_eq!(kinds, [ActionKind::Dealloc, ActionKind::Alloc]);
+/// assert_eq!(LOG.dropped(), 0);
+/// ```
+pub struct EventLogMonitor<const THREADS: usize, const RECORDS: usize> {
+    buffers: Slots<ThreadBuffer<RECORDS>, THREADS>,
+    mask: EventMask,
+    dropped: AtomicU64,
+    flushing: AtomicBool,
+}
+
+unsafe impl<const THREADS: usize, const RECORDS: usize> Sync for EventLogMonitor<THREADS, RECORDS> {}
+
+impl<const THREADS: usize, const RECORDS: usize> EventLogMonitor<THREADS, RECORDS> {
+    pub const fn new() -> Self {
+        Self {
+            buffers: Slots::new([const { ThreadBuffer::new() }; THREADS]),
+            mask: EventMask::ALL,
+            dropped: AtomicU64::new(0),
+            flushing: AtomicBool::new(false),
+        }
+    }
+
+    /// Only records events of the kinds in `mask`.
+    pub const fn mask(mut self, mask: EventMask) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Number of events that couldn't be recorded.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes the header that `LogReader` expects at the start of a log. This
+    /// should be called once per file, before the first flush.
+    pub fn write_header(&self, out: &mut impl io::Write) -> io::Result<()> {
+        out.write_all(&LOG_MAGIC)
+    }
+
+    /// Drains every thread's buffer into `out`, returning the number of records
+    /// written. Records are written thread by thread, so readers that need a
+    /// global ordering should sort by `EventRecord::serial`.
+    ///
+    /// Flushes from multiple threads are serialized. Note that `out` may well
+    /// allocate, so events caused by flushing end up in the log too.
+    ///
+    /// Records are only given back once they've been written in full. If
+    /// writing fails partway through a record, the next flush writes the rest of
+    /// it first, so it should go to the same writer to keep the log readable.
+    pub fn flush(&self, out: &mut impl io::Write) -> io::Result<usize> {
+        while self
+            .flushing
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::thread::yield_now();
+        }
+        let result = self.flush_locked(out);
+        self.flushing.store(false, Ordering::Release);
+        result
+    }
+
+    fn flush_locked(&self, out: &mut impl io::Write) -> io::Result<usize> {
+        let mut written = 0;
+        let mut batch = [0; BATCH * RECORD_SIZE];
+        for buffer in &self.buffers {
+            if buffer.owner.load(Ordering::Acquire) == 0 {
+                continue;
+            }
+            let head = buffer.head.load(Ordering::Acquire);
+            let mut tail = buffer.tail.load(Ordering::Relaxed);
+            while tail != head {
+                let records = head.wrapping_sub(tail).min(BATCH);
+                for i in 0..records {
+                    let record = unsafe { (*buffer.records.get())[tail.wrapping_add(i) % RECORDS] };
+                    batch[i * RECORD_SIZE..][..RECORD_SIZE].copy_from_slice(&record.encode());
+                }
+                let batch = &batch[..records * RECORD_SIZE];
+                let mut offset = buffer.written.load(Ordering::Relaxed);
+                let result = loop {
+                    if offset == batch.len() {
+                        break Ok(());
+                    }
+                    match out.write(&batch[offset..]) {
+                        Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                        Ok(n) => offset += n,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => break Err(e),
+                    }
+                };
+                // Slots are only given back once their records are written in
+                // full, and a record cut off by a failed write is finished by the
+                // next flush, rather than written again.
+                let done = offset / RECORD_SIZE;
+                tail = tail.wrapping_add(done);
+                buffer.tail.store(tail, Ordering::Release);
+                buffer
+                    .written
+                    .store(offset % RECORD_SIZE, Ordering::Relaxed);
+                written += done;
+                result?;
+            }
+        }
+        out.flush()?;
+        Ok(written)
+    }
+
+    /// The buffer owned by the current thread, claiming one if necessary.
+    fn buffer(&self) -> Option<&ThreadBuffer<RECORDS>> {
+        if self.buffers.is_empty() {
+            return None;
+        }
+        let token = thread_token();
+        let start = token % THREADS;
+        let slots = || (0..THREADS).map(|i| &self.buffers[(start + i) % THREADS]);
+        if let Some(buffer) = slots().find(|b| b.owner.load(Ordering::Relaxed) == token) {
+            return Some(buffer);
+        }
+        slots().find(|b| {
+            b.owner
+                .compare_exchange(0, token, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        })
+    }
+}
+
+impl<const THREADS: usize, const RECORDS: usize> Default for EventLogMonitor<THREADS, RECORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const THREADS: usize, const RECORDS: usize> AllocMonitor
+    for EventLogMonitor<THREADS, RECORDS>
+{
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        if !self.mask.matches(&action) {
+            return;
+        }
+        let buffer = match self.buffer() {
+            Some(buffer) if RECORDS > 0 => buffer,
+            _ => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        let head = buffer.head.load(Ordering::Relaxed);
+        let tail = buffer.tail.load(Ordering::Acquire);
+        if head.wrapping_sub(tail) >= RECORDS {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let record = EventRecord::new(layout, action);
+        unsafe { (*buffer.records.get())[head % RECORDS] = record };
+        buffer.head.store(head.wrapping_add(1), Ordering::Release);
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("EventLogMonitor", Overhead::ThreadLocal)
+            .param("threads", THREADS as u64)
+            .param("records", RECORDS as u64)
+    }
+}
+
+/// Reads back the records of a log written by `EventLogMonitor`.
+pub struct LogReader<R: io::Read> {
+    input: R,
+}
+
+impl<R: io::Read> LogReader<R> {
+    /// Checks the log header and returns a reader positioned at the first record.
+    pub fn new(input: R) -> io::Result<Self> {
+        Self::with_magic(input, &LOG_MAGIC, "not an interloc event log")
+    }
+
+    /// Checks that the input starts with `expected`, failing with `message` if it
+    /// doesn't, for other formats made of records.
+    pub(crate) fn with_magic(
+        mut input: R,
+        expected: &[u8; 8],
+        message: &'static str,
+    ) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if magic != *expected {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(Self { input })
+    }
+
+    /// Reads the next record, or returns `None` at the end of the log.
+    pub fn next_record(&mut self) -> io::Result<Option<EventRecord>> {
+        let mut bytes = [0; RECORD_SIZE];
+        let mut filled = 0;
+        while filled < RECORD_SIZE {
+            match self.input.read(&mut bytes[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        EventRecord::decode(&bytes).map(Some)
+    }
+}
+
+impl<R: io::Read> Iterator for LogReader<R> {
+    type Item = io::Result<EventRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
@@ -0,0 +1,218 @@
+use crate::alloc::{AllocAction, AllocMonitor, EventMask};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::event::EventRecord;
+use crate::rings::ThreadRings;
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+/// What an `EventQueueMonitor` does with an event when its thread's ring is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FullPolicy {
+    /// Drop the new event, keeping everything already queued.
+    DropNewest,
+    /// Discard the oldest queued event to make room for the new one, so the
+    /// queue always holds the most recent events.
+    DropOldest,
+    /// Spin until the consumer makes room, for at most the given number of
+    /// iterations, and drop the new event if it still doesn't fit.
+    ///
+    /// This is meant for lossless capture in tests, and is dangerous in
+    /// production: every allocation on a thread with a full ring stalls until
+    /// the consumer catches up. Allocations made by the consumer itself, while
+    /// it's polling, always spin for the full count once its own ring fills up.
+    BlockSpin(u32),
+}
+
+/// Hands every event to a consumer running on another thread, through
+/// pre-allocated per-thread rings.
+///
+/// Each of up to `THREADS` threads claims a ring of `RECORDS` records the first
+/// time it sees an event, and is the only producer for that ring. Rings aren't
+/// given back when threads exit. The events are taken out by an
+/// `EventConsumer`, which is polled from a thread of the user's choosing.
+///
+/// What happens when a thread's ring is full is up to the `FullPolicy`, which
+/// defaults to `DropNewest`. Every event that's dropped increments the ring's
+/// drop counter, and every queued event that's discarded to make room
+/// increments `overwritten`. Events of threads that couldn't claim a ring are
+/// always dropped, and counted in `unclaimed_drops`. Events outside the
+/// monitor's `EventMask` are ignored before any of that, and aren't counted
+/// anywhere.
+///
+/// Note that the rings are stored inline, so this struct is about
+/// `THREADS * RECORDS * 64` bytes large; it's meant to be put in a static.
+pub struct EventQueueMonitor<const THREADS: usize, const RECORDS: usize> {
+    rings: ThreadRings<THREADS, RECORDS>,
+    policy: FullPolicy,
+    mask: EventMask,
+    unclaimed: AtomicU64,
+    overwritten: AtomicU64,
+    max_spin: AtomicU32,
+    consuming: AtomicBool,
+}
+
+impl<const THREADS: usize, const RECORDS: usize> EventQueueMonitor<THREADS, RECORDS> {
+    pub const fn new() -> Self {
+        Self {
+            rings: ThreadRings::new(),
+            policy: FullPolicy::DropNewest,
+            mask: EventMask::ALL,
+            unclaimed: AtomicU64::new(0),
+            overwritten: AtomicU64::new(0),
+            max_spin: AtomicU32::new(0),
+            consuming: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets what happens to events when their thread's ring is full.
+    pub const fn full_policy(mut self, policy: FullPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Only queues events of the kinds in `mask`.
+    pub const fn mask(mut self, mask: EventMask) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// The policy for events that don't fit.
+    pub fn policy(&self) -> FullPolicy {
+        self.policy
+    }
+
+    /// The consumer of the queue, or `None` if there already is one. Dropping the
+    /// consumer lets another one be taken.
+    pub fn consumer(&self) -> Option<EventConsumer<'_, THREADS, RECORDS>> {
+        self.consuming
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        Some(EventConsumer { queue: self })
+    }
+
+    /// Number of events that couldn't be queued, over all threads.
+    pub fn dropped(&self) -> u64 {
+        let rings: u64 = self.rings.claimed().map(|r| r.dropped()).sum();
+        rings + self.unclaimed_drops()
+    }
+
+    /// Number of events dropped because their thread couldn't claim a ring.
+    pub fn unclaimed_drops(&self) -> u64 {
+        self.unclaimed.load(Ordering::Relaxed)
+    }
+
+    /// Calls `f` with the `thread_token` of each thread that has a ring, and the
+    /// number of its events that were dropped because the ring was full.
+    pub fn thread_drops(&self, mut f: impl FnMut(usize, u64)) {
+        for ring in self.rings.claimed() {
+            f(ring.owner(), ring.dropped());
+        }
+    }
+
+    /// Number of queued events that were discarded to make room for newer ones,
+    /// under `FullPolicy::DropOldest`.
+    pub fn overwritten(&self) -> u64 {
+        self.overwritten.load(Ordering::Relaxed)
+    }
+
+    /// The most iterations a single event has spun waiting for room, under
+    /// `FullPolicy::BlockSpin`.
+    pub fn max_spin(&self) -> u32 {
+        self.max_spin.load(Ordering::Relaxed)
+    }
+
+    /// Number of events waiting to be consumed.
+    pub fn pending(&self) -> usize {
+        self.rings.claimed().map(|r| r.len()).sum()
+    }
+}
+
+impl<const THREADS: usize, const RECORDS: usize> Default for EventQueueMonitor<THREADS, RECORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const THREADS: usize, const RECORDS: usize> AllocMonitor
+    for EventQueueMonitor<THREADS, RECORDS>
+{
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        if !self.mask.matches(&action) {
+            return;
+        }
+        let ring = match self.rings.ring() {
+            Some(ring) => ring,
+            None => {
+                self.unclaimed.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        let record = EventRecord::new(layout, action);
+        match self.policy {
+            FullPolicy::DropNewest => {
+                if !ring.push(&record) {
+                    ring.count_drop();
+                }
+            }
+            FullPolicy::DropOldest => {
+                if ring.push_overwrite(&record) {
+                    self.overwritten.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            FullPolicy::BlockSpin(max) => {
+                let mut spins = 0;
+                while ring.is_full() && spins < max {
+                    core::hint::spin_loop();
+                    spins += 1;
+                }
+                if spins > 0 {
+                    self.max_spin.fetch_max(spins, Ordering::Relaxed);
+                }
+                if !ring.push(&record) {
+                    ring.count_drop();
+                }
+            }
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("EventQueueMonitor", Overhead::ThreadLocal)
+            .param("threads", THREADS as u64)
+            .param("records", RECORDS as u64)
+    }
+}
+
+/// The consuming end of an `EventQueueMonitor`.
+pub struct EventConsumer<'a, const THREADS: usize, const RECORDS: usize> {
+    queue: &'a EventQueueMonitor<THREADS, RECORDS>,
+}
+
+impl<const THREADS: usize, const RECORDS: usize> EventConsumer<'_, THREADS, RECORDS> {
+    /// Takes every event queued so far and passes it to `f`, returning the
+    /// number of events taken. Events are taken thread by thread, so callers that
+    /// need a global ordering should sort by `EventRecord::serial`.
+    ///
+    /// Note that events caused by `f` are queued too, and are taken by the next
+    /// poll.
+    pub fn poll(&mut self, f: &mut impl FnMut(EventRecord)) -> usize {
+        let mut taken = 0;
+        for ring in self.queue.rings.claimed() {
+            // Only take what's already there, so a busy producer, or `f` itself,
+            // can't keep the poll going forever.
+            for _ in 0..ring.len() {
+                match ring.pop() {
+                    Some(record) => f(record),
+                    None => break,
+                }
+                taken += 1;
+            }
+        }
+        taken
+    }
+}
+
+impl<const THREADS: usize, const RECORDS: usize> Drop for EventConsumer<'_, THREADS, RECORDS> {
+    fn drop(&mut self) {
+        self.queue.consuming.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::monitor::AllocInfo;
+
+/// An `AllocInfo` laid out for C, with the same fields in the same order, for
+/// reading statistics from other languages. `include/interloc.h` declares it,
+/// along with `interloc_global_info` of the `ffi` feature, and is kept in sync
+/// with `cbindgen --config cbindgen.toml --output include/interloc.h`.
+///
+/// Changing the fields means regenerating the header:
+///
+/// ```rust
+/// use core::mem::{offset_of, size_of};
+/// use interloc::AllocInfoC;
+///
+/// let header = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/include/interloc.h"));
+///
+/// // Fails to compile if a field is added or renamed.
+/// let AllocInfoC { alloc: _, dealloc: _, realloc: _, bytes_alloc: _, bytes_dealloc: _, peak_bytes: _,
+///     baseline_bytes: _ } =
+///     AllocInfoC::default();
+/// let fields = [
+///     ("alloc", offset_of!(AllocInfoC, alloc)),
+///     ("dealloc", offset_of!(AllocInfoC, dealloc)),
+///     ("realloc", offset_of!(AllocInfoC, realloc)),
+///     ("bytes_alloc", offset_of!(AllocInfoC, bytes_alloc)),
+///     ("bytes_dealloc", offset_of!(AllocInfoC, bytes_dealloc)),
+///     ("peak_bytes", offset_of!(AllocInfoC, peak_bytes)),
+///     ("baseline_bytes", offset_of!(AllocInfoC, baseline_bytes)),
+/// ];
+///
+/// // What cbindgen generates for it.
+/// let mut expected = String::from("typedef struct AllocInfoC {\n");
+/// for (i, (name, offset)) in fields.iter().enumerate() {
+///     assert_eq!(*offset, i * 8);
+///     expected += &format!("  uint64_t {};\n", name);
+/// }
+/// expected += "} AllocInfoC;\n";
+/// assert_eq!(size_of::<AllocInfoC>(), fields.len() * 8);
+/// assert!(header.contains(&expected), "include/interloc.h is out of date");
+/// assert!(header.contains("int32_t interloc_global_info(AllocInfoC *out);\n"));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct AllocInfoC {
+    pub alloc: u64,
+    pub dealloc: u64,
+    pub realloc: u64,
+    pub bytes_alloc: u64,
+    pub bytes_dealloc: u64,
+    pub peak_bytes: u64,
+    pub baseline_bytes: u64,
+}
+
+impl AllocInfo {
+    /// The same statistics, laid out for C.
+    pub const fn to_c(&self) -> AllocInfoC {
+        AllocInfoC {
+            alloc: self.alloc,
+            dealloc: self.dealloc,
+            realloc: self.realloc,
+            bytes_alloc: self.bytes_alloc,
+            bytes_dealloc: self.bytes_dealloc,
+            peak_bytes: self.peak_bytes,
+            baseline_bytes: self.baseline_bytes,
+        }
+    }
+}
+
+impl From<AllocInfoC> for AllocInfo {
+    fn from(info: AllocInfoC) -> Self {
+        Self {
+            alloc: info.alloc,
+            dealloc: info.dealloc,
+            realloc: info.realloc,
+            bytes_alloc: info.bytes_alloc,
+            bytes_dealloc: info.bytes_dealloc,
+            peak_bytes: info.peak_bytes,
+            baseline_bytes: info.baseline_bytes,
+        }
+    }
+}
+
+/// Writes `global_info` to `out` and returns 0, or returns 1 and leaves `out`
+/// alone if there's no global monitor to read, or 2 if `out` is null. Exported
+/// unmangled, for programs and agents in other languages that link or load the
+/// Rust binary, using the declaration in `include/interloc.h`:
+///
+/// ```c
+/// #include <stdio.h>
+/// #include "interloc.h"
+///
+/// void report(void) {
+///     AllocInfoC info;
+///     if (interloc_global_info(&info) == 0) {
+///         printf("%llu bytes live\n",
+///                (unsigned long long)(info.bytes_alloc - info.bytes_dealloc));
+///     }
+/// }
+/// ```
+///
+/// It reads the monitor declared with `global!`, without allocating:
+///
+/// ```rust
+/// use interloc::{AllocInfoC, StatsMonitor};
+/// use std::alloc::System;
+///
+/// interloc::global!(System, StatsMonitor::new());
+///
+/// let mut info = AllocInfoC::default();
+/// let live = std::hint::black_box(vec![0u8; 100]);
+/// assert_eq!(unsafe { interloc::interloc_global_info(&mut info) }, 0);
+/// assert_eq!(info, monitor().info().to_c());
+/// assert_eq!(unsafe { interloc::interloc_global_info(core::ptr::null_mut()) }, 2);
+/// # drop(live);
+/// ```
+///
+/// # Safety
+/// `out` has to be null, or valid for writing an `AllocInfoC`.
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub unsafe extern "C" fn interloc_global_info(out: *mut AllocInfoC) -> i32 {
+    if out.is_null() {
+        return 2;
+    }
+    match crate::global::global_info() {
+        Some(info) => {
+            out.write(info.to_c());
+            0
+        }
+        None => 1,
+    }
+}
@@ -0,0 +1,166 @@
+// The hook is never called with the `disabled` feature.
+#![cfg_attr(feature = "disabled", allow(dead_code))]
+
+use crate::alloc::{suppress, AllocMonitor};
+use crate::fmt::FmtBuffer;
+use core::cell::Cell;
+use core::fmt::Write as _;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+use std::io::Write as _;
+
+/// The slots of `FIRST_EVENTS` go from empty, to claimed while a thread writes
+/// the monitor into them, to running while it calls the hook, to done.
+const SLOT_EMPTY: u8 = 0;
+const SLOT_CLAIMED: u8 = 1;
+const SLOT_RUNNING: u8 = 2;
+const SLOT_DONE: u8 = 3;
+
+/// A monitor whose `on_first_event` hook has been, or is being, called. Slots
+/// are claimed in order and never freed, so the first empty one ends the search.
+struct FirstEvent {
+    state: AtomicU8,
+    /// The monitor's address
+    monitor: AtomicUsize,
+    /// The address and length of the monitor's type name, to tell apart
+    /// monitors at the same address, like zero-sized ones or those a monitor
+    /// starts with. Names are compared rather than their addresses, or those of
+    /// the hooks, neither of which is sure to be the same for every event.
+    name: AtomicPtr<u8>,
+    name_len: AtomicUsize,
+}
+
+impl FirstEvent {
+    /// Whether the slot, claimed by now, holds the monitor at `address` named
+    /// `name`.
+    fn holds(&self, address: usize, name: &str) -> bool {
+        if self.monitor.load(Ordering::Relaxed) != address
+            || self.name_len.load(Ordering::Relaxed) != name.len()
+        {
+            return false;
+        }
+        let held = self.name.load(Ordering::Relaxed) as *const u8;
+        // Stored from a `&'static str` of this length.
+        held == name.as_ptr()
+            || unsafe { core::slice::from_raw_parts(held, name.len()) } == name.as_bytes()
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const FIRST_EVENT: FirstEvent = FirstEvent {
+    state: AtomicU8::new(SLOT_EMPTY),
+    monitor: AtomicUsize::new(0),
+    name: AtomicPtr::new(core::ptr::null_mut()),
+    name_len: AtomicUsize::new(0),
+};
+
+/// How many monitors `InterAlloc` can call `on_first_event` for.
+const FIRST_EVENT_SLOTS: usize = 64;
+
+static FIRST_EVENTS: [FirstEvent; FIRST_EVENT_SLOTS] = [FIRST_EVENT; FIRST_EVENT_SLOTS];
+
+/// Whether a monitor didn't fit in `FIRST_EVENTS`.
+static OVERFLOWED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The address and type name address of the last monitor this thread found
+    /// done, or gave up on, so that the events after the first skip the search.
+    static SEEN: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+/// Whether a monitor's `on_first_event` hook wasn't called because more than
+/// 64 monitors had been seen by an `InterAlloc` already. The first time that
+/// happens, it's also printed to stderr. Always false with the `disabled`
+/// feature.
+pub fn first_event_overflowed() -> bool {
+    OVERFLOWED.load(Ordering::Relaxed)
+}
+
+/// Reports that the hook of `F` won't be called.
+#[cold]
+fn overflowed<F: ?Sized>() {
+    if OVERFLOWED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let mut buf = FmtBuffer::<256>::new();
+    let _ = writeln!(
+        buf,
+        "interloc: more than {} monitors, on_first_event won't be called for {}",
+        FIRST_EVENT_SLOTS,
+        core::any::type_name::<F>()
+    );
+    let _ = std::io::stderr().write_all(buf.as_bytes());
+}
+
+/// Marks a slot done when dropped, so that a panicking hook doesn't leave other
+/// threads waiting forever.
+struct HookDone<'a>(&'a FirstEvent);
+
+impl Drop for HookDone<'_> {
+    fn drop(&mut self) {
+        self.0.state.store(SLOT_DONE, Ordering::Release);
+    }
+}
+
+/// Waits for the slot to leave `state`, which another thread moves it out of.
+fn wait_past(slot: &FirstEvent, state: u8) -> u8 {
+    loop {
+        let current = slot.state.load(Ordering::Acquire);
+        if current != state {
+            return current;
+        }
+        core::hint::spin_loop();
+        std::thread::yield_now();
+    }
+}
+
+/// Calls `monitor.on_first_event` if no thread has yet, and waits for it to
+/// return if another thread is calling it. Must be called with monitoring not
+/// suppressed; the hook runs with it suppressed, so its own allocations return
+/// straight away rather than waiting on themselves.
+#[inline]
+pub(crate) fn first_event<F: AllocMonitor + ?Sized>(monitor: &F) {
+    let address = monitor as *const F as *const u8 as usize;
+    let name = core::any::type_name::<F>();
+    let seen = (address, name.as_ptr() as usize);
+    if SEEN.with(Cell::get) == seen {
+        return;
+    }
+    let mut index = 0;
+    while let Some(slot) = FIRST_EVENTS.get(index) {
+        let mut state = slot.state.load(Ordering::Acquire);
+        if state == SLOT_EMPTY {
+            match slot.state.compare_exchange(
+                SLOT_EMPTY,
+                SLOT_CLAIMED,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    slot.monitor.store(address, Ordering::Relaxed);
+                    slot.name.store(name.as_ptr() as *mut u8, Ordering::Relaxed);
+                    slot.name_len.store(name.len(), Ordering::Relaxed);
+                    slot.state.store(SLOT_RUNNING, Ordering::Release);
+                    let done = HookDone(slot);
+                    suppress(|| monitor.on_first_event());
+                    drop(done);
+                    SEEN.with(|s| s.set(seen));
+                    return;
+                }
+                Err(current) => state = current,
+            }
+        }
+        if state == SLOT_CLAIMED {
+            state = wait_past(slot, SLOT_CLAIMED);
+        }
+        if slot.holds(address, name) {
+            if state == SLOT_RUNNING {
+                wait_past(slot, SLOT_RUNNING);
+            }
+            SEEN.with(|s| s.set(seen));
+            return;
+        }
+        index += 1;
+    }
+    overflowed::<F>();
+    SEEN.with(|s| s.set(seen));
+}
@@ -0,0 +1,191 @@
+use crate::alloc::{suppress, AllocAction, AllocMonitor};
+use crate::callsite::current_location;
+use crate::describe::{MonitorDesc, Overhead};
+use crate::keys::{pack_layout, unpack_layout, Inserted, KeySet, LocationSet};
+use core::alloc::Layout;
+use core::panic::Location;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// What `FirstTimeMonitor` tells allocations apart by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SiteKey {
+    /// The location set with `attributed` or `trace_alloc!`
+    Location(&'static Location<'static>),
+    /// The layout of an allocation made without a location set
+    Layout(Layout),
+}
+
+/// Called the first time a `SiteKey` is seen, with the layout of the allocation.
+pub type FirstTimeHandler = fn(SiteKey, Layout);
+
+/// A monitor that calls a handler the first time each site allocates, and only
+/// counts the allocations after that, e.g. to find out which code paths that
+/// should be allocation-free allocate at all, without a flood of events.
+///
+/// Allocations are told apart by the location set with `attributed` or
+/// `trace_alloc!`, or by their layout when there's none. Locations are compared
+/// by file, line and column, so copies of one count as one. Reallocations count
+/// with their new size. Locations and layouts are each kept in a table of up to
+/// `KEYS`, and the handler set with `on_first`, if any, is called with
+/// monitoring suppressed when a key is added. Allocations whose key is already in the table are
+/// counted in `repeats`, and those whose key found the table full, in
+/// `overflowed`; neither calls the handler.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use interloc::{AllocAction, AllocMonitor, FirstTimeMonitor, SiteKey};
+///
+/// static FIRSTS: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn first(key: SiteKey, _layout: Layout) {
+///     if let SiteKey::Location(location) = key {
+///         assert_eq!(location.file(), file!());
+///     }
+///     FIRSTS.fetch_add(1, Ordering::Relaxed);
+/// }
+///
+/// let monitor = FirstTimeMonitor::<8>::new().on_first(first);
+/// let small = Layout::from_size_align(16, 8).unwrap();
+/// let large = Layout::from_size_align(4096, 8).unwrap();
+/// for _ in 0..3 {
+///     monitor.monitor(small, AllocAction::Alloc);
+///     monitor.monitor(large, AllocAction::Alloc);
+///     interloc::trace_alloc! { monitor.monitor(small, AllocAction::Alloc) };
+/// }
+/// // Growing the small block to the large size is the large layout again.
+/// let ptr = core::ptr::null_mut();
+/// monitor.monitor(small, AllocAction::Realloc { ptr, new_size: 4096 });
+///
+/// assert_eq!(FIRSTS.load(Ordering::Relaxed), 3);
+/// assert_eq!((monitor.distinct(), monitor.repeats()), (3, 7));
+/// let keys: Vec<SiteKey> = monitor.keys().collect();
+/// assert!(keys.contains(&SiteKey::Layout(small)));
+/// assert!(keys.contains(&SiteKey::Layout(large)));
+/// ```
+///
+/// Keys that don't fit are counted every time they're seen:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, FirstTimeMonitor};
+///
+/// let monitor = FirstTimeMonitor::<2>::new();
+/// for _ in 0..2 {
+///     for size in 1..=4 {
+///         let layout = Layout::from_size_align(size, 1).unwrap();
+///         monitor.monitor(layout, AllocAction::Alloc);
+///     }
+/// }
+/// assert_eq!(monitor.distinct(), 2);
+/// assert_eq!((monitor.repeats(), monitor.overflowed()), (2, 4));
+/// ```
+pub struct FirstTimeMonitor<const KEYS: usize = 256> {
+    handler: Option<FirstTimeHandler>,
+    locations: LocationSet<KEYS>,
+    /// Packed layouts seen without a location
+    layouts: KeySet<KEYS>,
+    repeats: AtomicU64,
+    overflowed: AtomicU64,
+}
+
+impl<const KEYS: usize> FirstTimeMonitor<KEYS> {
+    pub const fn new() -> Self {
+        Self {
+            handler: None,
+            locations: LocationSet::new(),
+            layouts: KeySet::new(),
+            repeats: AtomicU64::new(0),
+            overflowed: AtomicU64::new(0),
+        }
+    }
+
+    /// Calls `handler` the first time each key is seen.
+    pub const fn on_first(mut self, handler: FirstTimeHandler) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    /// The keys seen, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = SiteKey> + '_ {
+        let layouts = self
+            .layouts
+            .iter()
+            .map(|key| SiteKey::Layout(unpack_layout(key)));
+        self.locations.iter().map(SiteKey::Location).chain(layouts)
+    }
+
+    /// How many keys have been seen.
+    pub fn distinct(&self) -> usize {
+        self.keys().count()
+    }
+
+    /// How many allocations had a key that had been seen before.
+    pub fn repeats(&self) -> u64 {
+        self.repeats.load(Ordering::Relaxed)
+    }
+
+    /// How many allocations had a key that wasn't in the table, and found it
+    /// full.
+    pub fn overflowed(&self) -> u64 {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+
+    /// Records the key, returning whether it's the first time it was seen.
+    fn record(&self, key: SiteKey) -> bool {
+        let inserted = match key {
+            SiteKey::Location(location) => self.locations.insert(location),
+            SiteKey::Layout(layout) => {
+                pack_layout(layout).map_or(Inserted::Full, |key| self.layouts.insert(key))
+            }
+        };
+        match inserted {
+            Inserted::New(_) => true,
+            Inserted::Seen(_) => {
+                self.repeats.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Inserted::Full => {
+                self.overflowed.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+}
+
+impl<const KEYS: usize> Default for FirstTimeMonitor<KEYS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const KEYS: usize> AllocMonitor for FirstTimeMonitor<KEYS> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let layout = match action {
+            AllocAction::Alloc | AllocAction::AllocZeroed => layout,
+            AllocAction::Realloc { new_size, .. } => {
+                match Layout::from_size_align(new_size, layout.align()) {
+                    Ok(layout) => layout,
+                    Err(_) => return,
+                }
+            }
+            _ => return,
+        };
+        let key = match current_location() {
+            Some(location) => SiteKey::Location(location),
+            None => SiteKey::Layout(layout),
+        };
+        if self.record(key) {
+            if let Some(handler) = self.handler {
+                suppress(|| handler(key, layout));
+            }
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("FirstTimeMonitor", Overhead::Table).param("keys", KEYS as u64)
+    }
+}
@@ -0,0 +1,182 @@
+use core::fmt;
+
+/// A fixed-capacity string that implements `fmt::Write`, for formatting without
+/// allocating. Writes that don't fit return `fmt::Error` and leave the buffer
+/// holding everything written before them.
+pub struct FmtBuffer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FmtBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    /// The text written so far.
+    pub fn as_str(&self) -> &str {
+        // Only whole `str`s are ever copied in, so this is always valid UTF-8.
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    /// The bytes written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for FmtBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for FmtBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > N {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for FmtBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for FmtBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Whether output meant for a terminal should be colored with ANSI escape codes.
+///
+/// ```rust
+/// use interloc::{AllocInfo, ColorMode, Report};
+///
+/// let before = AllocInfo { alloc: 5, bytes_alloc: 2048, ..AllocInfo::new() };
+/// let after = AllocInfo { alloc: 8, bytes_alloc: 2048, bytes_dealloc: 1024, ..before };
+/// let report = |color| {
+///     let report = Report::new().column("before", before).column("after", after);
+///     report.diff_last_two().color(color).to_string()
+/// };
+///
+/// // Growth is red and shrinking green, and unchanged rows aren't colored.
+/// let colored = report(ColorMode::Always);
+/// let lines: Vec<&str> = colored.lines().collect();
+/// assert_eq!(lines[1], "alloc                5        8  \x1b[31m      +3\x1b[0m");
+/// assert_eq!(lines[4], "bytes_alloc    2.0 KiB  2.0 KiB       0 B");
+/// assert_eq!(lines[6], "live_bytes     2.0 KiB  1.0 KiB  \x1b[32m-1.0 KiB\x1b[0m");
+///
+/// let plain = report(ColorMode::Never);
+/// assert!(!plain.contains('\x1b'));
+/// assert_eq!(plain, colored.replace("\x1b[31m", "").replace("\x1b[32m", "").replace("\x1b[0m", ""));
+/// ```
+///
+/// Byte counts round to a tenth of their unit, and move up a unit if that
+/// rounds to 1024 of them:
+///
+/// ```rust
+/// use interloc::{AllocInfo, Report};
+///
+/// let peak = |bytes| {
+///     let info = AllocInfo { peak_bytes: bytes, ..AllocInfo::new() };
+///     let report = Report::new().column("x", info).to_string();
+///     report.lines().last().unwrap().trim_start_matches("peak_bytes").trim().to_owned()
+/// };
+/// assert_eq!(peak(1023), "1023 B");
+/// assert_eq!(peak(1024), "1.0 KiB");
+/// assert_eq!(peak(1023 * 1024), "1023.0 KiB");
+/// assert_eq!(peak((1 << 20) - 1), "1.0 MiB");
+/// assert_eq!(peak((1 << 30) - (1 << 10)), "1.0 GiB");
+/// assert_eq!(peak(u64::MAX), "16.0 EiB");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum ColorMode {
+    /// Color output when standard output is a terminal.
+    Auto,
+    /// Always color output.
+    Always,
+    /// Never color output.
+    #[default]
+    Never,
+}
+
+impl ColorMode {
+    /// Whether output should be colored right now.
+    pub fn enabled(self) -> bool {
+        use std::io::IsTerminal;
+        match self {
+            ColorMode::Auto => std::io::stdout().is_terminal(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+pub(crate) const RED: &str = "\x1b[31m";
+pub(crate) const GREEN: &str = "\x1b[32m";
+pub(crate) const RESET: &str = "\x1b[0m";
+
+/// A number of bytes, displayed in binary units, like `1.5 KiB`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ByteSize(pub u128);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut unit = 0;
+        let mut scale = 1024;
+        while unit + 1 < UNITS.len() && self.0 >= scale * 1024 {
+            unit += 1;
+            scale *= 1024;
+        }
+        let mut tenths = (self.0 * 10 + scale / 2) / scale;
+        // Rounding up can reach the next unit, e.g. 1023.96 KiB.
+        if tenths >= 10 * 1024 && unit + 1 < UNITS.len() {
+            unit += 1;
+            scale *= 1024;
+            tenths = (self.0 * 10 + scale / 2) / scale;
+        }
+        write!(f, "{}.{} {}", tenths / 10, tenths % 10, UNITS[unit])
+    }
+}
+
+/// A signed difference, displayed with a `+` or `-` unless it's zero, and in
+/// binary units if it's a number of bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Signed {
+    pub value: i128,
+    pub bytes: bool,
+}
+
+impl fmt::Display for Signed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = match self.value {
+            v if v < 0 => "-",
+            0 => "",
+            _ => "+",
+        };
+        if self.bytes {
+            write!(f, "{}{}", sign, ByteSize(self.value.unsigned_abs()))
+        } else {
+            write!(f, "{}{}", sign, self.value.unsigned_abs())
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::sites::SymbolizedSite;
+use std::collections::BTreeMap;
+use std::io;
+
+/// What the stacks of a folded profile are weighted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FoldedWeight {
+    /// Bytes allocated
+    #[default]
+    Bytes,
+    /// Number of allocations
+    Count,
+}
+
+/// Writes allocation sites in the folded stacks format read by `inferno` and
+/// `flamegraph.pl`, one `outer;...;inner weight` line per distinct stack.
+///
+/// Frames are written by function name, or as a hex address if they couldn't
+/// be resolved. Sites whose stacks render the same are collapsed into one line,
+/// and lines are written sorted by stack.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FoldedWriter {
+    weight: FoldedWeight,
+}
+
+impl FoldedWriter {
+    /// A writer that weights stacks by bytes allocated.
+    pub const fn new() -> Self {
+        Self {
+            weight: FoldedWeight::Bytes,
+        }
+    }
+
+    /// Sets what stacks are weighted by.
+    pub const fn weight(mut self, weight: FoldedWeight) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Writes the stacks of `sites` to `out`. Sites with no frames or no weight
+    /// are left out.
+    pub fn write(&self, out: &mut impl io::Write, sites: &[SymbolizedSite]) -> io::Result<()> {
+        let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+        for site in sites {
+            let weight = match self.weight {
+                FoldedWeight::Bytes => site.bytes,
+                FoldedWeight::Count => site.count as u64,
+            };
+            if weight == 0 || site.frames.is_empty() {
+                continue;
+            }
+            let mut stack = String::new();
+            for (i, frame) in site.frames.iter().rev().enumerate() {
+                if i != 0 {
+                    stack.push(';');
+                }
+                // Semicolons separate frames, and can show up in names like
+                // `<[u8; 4]>::fmt`.
+                stack.extend(frame.name.chars().map(|c| if c == ';' { ',' } else { c }));
+            }
+            *stacks.entry(stack).or_insert(0) += weight;
+        }
+
+        for (stack, weight) in stacks {
+            writeln!(out, "{} {}", stack, weight)?;
+        }
+        Ok(())
+    }
+}
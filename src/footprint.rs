@@ -0,0 +1,244 @@
+use crate::alloc::internal;
+use crate::fmt::{ByteSize, Signed};
+use crate::monitor::InfoSource;
+use crate::os;
+use core::fmt;
+
+/// The heap bytes a monitor tracks next to the memory the OS says the process
+/// uses. What the OS counts beyond the live heap bytes is fragmentation,
+/// allocator overhead, and memory that isn't from the heap at all, like stacks,
+/// code and memory maps.
+///
+// Miri can't read the resident set from the OS.
+#[cfg_attr(not(any(miri, feature = "disabled")), doc = "```rust")]
+#[cfg_attr(any(miri, feature = "disabled"), doc = "```ignore")]
+/// use interloc::{FootprintReport, InterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, StatsMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// let held = vec![1u8; 64 << 20];
+/// let report = FootprintReport::capture(&MONITOR);
+/// assert!(report.live_bytes >= 64 << 20);
+/// if let Some(rss) = report.rss_bytes {
+///     assert!(rss >= report.live_bytes);
+/// }
+/// println!("{}", report);
+/// drop(held);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FootprintReport {
+    /// Heap bytes live according to the monitor
+    pub live_bytes: u64,
+    /// Most heap bytes live at once according to the monitor
+    pub peak_bytes: u64,
+    /// Resident set size of the process, if it could be read
+    pub rss_bytes: Option<u64>,
+    /// Usable bytes of the live heap blocks, if known, e.g. from
+    /// `UsableSizeInfo::live_usable`
+    pub live_usable_bytes: Option<u64>,
+}
+
+impl FootprintReport {
+    /// Takes a snapshot of `source` and reads the resident set size right after.
+    /// Reading it is slow, so this must not be called from a monitor. It's done
+    /// with monitoring suppressed, so it doesn't show up in the statistics.
+    pub fn capture(source: &impl InfoSource) -> Self {
+        let info = source.info();
+        Self {
+            live_bytes: info.live_bytes(),
+            peak_bytes: info.peak_bytes,
+            rss_bytes: internal(os::rss_bytes).map(|rss| rss as u64),
+            live_usable_bytes: None,
+        }
+    }
+
+    /// Adds the usable bytes of the live heap blocks, which `capture` can't
+    /// know, for a more precise `FragmentationReport`.
+    pub const fn with_live_usable(mut self, bytes: u64) -> Self {
+        self.live_usable_bytes = Some(bytes);
+        self
+    }
+
+    /// Resident bytes beyond the live heap bytes, if the resident set size is
+    /// known.
+    pub fn untracked_bytes(&self) -> Option<u64> {
+        Some(self.rss_bytes?.saturating_sub(self.live_bytes))
+    }
+}
+
+/// One line, like `live 64.0 MiB, peak 64.0 MiB, rss 66.1 MiB (2.1 MiB
+/// untracked)`, with `rss n/a` if it's unknown.
+impl fmt::Display for FootprintReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "live {}, peak {}, ",
+            ByteSize(self.live_bytes as u128),
+            ByteSize(self.peak_bytes as u128)
+        )?;
+        match (self.rss_bytes, self.untracked_bytes()) {
+            (Some(rss), Some(untracked)) => write!(
+                f,
+                "rss {} ({} untracked)",
+                ByteSize(rss as u128),
+                ByteSize(untracked as u128)
+            ),
+            _ => f.write_str("rss n/a"),
+        }
+    }
+}
+
+/// Estimates of how much memory is lost to fragmentation, from a
+/// `FootprintReport`.
+///
+/// External fragmentation is the share of the resident set that isn't in live
+/// blocks, `(rss - live_usable) / rss`: free memory the allocator holds on to
+/// between live blocks, along with everything resident that isn't heap at all.
+/// Internal fragmentation is the share of live blocks that wasn't asked for,
+/// `(live_usable - live_requested) / live_usable`. Without the usable bytes,
+/// internal fragmentation is unknown, and external fragmentation uses the
+/// requested bytes instead, so it counts internal fragmentation too.
+///
+// Miri can't read the resident set from the OS.
+#[cfg_attr(not(any(miri, feature = "disabled")), doc = "```rust")]
+#[cfg_attr(any(miri, feature = "disabled"), doc = "```ignore")]
+/// use interloc::{FootprintReport, FragmentationReport, InterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, StatsMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// let mut blocks: Vec<Option<Box<[u8; 1000]>>> = (0..40_000)
+///     .map(|_| Some(Box::new([1; 1000])))
+///     .collect();
+/// let before = FootprintReport::capture(&MONITOR);
+/// // Every other block is freed, leaving holes that can't be given back.
+/// for block in blocks.iter_mut().step_by(2) {
+///     *block = None;
+/// }
+/// let after = FootprintReport::capture(&MONITOR);
+///
+/// let report = FragmentationReport::new(&after);
+/// assert_eq!(report.internal, None);
+/// if cfg!(all(target_os = "linux", target_env = "gnu")) {
+///     assert!(report.external.unwrap() > 0.3);
+///     let trend = FragmentationReport::trend(&before, &after);
+///     assert!(trend.live_bytes < -(19_000_000));
+///     assert!(trend.external.unwrap() > 0.3);
+/// }
+/// println!("{}", report);
+/// drop(blocks);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FragmentationReport {
+    /// Share of the resident set outside live blocks, from 0 to 1, or `None` if
+    /// the resident set size is unknown
+    pub external: Option<f64>,
+    /// Share of live blocks that wasn't requested, from 0 to 1, or `None` if
+    /// the usable bytes are unknown or nothing is live
+    pub internal: Option<f64>,
+}
+
+impl FragmentationReport {
+    pub fn new(footprint: &FootprintReport) -> Self {
+        let live = footprint.live_usable_bytes.unwrap_or(footprint.live_bytes);
+        let external = match footprint.rss_bytes {
+            Some(rss) if rss != 0 => Some(rss.saturating_sub(live) as f64 / rss as f64),
+            _ => None,
+        };
+        let internal = match footprint.live_usable_bytes {
+            Some(usable) if usable != 0 => {
+                Some(usable.saturating_sub(footprint.live_bytes) as f64 / usable as f64)
+            }
+            _ => None,
+        };
+        Self { external, internal }
+    }
+
+    /// How the footprint and fragmentation changed from `before` to `after`.
+    pub fn trend(before: &FootprintReport, after: &FootprintReport) -> FragmentationTrend {
+        let diff = |before: Option<u64>, after: Option<u64>| Some(after? as i64 - before? as i64);
+        let change = |before: Option<f64>, after: Option<f64>| Some(after? - before?);
+        let (old, new) = (Self::new(before), Self::new(after));
+        FragmentationTrend {
+            live_bytes: after.live_bytes as i64 - before.live_bytes as i64,
+            rss_bytes: diff(before.rss_bytes, after.rss_bytes),
+            live_usable_bytes: diff(before.live_usable_bytes, after.live_usable_bytes),
+            external: change(old.external, new.external),
+            internal: change(old.internal, new.internal),
+        }
+    }
+}
+
+/// Formats a share from 0 to 1 as a percentage, or `n/a`, with a sign if the
+/// second field is set.
+struct Share(Option<f64>, bool);
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(share) if self.1 => write!(f, "{:+.1}%", share * 100.0),
+            Some(share) => write!(f, "{:.1}%", share * 100.0),
+            None => f.write_str("n/a"),
+        }
+    }
+}
+
+/// One line, like `external 48.2%, internal n/a`.
+impl fmt::Display for FragmentationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "external {}, internal {}",
+            Share(self.external, false),
+            Share(self.internal, false)
+        )
+    }
+}
+
+/// The changes between two `FootprintReport`s, as returned by
+/// `FragmentationReport::trend`. Each field is `None` if it's unknown in either
+/// report.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FragmentationTrend {
+    pub live_bytes: i64,
+    pub rss_bytes: Option<i64>,
+    pub live_usable_bytes: Option<i64>,
+    /// Change in external fragmentation, as a share from -1 to 1
+    pub external: Option<f64>,
+    /// Change in internal fragmentation, as a share from -1 to 1
+    pub internal: Option<f64>,
+}
+
+/// One line, like `live -19.1 MiB, rss 0 B, external +48.2%, internal n/a`.
+impl fmt::Display for FragmentationTrend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = |value: i64| Signed {
+            value: value as i128,
+            bytes: true,
+        };
+        write!(f, "live {}, rss ", bytes(self.live_bytes))?;
+        match self.rss_bytes {
+            Some(rss) => write!(f, "{}", bytes(rss))?,
+            None => f.write_str("n/a")?,
+        }
+        write!(
+            f,
+            ", external {}, internal {}",
+            Share(self.external, true),
+            Share(self.internal, true)
+        )
+    }
+}
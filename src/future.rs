@@ -0,0 +1,83 @@
+use crate::bench::measure;
+use crate::monitor::AllocInfo;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// What the polls of an `InstrumentedFuture` allocated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PollAllocStats {
+    /// Number of times the future was polled
+    pub polls: usize,
+    /// Number of polls that allocated or reallocated anything
+    pub allocating_polls: usize,
+    /// Everything allocated over all polls. `peak_bytes` is the highest peak of
+    /// any single poll.
+    pub total: AllocInfo,
+    /// The largest value of each field over single polls
+    pub max_poll: AllocInfo,
+}
+
+impl PollAllocStats {
+    fn add_poll(&mut self, poll: &AllocInfo) {
+        self.polls += 1;
+        if poll.alloc != 0 || poll.realloc != 0 {
+            self.allocating_polls += 1;
+        }
+        self.total.merge(poll);
+        let max = &mut self.max_poll;
+        max.alloc = max.alloc.max(poll.alloc);
+        max.dealloc = max.dealloc.max(poll.dealloc);
+        max.realloc = max.realloc.max(poll.realloc);
+        max.bytes_alloc = max.bytes_alloc.max(poll.bytes_alloc);
+        max.bytes_dealloc = max.bytes_dealloc.max(poll.bytes_dealloc);
+        max.peak_bytes = max.peak_bytes.max(poll.peak_bytes);
+    }
+}
+
+/// A future that counts what each call to its inner future's `poll` allocates,
+/// made with `FutureAllocExt::count_allocs`. It resolves to the output of the
+/// inner future along with the counts.
+///
+/// The counts come from `ThreadMonitor`, so the global allocator has to be an
+/// `InterAlloc` whose monitor includes one. Only allocations made by `poll`
+/// itself are counted, on whichever thread polls it, so this works with any
+/// executor; work the future hands off to other threads isn't counted.
+pub struct InstrumentedFuture<F> {
+    inner: F,
+    stats: PollAllocStats,
+}
+
+impl<F> InstrumentedFuture<F> {
+    /// What the polls so far have allocated.
+    pub fn stats(&self) -> &PollAllocStats {
+        &self.stats
+    }
+}
+
+impl<F: Future> Future for InstrumentedFuture<F> {
+    type Output = (F::Output, PollAllocStats);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The inner future is never moved out of `self`, and `stats` isn't
+        // pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+        let (poll, info) = measure(|| inner.poll(cx));
+        this.stats.add_poll(&info);
+        poll.map(|output| (output, this.stats))
+    }
+}
+
+/// Adds `count_allocs` to every future.
+pub trait FutureAllocExt: Future + Sized {
+    /// Wraps the future so that it counts what each of its polls allocates.
+    fn count_allocs(self) -> InstrumentedFuture<Self> {
+        InstrumentedFuture {
+            inner: self,
+            stats: PollAllocStats::default(),
+        }
+    }
+}
+
+impl<F: Future> FutureAllocExt for F {}
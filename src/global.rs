@@ -0,0 +1,212 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::MonitorDesc;
+use crate::describe::{print_banner, register_global_report, ConfigReport, GlobalReport};
+use crate::monitor::{AllocInfo, InfoSource};
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Returns the monitor of the global allocator declared with `global!`, if it's
+/// an `InfoSource`.
+pub type GlobalSource = fn() -> Option<&'static (dyn InfoSource + Sync)>;
+
+/// The `GlobalSource` of the program, or null until it's registered. Kept as a
+/// pointer rather than an address, so that it can be turned back into a
+/// function.
+static SOURCE: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// The statistics of the global allocator's monitor, if it was declared with
+/// `global!` and its monitor is an `InfoSource`, like `StatsMonitor`. For
+/// library code that wants the process's allocation statistics without knowing
+/// which monitor the application chose.
+///
+/// The monitor is registered when the allocator sees its first event, which
+/// happens before `main`. With the `disabled` feature, or any other global
+/// allocator, this returns `None`:
+///
+/// ```rust
+/// assert_eq!(interloc::global_info(), None);
+/// ```
+pub fn global_info() -> Option<AllocInfo> {
+    let source = SOURCE.load(Ordering::Acquire);
+    if source.is_null() {
+        return None;
+    }
+    let source: GlobalSource = unsafe { core::mem::transmute(source) };
+    Some(source()?.info())
+}
+
+/// The monitor of a global allocator declared with `global!`, which registers
+/// it for `global_info` and `describe` on the first event, and prints the
+/// banner if asked to.
+#[doc(hidden)]
+pub struct GlobalMonitor<M> {
+    pub monitor: M,
+    pub source: GlobalSource,
+    pub report: GlobalReport,
+    pub banner: bool,
+}
+
+impl<M: AllocMonitor> AllocMonitor for GlobalMonitor<M> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        self.monitor.monitor(layout, action);
+    }
+
+    fn on_first_event(&self) {
+        SOURCE.store(self.source as *mut (), Ordering::Release);
+        register_global_report(self.report);
+        self.monitor.on_first_event();
+        if self.banner {
+            print_banner(&(self.report)());
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        self.monitor.describe()
+    }
+
+    fn describe_inner(&self, report: &mut ConfigReport) {
+        self.monitor.describe_inner(report);
+    }
+}
+
+/// Picks `ViaInfoSource` for monitors that are `InfoSource`s and `NoInfoSource`
+/// for the rest, when called as `(&monitor).global_source()`: the first takes
+/// the monitor by reference and is found before the second, which takes it by
+/// double reference, is tried.
+#[doc(hidden)]
+pub trait ViaInfoSource {
+    fn global_source(&'static self) -> Option<&'static (dyn InfoSource + Sync)>;
+}
+
+impl<M: InfoSource + Sync> ViaInfoSource for M {
+    fn global_source(&'static self) -> Option<&'static (dyn InfoSource + Sync)> {
+        Some(self)
+    }
+}
+
+#[doc(hidden)]
+pub trait NoInfoSource {
+    fn global_source(&self) -> Option<&'static (dyn InfoSource + Sync)>;
+}
+
+impl<M> NoInfoSource for &M {
+    fn global_source(&self) -> Option<&'static (dyn InfoSource + Sync)> {
+        None
+    }
+}
+
+/// Declares the global allocator: a static monitor, an `InterAlloc` over it marked
+/// `#[global_allocator]`, and a `pub fn monitor() -> &'static M` to get at the
+/// monitor from anywhere in the crate. The monitor is also registered for
+/// `global_info` and `describe`. It can go in `main.rs` or `lib.rs`, at most
+/// once per program, like any `#[global_allocator]`.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::StatsMonitor;
+/// use std::alloc::System;
+///
+/// interloc::global!(System, StatsMonitor::new());
+///
+/// let before = monitor().info();
+/// drop(std::hint::black_box(vec![0u8; 100]));
+/// assert_eq!(monitor().info().relative_to(&before).bytes_alloc, 100);
+/// assert_eq!(interloc::global_info(), Some(monitor().info()));
+/// ```
+///
+/// The inner allocator is named by its path, so it has to be a unit struct,
+/// like `std::alloc::System` or `tikv_jemallocator::Jemalloc`. The monitor is
+/// a call to one of its constructors, which has to be a `const fn`, and its
+/// type is taken from the path of the call. When the type can't be, e.g. it has
+/// generic arguments, give it before the constructor, separated by `=`. The
+/// accessor can be given another name and visibility, before the rest and
+/// separated by `:`:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::CallsiteMonitor;
+/// use std::alloc::System;
+///
+/// interloc::global!(
+///     pub(crate) fn callsites: System,
+///     CallsiteMonitor<64> = CallsiteMonitor::new()
+/// );
+///
+/// let v = interloc::trace_alloc! { vec![0u8; 100] };
+/// assert_eq!(callsites().callsites()[0].bytes, 100);
+/// // A CallsiteMonitor doesn't keep an AllocInfo.
+/// assert_eq!(interloc::global_info(), None);
+/// # drop(v);
+/// ```
+///
+/// Starting with `banner,` prints the `describe` report to stderr on the first
+/// event, before `main`, as a single line that says which monitors a service
+/// runs with, and how they're configured.
+///
+/// A constructor that isn't `const` is rejected, since it can't initialize a
+/// static:
+///
+/// ```rust,compile_fail,E0015
+/// use interloc::StatsMonitor;
+/// use std::alloc::System;
+///
+/// interloc::global!(System, StatsMonitor = Default::default());
+/// ```
+#[macro_export]
+macro_rules! global {
+    (@emit [$vis:vis $name:ident $banner:literal] $inner:path, $monitor:ty, $init:expr) => {
+        static __INTERLOC_MONITOR: $crate::GlobalMonitor<$monitor> = $crate::GlobalMonitor {
+            monitor: $init,
+            source: || {
+                #[allow(unused_imports)]
+                use $crate::{NoInfoSource as _, ViaInfoSource as _};
+                (&__INTERLOC_MONITOR.monitor).global_source()
+            },
+            report: || $crate::ConfigReport::of(&__INTERLOC_MONITOR.monitor),
+            banner: $banner,
+        };
+
+        #[global_allocator]
+        static __INTERLOC_GLOBAL: $crate::InterAlloc<$inner, $crate::GlobalMonitor<$monitor>> =
+            $crate::InterAlloc {
+                inner: $inner,
+                monitor: &__INTERLOC_MONITOR,
+            };
+
+        /// The monitor of the global allocator.
+        #[allow(dead_code)]
+        $vis fn $name() -> &'static $monitor {
+            &__INTERLOC_MONITOR.monitor
+        }
+    };
+    // Splits the path of the constructor call into the type and the constructor.
+    (@call $accessor:tt $inner:path, [$($ty:ident)*] $ctor:ident ($($args:tt)*) $(,)?) => {
+        $crate::global!(
+            @emit $accessor $inner,
+            $($ty)::*,
+            $($ty)::*::$ctor($($args)*)
+        );
+    };
+    (@call $accessor:tt $inner:path, [$($ty:ident)*] $segment:ident :: $($rest:tt)+) => {
+        $crate::global!(@call $accessor $inner, [$($ty)* $segment] $($rest)+);
+    };
+    (@monitor $accessor:tt $inner:path, $($segment:ident)::+ ($($args:tt)*) $(,)?) => {
+        $crate::global!(@call $accessor $inner, [] $($segment)::+ ($($args)*));
+    };
+    (@monitor $accessor:tt $inner:path, $monitor:ty = $init:expr $(,)?) => {
+        $crate::global!(@emit $accessor $inner, $monitor, $init);
+    };
+    (banner, $vis:vis fn $name:ident : $inner:path, $($monitor:tt)+) => {
+        $crate::global!(@monitor [$vis $name true] $inner, $($monitor)+);
+    };
+    (banner, $inner:path, $($monitor:tt)+) => {
+        $crate::global!(@monitor [pub monitor true] $inner, $($monitor)+);
+    };
+    ($vis:vis fn $name:ident : $inner:path, $($monitor:tt)+) => {
+        $crate::global!(@monitor [$vis $name false] $inner, $($monitor)+);
+    };
+    ($inner:path, $($monitor:tt)+) => {
+        $crate::global!(@monitor [pub monitor false] $inner, $($monitor)+);
+    };
+}
@@ -0,0 +1,366 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use core::alloc::Layout;
+use core::fmt;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The counters of a histogram, one per bucket. It's implemented for arrays of
+/// `AtomicU64`, whose length is the number of buckets, as the `Counts` of a
+/// `Bucketing`.
+pub trait BucketCounts: Sync {
+    /// The number of counters.
+    const LEN: usize;
+    /// Every counter at zero, to build a `HistogramMonitor` from.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: Self;
+
+    fn counts(&self) -> &[AtomicU64];
+}
+
+impl<const N: usize> BucketCounts for [AtomicU64; N] {
+    const LEN: usize = N;
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: Self = [const { AtomicU64::new(0) }; N];
+
+    fn counts(&self) -> &[AtomicU64] {
+        self
+    }
+}
+
+/// How a `HistogramMonitor` splits allocation sizes into buckets. Buckets are
+/// ranges of sizes, one after the other: the first starts at 0, each one ends
+/// where the next one starts, and the last one has no end.
+///
+/// It's implemented by zero-sized types that pick the strategy at compile time:
+/// `LogBuckets`, `LinearBuckets` and `BoundaryBuckets`.
+pub trait Bucketing {
+    /// The number of buckets.
+    const BUCKETS: usize;
+    /// `[AtomicU64; BUCKETS]`, since the number of buckets can't size an array
+    /// by itself.
+    type Counts: BucketCounts;
+
+    /// The bucket that allocations of `size` bytes go in.
+    fn bucket(size: usize) -> usize;
+    /// The smallest size that goes in `bucket`.
+    fn start(bucket: usize) -> usize;
+}
+
+/// Buckets by powers of two: sizes of 0, then sizes from `2^(i - 1)` up to but
+/// not including `2^i` in bucket `i`, with every larger size in the last bucket.
+/// With `N = 64` and 64-bit sizes, every power of two has a bucket of its own.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogBuckets<const N: usize = 64>;
+
+impl<const N: usize> Bucketing for LogBuckets<N> {
+    const BUCKETS: usize = N;
+    type Counts = [AtomicU64; N];
+
+    #[inline]
+    fn bucket(size: usize) -> usize {
+        ((usize::BITS - size.leading_zeros()) as usize).min(N - 1)
+    }
+
+    fn start(bucket: usize) -> usize {
+        match bucket {
+            0 => 0,
+            i => 1usize.checked_shl(i as u32 - 1).unwrap_or(usize::MAX),
+        }
+    }
+}
+
+/// `COUNT` buckets of `WIDTH` bytes each, starting at `START`: bucket `i` holds
+/// the sizes from `START + i * WIDTH` until the next one, except that the first
+/// also holds every size below `START`, and the last every size above it.
+/// Neither `WIDTH` nor `COUNT` can be 0, which doesn't compile:
+///
+/// ```rust,compile_fail,E0080
+/// use interloc::{HistogramMonitor, LinearBuckets};
+///
+/// let monitor = HistogramMonitor::<LinearBuckets<0, 0, 8>>::new();
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LinearBuckets<const START: usize, const WIDTH: usize, const COUNT: usize>;
+
+impl<const START: usize, const WIDTH: usize, const COUNT: usize> Bucketing
+    for LinearBuckets<START, WIDTH, COUNT>
+{
+    const BUCKETS: usize = {
+        assert!(
+            WIDTH > 0 && COUNT > 0,
+            "linear buckets need a width and a count"
+        );
+        COUNT
+    };
+    type Counts = [AtomicU64; COUNT];
+
+    #[inline]
+    fn bucket(size: usize) -> usize {
+        // Through `BUCKETS`, so that a width of 0 fails to compile rather than
+        // dividing by it.
+        (size.saturating_sub(START) / WIDTH).min(Self::BUCKETS - 1)
+    }
+
+    fn start(bucket: usize) -> usize {
+        match bucket {
+            0 => 0,
+            i => START.saturating_add(i.saturating_mul(WIDTH)),
+        }
+    }
+}
+
+/// The boundaries of a `BoundaryBuckets`.
+pub trait Boundaries {
+    /// Where each bucket after the first starts, in increasing order.
+    const BOUNDS: &'static [usize];
+}
+
+/// Buckets between the boundaries of `B`, so `B::BOUNDS.len() + 1` of them,
+/// which `N` has to be. Finding the bucket of a size is a binary search of the
+/// boundaries.
+pub struct BoundaryBuckets<B, const N: usize>(PhantomData<B>);
+
+/// The number of buckets between `bounds`, which have to be increasing.
+const fn boundary_buckets(bounds: &[usize]) -> usize {
+    let mut i = 1;
+    while i < bounds.len() {
+        assert!(
+            bounds[i - 1] < bounds[i],
+            "bucket boundaries must be increasing"
+        );
+        i += 1;
+    }
+    bounds.len() + 1
+}
+
+impl<B: Boundaries, const N: usize> Bucketing for BoundaryBuckets<B, N> {
+    const BUCKETS: usize = boundary_buckets(B::BOUNDS);
+    type Counts = [AtomicU64; N];
+
+    #[inline]
+    fn bucket(size: usize) -> usize {
+        B::BOUNDS.partition_point(|&bound| bound <= size)
+    }
+
+    fn start(bucket: usize) -> usize {
+        match bucket {
+            0 => 0,
+            i => B::BOUNDS[i - 1],
+        }
+    }
+}
+
+/// A bucket of a `HistogramMonitor`: how many allocations had a size from
+/// `start` up to but not including `end`, or with no upper bound if `end` is
+/// `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HistogramBucket {
+    pub start: usize,
+    pub end: Option<usize>,
+    pub count: u64,
+}
+
+/// Shown as a range of sizes in bytes, like `[1024, 2048)` or `[65536, inf)`.
+impl fmt::Display for HistogramBucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "[{}, {})", self.start, end),
+            None => write!(f, "[{}, inf)", self.start),
+        }
+    }
+}
+
+/// A monitor that counts allocations by size, in the buckets of `B`. Sizes are
+/// those asked for, and reallocations count with their new size.
+///
+/// By default, buckets are powers of two, which is coarse for sizes in the
+/// kilobytes; `LinearBuckets` and `BoundaryBuckets` split a range of sizes more
+/// finely. Finding a bucket is a few instructions, or a binary search of the
+/// boundaries for `BoundaryBuckets`, and counting is an atomic increment.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, HistogramMonitor, LinearBuckets, LogBuckets};
+///
+/// fn alloc(monitor: &impl AllocMonitor, size: usize) {
+///     monitor.monitor(Layout::from_size_align(size, 1).unwrap(), AllocAction::Alloc);
+/// }
+///
+/// let log = HistogramMonitor::<LogBuckets>::new();
+/// for size in [0, 1, 2, 3, 4, 1023, 1024, 2047, 2048] {
+///     alloc(&log, size);
+/// }
+/// let counts: Vec<(usize, u64)> = log.buckets().map(|b| (b.start, b.count)).collect();
+/// assert_eq!(&counts[..4], &[(0, 1), (1, 1), (2, 2), (4, 1)]);
+/// assert_eq!((counts[10], counts[11], counts[12]), ((512, 1), (1024, 2), (2048, 1)));
+/// assert_eq!(log.buckets().last().unwrap().end, None);
+///
+/// // Up to 2 KiB, then 1 KiB steps up to 64 KiB, then the rest.
+/// let linear = HistogramMonitor::<LinearBuckets<1024, 1024, 64>>::new();
+/// for size in [0, 1024, 2047, 2048, 65535, 65536, 1 << 20] {
+///     alloc(&linear, size);
+/// }
+/// assert_eq!(linear.count(0), 3);
+/// assert_eq!(linear.count(1), 1);
+/// assert_eq!(linear.count(62), 1);
+/// assert_eq!(linear.count(63), 2);
+/// let buckets: Vec<String> = linear.buckets().map(|b| b.to_string()).collect();
+/// assert_eq!(buckets[0], "[0, 2048)");
+/// assert_eq!(buckets[1], "[2048, 3072)");
+/// assert_eq!(buckets[63], "[65536, inf)");
+/// assert_eq!(linear.total(), 7);
+/// ```
+///
+/// Boundaries of any kind can be given by a type of their own:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, Boundaries, BoundaryBuckets, HistogramMonitor};
+///
+/// struct PageSizes;
+///
+/// impl Boundaries for PageSizes {
+///     const BOUNDS: &'static [usize] = &[64, 4096, 2 << 20];
+/// }
+///
+/// static MONITOR: HistogramMonitor<BoundaryBuckets<PageSizes, 4>> = HistogramMonitor::new();
+///
+/// for size in [63, 64, 4095, 4096, (2 << 20) - 1, 2 << 20] {
+///     let layout = Layout::from_size_align(size, 1).unwrap();
+///     MONITOR.monitor(layout, AllocAction::Alloc);
+/// }
+/// let counts: Vec<u64> = MONITOR.buckets().map(|b| b.count).collect();
+/// assert_eq!(counts, [1, 2, 2, 1]);
+/// assert_eq!(MONITOR.bytes(), 63 + 64 + 4095 + 4096 + (2 << 20) - 1 + (2 << 20));
+///
+/// print!("{}", MONITOR);
+/// ```
+///
+/// The wrong number of buckets for the boundaries doesn't compile, when the
+/// monitor is a static:
+///
+/// ```rust,compile_fail,E0080
+/// use interloc::{Boundaries, BoundaryBuckets, HistogramMonitor};
+///
+/// struct PageSizes;
+///
+/// impl Boundaries for PageSizes {
+///     const BOUNDS: &'static [usize] = &[64, 4096, 2 << 20];
+/// }
+///
+/// static MONITOR: HistogramMonitor<BoundaryBuckets<PageSizes, 3>> = HistogramMonitor::new();
+/// ```
+pub struct HistogramMonitor<B: Bucketing = LogBuckets> {
+    #[cfg(not(feature = "disabled"))]
+    counts: B::Counts,
+    /// Compiled out like the tables of other monitors, and so no buckets
+    #[cfg(feature = "disabled")]
+    counts: PhantomData<B::Counts>,
+    bytes: AtomicU64,
+    bucketing: PhantomData<fn() -> B>,
+}
+
+impl<B: Bucketing> HistogramMonitor<B> {
+    /// # Panics
+    /// Panics, at compile time if used in a `static`, unless `B` has as many
+    /// counters as buckets, and at least one.
+    pub const fn new() -> Self {
+        assert!(
+            B::BUCKETS == <B::Counts as BucketCounts>::LEN,
+            "a histogram needs as many counters as buckets"
+        );
+        assert!(B::BUCKETS > 0, "a histogram needs a bucket");
+        Self {
+            #[cfg(not(feature = "disabled"))]
+            counts: <B::Counts as BucketCounts>::ZERO,
+            #[cfg(feature = "disabled")]
+            counts: PhantomData,
+            bytes: AtomicU64::new(0),
+            bucketing: PhantomData,
+        }
+    }
+
+    /// How many allocations went in `bucket`, or 0 if there's no such bucket.
+    pub fn count(&self, bucket: usize) -> u64 {
+        self.counters()
+            .get(bucket)
+            .map_or(0, |count| count.load(Ordering::Relaxed))
+    }
+
+    /// The buckets, smallest sizes first.
+    pub fn buckets(&self) -> impl Iterator<Item = HistogramBucket> + '_ {
+        let last = B::BUCKETS - 1;
+        self.counters()
+            .iter()
+            .enumerate()
+            .map(move |(i, count)| HistogramBucket {
+                start: B::start(i),
+                end: (i < last).then(|| B::start(i + 1)),
+                count: count.load(Ordering::Relaxed),
+            })
+    }
+
+    /// How many allocations were counted, in every bucket.
+    pub fn total(&self) -> u64 {
+        self.counters()
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// The sizes of the allocations counted, added up.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    #[cfg(not(feature = "disabled"))]
+    fn counters(&self) -> &[AtomicU64] {
+        self.counts.counts()
+    }
+
+    #[cfg(feature = "disabled")]
+    fn counters(&self) -> &[AtomicU64] {
+        &[]
+    }
+}
+
+impl<B: Bucketing> Default for HistogramMonitor<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A table of the buckets that aren't empty, with their ranges and counts.
+impl<B: Bucketing> fmt::Display for HistogramMonitor<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<28} {:>12}", "size", "count")?;
+        for bucket in self.buckets().filter(|bucket| bucket.count != 0) {
+            // Formatted first, since ranges don't pad.
+            let range = bucket.to_string();
+            writeln!(f, "{:<28} {:>12}", range, bucket.count)?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: Bucketing> AllocMonitor for HistogramMonitor<B> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let size = match action {
+            AllocAction::Alloc | AllocAction::AllocZeroed => layout.size(),
+            AllocAction::Realloc { new_size, .. } => new_size,
+            _ => return,
+        };
+        if let Some(count) = self.counters().get(B::bucket(size)) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+        self.bytes.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("HistogramMonitor", Overhead::Atomic).param("buckets", B::BUCKETS as u64)
+    }
+}
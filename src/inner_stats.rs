@@ -0,0 +1,256 @@
+use crate::alloc::{internal, AllocMonitor, ScopedInterAlloc};
+use crate::arena::ArenaAlloc;
+use crate::fmt::ByteSize;
+use crate::monitor::{AllocInfo, InfoSource};
+use core::alloc::GlobalAlloc;
+use core::fmt;
+use std::alloc::System;
+
+/// Statistics that an inner allocator keeps about itself, as reported by
+/// `InnerStats`. A field is `None` when the allocator doesn't keep that number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct InnerStatsReport {
+    /// Bytes in blocks the allocator has handed out, by its own count
+    pub allocated: Option<u64>,
+    /// Bytes the allocator holds in physical memory, for its blocks, free
+    /// space and metadata together
+    pub resident: Option<u64>,
+    /// Bytes the allocator uses for its own bookkeeping
+    pub metadata: Option<u64>,
+}
+
+impl InnerStatsReport {
+    pub const fn new() -> Self {
+        Self {
+            allocated: None,
+            resident: None,
+            metadata: None,
+        }
+    }
+}
+
+/// Formats a byte count, or `n/a`.
+struct MaybeBytes(Option<u64>);
+
+impl fmt::Display for MaybeBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(bytes) => write!(f, "{}", ByteSize(bytes as u128)),
+            None => f.write_str("n/a"),
+        }
+    }
+}
+
+/// One line, like `allocated 12.0 MiB, resident 16.4 MiB, metadata 1.2 MiB`.
+impl fmt::Display for InnerStatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "allocated {}, resident {}, metadata {}",
+            MaybeBytes(self.allocated),
+            MaybeBytes(self.resident),
+            MaybeBytes(self.metadata)
+        )
+    }
+}
+
+/// An allocator that can report statistics of its own, next to what a monitor
+/// counts, e.g. jemalloc through `mallctl`.
+///
+/// Implemented for `tikv_jemallocator::Jemalloc` with the `jemalloc` feature,
+/// and `mimalloc::MiMalloc` with the `mimalloc` feature. `ArenaAlloc` reports
+/// the bytes it has handed out, and `System` doesn't keep any statistics.
+/// Reading them can be slow and may allocate, so this mustn't be called from a
+/// monitor.
+pub trait InnerStats {
+    /// The allocator's statistics, or `None` if it doesn't keep any.
+    fn inner_stats(&self) -> Option<InnerStatsReport>;
+}
+
+impl<T: InnerStats + ?Sized> InnerStats for &T {
+    fn inner_stats(&self) -> Option<InnerStatsReport> {
+        (**self).inner_stats()
+    }
+}
+
+impl InnerStats for System {
+    fn inner_stats(&self) -> Option<InnerStatsReport> {
+        None
+    }
+}
+
+impl<const N: usize> InnerStats for ArenaAlloc<N> {
+    fn inner_stats(&self) -> Option<InnerStatsReport> {
+        Some(InnerStatsReport {
+            allocated: Some(self.used() as u64),
+            ..InnerStatsReport::new()
+        })
+    }
+}
+
+/// Reads jemalloc's `stats.allocated`, `stats.resident` and `stats.metadata`,
+/// after advancing its epoch so they're current. They're only kept if
+/// jemalloc was built with statistics, which the `jemalloc` feature makes sure
+/// of.
+///
+/// ```rust
+/// use interloc::{InterAlloc, StatsMonitor};
+/// use tikv_jemallocator::Jemalloc;
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<Jemalloc, StatsMonitor> = InterAlloc {
+///     inner: Jemalloc,
+///     monitor: &MONITOR,
+/// };
+///
+/// let blocks: Vec<Vec<u8>> = (0..1000).map(|i| vec![1; 1000 + i]).collect();
+/// let report = GLOBAL.full_report();
+/// let inner = report.inner.unwrap();
+/// let (allocated, resident) = (inner.allocated.unwrap(), inner.resident.unwrap());
+/// // jemalloc rounds sizes up, and sees allocations from before main.
+/// assert!(allocated >= report.info.live_bytes());
+/// assert!(allocated < 2 * report.info.live_bytes() + (1 << 20));
+/// assert!(resident >= allocated);
+/// assert!(inner.metadata.unwrap() > 0);
+/// drop(blocks);
+/// ```
+#[cfg(feature = "jemalloc")]
+impl InnerStats for tikv_jemallocator::Jemalloc {
+    fn inner_stats(&self) -> Option<InnerStatsReport> {
+        use tikv_jemalloc_ctl::{epoch, stats};
+
+        epoch::advance().ok()?;
+        Some(InnerStatsReport {
+            allocated: stats::allocated::read().ok().map(|bytes| bytes as u64),
+            resident: stats::resident::read().ok().map(|bytes| bytes as u64),
+            metadata: stats::metadata::read().ok().map(|bytes| bytes as u64),
+        })
+    }
+}
+
+/// Reads the memory mimalloc has committed from the OS with `mi_process_info`,
+/// as the resident bytes. Committed memory is an upper bound on what's really
+/// resident. mimalloc doesn't count allocated or metadata bytes outside of its
+/// debug builds, so those are `None`.
+///
+/// ```rust
+/// use interloc::{InterAlloc, StatsMonitor};
+/// use mimalloc::MiMalloc;
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<MiMalloc, StatsMonitor> = InterAlloc {
+///     inner: MiMalloc,
+///     monitor: &MONITOR,
+/// };
+///
+/// let blocks: Vec<Vec<u8>> = (0..1000).map(|i| vec![1; 1000 + i]).collect();
+/// let report = GLOBAL.full_report();
+/// let inner = report.inner.unwrap();
+/// assert!(inner.resident.unwrap() >= report.info.live_bytes());
+/// assert_eq!(inner.allocated, None);
+/// drop(blocks);
+/// ```
+#[cfg(feature = "mimalloc")]
+impl InnerStats for mimalloc::MiMalloc {
+    fn inner_stats(&self) -> Option<InnerStatsReport> {
+        let (mut elapsed, mut user, mut system, mut faults) = (0, 0, 0, 0);
+        let (mut rss, mut peak_rss, mut commit, mut peak_commit) = (0, 0, 0, 0);
+        unsafe {
+            libmimalloc_sys::mi_process_info(
+                &mut elapsed,
+                &mut user,
+                &mut system,
+                &mut rss,
+                &mut peak_rss,
+                &mut commit,
+                &mut peak_commit,
+                &mut faults,
+            )
+        };
+        Some(InnerStatsReport {
+            resident: Some(commit as u64),
+            ..InnerStatsReport::new()
+        })
+    }
+}
+
+/// The statistics of an `InterAlloc`'s monitor next to those of its inner
+/// allocator, as returned by `InterAlloc::full_report`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FullReport {
+    /// What the monitor counted
+    pub info: AllocInfo,
+    /// What the inner allocator reports, if it keeps statistics
+    pub inner: Option<InnerStatsReport>,
+}
+
+impl FullReport {
+    /// Bytes the inner allocator counts as allocated beyond the live bytes the
+    /// monitor counts, if it counts them: the rounding up to size classes, and
+    /// allocations made with monitoring suppressed.
+    pub fn uncounted_bytes(&self) -> Option<u64> {
+        let allocated = self.inner?.allocated?;
+        Some(allocated.saturating_sub(self.info.live_bytes()))
+    }
+}
+
+/// One line, like `live 10.0 MiB, peak 12.0 MiB, 1000 allocs; inner allocated
+/// 12.0 MiB, resident 16.4 MiB, metadata n/a`, ending in `inner n/a` if the
+/// inner allocator doesn't keep statistics.
+impl fmt::Display for FullReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "live {}, peak {}, {} allocs; ",
+            ByteSize(self.info.live_bytes() as u128),
+            ByteSize(self.info.peak_bytes as u128),
+            self.info.alloc
+        )?;
+        match &self.inner {
+            Some(inner) => write!(f, "inner {}", inner),
+            None => f.write_str("inner n/a"),
+        }
+    }
+}
+
+impl<'a, T, F> ScopedInterAlloc<'a, T, F>
+where
+    T: GlobalAlloc + InnerStats,
+    F: AllocMonitor + InfoSource,
+{
+    /// A snapshot of the monitor's statistics, next to the inner allocator's own.
+    /// The allocator's are read with monitoring suppressed, so reading them
+    /// doesn't show up in the monitor's.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use interloc::{ArenaAlloc, InterAlloc, StatsMonitor};
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static MONITOR: StatsMonitor = StatsMonitor::new();
+    ///
+    /// let alloc = InterAlloc {
+    ///     inner: ArenaAlloc::<4096>::new(),
+    ///     monitor: &MONITOR,
+    /// };
+    /// let layout = Layout::from_size_align(100, 8).unwrap();
+    /// let ptr = unsafe { alloc.alloc(layout) };
+    /// let report = alloc.full_report();
+    /// assert_eq!(report.info.live_bytes(), 100);
+    /// // The arena rounds blocks up to 16 bytes.
+    /// assert_eq!(report.inner.unwrap().allocated, Some(112));
+    /// assert_eq!(report.uncounted_bytes(), Some(12));
+    /// unsafe { alloc.dealloc(ptr, layout) };
+    /// ```
+    pub fn full_report(&self) -> FullReport {
+        let info = self.monitor.info();
+        FullReport {
+            info,
+            inner: internal(|| self.inner.inner_stats()),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::histogram::{Bucketing, HistogramMonitor};
+use crate::monitor::AllocInfo;
+use core::fmt;
+
+impl AllocInfo {
+    /// Writes this snapshot as a flat JSON object, with one key per field.
+    ///
+    /// Only `core::fmt` machinery is used, so nothing is allocated unless `out`
+    /// itself allocates. This makes it usable from places where allocating is
+    /// risky, like a panic hook.
+    ///
+    /// ```rust
+    /// use interloc::{AllocInfo, FmtBuffer};
+    ///
+    /// let info = AllocInfo {
+    ///     alloc: 5,
+    ///     dealloc: 3,
+    ///     realloc: 1,
+    ///     bytes_alloc: 640,
+    ///     bytes_dealloc: 512,
+    ///     peak_bytes: 600,
+    ///     baseline_bytes: 0,
+    /// };
+    /// let mut out = String::new();
+    /// info.write_json(&mut out).unwrap();
+    /// assert_eq!(
+    ///     out,
+    ///     r#"{"alloc":5,"dealloc":3,"realloc":1,"bytes_alloc":640,"bytes_dealloc":512,"peak_bytes":600,"baseline_bytes":0}"#
+    /// );
+    ///
+    /// // The longest object there is fits in 256 bytes.
+    /// let max = AllocInfo {
+    ///     alloc: u64::MAX,
+    ///     dealloc: u64::MAX,
+    ///     realloc: u64::MAX,
+    ///     bytes_alloc: u64::MAX,
+    ///     bytes_dealloc: u64::MAX,
+    ///     peak_bytes: u64::MAX,
+    ///     baseline_bytes: u64::MAX,
+    /// };
+    /// let mut buf = FmtBuffer::<256>::new();
+    /// max.write_json(&mut buf).unwrap();
+    /// assert_eq!(
+    ///     buf.as_str(),
+    ///     concat!(
+    ///         r#"{"alloc":18446744073709551615,"dealloc":18446744073709551615,"#,
+    ///         r#""realloc":18446744073709551615,"bytes_alloc":18446744073709551615,"#,
+    ///         r#""bytes_dealloc":18446744073709551615,"peak_bytes":18446744073709551615,"#,
+    ///         r#""baseline_bytes":18446744073709551615}"#,
+    ///     )
+    /// );
+    /// ```
+    ///
+    /// Written from a panic hook while the global allocator is monitored, it
+    /// doesn't allocate:
+    ///
+    /// ```rust
+    /// use interloc::{FmtBuffer, InterAlloc, StatsMonitor};
+    /// use std::alloc::System;
+    /// use std::sync::Mutex;
+    ///
+    /// static MONITOR: StatsMonitor = StatsMonitor::new();
+    ///
+    /// #[global_allocator]
+    /// static GLOBAL: InterAlloc<System, StatsMonitor> = InterAlloc {
+    ///     inner: System,
+    ///     monitor: &MONITOR,
+    /// };
+    ///
+    /// static REPORT: Mutex<(FmtBuffer<256>, u64)> = Mutex::new((FmtBuffer::new(), u64::MAX));
+    ///
+    /// std::panic::set_hook(Box::new(|_| {
+    ///     let mut report = REPORT.lock().unwrap();
+    ///     let before = MONITOR.info();
+    ///     let written = before.write_json(&mut report.0);
+    ///     let allocs = MONITOR.info().alloc - before.alloc;
+    ///     report.1 = allocs;
+    ///     written.unwrap();
+    /// }));
+    /// let crashed = std::panic::catch_unwind(|| panic!("crash {}", std::process::id()));
+    /// let _ = std::panic::take_hook();
+    /// assert!(crashed.is_err());
+    ///
+    /// let report = REPORT.lock().unwrap();
+    /// assert_eq!(report.1, 0);
+    /// assert!(report.0.as_str().starts_with(r#"{"alloc":"#));
+    /// assert!(report.0.as_str().ends_with('}'));
+    /// ```
+    pub fn write_json(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        write!(
+            out,
+            "{{\"alloc\":{},\"dealloc\":{},\"realloc\":{},\"bytes_alloc\":{},\
+             \"bytes_dealloc\":{},\"peak_bytes\":{},\"baseline_bytes\":{}}}",
+            self.alloc,
+            self.dealloc,
+            self.realloc,
+            self.bytes_alloc,
+            self.bytes_dealloc,
+            self.peak_bytes,
+            self.baseline_bytes,
+        )
+    }
+}
+
+impl<B: Bucketing> HistogramMonitor<B> {
+    /// Writes the buckets as a JSON array, smallest sizes first, with an object
+    /// per bucket holding its `start`, its `end`, or `null` for the last one,
+    /// and its `count`. The sum of the sizes isn't written; see `bytes`.
+    ///
+    /// Like `AllocInfo::write_json`, this doesn't allocate unless `out` does.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, FmtBuffer, HistogramMonitor, LinearBuckets};
+    ///
+    /// let histogram = HistogramMonitor::<LinearBuckets<1024, 1024, 3>>::new();
+    /// for size in [0, 100, 2048, 1 << 20] {
+    ///     let layout = Layout::from_size_align(size, 1).unwrap();
+    ///     histogram.monitor(layout, AllocAction::Alloc);
+    /// }
+    /// let mut buf = FmtBuffer::<256>::new();
+    /// histogram.write_json(&mut buf).unwrap();
+    /// assert_eq!(
+    ///     buf.as_str(),
+    ///     concat!(
+    ///         r#"[{"start":0,"end":2048,"count":2},"#,
+    ///         r#"{"start":2048,"end":3072,"count":1},"#,
+    ///         r#"{"start":3072,"end":null,"count":1}]"#,
+    ///     )
+    /// );
+    /// ```
+    pub fn write_json(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        out.write_char('[')?;
+        for (i, bucket) in self.buckets().enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            write!(out, "{{\"start\":{},\"end\":", bucket.start)?;
+            match bucket.end {
+                Some(end) => write!(out, "{}", end)?,
+                None => out.write_str("null")?,
+            }
+            write!(out, ",\"count\":{}}}", bucket.count)?;
+        }
+        out.write_char(']')
+    }
+}
+
+/// Writes `s` as a quoted JSON string.
+pub(crate) fn write_json_str(out: &mut impl fmt::Write, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
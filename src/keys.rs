@@ -0,0 +1,135 @@
+//! The sets behind the monitors that record each key they see once, which are
+//! empty with the `disabled` feature.
+use crate::callsite::hash_location;
+use crate::slots::Slots;
+use core::alloc::Layout;
+use core::panic::Location;
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+/// What inserting into a `KeySet` or `LocationSet` found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Inserted {
+    /// The key wasn't in the set, and was added at this index
+    New(usize),
+    /// The key was already in the set, at this index
+    Seen(usize),
+    /// The key wasn't in the set, and there was no room for it
+    Full,
+}
+
+/// A set of up to `N` nonzero keys, kept in open addressing slots that are 0
+/// until a key is added. Keys are never removed, so adding one takes a single
+/// compare and swap, and it never allocates or blocks.
+pub(crate) struct KeySet<const N: usize> {
+    keys: Slots<AtomicU64, N>,
+}
+
+impl<const N: usize> KeySet<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            keys: Slots::new([const { AtomicU64::new(0) }; N]),
+        }
+    }
+
+    /// Adds `key`, which must not be 0.
+    pub(crate) fn insert(&self, key: u64) -> Inserted {
+        debug_assert_ne!(key, 0);
+        if self.keys.is_empty() {
+            return Inserted::Full;
+        }
+        let start = start(key, N);
+        for i in 0..N {
+            let index = (start + i) % N;
+            match self.keys[index].compare_exchange(0, key, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Inserted::New(index),
+                Err(seen) if seen == key => return Inserted::Seen(index),
+                Err(_) => {}
+            }
+        }
+        Inserted::Full
+    }
+
+    /// The keys added, in no particular order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.keys
+            .iter()
+            .map(|key| key.load(Ordering::Acquire))
+            .filter(|key| *key != 0)
+    }
+}
+
+/// A set of up to `N` locations, like a `KeySet`, but told apart by their
+/// file, line and column rather than their address, since the same location can
+/// be at several addresses, e.g. in different codegen units. They're kept as
+/// pointers, so they can be read back.
+pub(crate) struct LocationSet<const N: usize> {
+    locations: Slots<AtomicPtr<Location<'static>>, N>,
+}
+
+impl<const N: usize> LocationSet<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            locations: Slots::new([const { AtomicPtr::new(core::ptr::null_mut()) }; N]),
+        }
+    }
+
+    /// Adds `location`, unless an equal location was added before.
+    pub(crate) fn insert(&self, location: &'static Location<'static>) -> Inserted {
+        if self.locations.is_empty() {
+            return Inserted::Full;
+        }
+        let ptr = location as *const Location<'static> as *mut Location<'static>;
+        let start = start(hash_location(location), N);
+        for i in 0..N {
+            let index = (start + i) % N;
+            let slot = &self.locations[index];
+            match slot.compare_exchange(
+                core::ptr::null_mut(),
+                ptr,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Inserted::New(index),
+                // Only ever set from a `&'static Location`.
+                Err(seen) if unsafe { *seen == *location } => return Inserted::Seen(index),
+                Err(_) => {}
+            }
+        }
+        Inserted::Full
+    }
+
+    /// The location added at `index`, if any.
+    pub(crate) fn get(&self, index: usize) -> Option<&'static Location<'static>> {
+        let ptr = self.locations.get(index)?.load(Ordering::Acquire);
+        // Only ever set from a `&'static Location`.
+        unsafe { ptr.as_ref() }
+    }
+
+    /// The locations added, in no particular order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &'static Location<'static>> + '_ {
+        (0..self.locations.len()).filter_map(move |index| self.get(index))
+    }
+}
+
+/// The slot to start looking for `key` from, in a set of `n` slots. Fibonacci
+/// hashing spreads the sizes of a type's arrays out.
+fn start(key: u64, n: usize) -> usize {
+    (key.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as usize % n
+}
+
+/// Packs a layout into an odd, and so nonzero, key: the size, the log of the
+/// alignment in the 6 bits below it, and a 1. Layouts too big to pack don't get
+/// a key.
+pub(crate) fn pack_layout(layout: Layout) -> Option<u64> {
+    let size = layout.size() as u64;
+    if size >> 57 != 0 {
+        return None;
+    }
+    Some((size << 6 | layout.align().trailing_zeros() as u64) << 1 | 1)
+}
+
+/// The layout `pack_layout` packed into `key`.
+pub(crate) fn unpack_layout(key: u64) -> Layout {
+    let key = key >> 1;
+    Layout::from_size_align((key >> 6) as usize, 1 << (key & 63)).unwrap()
+}
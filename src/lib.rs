@@ -46,11 +46,189 @@
 //!     println!("{:#?}", MONITOR.local.info().relative_to(&alloc_info));
 //! }
 //! ```
-extern crate lock_api;
-extern crate parking_lot;
+//!
+//! # Miri
+//! `interloc` can be used as the global allocator in programs run under Miri. Its
+//! own test suite runs clean with:
+//!
+//! ```sh
+//! cargo +nightly miri test
+//! ```
+
+#![cfg_attr(feature = "nightly", feature(alloc_error_hook))]
 
+mod align;
 mod alloc;
+mod alloc_error;
+mod arena;
+#[cfg(feature = "backtrace")]
+mod backtrace_monitor;
+pub mod bench;
+mod broadcast;
+mod calibrate;
+mod callback;
+mod callsite;
+mod census;
+mod clock;
+mod contention;
+mod counted;
+mod counters;
+#[cfg(feature = "criterion")]
+pub mod criterion;
+mod csv;
+mod describe;
+mod dhat;
+mod drop_stats;
+#[cfg(all(windows, feature = "etw"))]
+mod etw;
+mod event;
+mod event_log;
+mod event_queue;
+mod ffi;
+mod first_event;
+mod first_time;
+mod fmt;
+#[cfg(feature = "backtrace")]
+mod folded;
+mod footprint;
+#[cfg(feature = "futures")]
+mod future;
+mod global;
+mod histogram;
+mod inner_stats;
+mod json;
+mod keys;
+mod limit;
+mod live_bytes;
+#[cfg(all(unix, feature = "mirror"))]
+mod lock;
+mod massif;
+#[cfg(all(unix, feature = "mirror"))]
+mod mirror;
+#[cfg(feature = "backtrace")]
+mod module_attribution;
 mod monitor;
+mod monitor_panic;
+pub mod os;
+#[cfg(feature = "otel")]
+pub mod otel;
+#[cfg(all(unix, feature = "page-guard"))]
+mod page_guard;
+pub mod panic;
+#[cfg(feature = "plot")]
+pub mod plot;
+mod pool_bypass;
+#[cfg(feature = "pprof")]
+mod pprof;
+mod prometheus;
+#[cfg(feature = "puffin")]
+pub mod puffin;
+mod rate_limit;
+#[cfg(feature = "rayon")]
+pub mod rayon;
+mod realloc_move;
+mod recent;
+mod redzone;
+mod regression;
+mod report;
+mod rings;
+mod sample;
+mod seqlock;
+#[cfg(all(unix, feature = "signal"))]
+pub mod signal;
+mod sites;
+mod slots;
+mod snapshot;
+#[cfg(feature = "statsd")]
+mod statsd;
+mod sync;
+mod tag;
+mod tag_limit;
+pub mod testing;
+mod thread_filter;
+mod thread_init;
+mod thread_registry;
+mod trace;
+mod tracking;
+#[cfg(feature = "tracy")]
+mod tracy;
+#[cfg(all(target_os = "linux", feature = "usable-size"))]
+mod usable_size;
+#[cfg(all(target_os = "linux", feature = "usdt"))]
+mod usdt;
 
+pub use align::*;
 pub use alloc::*;
+pub use alloc_error::*;
+pub use arena::*;
+#[cfg(feature = "backtrace")]
+pub use backtrace_monitor::*;
+pub use broadcast::*;
+pub use calibrate::*;
+pub use callback::*;
+pub use callsite::*;
+pub use census::*;
+pub use clock::*;
+#[cfg(feature = "self-metrics")]
+pub use contention::ContentionStats;
+pub use counted::*;
+pub use csv::*;
+pub use describe::*;
+pub use dhat::*;
+pub use drop_stats::*;
+#[cfg(all(windows, feature = "etw"))]
+pub use etw::*;
+pub use event::*;
+pub use event_log::*;
+pub use event_queue::*;
+pub use ffi::*;
+pub use first_event::*;
+pub use first_time::*;
+pub use fmt::{ColorMode, FmtBuffer};
+#[cfg(feature = "backtrace")]
+pub use folded::*;
+pub use footprint::*;
+#[cfg(feature = "futures")]
+pub use future::*;
+pub use global::*;
+pub use histogram::*;
+pub use inner_stats::*;
+pub use limit::*;
+pub use live_bytes::*;
+pub use massif::*;
+#[cfg(all(unix, feature = "mirror"))]
+pub use mirror::*;
+#[cfg(feature = "backtrace")]
+pub use module_attribution::*;
 pub use monitor::*;
+pub use monitor_panic::*;
+#[cfg(all(unix, feature = "page-guard"))]
+pub use page_guard::*;
+pub use pool_bypass::*;
+#[cfg(feature = "pprof")]
+pub use pprof::*;
+pub use rate_limit::*;
+pub use realloc_move::*;
+pub use recent::*;
+pub use redzone::*;
+pub use regression::*;
+pub use report::*;
+pub use sample::*;
+pub use seqlock::*;
+pub use sites::*;
+pub use snapshot::*;
+#[cfg(feature = "statsd")]
+pub use statsd::*;
+pub use tag::*;
+pub use tag_limit::*;
+pub use thread_filter::*;
+pub use thread_init::*;
+pub use thread_registry::*;
+pub use trace::*;
+pub use tracking::*;
+#[cfg(feature = "tracy")]
+pub use tracy::*;
+#[cfg(all(target_os = "linux", feature = "usable-size"))]
+pub use usable_size::*;
+#[cfg(all(target_os = "linux", feature = "usdt"))]
+pub use usdt::*;
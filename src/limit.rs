@@ -0,0 +1,181 @@
+use crate::live_bytes::LiveBytes;
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// An allocator that fails allocations that would leave more than `limit` bytes
+/// live in it, and passes the rest to `inner`. The bytes are the sizes of the
+/// layouts asked for, as in `AllocInfo`. Reallocations only need room for what
+/// the block grows by, which is handed back if the inner allocator fails, and a
+/// block that shrinks only frees its bytes once it has.
+///
+/// Failures return null like any other, which the standard library usually
+/// turns into an abort through `handle_alloc_error`, so the limit is mostly for
+/// code that allocates fallibly, like `Vec::try_reserve`, or for tests. Wrap it
+/// in an `InterAlloc` to monitor what gets through.
+///
+/// ```rust
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::{ArenaAlloc, LimitAlloc};
+///
+/// let limited = LimitAlloc::new(ArenaAlloc::<4096>::new(), 1000);
+/// let small = Layout::from_size_align(400, 8).unwrap();
+/// unsafe {
+///     let a = limited.alloc(small);
+///     let b = limited.alloc(small);
+///     assert!(limited.alloc(small).is_null());
+///     assert_eq!((limited.live(), limited.denied()), (800, 1));
+///
+///     // Growing only needs room for the difference.
+///     let a = limited.realloc(a, small, 600);
+///     assert!(!a.is_null());
+///     assert_eq!(limited.live(), 1000);
+///     assert!(limited.realloc(b, small, 401).is_null());
+///
+///     // Shrinking makes room once it's done.
+///     let grown = Layout::from_size_align(600, 8).unwrap();
+///     let a = limited.realloc(a, grown, 100);
+///     assert_eq!(limited.live(), 500);
+///
+///     // When the inner allocator fails a reallocation that fit the limit, the
+///     // growth is given back.
+///     let huge = LimitAlloc::new(ArenaAlloc::<4096>::new(), 1 << 20);
+///     let c = huge.alloc(small);
+///     assert!(huge.realloc(c, small, 8192).is_null());
+///     assert_eq!(huge.live(), 400);
+///     assert_eq!(huge.denied(), 0);
+///
+///     limited.dealloc(a, Layout::from_size_align(100, 8).unwrap());
+///     limited.dealloc(b, small);
+///     huge.dealloc(c, small);
+/// }
+/// assert_eq!((limited.live(), limited.peak()), (0, 1000));
+/// ```
+///
+/// Threads racing for the last bytes can't overshoot the limit together, and
+/// reallocations that fail give back exactly what they reserved:
+///
+/// ```rust
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::LimitAlloc;
+/// use std::alloc::System;
+///
+/// let limited = LimitAlloc::new(System, 4096);
+/// let layout = Layout::from_size_align(512, 8).unwrap();
+/// std::thread::scope(|s| {
+///     for _ in 0..4 {
+///         s.spawn(|| unsafe {
+///             for _ in 0..1000 {
+///                 let ptr = limited.alloc(layout);
+///                 if ptr.is_null() {
+///                     continue;
+///                 }
+///                 // Grows past the limit if other threads hold enough.
+///                 let grown = limited.realloc(ptr, layout, 2048);
+///                 assert!(limited.live() <= 4096);
+///                 if grown.is_null() {
+///                     limited.dealloc(ptr, layout);
+///                 } else {
+///                     limited.dealloc(grown, Layout::from_size_align(2048, 8).unwrap());
+///                 }
+///             }
+///         });
+///     }
+/// });
+/// assert_eq!(limited.live(), 0);
+/// assert!(limited.peak() <= 4096);
+/// ```
+pub struct LimitAlloc<A> {
+    inner: A,
+    limit: u64,
+    live: LiveBytes,
+    denied: AtomicU64,
+}
+
+impl<A> LimitAlloc<A> {
+    pub const fn new(inner: A, limit: u64) -> Self {
+        Self {
+            inner,
+            limit,
+            live: LiveBytes::new(),
+            denied: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// The bytes live in the allocator.
+    pub fn live(&self) -> u64 {
+        self.live.live()
+    }
+
+    /// The most bytes that were live in the allocator at once.
+    pub fn peak(&self) -> u64 {
+        self.live.peak()
+    }
+
+    /// How many allocations and reallocations were failed for the limit.
+    pub fn denied(&self) -> u64 {
+        self.denied.load(Ordering::Relaxed)
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    #[cold]
+    fn deny(&self) -> *mut u8 {
+        self.denied.fetch_add(1, Ordering::Relaxed);
+        core::ptr::null_mut()
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for LimitAlloc<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let size = layout.size() as u64;
+        if !self.live.try_charge(size, self.limit) {
+            return self.deny();
+        }
+        let ptr = self.inner.alloc(layout);
+        if ptr.is_null() {
+            self.live.credit(size);
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let size = layout.size() as u64;
+        if !self.live.try_charge(size, self.limit) {
+            return self.deny();
+        }
+        let ptr = self.inner.alloc_zeroed(layout);
+        if ptr.is_null() {
+            self.live.credit(size);
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.live.credit(layout.size() as u64);
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let charge =
+            match self
+                .live
+                .try_charge_realloc(layout.size() as u64, new_size as u64, self.limit)
+            {
+                Some(charge) => charge,
+                None => return self.deny(),
+            };
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        charge.finish(!new_ptr.is_null());
+        new_ptr
+    }
+}
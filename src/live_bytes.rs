@@ -0,0 +1,264 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::monitor::Accounting;
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A count of live bytes, with its high-water mark, for monitors and allocators
+/// that keep one against a threshold or a limit.
+///
+/// Reallocation is where such counts go wrong, so it has helpers of its own: a
+/// block that grows is only charged the difference, a block that shrinks is only
+/// credited it, and a reallocation that fails changes nothing. Code that can only
+/// see a reallocation after the fact uses `realloc_result`. Code that has to
+/// refuse it beforehand reserves the growth with `try_charge_realloc`, then
+/// commits or rolls back the `ReallocCharge` once it knows how it went.
+///
+/// As a monitor, it counts what's live through the allocator, charging blocks
+/// when they're handed out and crediting them when they're freed, so failed
+/// calls don't count:
+///
+/// ```rust
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, LiveBytes};
+///
+/// let live = LiveBytes::new();
+/// let layout = Layout::from_size_align(1 << 20, 1).unwrap();
+/// let ptr = 0x1000 as *mut u8;
+/// live.monitor(layout, AllocAction::AllocResult { ptr });
+///
+/// // Growing from 1 MiB to 1.5 MiB only charges the difference, once it's done.
+/// let new_size = 3 << 19;
+/// live.monitor(layout, AllocAction::Realloc { ptr, new_size });
+/// assert_eq!(live.live(), 1 << 20);
+/// live.monitor(layout, AllocAction::ReallocResult { ptr, new_size });
+/// assert_eq!(live.live(), 3 << 19);
+///
+/// // A failed reallocation leaves the block as it was.
+/// let grown = Layout::from_size_align(new_size, 1).unwrap();
+/// let null = core::ptr::null_mut();
+/// live.monitor(grown, AllocAction::ReallocResult { ptr: null, new_size: 4 << 20 });
+/// assert_eq!(live.live(), 3 << 19);
+///
+/// // Shrinking credits the difference, and the peak stays where it was.
+/// live.monitor(grown, AllocAction::ReallocResult { ptr, new_size: 100 });
+/// assert_eq!((live.live(), live.peak()), (100, 3 << 19));
+/// ```
+pub struct LiveBytes {
+    live: AtomicU64,
+    peak: AtomicU64,
+    accounting: Accounting,
+}
+
+impl LiveBytes {
+    pub const fn new() -> Self {
+        Self {
+            live: AtomicU64::new(0),
+            peak: AtomicU64::new(0),
+            accounting: Accounting::Requested,
+        }
+    }
+
+    /// Counts the bytes of blocks as `accounting` says when used as a monitor,
+    /// `Accounting::Requested` by default.
+    ///
+    /// ```rust
+    /// use core::alloc::Layout;
+    /// use interloc::{Accounting, AllocAction, AllocMonitor, LiveBytes};
+    ///
+    /// let requested = LiveBytes::new();
+    /// let padded = LiveBytes::new().accounting(Accounting::PaddedToAlign);
+    /// let layout = Layout::from_size_align(1, 64).unwrap();
+    /// let ptr = 0x1000 as *mut u8;
+    /// for live in [&requested, &padded] {
+    ///     live.monitor(layout, AllocAction::AllocResult { ptr });
+    /// }
+    /// assert_eq!(padded.live() - requested.live(), 63);
+    ///
+    /// for live in [&requested, &padded] {
+    ///     live.monitor(layout, AllocAction::ReallocResult { ptr, new_size: 65 });
+    /// }
+    /// assert_eq!((requested.live(), padded.live()), (65, 128));
+    /// ```
+    pub const fn accounting(mut self, accounting: Accounting) -> Self {
+        self.accounting = accounting;
+        self
+    }
+
+    /// How the bytes of blocks are counted when used as a monitor.
+    pub fn accounting_mode(&self) -> Accounting {
+        self.accounting
+    }
+
+    /// The bytes charged and not credited yet.
+    pub fn live(&self) -> u64 {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    /// The most bytes that were live at once.
+    pub fn peak(&self) -> u64 {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Charges `bytes`, whatever the total comes to.
+    #[inline]
+    pub fn charge(&self, bytes: u64) {
+        let live = self.live.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak.fetch_max(live, Ordering::Relaxed);
+    }
+
+    /// Charges `bytes` if that leaves at most `limit` live, returning whether it
+    /// did. Concurrent charges can't overshoot the limit together.
+    #[inline]
+    pub fn try_charge(&self, bytes: u64, limit: u64) -> bool {
+        let charged = self
+            .live
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+                live.checked_add(bytes).filter(|&live| live <= limit)
+            });
+        match charged {
+            Ok(live) => {
+                self.peak.fetch_max(live + bytes, Ordering::Relaxed);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Credits `bytes` that were charged before.
+    #[inline]
+    pub fn credit(&self, bytes: u64) {
+        self.live.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Counts a reallocation from `old_size` to `new_size` bytes that's already
+    /// happened, if it `succeeded`.
+    #[inline]
+    pub fn realloc_result(&self, old_size: u64, new_size: u64, succeeded: bool) {
+        if !succeeded {
+            return;
+        }
+        if new_size > old_size {
+            self.charge(new_size - old_size);
+        } else {
+            self.credit(old_size - new_size);
+        }
+    }
+
+    /// Reserves what a reallocation from `old_size` to `new_size` bytes would
+    /// grow the block by, if that leaves at most `limit` live, before it's
+    /// attempted. A shrinking reallocation always gets a charge, reserving
+    /// nothing, and isn't credited until it's committed, so the bytes it frees
+    /// can't be handed out before they are.
+    ///
+    /// ```rust
+    /// use interloc::LiveBytes;
+    ///
+    /// let live = LiveBytes::new();
+    /// live.charge(600);
+    ///
+    /// // Growing past the limit is refused, and changes nothing.
+    /// assert!(live.try_charge_realloc(600, 2000, 1000).is_none());
+    /// assert_eq!(live.live(), 600);
+    ///
+    /// // The growth is charged until the reallocation is known to have failed.
+    /// let charge = live.try_charge_realloc(600, 900, 1000).unwrap();
+    /// assert_eq!(live.live(), 900);
+    /// charge.rollback();
+    /// assert_eq!(live.live(), 600);
+    ///
+    /// // Shrinking only credits once it's done.
+    /// let charge = live.try_charge_realloc(600, 100, 1000).unwrap();
+    /// assert_eq!(live.live(), 600);
+    /// charge.commit();
+    /// assert_eq!(live.live(), 100);
+    /// ```
+    #[inline]
+    pub fn try_charge_realloc(
+        &self,
+        old_size: u64,
+        new_size: u64,
+        limit: u64,
+    ) -> Option<ReallocCharge<'_>> {
+        if new_size > old_size && !self.try_charge(new_size - old_size, limit) {
+            return None;
+        }
+        Some(ReallocCharge {
+            live: self,
+            old_size,
+            new_size,
+        })
+    }
+}
+
+impl Default for LiveBytes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AllocMonitor for LiveBytes {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let size = self.accounting.bytes(layout.size(), layout.align());
+        match action {
+            AllocAction::AllocResult { ptr } | AllocAction::AllocZeroedResult { ptr }
+                if !ptr.is_null() =>
+            {
+                self.charge(size)
+            }
+            AllocAction::DeallocResult => self.credit(size),
+            AllocAction::ReallocResult { ptr, new_size } => {
+                let new_size = self.accounting.bytes(new_size, layout.align());
+                self.realloc_result(size, new_size, !ptr.is_null())
+            }
+            _ => {}
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        let desc = MonitorDesc::new("LiveBytes", Overhead::Atomic);
+        match self.accounting {
+            Accounting::Requested => desc,
+            Accounting::PaddedToAlign => desc.param("padded_to_align", 1),
+        }
+    }
+}
+
+/// The growth of a block reserved by `LiveBytes::try_charge_realloc`, to be
+/// committed if the reallocation succeeds and rolled back if it fails.
+#[must_use = "the charge has to be committed or rolled back"]
+pub struct ReallocCharge<'a> {
+    live: &'a LiveBytes,
+    old_size: u64,
+    new_size: u64,
+}
+
+impl<'a> ReallocCharge<'a> {
+    /// The reallocation succeeded: keeps what was reserved, and credits what the
+    /// block shrank by.
+    #[inline]
+    pub fn commit(self) {
+        if self.new_size < self.old_size {
+            self.live.credit(self.old_size - self.new_size);
+        }
+    }
+
+    /// The reallocation failed: gives back what was reserved.
+    #[inline]
+    pub fn rollback(self) {
+        if self.new_size > self.old_size {
+            self.live.credit(self.new_size - self.old_size);
+        }
+    }
+
+    /// Commits the charge if `succeeded`, and rolls it back otherwise.
+    #[inline]
+    pub fn finish(self, succeeded: bool) {
+        if succeeded {
+            self.commit()
+        } else {
+            self.rollback()
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! The raw read-write lock behind `MirrorMonitor`, which is parking_lot's with
+//! the `parking_lot` feature, and a spinlock otherwise.
+//!
+//! Locks taken from a monitor run inside the global allocator, so they mustn't
+//! allocate while held, and a thread mustn't come back for a lock it's waiting
+//! on. The spinlock never allocates. parking_lot's can: a thread that parks
+//! registers itself in a global table, which is allocated, and grown, on
+//! demand, with parking_lot's own bucket locks held. If that allocation reached
+//! the same lock again, the thread would park again before it's done
+//! registering, and recurse until its stack overflows, or wait on the bucket
+//! locks it holds itself. So `MirrorMonitor` marks the thread while it's
+//! inside, and counts events that arrive while it is on the side, in atomics,
+//! to merge in once it holds the lock.
+//!
+//! `StatsMonitor` doesn't lock at all: updates go through a `SeqLock`, whose
+//! writers spin and never park.
+
+#[cfg(feature = "parking_lot")]
+pub(crate) use parking_lot_lock::RawRwLock;
+#[cfg(not(feature = "parking_lot"))]
+pub(crate) use spin_lock::RawRwLock;
+
+#[cfg(feature = "parking_lot")]
+mod parking_lot_lock {
+    use crate::contention::Contention;
+    use lock_api::RawRwLock as _;
+
+    pub(crate) struct RawRwLock(parking_lot::RawRwLock, pub(crate) Contention);
+
+    impl RawRwLock {
+        pub(crate) const fn new() -> Self {
+            Self(parking_lot::RawRwLock::INIT, Contention::new())
+        }
+
+        /// Takes the lock, counting it as contended if it had to wait. The wait
+        /// parks, so there are no loops to count.
+        #[inline]
+        pub(crate) fn lock_shared(&self) {
+            if !self.0.try_lock_shared() {
+                self.1.retried(1);
+                self.0.lock_shared()
+            }
+        }
+
+        #[inline]
+        pub(crate) fn unlock_shared(&self) {
+            self.0.unlock_shared()
+        }
+
+        #[inline]
+        pub(crate) fn lock_exclusive(&self) {
+            if !self.0.try_lock_exclusive() {
+                self.1.waited(0);
+                self.0.lock_exclusive()
+            }
+        }
+
+        #[inline]
+        pub(crate) fn unlock_exclusive(&self) {
+            self.0.unlock_exclusive()
+        }
+    }
+}
+
+#[cfg(not(feature = "parking_lot"))]
+mod spin_lock {
+    use crate::contention::Contention;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Set in the lock word while a writer holds the lock. The other bits count
+    /// the readers.
+    const WRITER: usize = 1 << (usize::BITS - 1);
+
+    pub(crate) struct RawRwLock(AtomicUsize, pub(crate) Contention);
+
+    impl RawRwLock {
+        pub(crate) const fn new() -> Self {
+            Self(AtomicUsize::new(0), Contention::new())
+        }
+
+        #[inline]
+        pub(crate) fn lock_shared(&self) {
+            let mut loops = 0;
+            while !self.try_lock_shared() {
+                loops += 1;
+                core::hint::spin_loop();
+            }
+            if loops > 0 {
+                self.1.retried(loops);
+            }
+        }
+
+        #[inline]
+        fn try_lock_shared(&self) -> bool {
+            let state = self.0.load(Ordering::Relaxed);
+            state & WRITER == 0
+                && self
+                    .0
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+        }
+
+        #[inline]
+        pub(crate) fn unlock_shared(&self) {
+            self.0.fetch_sub(1, Ordering::Release);
+        }
+
+        #[inline]
+        pub(crate) fn lock_exclusive(&self) {
+            let mut loops = 0;
+            while self
+                .0
+                .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                loops += 1;
+                core::hint::spin_loop();
+            }
+            if loops > 0 {
+                self.1.waited(loops);
+            }
+        }
+
+        #[inline]
+        pub(crate) fn unlock_exclusive(&self) {
+            self.0.store(0, Ordering::Release);
+        }
+    }
+}
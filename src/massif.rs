@@ -0,0 +1,108 @@
+use std::io;
+
+/// A single point in time of a heap profile, for `MassifWriter`.
+#[derive(Clone, Copy, Debug)]
+pub struct MassifSnapshot<'a> {
+    /// The time of the snapshot, in the writer's time unit
+    pub time: u64,
+    /// Bytes live on the heap
+    pub heap_bytes: usize,
+    /// Optional breakdown of the live bytes by allocation site, as a label and a
+    /// number of bytes. Snapshots with a breakdown are written as detailed
+    /// snapshots.
+    pub sites: &'a [(&'a str, usize)],
+}
+
+/// Writes heap profiles in the output format of Valgrind's massif tool, so that
+/// they can be viewed with `ms_print` or massif-visualizer.
+///
+/// The snapshot with the most live bytes is marked as the peak, and is always
+/// written with a heap tree, even if it has no per-site breakdown.
+#[derive(Clone, Copy, Debug)]
+pub struct MassifWriter<'a> {
+    desc: &'a str,
+    cmd: &'a str,
+    time_unit: &'a str,
+}
+
+impl<'a> MassifWriter<'a> {
+    /// A writer for a profile of the command `cmd`, with times in milliseconds.
+    pub const fn new(cmd: &'a str) -> Self {
+        Self {
+            desc: "(none)",
+            cmd,
+            time_unit: "ms",
+        }
+    }
+
+    /// Sets the description line of the profile.
+    pub const fn desc(mut self, desc: &'a str) -> Self {
+        self.desc = desc;
+        self
+    }
+
+    /// Sets the unit of the snapshot times. Massif itself uses `i` for
+    /// instructions, `ms` for milliseconds or `B` for bytes allocated.
+    pub const fn time_unit(mut self, time_unit: &'a str) -> Self {
+        self.time_unit = time_unit;
+        self
+    }
+
+    /// Writes a profile made from `snapshots` to `out`.
+    pub fn write(&self, out: &mut impl io::Write, snapshots: &[MassifSnapshot]) -> io::Result<()> {
+        writeln!(out, "desc: {}", self.desc)?;
+        writeln!(out, "cmd: {}", self.cmd)?;
+        writeln!(out, "time_unit: {}", self.time_unit)?;
+
+        let peak = snapshots
+            .iter()
+            .enumerate()
+            .fold(None, |peak: Option<(usize, usize)>, (i, s)| match peak {
+                Some((_, bytes)) if bytes >= s.heap_bytes => peak,
+                _ => Some((i, s.heap_bytes)),
+            })
+            .map(|(i, _)| i);
+
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            writeln!(out, "#-----------")?;
+            writeln!(out, "snapshot={}", i)?;
+            writeln!(out, "#-----------")?;
+            writeln!(out, "time={}", snapshot.time)?;
+            writeln!(out, "mem_heap_B={}", snapshot.heap_bytes)?;
+            writeln!(out, "mem_heap_extra_B=0")?;
+            writeln!(out, "mem_stacks_B=0")?;
+
+            let is_peak = peak == Some(i);
+            if !is_peak && snapshot.sites.is_empty() {
+                writeln!(out, "heap_tree=empty")?;
+                continue;
+            }
+            writeln!(
+                out,
+                "heap_tree={}",
+                if is_peak { "peak" } else { "detailed" }
+            )?;
+            self.write_tree(out, snapshot)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the heap tree of a snapshot, with its sites as the only level below
+    /// the root. Massif expects children sorted by size, largest first.
+    fn write_tree(&self, out: &mut impl io::Write, snapshot: &MassifSnapshot) -> io::Result<()> {
+        let mut sites: Vec<_> = snapshot.sites.iter().filter(|s| s.1 > 0).collect();
+        sites.sort_by_key(|s| core::cmp::Reverse(s.1));
+
+        let total = snapshot.heap_bytes.max(sites.iter().map(|s| s.1).sum());
+        writeln!(
+            out,
+            "n{}: {} (heap allocation functions) malloc/new/new[], --alloc-fns, etc.",
+            sites.len(),
+            total
+        )?;
+        for (label, bytes) in sites {
+            writeln!(out, " n0: {} {}", bytes, label)?;
+        }
+        Ok(())
+    }
+}
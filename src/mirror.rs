@@ -0,0 +1,429 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+#[cfg(feature = "self-metrics")]
+use crate::contention::ContentionStats;
+use crate::describe::{MonitorDesc, Overhead};
+use crate::lock::RawRwLock;
+use crate::monitor::{AllocInfo, InfoSource};
+use core::alloc::Layout;
+use core::cell::{Cell, UnsafeCell};
+use core::ptr;
+use core::sync::atomic::{fence, AtomicPtr, AtomicU64, Ordering};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+thread_local! {
+    /// Whether this thread is inside a `MirrorMonitor` call that takes its lock.
+    static IN_MONITOR: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as inside a `MirrorMonitor` call until dropped.
+/// `None` if it already was, in which case the lock mustn't be taken again.
+struct Entered(());
+
+impl Entered {
+    fn enter() -> Option<Self> {
+        // Not `then_some`, which would build, and so drop, a guard either way.
+        if IN_MONITOR.with(|entered| entered.replace(true)) {
+            None
+        } else {
+            Some(Self(()))
+        }
+    }
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        IN_MONITOR.with(|entered| entered.set(false));
+    }
+}
+
+/// Counts of the events that reached a `MirrorMonitor` while the same thread was
+/// already inside it, kept without the lock until they're merged into its
+/// `AllocInfo`.
+struct Overflow {
+    alloc: AtomicU64,
+    dealloc: AtomicU64,
+    realloc: AtomicU64,
+    bytes_alloc: AtomicU64,
+    bytes_dealloc: AtomicU64,
+}
+
+impl Overflow {
+    const fn new() -> Self {
+        Self {
+            alloc: AtomicU64::new(0),
+            dealloc: AtomicU64::new(0),
+            realloc: AtomicU64::new(0),
+            bytes_alloc: AtomicU64::new(0),
+            bytes_dealloc: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts the event like `AllocInfo::apply`, apart from the peak.
+    fn add(&self, layout: Layout, action: AllocAction) {
+        let size = layout.size() as u64;
+        match action {
+            AllocAction::Alloc | AllocAction::AllocZeroed => {
+                self.alloc.fetch_add(1, Ordering::Relaxed);
+                self.bytes_alloc.fetch_add(size, Ordering::Relaxed);
+            }
+            AllocAction::Dealloc { .. } => {
+                self.dealloc.fetch_add(1, Ordering::Relaxed);
+                self.bytes_dealloc.fetch_add(size, Ordering::Relaxed);
+            }
+            AllocAction::Realloc { new_size, .. } => {
+                self.realloc.fetch_add(1, Ordering::Relaxed);
+                self.bytes_alloc
+                    .fetch_add(new_size as u64, Ordering::Relaxed);
+                self.bytes_dealloc.fetch_add(size, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds the counts to `info`, clearing them if `take` is set.
+    fn merge_into(&self, info: &mut AllocInfo, take: bool) {
+        let read = |counter: &AtomicU64| {
+            if take {
+                counter.swap(0, Ordering::Relaxed)
+            } else {
+                counter.load(Ordering::Relaxed)
+            }
+        };
+        info.alloc += read(&self.alloc);
+        info.dealloc += read(&self.dealloc);
+        info.realloc += read(&self.realloc);
+        info.bytes_alloc += read(&self.bytes_alloc);
+        info.bytes_dealloc += read(&self.bytes_dealloc);
+        info.peak_bytes = info.peak_bytes.max(info.live_bytes());
+    }
+}
+
+/// The first field of every mirror region: the bytes `ILMIRROR` read as a
+/// little-endian `u64`. Like every field, it's stored native-endian, so a region
+/// only starts with those bytes on little-endian targets.
+pub const MIRROR_MAGIC: u64 = u64::from_le_bytes(*b"ILMIRROR");
+
+/// Version of the mirror layout described by `MirrorLayout`.
+pub const MIRROR_VERSION: u32 = 1;
+
+/// Number of values stored in the mirror.
+const MIRROR_FIELDS: usize = 6;
+
+/// The layout of a mirror region. All fields are native-endian.
+///
+/// | Offset | Type                            | Contents                    |
+/// |--------|---------------------------------|-----------------------------|
+/// | 0      | `u64`                           | `MIRROR_MAGIC`              |
+/// | 8      | `u32`                           | `MIRROR_VERSION`            |
+/// | 12     | `u32`                           | number of values that follow|
+/// | 16     | `u64`                           | sequence counter            |
+/// | 24     | `[u64; n]`                      | the values                  |
+///
+/// The values are the fields of `AllocInfo` in declaration order: `alloc`,
+/// `dealloc`, `realloc`, `bytes_alloc`, `bytes_dealloc` and `peak_bytes`. Newer
+/// versions only ever append values.
+///
+/// The sequence counter is odd while the values are being written. Readers take
+/// a snapshot by reading the counter, then the values, then the counter again,
+/// and retry if either read of the counter was odd or they differ.
+#[repr(C)]
+pub struct MirrorLayout {
+    pub magic: u64,
+    pub version: u32,
+    pub fields: u32,
+    pub sequence: AtomicU64,
+    pub values: [AtomicU64; MIRROR_FIELDS],
+}
+
+/// Size of the file backing a mirror region.
+const MIRROR_FILE_SIZE: usize = 4096;
+
+/// Maps `len` bytes of `file` into memory.
+fn map(file: &File, len: usize, writable: bool) -> io::Result<*mut MirrorLayout> {
+    let prot = if writable {
+        libc::PROT_READ | libc::PROT_WRITE
+    } else {
+        libc::PROT_READ
+    };
+    let addr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            prot,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(addr as *mut MirrorLayout)
+}
+
+/// Keeps an `AllocInfo` like `StatsMonitor` does, and additionally copies it into
+/// a shared memory region once one is attached, so that other processes can read
+/// it with `MirrorReader`.
+///
+/// With the `parking_lot` feature, a thread that finds the lock taken may park,
+/// and parking can allocate. That allocation comes back to this monitor on the
+/// same thread, in the middle of parking, so instead of taking the lock again
+/// and maybe deadlocking inside parking_lot, the monitor counts it without the
+/// lock, and merges it in on the next call that does take the lock. Until then,
+/// `info` still includes it, but it doesn't reach the region, and it counts
+/// towards the peak late, if at all.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{InterAlloc, MirrorMonitor};
+/// use std::alloc::System;
+///
+/// static MONITOR: MirrorMonitor = MirrorMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, MirrorMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// // Enough threads that the lock is contended, and enough of them parking
+/// // that parking_lot has to grow its table, allocating.
+/// let threads: Vec<_> = (0..128)
+///     .map(|_| {
+///         std::thread::spawn(|| {
+///             let mut held = Vec::new();
+///             for i in 0..2_000 {
+///                 held.push(vec![0u8; i % 256 + 1]);
+///                 if held.len() == 50 {
+///                     held.clear();
+///                 }
+///                 let _ = MONITOR.info();
+///             }
+///         })
+///     })
+///     .collect();
+/// for thread in threads {
+///     thread.join().unwrap();
+/// }
+/// let info = MONITOR.info();
+/// assert!(info.alloc >= 128 * 2_000);
+/// ```
+pub struct MirrorMonitor {
+    info: UnsafeCell<AllocInfo>,
+    lock: RawRwLock,
+    region: AtomicPtr<MirrorLayout>,
+    overflow: Overflow,
+}
+
+unsafe impl Sync for MirrorMonitor {}
+
+impl MirrorMonitor {
+    /// New instance of this monitor, without a region attached.
+    pub const fn new() -> Self {
+        Self {
+            info: UnsafeCell::new(AllocInfo::new()),
+            lock: RawRwLock::new(),
+            region: AtomicPtr::new(ptr::null_mut()),
+            overflow: Overflow::new(),
+        }
+    }
+
+    /// Creates the file at `path`, maps it into memory, and attaches it. The
+    /// mapping lives until the process exits.
+    pub fn create(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(MIRROR_FILE_SIZE as u64)?;
+        let region = map(&file, MIRROR_FILE_SIZE, true)?;
+        unsafe {
+            (*region).magic = MIRROR_MAGIC;
+            (*region).version = MIRROR_VERSION;
+            (*region).fields = MIRROR_FIELDS as u32;
+            self.attach(region);
+        }
+        Ok(())
+    }
+
+    /// Starts mirroring into `region`, and writes the current statistics to it.
+    ///
+    /// # Safety
+    /// `region` must point to writable memory that's valid for the rest of the
+    /// program, with its header already filled in.
+    pub unsafe fn attach(&self, region: *mut MirrorLayout) {
+        let _entered = Entered::enter();
+        self.lock.lock_exclusive();
+        self.region.store(region, Ordering::Relaxed);
+        let info = &mut *self.info.get();
+        self.overflow.merge_into(info, true);
+        self.publish(info);
+        self.lock.unlock_exclusive();
+    }
+
+    #[inline]
+    pub fn info(&self) -> AllocInfo {
+        let _entered = Entered::enter();
+        self.lock.lock_shared();
+        let mut info = unsafe { *self.info.get() };
+        self.lock.unlock_shared();
+        self.overflow.merge_into(&mut info, false);
+        info
+    }
+
+    /// How often the lock had to be waited for, with parking_lot's or the
+    /// spinlock.
+    #[cfg(feature = "self-metrics")]
+    pub fn contention(&self) -> ContentionStats {
+        self.lock.1.stats()
+    }
+
+    /// Writes `info` to the region. Must be called with the lock held exclusively,
+    /// so that there is only ever one writer.
+    #[inline]
+    fn publish(&self, info: &AllocInfo) {
+        let region = match unsafe { self.region.load(Ordering::Relaxed).as_ref() } {
+            Some(region) => region,
+            None => return,
+        };
+        let values = [
+            info.alloc,
+            info.dealloc,
+            info.realloc,
+            info.bytes_alloc,
+            info.bytes_dealloc,
+            info.peak_bytes,
+        ];
+        let seq = region.sequence.load(Ordering::Relaxed);
+        region
+            .sequence
+            .store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        for (slot, value) in region.values.iter().zip(values.iter()) {
+            slot.store(*value, Ordering::Relaxed);
+        }
+        region
+            .sequence
+            .store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
+
+impl Default for MirrorMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InfoSource for MirrorMonitor {
+    fn info(&self) -> AllocInfo {
+        MirrorMonitor::info(self)
+    }
+}
+
+impl AllocMonitor for MirrorMonitor {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let _entered = match Entered::enter() {
+            Some(entered) => entered,
+            None => return self.overflow.add(layout, action),
+        };
+        self.lock.lock_exclusive();
+        let info = unsafe { &mut *self.info.get() };
+        self.overflow.merge_into(info, true);
+        info.apply(layout, action);
+        self.publish(info);
+        self.lock.unlock_exclusive();
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("MirrorMonitor", Overhead::Atomic)
+    }
+}
+
+/// Reads snapshots out of a mirror region written by `MirrorMonitor`, possibly in
+/// another process.
+pub struct MirrorReader {
+    region: *const MirrorLayout,
+    len: usize,
+}
+
+unsafe impl Send for MirrorReader {}
+unsafe impl Sync for MirrorReader {}
+
+impl MirrorReader {
+    /// Maps the mirror file at `path`, checking its header.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len < core::mem::size_of::<MirrorLayout>() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "mirror file is too small",
+            ));
+        }
+        let region = map(&file, len, false)?;
+        let reader = Self { region, len };
+        let header = unsafe { &*region };
+        if header.magic != MIRROR_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an interloc mirror",
+            ));
+        }
+        if header.version != MIRROR_VERSION || (header.fields as usize) < MIRROR_FIELDS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported mirror version",
+            ));
+        }
+        Ok(reader)
+    }
+
+    /// Takes a consistent snapshot of the mirrored statistics, retrying while the
+    /// writer is in the middle of an update. Returns `None` if no consistent
+    /// snapshot could be read after `attempts` tries, e.g. because the writer
+    /// died mid-update.
+    pub fn read(&self, attempts: usize) -> Option<AllocInfo> {
+        let region = unsafe { &*self.region };
+        for _ in 0..attempts {
+            let before = region.sequence.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                core::hint::spin_loop();
+                continue;
+            }
+            let mut values = [0u64; MIRROR_FIELDS];
+            for (value, slot) in values.iter_mut().zip(region.values.iter()) {
+                *value = slot.load(Ordering::Relaxed);
+            }
+            fence(Ordering::Acquire);
+            if region.sequence.load(Ordering::Relaxed) == before {
+                return Some(AllocInfo {
+                    alloc: values[0],
+                    dealloc: values[1],
+                    realloc: values[2],
+                    bytes_alloc: values[3],
+                    bytes_dealloc: values[4],
+                    peak_bytes: values[5],
+                    ..AllocInfo::new()
+                });
+            }
+        }
+        None
+    }
+
+    /// The current value of the sequence counter, which increases by two with
+    /// every update.
+    pub fn sequence(&self) -> u64 {
+        unsafe { (*self.region).sequence.load(Ordering::Acquire) }
+    }
+}
+
+impl Drop for MirrorReader {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.region as *mut libc::c_void, self.len) };
+    }
+}
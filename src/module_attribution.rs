@@ -0,0 +1,154 @@
+use crate::alloc::{internal, AllocAction, AllocMonitor, AllocRel};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::monitor::{AllocInfo, StatsMonitor};
+use crate::slots::Slots;
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many frames of each stack are looked at for a matching function.
+const MAX_FRAMES: usize = 32;
+
+/// A cached classification of a return address. `class` is the index of the
+/// matching prefix plus one, or `usize::MAX` if no prefix matched.
+struct CacheSlot {
+    ip: AtomicUsize,
+    class: AtomicUsize,
+}
+
+impl CacheSlot {
+    const fn new() -> Self {
+        Self {
+            ip: AtomicUsize::new(0),
+            class: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Attributes allocator calls to modules, by a list of module path prefixes
+/// like `["myapp::net", "myapp::db", "serde"]`.
+///
+/// Each call is attributed to the first prefix that the innermost matching
+/// frame of its stack starts with, and to `"other"` if no frame matches.
+/// Return addresses are symbolized once and their classification cached in a
+/// fixed table of `CACHE` entries, but capturing the stack is still expensive,
+/// so this is best wrapped in a `SampleMonitor`.
+///
+/// Deallocations are attributed to the code that frees the memory, so the live
+/// bytes of a module are only meaningful if it frees what it allocated.
+pub struct ModuleAttributionMonitor<const N: usize, const CACHE: usize = 1024> {
+    prefixes: [&'static str; N],
+    modules: [StatsMonitor; N],
+    other: StatsMonitor,
+    cache: Slots<CacheSlot, CACHE>,
+}
+
+impl<const N: usize, const CACHE: usize> ModuleAttributionMonitor<N, CACHE> {
+    pub const fn new(prefixes: [&'static str; N]) -> Self {
+        Self {
+            prefixes,
+            modules: [const { StatsMonitor::new() }; N],
+            other: StatsMonitor::new(),
+            cache: Slots::new([const { CacheSlot::new() }; CACHE]),
+        }
+    }
+
+    /// The statistics of each prefix, in the order they were given, followed by
+    /// the statistics of `"other"`.
+    pub fn per_module(&self) -> Vec<(&'static str, AllocInfo)> {
+        internal(|| {
+            self.prefixes
+                .iter()
+                .zip(&self.modules)
+                .map(|(prefix, stats)| (*prefix, stats.info()))
+                .chain(core::iter::once(("other", self.other.info())))
+                .collect()
+        })
+    }
+
+    /// The prefix index plus one that `ip` is in, or `usize::MAX` for none.
+    fn classify(&self, ip: usize) -> usize {
+        let len = self.cache.len();
+        let start = ip % len.max(1);
+        let mut free = None;
+        for i in 0..len {
+            let slot = &self.cache[(start + i) % len];
+            let cached = slot.ip.load(Ordering::Acquire);
+            if cached == ip {
+                let class = slot.class.load(Ordering::Acquire);
+                if class != 0 {
+                    return class;
+                }
+                break;
+            }
+            if cached == 0 {
+                free = Some(slot);
+                break;
+            }
+        }
+
+        let class = self.resolve(ip);
+        if let Some(slot) = free {
+            if slot
+                .ip
+                .compare_exchange(0, ip, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                slot.class.store(class, Ordering::Release);
+            }
+        }
+        class
+    }
+
+    fn resolve(&self, ip: usize) -> usize {
+        let mut class = usize::MAX;
+        // Return addresses point just past the call, which may already be the
+        // next line or function.
+        let lookup = ip.saturating_sub(1) as *mut core::ffi::c_void;
+        backtrace::resolve(lookup, |symbol| {
+            if class != usize::MAX {
+                return;
+            }
+            if let Some(name) = symbol.name() {
+                let name = format!("{:#}", name);
+                let name = name.trim_start_matches('<');
+                if let Some(i) = self.prefixes.iter().position(|p| name.starts_with(p)) {
+                    class = i + 1;
+                }
+            }
+        });
+        class
+    }
+
+    fn stats_for_current_stack(&self) -> &StatsMonitor {
+        let mut ips = [0usize; MAX_FRAMES];
+        let mut len = 0;
+        backtrace::trace(|frame| {
+            ips[len] = frame.ip() as usize;
+            len += 1;
+            len < MAX_FRAMES
+        });
+        ips[..len]
+            .iter()
+            .map(|ip| self.classify(*ip))
+            .find(|class| *class != usize::MAX)
+            .map_or(&self.other, |class| &self.modules[class - 1])
+    }
+}
+
+impl<const N: usize, const CACHE: usize> AllocMonitor for ModuleAttributionMonitor<N, CACHE> {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        if action.relation() != AllocRel::Before {
+            return;
+        }
+        internal(|| {
+            let stats = self.stats_for_current_stack();
+            stats.monitor(layout, action);
+        });
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("ModuleAttributionMonitor", Overhead::Heavy)
+            .param("modules", N as u64)
+            .param("cache", CACHE as u64)
+    }
+}
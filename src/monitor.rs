@@ -1,26 +1,68 @@
 use crate::alloc::*;
+#[cfg(feature = "self-metrics")]
+use crate::contention::ContentionStats;
+use crate::describe::{MonitorDesc, Overhead};
+use crate::fmt::{ByteSize, Signed};
+use crate::regression::{AllocComparison, AllocField, AllocPercent};
+#[cfg(not(feature = "disabled"))]
+use crate::seqlock::SeqLock;
+use crate::snapshot::AllocSnapshot;
 use core::alloc::Layout;
-use core::cell::RefCell;
-use core::sync::atomic::{fence, Ordering};
-use lock_api::RawRwLock as RawRwLockTrait;
-use parking_lot::RawRwLock;
+use core::cell::{Cell, RefCell};
+use core::cmp::Ordering;
+use core::sync::atomic::{self, AtomicBool};
 
-/// Information about allocations by the allocator.
+/// Which size a monitor counts for a block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Accounting {
+    /// The size of the layout, as requested, the default
+    #[default]
+    Requested,
+    /// The size of the layout rounded up to its alignment, as with
+    /// `Layout::pad_to_align`, which the allocator has to provide at least. A
+    /// 1-byte block aligned to 64 counts as 64 bytes.
+    PaddedToAlign,
+}
+
+impl Accounting {
+    /// The bytes counted for a block of `size` bytes aligned to `align`.
+    #[inline]
+    pub const fn bytes(self, size: usize, align: usize) -> u64 {
+        match self {
+            Accounting::Requested => size as u64,
+            Accounting::PaddedToAlign => {
+                let mask = align as u64 - 1;
+                (size as u64).saturating_add(mask) & !mask
+            }
+        }
+    }
+}
+
+/// Information about allocations by the allocator. The counters are 64 bits
+/// wide on every target, so they don't overflow on 32-bit targets after a few
+/// GiB of allocation.
 #[derive(Clone, Default, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct AllocInfo {
     // Taken directly from https://github.com/neoeinstein/stats_alloc, or stats_alloc
     // on crates.io - all credit to the original writer of this struct, the user
     // neoeinstein on GitHub.
     /// Number of calls to alloc
-    pub alloc: usize,
+    pub alloc: u64,
     /// Number of calls to dealloc
-    pub dealloc: usize,
+    pub dealloc: u64,
     /// Number of calls to realloc
-    pub realloc: usize,
+    pub realloc: u64,
     /// Total bytes allocated
-    pub bytes_alloc: usize,
+    pub bytes_alloc: u64,
     /// Total bytes deallocated
-    pub bytes_dealloc: usize,
+    pub bytes_dealloc: u64,
+    /// Highest number of bytes live at once
+    pub peak_bytes: u64,
+    /// Bytes that were already live when counting started, e.g. those carried
+    /// over a reset by `StatsMonitor::take`. They count toward `live_bytes`,
+    /// but not toward `bytes_alloc`, so the counters only count calls made
+    /// since.
+    pub baseline_bytes: u64,
 }
 
 impl AllocInfo {
@@ -31,8 +73,19 @@ impl AllocInfo {
             realloc: 0,
             bytes_alloc: 0,
             bytes_dealloc: 0,
+            peak_bytes: 0,
+            baseline_bytes: 0,
         }
     }
+
+    /// Bytes allocated and not yet deallocated, including `baseline_bytes`.
+    #[inline]
+    pub fn live_bytes(&self) -> u64 {
+        (self.baseline_bytes + self.bytes_alloc).saturating_sub(self.bytes_dealloc)
+    }
+
+    /// The allocations that happened between `origin` and `self`. Peaks can't be
+    /// subtracted, so the result carries over the peak of `self`.
     pub fn relative_to(&self, origin: &Self) -> Self {
         Self {
             alloc: self.alloc - origin.alloc,
@@ -40,90 +93,955 @@ impl AllocInfo {
             realloc: self.realloc - origin.realloc,
             bytes_alloc: self.bytes_alloc - origin.bytes_alloc,
             bytes_dealloc: self.bytes_dealloc - origin.bytes_dealloc,
+            peak_bytes: self.peak_bytes,
+            baseline_bytes: self.baseline_bytes - origin.baseline_bytes,
+        }
+    }
+
+    /// Like `relative_to`, but `None` instead of overflowing if `origin` counted
+    /// more than `self` in any field, e.g. because the monitor was reset after
+    /// it was taken. `AllocSnapshot::delta_since` also catches resets that
+    /// don't overflow.
+    ///
+    /// ```rust
+    /// use interloc::AllocInfo;
+    ///
+    /// let mut origin = AllocInfo::new();
+    /// origin.alloc = 3;
+    /// let mut end = origin;
+    /// end.alloc = 5;
+    /// assert_eq!(end.checked_relative_to(&origin).unwrap().alloc, 2);
+    /// assert_eq!(AllocInfo::new().checked_relative_to(&origin), None);
+    /// ```
+    pub fn checked_relative_to(&self, origin: &Self) -> Option<Self> {
+        Some(Self {
+            alloc: self.alloc.checked_sub(origin.alloc)?,
+            dealloc: self.dealloc.checked_sub(origin.dealloc)?,
+            realloc: self.realloc.checked_sub(origin.realloc)?,
+            bytes_alloc: self.bytes_alloc.checked_sub(origin.bytes_alloc)?,
+            bytes_dealloc: self.bytes_dealloc.checked_sub(origin.bytes_dealloc)?,
+            peak_bytes: self.peak_bytes,
+            baseline_bytes: self.baseline_bytes.checked_sub(origin.baseline_bytes)?,
+        })
+    }
+
+    /// Adds the allocations of `other` to `self`, as if they had happened one
+    /// after the other. Counts and byte totals are added; peaks can't be, so
+    /// `peak_bytes` becomes the higher of the two.
+    ///
+    /// ```rust
+    /// use interloc::AllocInfo;
+    ///
+    /// let mut first = AllocInfo::new();
+    /// first.alloc = 2;
+    /// first.bytes_alloc = 300;
+    /// first.peak_bytes = 200;
+    /// let mut second = AllocInfo::new();
+    /// second.alloc = 1;
+    /// second.bytes_alloc = 100;
+    /// second.peak_bytes = 100;
+    ///
+    /// let total: AllocInfo = [first, second].iter().sum();
+    /// assert_eq!(total.alloc, 3);
+    /// assert_eq!(total.bytes_alloc, 400);
+    /// // Not 300
+    /// assert_eq!(total.peak_bytes, 200);
+    /// ```
+    pub fn merge(&mut self, other: &Self) {
+        self.alloc += other.alloc;
+        self.dealloc += other.dealloc;
+        self.realloc += other.realloc;
+        self.bytes_alloc += other.bytes_alloc;
+        self.bytes_dealloc += other.bytes_dealloc;
+        self.peak_bytes = self.peak_bytes.max(other.peak_bytes);
+        self.baseline_bytes += other.baseline_bytes;
+    }
+
+    /// Whether `self` costs no more than `other` in every field of
+    /// `AllocField::COST`.
+    pub fn dominated_by(&self, other: &Self) -> bool {
+        AllocField::COST
+            .iter()
+            .all(|field| field.of(self) <= field.of(other))
+    }
+
+    /// How `self` compares to `other` in each field of `AllocField::COST`, for
+    /// explaining the result of `compare`.
+    pub fn compare_fields(&self, other: &Self) -> [(AllocField, Ordering); 5] {
+        AllocField::COST.map(|field| (field, field.of(self).cmp(&field.of(other))))
+    }
+
+    /// Whether `self` is better, worse, the same, or a mix compared to `other`,
+    /// where lower costs are better.
+    ///
+    /// ```rust
+    /// use interloc::{AllocComparison, AllocInfo};
+    ///
+    /// let mut small = AllocInfo::new();
+    /// small.alloc = 1;
+    /// small.bytes_alloc = 64;
+    /// let mut big = small;
+    /// big.bytes_alloc = 128;
+    /// assert_eq!(small.compare(&big), AllocComparison::Better);
+    /// assert_eq!(big.compare(&small), AllocComparison::Worse);
+    /// assert!(small.dominated_by(&big));
+    ///
+    /// // Fewer bytes, but more calls
+    /// let mut many = small;
+    /// many.alloc = 4;
+    /// many.bytes_alloc = 32;
+    /// assert_eq!(many.compare(&big), AllocComparison::Mixed);
+    /// assert!(!many.dominated_by(&big) && !big.dominated_by(&many));
+    /// ```
+    pub fn compare(&self, other: &Self) -> AllocComparison {
+        AllocComparison::from_fields(&self.compare_fields(other))
+    }
+
+    /// The signed change in every field from `origin` to `self`. Unlike
+    /// `relative_to`, `origin` doesn't have to be an earlier snapshot of the same
+    /// counters, so this can compare two unrelated runs.
+    pub fn delta_from(&self, origin: &Self) -> AllocDelta {
+        let diff = |new: u64, old: u64| (new as i64).wrapping_sub(old as i64);
+        AllocDelta {
+            alloc: diff(self.alloc, origin.alloc),
+            dealloc: diff(self.dealloc, origin.dealloc),
+            realloc: diff(self.realloc, origin.realloc),
+            bytes_alloc: diff(self.bytes_alloc, origin.bytes_alloc),
+            bytes_dealloc: diff(self.bytes_dealloc, origin.bytes_dealloc),
+            peak_bytes: diff(self.peak_bytes, origin.peak_bytes),
         }
     }
 
+    /// Updates the statistics in place for a call to the allocator.
+    ///
+    /// ```rust
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocInfo};
+    ///
+    /// let layout = Layout::from_size_align(1 << 30, 1).unwrap();
+    /// let mut info = AllocInfo::new();
+    /// for _ in 0..5 {
+    ///     info.apply(layout, AllocAction::Alloc);
+    /// }
+    /// // More than fits in a 32-bit usize
+    /// assert_eq!(info.bytes_alloc, 5 << 30);
+    /// assert_eq!(info.peak_bytes, 5 << 30);
+    /// ```
     #[inline]
-    pub fn after_call(&self, layout: Layout, action: AllocAction) -> Self {
+    pub fn apply(&mut self, layout: Layout, action: AllocAction) {
+        self.apply_with(Accounting::Requested, layout, action);
+    }
+
+    /// Like `apply`, but counts the bytes of each block as `accounting` says, on
+    /// both sides of a reallocation.
+    #[inline]
+    pub fn apply_with(&mut self, accounting: Accounting, layout: Layout, action: AllocAction) {
         use AllocAction::*;
-        let mut info = *self;
-        let size = layout.size();
+        let size = accounting.bytes(layout.size(), layout.align());
         match action {
             Alloc | AllocZeroed => {
-                info.alloc += 1;
-                info.bytes_alloc += size;
-                info
+                self.alloc += 1;
+                self.bytes_alloc += size;
+                self.peak_bytes = self.peak_bytes.max(self.live_bytes());
             }
             Dealloc { ptr: _ } => {
-                info.dealloc += 1;
-                info.bytes_dealloc += size;
-                info
+                self.dealloc += 1;
+                self.bytes_dealloc += size;
             }
             Realloc { ptr: _, new_size } => {
-                info.realloc += 1;
-                info.bytes_alloc += new_size;
-                info.bytes_dealloc += size;
-                info
+                self.realloc += 1;
+                self.bytes_alloc += accounting.bytes(new_size, layout.align());
+                self.bytes_dealloc += size;
+                self.peak_bytes = self.peak_bytes.max(self.live_bytes());
             }
-            _ => info,
+            _ => {}
+        }
+    }
+
+    /// What a monitor starts from when it's reset: no calls, and the bytes still
+    /// live as the baseline, so that `live_bytes` stays right when they're
+    /// freed. The peak starts over from those live bytes.
+    pub(crate) fn carried_over(&self) -> Self {
+        Self {
+            peak_bytes: self.live_bytes(),
+            baseline_bytes: self.live_bytes(),
+            ..Self::new()
+        }
+    }
+
+    /// The statistics after a call to the allocator. Like `apply`, but returns a
+    /// copy instead.
+    #[inline]
+    pub fn after_call(&self, layout: Layout, action: AllocAction) -> Self {
+        let mut info = *self;
+        info.apply(layout, action);
+        info
+    }
+}
+
+/// Merges two `AllocInfo`s, as with `AllocInfo::merge`.
+impl core::ops::Add for AllocInfo {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        self.merge(&other);
+        self
+    }
+}
+
+/// Merges every `AllocInfo`, as with `AllocInfo::merge`.
+impl core::iter::FromIterator<AllocInfo> for AllocInfo {
+    fn from_iter<I: IntoIterator<Item = AllocInfo>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::new(), |mut total, info| {
+            total.merge(&info);
+            total
+        })
+    }
+}
+
+/// Merges every `AllocInfo`, as with `AllocInfo::merge`.
+impl core::iter::Sum for AllocInfo {
+    fn sum<I: Iterator<Item = AllocInfo>>(iter: I) -> Self {
+        iter.collect()
+    }
+}
+
+/// Merges every `AllocInfo`, as with `AllocInfo::merge`.
+impl<'a> core::iter::Sum<&'a AllocInfo> for AllocInfo {
+    fn sum<I: Iterator<Item = &'a AllocInfo>>(iter: I) -> Self {
+        iter.copied().collect()
+    }
+}
+
+/// Anything that can produce a snapshot of allocation statistics, so that code
+/// reading statistics doesn't have to care which monitor they come from.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{AllocAction, AllocInfo, AllocMonitor, InfoSource, StatsMonitor, ThreadMonitor};
+/// use core::alloc::Layout;
+///
+/// fn live_kib(source: &impl InfoSource) -> u64 {
+///     source.info().live_bytes() / 1024
+/// }
+///
+/// static GLOBAL: StatsMonitor = StatsMonitor::new();
+/// let thread = ThreadMonitor::new();
+/// let layout = Layout::from_size_align(4096, 8).unwrap();
+/// GLOBAL.monitor(layout, AllocAction::Alloc);
+/// thread.monitor(layout, AllocAction::Alloc);
+/// thread.monitor(layout, AllocAction::Alloc);
+///
+/// let mut snapshot = AllocInfo::new();
+/// snapshot.bytes_alloc = 3072;
+/// assert_eq!(live_kib(&GLOBAL), 4);
+/// assert_eq!(live_kib(&thread), 8);
+/// assert_eq!(live_kib(&snapshot), 3);
+/// ```
+pub trait InfoSource {
+    /// The statistics as of now.
+    fn info(&self) -> AllocInfo;
+}
+
+/// A snapshot is a source that never changes.
+impl InfoSource for AllocInfo {
+    fn info(&self) -> AllocInfo {
+        *self
+    }
+}
+
+impl<T: InfoSource + ?Sized> InfoSource for &T {
+    fn info(&self) -> AllocInfo {
+        (**self).info()
+    }
+}
+
+/// The signed difference between two `AllocInfo`s, as returned by
+/// `AllocInfo::delta_from`. Displayed on one line, with a sign on every nonzero
+/// field:
+///
+/// ```rust
+/// use interloc::AllocInfo;
+///
+/// let mut before = AllocInfo::new();
+/// before.alloc = 3;
+/// before.bytes_alloc = 2048;
+/// let mut after = before;
+/// after.dealloc = 1;
+/// after.bytes_dealloc = 512;
+///
+/// let delta = after.delta_from(&before);
+/// assert_eq!(delta.net_bytes(), -512);
+/// assert!(delta.is_leak_free());
+/// assert_eq!(
+///     delta.to_string(),
+///     "alloc 0, dealloc +1, realloc 0, bytes_alloc 0 B, bytes_dealloc +512 B, net -512 B, peak 0 B"
+/// );
+/// ```
+#[derive(Clone, Default, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct AllocDelta {
+    /// Change in the number of calls to alloc
+    pub alloc: i64,
+    /// Change in the number of calls to dealloc
+    pub dealloc: i64,
+    /// Change in the number of calls to realloc
+    pub realloc: i64,
+    /// Change in the total bytes allocated
+    pub bytes_alloc: i64,
+    /// Change in the total bytes deallocated
+    pub bytes_dealloc: i64,
+    /// Change in the highest number of bytes live at once
+    pub peak_bytes: i64,
+}
+
+impl AllocDelta {
+    pub const fn new() -> Self {
+        Self {
+            alloc: 0,
+            dealloc: 0,
+            realloc: 0,
+            bytes_alloc: 0,
+            bytes_dealloc: 0,
+            peak_bytes: 0,
         }
     }
+
+    /// Change in the number of bytes live, i.e. bytes allocated minus bytes
+    /// deallocated.
+    pub fn net_bytes(&self) -> i64 {
+        self.bytes_alloc.wrapping_sub(self.bytes_dealloc)
+    }
+
+    /// Each field of the delta as a percentage of the same field of `baseline`,
+    /// which is usually the snapshot the delta was taken from. Fields that are
+    /// zero in `baseline` are `None`.
+    pub fn percent_of(&self, baseline: &AllocInfo) -> AllocPercent {
+        let percent = |field: AllocField| match field.of(baseline) {
+            0 => None,
+            base => Some(field.of_delta(self) as f64 * 100.0 / base as f64),
+        };
+        AllocPercent {
+            alloc: percent(AllocField::Alloc),
+            dealloc: percent(AllocField::Dealloc),
+            realloc: percent(AllocField::Realloc),
+            bytes_alloc: percent(AllocField::BytesAlloc),
+            bytes_dealloc: percent(AllocField::BytesDealloc),
+            peak_bytes: percent(AllocField::PeakBytes),
+        }
+    }
+
+    /// Whether at least as many bytes were freed as were allocated, so nothing
+    /// allocated in between is still live.
+    pub fn is_leak_free(&self) -> bool {
+        self.net_bytes() <= 0
+    }
 }
 
-/// Monitor of global memory usage statistics. Uses a read-write lock to prevent
-/// data corruption.
+impl core::fmt::Display for AllocDelta {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let count = |value: i64| Signed {
+            value: value as i128,
+            bytes: false,
+        };
+        let bytes = |value: i64| Signed {
+            value: value as i128,
+            bytes: true,
+        };
+        write!(
+            f,
+            "alloc {}, dealloc {}, realloc {}, bytes_alloc {}, bytes_dealloc {}, net {}, peak {}",
+            count(self.alloc),
+            count(self.dealloc),
+            count(self.realloc),
+            bytes(self.bytes_alloc),
+            bytes(self.bytes_dealloc),
+            bytes(self.net_bytes()),
+            bytes(self.peak_bytes),
+        )
+    }
+}
+
+/// Monitor of global memory usage statistics. Uses a sequence lock, so reading
+/// the statistics never blocks the allocator, and vice versa.
+///
+/// ```rust
+/// use interloc::{AllocAction, AllocMonitor, StatsMonitor};
+/// use core::alloc::Layout;
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// let layout = Layout::new::<u64>();
+/// let threads: Vec<_> = (0..4)
+///     .map(|_| {
+///         std::thread::spawn(move || {
+///             for _ in 0..10 {
+///                 MONITOR.monitor(layout, AllocAction::Alloc);
+///             }
+///         })
+///     })
+///     .collect();
+/// for _ in 0..10 {
+///     // Never torn, even while the threads are writing
+///     let info = MONITOR.info();
+///     assert_eq!(info.bytes_alloc, info.alloc * 8);
+/// }
+/// for thread in threads {
+///     thread.join().unwrap();
+/// }
+/// assert_eq!(MONITOR.info().alloc, 40);
+/// assert_eq!(MONITOR.info().bytes_alloc, 320);
+/// ```
+#[cfg(not(feature = "disabled"))]
 pub struct StatsMonitor {
-    info: AllocInfo,
-    lock: RawRwLock,
+    info: SeqLock<Stats>,
+    name: Option<&'static str>,
+    accounting: Accounting,
+}
+
+/// Set by `mark_main_start`.
+static MAIN_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Marks the end of startup, for `StatsMonitor::startup_info` and
+/// `StatsMonitor::since_main`: what every `StatsMonitor` counted before this
+/// was first called, including allocations made before `main` by the runtime
+/// and by constructors, is startup, and everything after is the program's.
+/// Call it first thing in `main`, or once initialization is done; later calls
+/// do nothing.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{InterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, StatsMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// fn check() {
+///     let info = MONITOR.info();
+///     assert_eq!(MONITOR.startup_info() + MONITOR.since_main(), info);
+/// }
+///
+/// // Loading configuration, before the program really starts.
+/// let config = std::hint::black_box(vec![0u8; 4096]);
+/// check();
+/// assert_eq!(MONITOR.baseline(), None);
+/// assert_eq!(MONITOR.since_main().bytes_alloc, 0);
+///
+/// interloc::mark_main_start();
+/// check();
+/// for _ in 0..10 {
+///     drop(std::hint::black_box(vec![0u8; 100]));
+///     check();
+/// }
+/// drop(config);
+/// check();
+///
+/// let startup = MONITOR.startup_info();
+/// assert!(startup.bytes_alloc >= 4096);
+/// assert_eq!(MONITOR.baseline(), Some(startup));
+/// let steady = MONITOR.since_main();
+/// assert_eq!((steady.alloc, steady.bytes_alloc), (10, 1000));
+/// assert_eq!(steady.bytes_dealloc, 1000 + 4096);
+/// ```
+pub fn mark_main_start() {
+    MAIN_STARTED.store(true, atomic::Ordering::Release);
+}
+
+#[inline]
+fn main_started() -> bool {
+    MAIN_STARTED.load(atomic::Ordering::Acquire)
 }
 
+/// What a `StatsMonitor` keeps behind its lock.
+#[cfg(not(feature = "disabled"))]
+#[derive(Clone, Copy)]
+struct Stats {
+    snapshot: AllocSnapshot,
+    /// What was counted before `mark_main_start`, once it's been seen
+    startup: Option<AllocInfo>,
+}
+
+#[cfg(not(feature = "disabled"))]
+impl Stats {
+    const fn new() -> Self {
+        Self {
+            snapshot: AllocSnapshot::new(),
+            startup: None,
+        }
+    }
+
+    /// Sets aside what was counted as startup, if `mark_main_start` was called
+    /// since the last update.
+    #[inline]
+    fn mark_startup(&mut self) {
+        if self.startup.is_none() && main_started() {
+            self.startup = Some(self.snapshot.info);
+        }
+    }
+
+    /// Starts over after a reset, with nothing counted as startup if it's over.
+    fn reset_startup(&mut self) {
+        self.startup = main_started().then(AllocInfo::new);
+    }
+
+    fn startup_info(&self) -> AllocInfo {
+        self.startup.unwrap_or(self.snapshot.info)
+    }
+}
+
+#[cfg(not(feature = "disabled"))]
 impl StatsMonitor {
     /// New instance of this monitor.
+    #[cfg(not(loom))]
     pub const fn new() -> Self {
         Self {
-            info: AllocInfo::new(),
-            lock: RawRwLock::INIT,
+            info: SeqLock::new(Stats::new()),
+            name: None,
+            accounting: Accounting::Requested,
         }
     }
 
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self {
+            info: SeqLock::new(Stats::new()),
+            name: None,
+            accounting: Accounting::Requested,
+        }
+    }
+
+    /// New instance of this monitor, labeled `name` in its output, to tell it
+    /// apart from other monitors in the same process.
+    ///
+    /// ```rust
+    /// use interloc::StatsMonitor;
+    ///
+    /// static ARENA: StatsMonitor = StatsMonitor::named("arena");
+    ///
+    /// assert!(ARENA.to_string().starts_with("arena: alloc 0,"));
+    /// let mut metrics = String::new();
+    /// ARENA.to_prometheus("app", &mut metrics).unwrap();
+    /// assert!(metrics.contains("app_alloc_total{monitor=\"arena\"} 0"));
+    /// ```
+    #[cfg(not(loom))]
+    pub const fn named(name: &'static str) -> Self {
+        Self {
+            info: SeqLock::new(Stats::new()),
+            name: Some(name),
+            accounting: Accounting::Requested,
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn named(name: &'static str) -> Self {
+        Self {
+            info: SeqLock::new(Stats::new()),
+            name: Some(name),
+            accounting: Accounting::Requested,
+        }
+    }
+
+    /// Counts the bytes of blocks as `accounting` says, on every call,
+    /// `Accounting::Requested` by default. Snapshots are stamped with it, so
+    /// they can't be subtracted from those of a monitor that counts bytes
+    /// another way.
+    ///
+    /// ```rust
+    /// use core::alloc::Layout;
+    /// use interloc::{Accounting, AllocAction, AllocMonitor, SnapshotError, StatsMonitor};
+    ///
+    /// let requested = StatsMonitor::new();
+    /// let padded = StatsMonitor::new().accounting(Accounting::PaddedToAlign);
+    /// let layout = Layout::from_size_align(1, 64).unwrap();
+    /// let ptr = core::ptr::null_mut();
+    /// for monitor in [&requested, &padded] {
+    ///     monitor.monitor(layout, AllocAction::Alloc);
+    /// }
+    /// let (start, padded_start) = (requested.snapshot(), padded.snapshot());
+    /// assert_eq!(padded_start.info.bytes_alloc - start.info.bytes_alloc, 63);
+    ///
+    /// // Both sides of a reallocation are padded.
+    /// for monitor in [&requested, &padded] {
+    ///     monitor.monitor(layout, AllocAction::Realloc { ptr, new_size: 65 });
+    /// }
+    /// let grown = requested.snapshot().delta_since(&start).unwrap();
+    /// let padded_grown = padded.snapshot().delta_since(&padded_start).unwrap();
+    /// assert_eq!((grown.bytes_alloc, grown.bytes_dealloc), (65, 1));
+    /// assert_eq!((padded_grown.bytes_alloc, padded_grown.bytes_dealloc), (128, 64));
+    ///
+    /// assert_eq!(
+    ///     padded.snapshot().delta_since(&start),
+    ///     Err(SnapshotError::AccountingMismatch {
+    ///         origin: Accounting::Requested,
+    ///         current: Accounting::PaddedToAlign,
+    ///     })
+    /// );
+    /// ```
+    pub const fn accounting(mut self, accounting: Accounting) -> Self {
+        self.accounting = accounting;
+        self
+    }
+
+    /// The label given to `named`, if any.
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// How the bytes of blocks are counted.
+    pub fn accounting_mode(&self) -> Accounting {
+        self.accounting
+    }
+
     #[inline]
     pub fn info(&self) -> AllocInfo {
-        self.lock.lock_shared();
-        fence(Ordering::SeqCst);
-        let info = self.info;
-        fence(Ordering::SeqCst);
-        self.lock.unlock_shared();
-        info
+        self.info.read().snapshot.info
+    }
+
+    /// The statistics, with the number of times they were reset, so that
+    /// `AllocSnapshot::delta_since` can tell if they were in between.
+    #[inline]
+    pub fn snapshot(&self) -> AllocSnapshot {
+        AllocSnapshot {
+            accounting: self.accounting,
+            ..self.info.read().snapshot
+        }
+    }
+
+    /// What was counted before `mark_main_start` was first called, or `None`
+    /// if it hasn't been. See `mark_main_start`.
+    pub fn baseline(&self) -> Option<AllocInfo> {
+        let stats = self.info.read();
+        stats
+            .startup
+            .or_else(|| main_started().then_some(stats.snapshot.info))
+    }
+
+    /// What was counted before `mark_main_start` was first called, or
+    /// everything so far if it hasn't been. A reset after it starts from
+    /// nothing.
+    pub fn startup_info(&self) -> AllocInfo {
+        let mut stats = self.info.read();
+        stats.mark_startup();
+        stats.startup_info()
+    }
+
+    /// What was counted since `mark_main_start` was first called, or nothing if
+    /// it hasn't been, with the peak of `info`. Together with `startup_info`,
+    /// that adds up to `info`.
+    pub fn since_main(&self) -> AllocInfo {
+        let mut stats = self.info.read();
+        stats.mark_startup();
+        stats.snapshot.info.relative_to(&stats.startup_info())
+    }
+
+    /// Like `info`, but gives up instead of retrying when a write is in progress.
+    /// This never blocks, so it can be used from signal handlers.
+    #[inline]
+    pub fn try_info(&self) -> Option<AllocInfo> {
+        self.info.try_read().map(|stats| stats.snapshot.info)
     }
 
+    /// Replaces the statistics, which counts as a reset.
     #[inline]
     pub fn write_info(&self, new_info: AllocInfo) {
-        let info = &self.info as *const AllocInfo as *mut AllocInfo;
-        self.lock.lock_exclusive();
-        fence(Ordering::SeqCst);
-        unsafe { *info = new_info };
-        fence(Ordering::SeqCst);
-        self.lock.unlock_exclusive();
+        self.info.update(|stats| {
+            stats.snapshot.info = new_info;
+            stats.snapshot.generation += 1;
+            stats.reset_startup();
+        });
+    }
+
+    /// Merges `info` into the statistics, as with `AllocInfo::merge`.
+    pub(crate) fn add(&self, info: &AllocInfo) {
+        self.info.update(|stats| {
+            stats.mark_startup();
+            stats.snapshot.info.merge(info)
+        });
+    }
+
+    /// Starts the statistics over, e.g. between the phases of a benchmark. See
+    /// `take`.
+    pub fn reset(&self) {
+        self.take();
+    }
+
+    /// Returns the statistics and starts them over, in one step, so every call
+    /// is counted either in what's returned or after it, never both or neither.
+    ///
+    /// Bytes that are still live are carried over as `baseline_bytes`, so that
+    /// freeing them later doesn't throw off the live bytes, while `bytes_alloc`
+    /// starts over from zero like the other counters. The peak starts over from
+    /// the live bytes rather than from zero:
+    ///
+    /// ```rust
+    /// use interloc::{AllocAction, AllocMonitor, StatsMonitor};
+    /// use core::alloc::Layout;
+    ///
+    /// let monitor = StatsMonitor::new();
+    /// let layout = Layout::from_size_align(100, 1).unwrap();
+    /// monitor.monitor(layout, AllocAction::Alloc);
+    /// monitor.monitor(layout, AllocAction::Alloc);
+    /// monitor.monitor(layout, AllocAction::Dealloc { ptr: core::ptr::null_mut() });
+    ///
+    /// let phase = monitor.take();
+    /// assert_eq!((phase.alloc, phase.dealloc, phase.peak_bytes), (2, 1, 200));
+    /// let info = monitor.info();
+    /// assert_eq!((info.alloc, info.dealloc, info.bytes_alloc), (0, 0, 0));
+    /// assert_eq!((info.live_bytes(), info.peak_bytes), (100, 100));
+    ///
+    /// monitor.monitor(layout, AllocAction::Alloc);
+    /// let phase = monitor.take();
+    /// assert_eq!((phase.bytes_alloc, phase.baseline_bytes), (100, 100));
+    /// assert_eq!((phase.live_bytes(), phase.peak_bytes), (200, 200));
+    /// assert_eq!(monitor.info().baseline_bytes, 200);
+    /// ```
+    pub fn take(&self) -> AllocInfo {
+        self.info.update(|stats| {
+            let taken = stats.snapshot.info;
+            stats.snapshot.info = taken.carried_over();
+            stats.snapshot.generation += 1;
+            stats.reset_startup();
+            taken
+        })
+    }
+
+    /// How often updates had to wait for each other, and reads had to start
+    /// over, since the monitor was made. Counting costs nothing until there's
+    /// contention.
+    ///
+    /// ```rust
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, ContentionStats, StatsMonitor};
+    ///
+    /// let monitor = StatsMonitor::new();
+    /// let layout = Layout::new::<u64>();
+    /// for _ in 0..1000 {
+    ///     monitor.monitor(layout, AllocAction::Alloc);
+    /// }
+    /// monitor.info();
+    /// assert_eq!(monitor.contention(), ContentionStats::new());
+    ///
+    /// // Under load, updates end up waiting for each other.
+    /// std::thread::scope(|s| {
+    ///     for _ in 0..4 {
+    ///         s.spawn(|| {
+    ///             while monitor.contention().contended == 0 {
+    ///                 for _ in 0..1000 {
+    ///                     monitor.monitor(layout, AllocAction::Alloc);
+    ///                 }
+    ///             }
+    ///         });
+    ///     }
+    /// });
+    /// let contention = monitor.contention();
+    /// assert!(contention.contended > 0);
+    /// assert!(contention.wait_loops >= contention.contended);
+    /// ```
+    #[cfg(feature = "self-metrics")]
+    pub fn contention(&self) -> ContentionStats {
+        self.info.contention()
+    }
+}
+
+#[cfg(feature = "disabled")]
+/// Monitor of global memory usage statistics. With the `disabled` feature, this
+/// is zero-sized and always reports zeroes.
+pub struct StatsMonitor;
+
+#[cfg(feature = "disabled")]
+impl StatsMonitor {
+    pub const fn new() -> Self {
+        Self
+    }
+
+    /// Labels are dropped along with everything else with the `disabled`
+    /// feature.
+    pub const fn named(_: &'static str) -> Self {
+        Self
+    }
+
+    pub const fn accounting(self, _: Accounting) -> Self {
+        self
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        None
+    }
+
+    pub fn accounting_mode(&self) -> Accounting {
+        Accounting::Requested
+    }
+
+    #[inline]
+    pub fn info(&self) -> AllocInfo {
+        AllocInfo::new()
+    }
+
+    #[inline]
+    pub fn try_info(&self) -> Option<AllocInfo> {
+        Some(AllocInfo::new())
+    }
+
+    #[inline]
+    pub fn snapshot(&self) -> AllocSnapshot {
+        AllocSnapshot::new()
+    }
+
+    pub fn baseline(&self) -> Option<AllocInfo> {
+        main_started().then(AllocInfo::new)
+    }
+
+    pub fn startup_info(&self) -> AllocInfo {
+        AllocInfo::new()
+    }
+
+    pub fn since_main(&self) -> AllocInfo {
+        AllocInfo::new()
+    }
+
+    #[inline]
+    pub fn write_info(&self, _: AllocInfo) {}
+
+    pub(crate) fn add(&self, _: &AllocInfo) {}
+
+    pub fn reset(&self) {}
+
+    pub fn take(&self) -> AllocInfo {
+        AllocInfo::new()
+    }
+
+    #[cfg(feature = "self-metrics")]
+    pub fn contention(&self) -> ContentionStats {
+        ContentionStats::new()
+    }
+}
+
+/// A one-line summary of the statistics, prefixed with the monitor's label if it
+/// has one.
+impl core::fmt::Display for StatsMonitor {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let info = self.info();
+        if let Some(name) = self.name() {
+            write!(f, "{}: ", name)?;
+        }
+        write!(
+            f,
+            "alloc {}, dealloc {}, realloc {}, bytes_alloc {}, bytes_dealloc {}, live {}, peak {}",
+            info.alloc,
+            info.dealloc,
+            info.realloc,
+            ByteSize(info.bytes_alloc as u128),
+            ByteSize(info.bytes_dealloc as u128),
+            ByteSize(info.live_bytes() as u128),
+            ByteSize(info.peak_bytes as u128),
+        )
+    }
+}
+
+impl Default for StatsMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InfoSource for StatsMonitor {
+    #[inline]
+    fn info(&self) -> AllocInfo {
+        StatsMonitor::info(self)
     }
 }
 
 impl AllocMonitor for StatsMonitor {
+    #[cfg(not(feature = "disabled"))]
     fn monitor(&self, layout: Layout, action: AllocAction) {
-        let info = self.info();
-        self.write_info(info.after_call(layout, action));
+        self.info.update(|stats| {
+            stats.mark_startup();
+            stats
+                .snapshot
+                .info
+                .apply_with(self.accounting, layout, action)
+        });
+    }
+
+    #[cfg(feature = "disabled")]
+    fn monitor(&self, _: Layout, _: AllocAction) {}
+
+    fn describe(&self) -> MonitorDesc {
+        let overhead = if cfg!(feature = "disabled") {
+            Overhead::Free
+        } else {
+            Overhead::Atomic
+        };
+        let desc = MonitorDesc::new("StatsMonitor", overhead);
+        match self.accounting_mode() {
+            Accounting::Requested => desc,
+            Accounting::PaddedToAlign => desc.param("padded_to_align", 1),
+        }
     }
 }
 
 /// Thread-local statistics on memory usage.
-pub struct ThreadMonitor;
+///
+/// The statistics of a thread are shared by every `ThreadMonitor`, so the one
+/// that counts them, in the allocator, decides how they count bytes, and the
+/// others should be made with the same `Accounting` to stamp their snapshots
+/// right.
+pub struct ThreadMonitor {
+    accounting: Accounting,
+}
 
 impl ThreadMonitor {
     thread_local! {
-    static THREAD_INFO: RefCell<AllocInfo> = RefCell::new(AllocInfo::new());
+    static THREAD_INFO: RefCell<AllocInfo> = const { RefCell::new(AllocInfo::new()) };
+    static GENERATION: Cell<u64> = const { Cell::new(0) };
     }
 
     pub const fn new() -> Self {
-        Self {}
+        Self {
+            accounting: Accounting::Requested,
+        }
+    }
+
+    /// Counts the bytes of blocks as `accounting` says, like
+    /// `StatsMonitor::accounting`, `Accounting::Requested` by default.
+    ///
+    /// ```rust
+    /// use core::alloc::Layout;
+    /// use interloc::{Accounting, AllocAction, AllocMonitor, SnapshotError, ThreadMonitor};
+    ///
+    /// let requested = ThreadMonitor::new();
+    /// let padded = ThreadMonitor::new().accounting(Accounting::PaddedToAlign);
+    /// let layout = Layout::from_size_align(1, 64).unwrap();
+    /// let start = requested.info();
+    /// requested.monitor(layout, AllocAction::Alloc);
+    /// let after_requested = requested.info();
+    /// padded.monitor(layout, AllocAction::Alloc);
+    /// let after_padded = padded.info();
+    /// assert_eq!(after_requested.relative_to(&start).bytes_alloc, 1);
+    /// assert_eq!(after_padded.relative_to(&after_requested).bytes_alloc, 64);
+    ///
+    /// // They share the thread's statistics, but not the stamp on snapshots.
+    /// assert_eq!(
+    ///     padded.snapshot().delta_since(&requested.snapshot()),
+    ///     Err(SnapshotError::AccountingMismatch {
+    ///         origin: Accounting::Requested,
+    ///         current: Accounting::PaddedToAlign,
+    ///     })
+    /// );
+    /// ```
+    pub const fn accounting(mut self, accounting: Accounting) -> Self {
+        self.accounting = accounting;
+        self
+    }
+
+    /// How the bytes of blocks are counted.
+    pub fn accounting_mode(&self) -> Accounting {
+        self.accounting
     }
 
     /// Returns an `AllocInfo` struct with information only related
@@ -132,14 +1050,102 @@ impl ThreadMonitor {
         Self::THREAD_INFO.with(|i| *i.borrow())
     }
 
-    /// Writes to the history of the current thread of execution only.
+    /// Like `info`, but returns `None` instead of panicking if the thread's
+    /// statistics are being written to, e.g. when called from a panic hook while
+    /// the thread panicked inside a monitor.
+    pub(crate) fn try_info(&self) -> Option<AllocInfo> {
+        Self::THREAD_INFO.with(|i| i.try_borrow().ok().map(|i| *i))
+    }
+
+    /// The statistics of the current thread, with the number of times they were
+    /// reset.
+    pub fn snapshot(&self) -> AllocSnapshot {
+        AllocSnapshot {
+            info: self.info(),
+            generation: Self::GENERATION.with(|g| g.get()),
+            accounting: self.accounting,
+        }
+    }
+
+    /// Writes to the history of the current thread of execution only, which
+    /// counts as a reset.
     pub fn write_info(&self, info: AllocInfo) {
         Self::THREAD_INFO.with(|i| *i.borrow_mut() = info);
+        Self::bump_generation();
+    }
+
+    /// Sets the peak of the current thread, for measurements that start it over
+    /// from the live bytes and then put it back, without counting as a reset.
+    pub(crate) fn set_peak(&self, peak_bytes: u64) {
+        Self::THREAD_INFO.with(|i| i.borrow_mut().peak_bytes = peak_bytes);
+    }
+
+    fn bump_generation() {
+        Self::GENERATION.with(|g| g.set(g.get() + 1));
+    }
+
+    /// Starts the statistics of the current thread over. See `take`.
+    pub fn reset(&self) {
+        self.take();
+    }
+
+    /// Returns the statistics of the current thread and starts them over. Like
+    /// `StatsMonitor::take`, bytes that are still live are carried over as the
+    /// baseline, and the peak starts over from them.
+    pub fn take(&self) -> AllocInfo {
+        let taken = Self::THREAD_INFO.with(|i| {
+            let mut info = i.borrow_mut();
+            let taken = *info;
+            *info = taken.carried_over();
+            taken
+        });
+        Self::bump_generation();
+        taken
+    }
+}
+
+impl Default for ThreadMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InfoSource for ThreadMonitor {
+    fn info(&self) -> AllocInfo {
+        ThreadMonitor::info(self)
     }
 }
 
 impl AllocMonitor for ThreadMonitor {
     fn monitor(&self, layout: Layout, action: AllocAction) {
-        self.write_info(self.info().after_call(layout, action));
+        Self::THREAD_INFO.with(|i| i.borrow_mut().apply_with(self.accounting, layout, action));
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        let desc = MonitorDesc::new("ThreadMonitor", Overhead::ThreadLocal);
+        match self.accounting {
+            Accounting::Requested => desc,
+            Accounting::PaddedToAlign => desc.param("padded_to_align", 1),
+        }
+    }
+}
+
+/// A monitor that does nothing, as a baseline for measuring others, or a
+/// placeholder.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopMonitor;
+
+impl NoopMonitor {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl AllocMonitor for NoopMonitor {
+    #[inline]
+    fn monitor(&self, _: Layout, _: AllocAction) {}
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("NoopMonitor", Overhead::Free)
     }
 }
@@ -0,0 +1,98 @@
+#[cfg(not(feature = "disabled"))]
+use crate::fmt::FmtBuffer;
+#[cfg(not(feature = "disabled"))]
+use core::any::Any;
+#[cfg(not(feature = "disabled"))]
+use core::fmt::Write as _;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+#[cfg(not(feature = "disabled"))]
+use std::io::Write as _;
+
+/// What `InterAlloc` does when its monitor panics, as set with
+/// `set_monitor_panic_policy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum MonitorPanicPolicy {
+    /// Lets the panic unwind out of the allocator, the default. `GlobalAlloc`
+    /// methods aren't allowed to unwind, so depending on the toolchain and the
+    /// caller, this aborts with little more than the panic message, or
+    /// unwinds through code that doesn't expect it.
+    ReRaise = 0,
+    /// Drops the panic and carries on with the call to the inner allocator, as
+    /// if the monitor had returned.
+    Ignore = 1,
+    /// Prints which monitor panicked to stderr, without allocating, and aborts.
+    Abort = 2,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(MonitorPanicPolicy::ReRaise as u8);
+static PANICS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets what every `InterAlloc` in the process does when its monitor panics,
+/// including in `AllocMonitor::on_first_event`.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, InterAlloc, MonitorPanicPolicy};
+/// use std::alloc::System;
+///
+/// struct Picky;
+///
+/// impl AllocMonitor for Picky {
+///     fn monitor(&self, layout: Layout, action: AllocAction) {
+///         if layout.size() == 12345 && action == AllocAction::Alloc {
+///             panic!("not that size");
+///         }
+///     }
+/// }
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, Picky> = InterAlloc {
+///     inner: System,
+///     monitor: &Picky,
+/// };
+///
+/// interloc::set_monitor_panic_policy(MonitorPanicPolicy::Ignore);
+/// let block = std::hint::black_box(vec![7u8; 12345]);
+/// let again = std::hint::black_box(vec![7u8; 12345]);
+/// assert_eq!(block, again);
+/// assert_eq!(interloc::monitor_panics(), 2);
+/// ```
+pub fn set_monitor_panic_policy(policy: MonitorPanicPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+pub fn monitor_panic_policy() -> MonitorPanicPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => MonitorPanicPolicy::Ignore,
+        2 => MonitorPanicPolicy::Abort,
+        _ => MonitorPanicPolicy::ReRaise,
+    }
+}
+
+/// How many times a monitor panicked inside an `InterAlloc`, under any policy.
+pub fn monitor_panics() -> usize {
+    PANICS.load(Ordering::Relaxed)
+}
+
+/// Handles a panic caught from the monitor `M`, according to the policy.
+#[cfg(not(feature = "disabled"))]
+#[cold]
+pub(crate) fn monitor_panicked<M: ?Sized>(payload: Box<dyn Any + Send>) {
+    PANICS.fetch_add(1, Ordering::Relaxed);
+    match monitor_panic_policy() {
+        MonitorPanicPolicy::ReRaise => std::panic::resume_unwind(payload),
+        MonitorPanicPolicy::Ignore => drop(payload),
+        MonitorPanicPolicy::Abort => {
+            let mut buf = FmtBuffer::<256>::new();
+            let _ = writeln!(
+                buf,
+                "interloc: monitor {} panicked inside the allocator, aborting",
+                core::any::type_name::<M>()
+            );
+            let _ = std::io::stderr().write_all(buf.as_bytes());
+            std::process::abort();
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Memory usage as the operating system sees it.
+//!
+//! These read the OS's accounting on demand, which is slow next to a monitor
+//! and may allocate, so they're meant to be called now and then from user code,
+//! never from a monitor.
+
+/// The resident set size of the current process, in bytes: its memory that's
+/// in RAM, heap or not. `None` if it couldn't be read, or the platform isn't
+/// supported.
+///
+/// Read from `/proc/self/statm` on Linux, `task_info` on macOS, and
+/// `GetProcessMemoryInfo` on Windows.
+pub fn rss_bytes() -> Option<usize> {
+    imp::rss_bytes()
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::raw::{c_int, c_long};
+
+    extern "C" {
+        fn sysconf(name: c_int) -> c_long;
+    }
+
+    /// The same on every architecture, with both glibc and musl.
+    const SC_PAGESIZE: c_int = 30;
+
+    pub fn rss_bytes() -> Option<usize> {
+        // The second field is the resident set size, in pages.
+        let mut buf = [0u8; 128];
+        let len = File::open("/proc/self/statm").ok()?.read(&mut buf).ok()?;
+        let statm = core::str::from_utf8(&buf[..len]).ok()?;
+        let pages: usize = statm.split_ascii_whitespace().nth(1)?.parse().ok()?;
+        let page_size = unsafe { sysconf(SC_PAGESIZE) };
+        if page_size <= 0 {
+            return None;
+        }
+        pages.checked_mul(page_size as usize)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use core::convert::TryFrom;
+    use core::mem::size_of;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct TimeValue {
+        seconds: i32,
+        microseconds: i32,
+    }
+
+    /// `mach_task_basic_info`, which is declared with 4-byte packing.
+    #[repr(C, packed(4))]
+    #[derive(Default)]
+    struct MachTaskBasicInfo {
+        virtual_size: u64,
+        resident_size: u64,
+        resident_size_max: u64,
+        user_time: TimeValue,
+        system_time: TimeValue,
+        policy: i32,
+        suspend_count: i32,
+    }
+
+    const MACH_TASK_BASIC_INFO: u32 = 20;
+
+    extern "C" {
+        static mach_task_self_: u32;
+        fn task_info(task: u32, flavor: u32, info: *mut i32, count: *mut u32) -> i32;
+    }
+
+    pub fn rss_bytes() -> Option<usize> {
+        let mut info = MachTaskBasicInfo::default();
+        let mut count = (size_of::<MachTaskBasicInfo>() / size_of::<u32>()) as u32;
+        let result = unsafe {
+            task_info(
+                mach_task_self_,
+                MACH_TASK_BASIC_INFO,
+                &mut info as *mut MachTaskBasicInfo as *mut i32,
+                &mut count,
+            )
+        };
+        if result != 0 {
+            return None;
+        }
+        let resident = info.resident_size;
+        usize::try_from(resident).ok()
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use core::ffi::c_void;
+    use core::mem::size_of;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct ProcessMemoryCounters {
+        cb: u32,
+        page_fault_count: u32,
+        peak_working_set_size: usize,
+        working_set_size: usize,
+        quota_peak_paged_pool_usage: usize,
+        quota_paged_pool_usage: usize,
+        quota_peak_non_paged_pool_usage: usize,
+        quota_non_paged_pool_usage: usize,
+        pagefile_usage: usize,
+        peak_pagefile_usage: usize,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcess() -> *mut c_void;
+        fn K32GetProcessMemoryInfo(
+            process: *mut c_void,
+            counters: *mut ProcessMemoryCounters,
+            cb: u32,
+        ) -> i32;
+    }
+
+    pub fn rss_bytes() -> Option<usize> {
+        let cb = size_of::<ProcessMemoryCounters>() as u32;
+        let mut counters = ProcessMemoryCounters {
+            cb,
+            ..Default::default()
+        };
+        let ok = unsafe { K32GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, cb) };
+        if ok == 0 {
+            return None;
+        }
+        Some(counters.working_set_size)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod imp {
+    pub fn rss_bytes() -> Option<usize> {
+        None
+    }
+}
@@ -0,0 +1,184 @@
+//! Bridge from monitors like `StatsMonitor` to OpenTelemetry metrics.
+//!
+//! The instruments created here are asynchronous: the OpenTelemetry SDK calls
+//! back into interloc when it collects, and each callback takes a snapshot of the
+//! monitor with `InfoSource::info`. Nothing is done on the allocation path.
+use crate::monitor::{AllocInfo, InfoSource};
+use opentelemetry::metrics::{Meter, ObservableCounter, ObservableGauge};
+
+/// The instruments registered by `register`. The SDK keeps the callbacks alive,
+/// but the handles are returned so that they can be held onto alongside the
+/// meter.
+pub struct Instruments {
+    pub alloc: ObservableCounter<u64>,
+    pub dealloc: ObservableCounter<u64>,
+    pub realloc: ObservableCounter<u64>,
+    pub bytes_alloc: ObservableCounter<u64>,
+    pub bytes_dealloc: ObservableCounter<u64>,
+    pub live_bytes: ObservableGauge<u64>,
+    pub peak_bytes: ObservableGauge<u64>,
+}
+
+fn counter<S: InfoSource + Sync>(
+    meter: &Meter,
+    source: &'static S,
+    name: &'static str,
+    unit: &'static str,
+    description: &'static str,
+    field: fn(&AllocInfo) -> u64,
+) -> ObservableCounter<u64> {
+    meter
+        .u64_observable_counter(name)
+        .with_unit(unit)
+        .with_description(description)
+        .with_callback(move |obs| obs.observe(field(&source.info()), &[]))
+        .build()
+}
+
+fn gauge<S: InfoSource + Sync>(
+    meter: &Meter,
+    source: &'static S,
+    name: &'static str,
+    description: &'static str,
+    field: fn(&AllocInfo) -> u64,
+) -> ObservableGauge<u64> {
+    meter
+        .u64_observable_gauge(name)
+        .with_unit("By")
+        .with_description(description)
+        .with_callback(move |obs| obs.observe(field(&source.info()), &[]))
+        .build()
+}
+
+/// Registers observable instruments on `meter` that report the statistics of
+/// `source`. Counts use the unit `{call}` and byte amounts use `By`. Callbacks
+/// run on whichever thread the SDK collects from, so a `ThreadMonitor` source
+/// reports that thread's allocations.
+///
+/// | Instrument               | Kind    |
+/// |--------------------------|---------|
+/// | `interloc.alloc`         | counter |
+/// | `interloc.dealloc`       | counter |
+/// | `interloc.realloc`       | counter |
+/// | `interloc.bytes_alloc`   | counter |
+/// | `interloc.bytes_dealloc` | counter |
+/// | `interloc.live_bytes`    | gauge   |
+/// | `interloc.peak_bytes`    | gauge   |
+///
+/// ```rust
+/// use core::alloc::Layout;
+/// use interloc::{otel, AllocAction, AllocMonitor, StatsMonitor};
+/// use opentelemetry::metrics::MeterProvider as _;
+/// use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+/// use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// let exporter = InMemoryMetricExporter::default();
+/// let provider = SdkMeterProvider::builder()
+///     .with_reader(PeriodicReader::builder(exporter.clone()).build())
+///     .build();
+/// let _instruments = otel::register(&provider.meter("app"), &MONITOR);
+///
+/// let ptr = core::ptr::null_mut();
+/// for size in [100, 200, 300] {
+///     let layout = Layout::from_size_align(size, 8).unwrap();
+///     MONITOR.monitor(layout, AllocAction::Alloc);
+/// }
+/// let layout = Layout::from_size_align(200, 8).unwrap();
+/// MONITOR.monitor(layout, AllocAction::Dealloc { ptr });
+/// provider.force_flush().unwrap();
+///
+/// let mut exported = Vec::new();
+/// for resource in exporter.get_finished_metrics().unwrap() {
+///     for scope in resource.scope_metrics() {
+///         for metric in scope.metrics() {
+///             let (kind, value) = match metric.data() {
+///                 AggregatedMetrics::U64(MetricData::Sum(sum)) => {
+///                     assert!(sum.is_monotonic());
+///                     ("counter", sum.data_points().next().unwrap().value())
+///                 }
+///                 AggregatedMetrics::U64(MetricData::Gauge(gauge)) => {
+///                     ("gauge", gauge.data_points().next().unwrap().value())
+///                 }
+///                 data => panic!("unexpected data {:?}", data),
+///             };
+///             exported.push((metric.name().to_owned(), metric.unit().to_owned(), kind, value));
+///         }
+///     }
+/// }
+/// exported.sort();
+/// let expected = [
+///     ("interloc.alloc", "{call}", "counter", 3),
+///     ("interloc.bytes_alloc", "By", "counter", 600),
+///     ("interloc.bytes_dealloc", "By", "counter", 200),
+///     ("interloc.dealloc", "{call}", "counter", 1),
+///     ("interloc.live_bytes", "By", "gauge", 400),
+///     ("interloc.peak_bytes", "By", "gauge", 600),
+///     ("interloc.realloc", "{call}", "counter", 0),
+/// ];
+/// let expected: Vec<_> = expected
+///     .iter()
+///     .map(|&(name, unit, kind, value)| (name.to_owned(), unit.to_owned(), kind, value))
+///     .collect();
+/// assert_eq!(exported, expected);
+/// provider.shutdown().unwrap();
+/// ```
+pub fn register<S: InfoSource + Sync>(meter: &Meter, source: &'static S) -> Instruments {
+    Instruments {
+        alloc: counter(
+            meter,
+            source,
+            "interloc.alloc",
+            "{call}",
+            "Number of calls to alloc",
+            |i| i.alloc,
+        ),
+        dealloc: counter(
+            meter,
+            source,
+            "interloc.dealloc",
+            "{call}",
+            "Number of calls to dealloc",
+            |i| i.dealloc,
+        ),
+        realloc: counter(
+            meter,
+            source,
+            "interloc.realloc",
+            "{call}",
+            "Number of calls to realloc",
+            |i| i.realloc,
+        ),
+        bytes_alloc: counter(
+            meter,
+            source,
+            "interloc.bytes_alloc",
+            "By",
+            "Total bytes allocated",
+            |i| i.bytes_alloc,
+        ),
+        bytes_dealloc: counter(
+            meter,
+            source,
+            "interloc.bytes_dealloc",
+            "By",
+            "Total bytes deallocated",
+            |i| i.bytes_dealloc,
+        ),
+        live_bytes: gauge(
+            meter,
+            source,
+            "interloc.live_bytes",
+            "Bytes currently allocated",
+            AllocInfo::live_bytes,
+        ),
+        peak_bytes: gauge(
+            meter,
+            source,
+            "interloc.peak_bytes",
+            "Highest number of bytes live at once",
+            |i| i.peak_bytes,
+        ),
+    }
+}
@@ -0,0 +1,131 @@
+use crate::alloc::realloc_via_alloc_copy;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The page size, once it's been asked for.
+static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+fn page_size() -> usize {
+    match PAGE_SIZE.load(Ordering::Relaxed) {
+        0 => {
+            let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+            PAGE_SIZE.store(size, Ordering::Relaxed);
+            size
+        }
+        size => size,
+    }
+}
+
+/// An allocator that maps pages of its own from the OS for every block, and
+/// puts the block at the end of them, right before an inaccessible guard page,
+/// so that reading or writing past the end of a block faults at once. Blocks
+/// are aligned down from the guard page, so accesses up to `align - 1` bytes
+/// past the end of a block don't fault. Freeing a block unmaps its pages, so
+/// that using it after it's freed faults too, until its addresses are mapped
+/// again.
+///
+/// Every block costs at least two pages and two system calls, so this is for
+/// finding overruns in tests, not for production. Alignments larger than a page
+/// aren't supported, and allocations asking for one return null, like those the
+/// OS can't map. Reallocations always move the block, to keep it against the
+/// guard page; when that fails, the old block is left as it was. Unix only.
+///
+/// ```rust
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::PageGuardAlloc;
+///
+/// let alloc = PageGuardAlloc::new();
+/// let layout = Layout::from_size_align(100, 4).unwrap();
+/// unsafe {
+///     let ptr = alloc.alloc(layout);
+///     ptr.write_bytes(1, 100);
+///     // The block ends where the guard page starts.
+///     assert_eq!((ptr as usize + 100) % 4096, 0);
+///     let ptr = alloc.realloc(ptr, layout, 5000);
+///     assert_eq!(*ptr.add(99), 1);
+///     alloc.dealloc(ptr, Layout::from_size_align(5000, 4).unwrap());
+/// }
+/// assert_eq!(alloc.mapped(), 0);
+/// ```
+pub struct PageGuardAlloc {
+    mapped: AtomicU64,
+}
+
+impl PageGuardAlloc {
+    pub const fn new() -> Self {
+        Self {
+            mapped: AtomicU64::new(0),
+        }
+    }
+
+    /// The bytes mapped for blocks live in the allocator, guard pages included.
+    pub fn mapped(&self) -> u64 {
+        self.mapped.load(Ordering::Relaxed)
+    }
+
+    /// The bytes mapped for the block before its guard page, if it's not too
+    /// big.
+    fn data_len(layout: Layout, page: usize) -> Option<usize> {
+        layout.size().checked_next_multiple_of(page)
+    }
+}
+
+impl Default for PageGuardAlloc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for PageGuardAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let page = page_size();
+        let data = match Self::data_len(layout, page) {
+            Some(data) if layout.align() <= page => data,
+            _ => return ptr::null_mut(),
+        };
+        let len = match data.checked_add(page) {
+            Some(len) => len,
+            None => return ptr::null_mut(),
+        };
+        let base = libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if base == libc::MAP_FAILED {
+            return ptr::null_mut();
+        }
+        let base = base as *mut u8;
+        if libc::mprotect(base.add(data) as *mut libc::c_void, page, libc::PROT_NONE) != 0 {
+            libc::munmap(base as *mut libc::c_void, len);
+            return ptr::null_mut();
+        }
+        self.mapped.fetch_add(len as u64, Ordering::Relaxed);
+        // Less than a page in, since the block takes less than a page more
+        // than the pages before the last.
+        let offset = (data - layout.size()) & !(layout.align() - 1);
+        base.add(offset)
+    }
+
+    /// Fresh mappings are zeroed already.
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let page = page_size();
+        // The layout was checked when the block was allocated.
+        let len = Self::data_len(layout, page).unwrap() + page;
+        let base = (ptr as usize & !(page - 1)) as *mut libc::c_void;
+        libc::munmap(base, len);
+        self.mapped.fetch_sub(len as u64, Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        realloc_via_alloc_copy(self, ptr, layout, new_size)
+    }
+}
@@ -0,0 +1,73 @@
+//! Allocation summaries attached to panics.
+//!
+//! After `install` is called, every panic prints an extra line after the output
+//! of the previously installed panic hook, with the allocations made by the
+//! panicking thread since it last called `anchor`:
+//!
+//! ```text
+//! interloc: allocations since anchor: {"alloc":3,"dealloc":1,...}
+//! ```
+//!
+//! Threads that never called `anchor` report everything since they started.
+use crate::fmt::FmtBuffer;
+use crate::monitor::{AllocInfo, ThreadMonitor};
+use core::cell::Cell;
+use core::fmt::Write as _;
+use std::io::Write as _;
+
+thread_local! {
+    static ANCHOR: Cell<AllocInfo> = const { Cell::new(AllocInfo::new()) };
+    static IN_HOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current point in the current thread's allocations, so that
+/// summaries of panics on this thread only include allocations made after it.
+pub fn anchor() {
+    ANCHOR.with(|a| a.set(ThreadMonitor::new().info()));
+}
+
+/// Whether every counter in `anchor` is at most the matching one in `info`,
+/// i.e. `info.relative_to(anchor)` won't overflow.
+fn precedes(anchor: &AllocInfo, info: &AllocInfo) -> bool {
+    anchor.alloc <= info.alloc
+        && anchor.dealloc <= info.dealloc
+        && anchor.realloc <= info.realloc
+        && anchor.bytes_alloc <= info.bytes_alloc
+        && anchor.bytes_dealloc <= info.bytes_dealloc
+}
+
+/// Chains a panic hook after the current one that prints the allocations made
+/// by the panicking thread since its last `anchor`, as recorded by `monitor`.
+///
+/// The summary is formatted without allocating. If the thread panics again
+/// while the summary is being written, the nested panic doesn't print one.
+pub fn install(monitor: &'static ThreadMonitor) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        if IN_HOOK.with(|h| h.replace(true)) {
+            return;
+        }
+
+        let mut buf = FmtBuffer::<320>::new();
+        let _ = buf.write_str("interloc: allocations since anchor: ");
+        match monitor.try_info() {
+            Some(current) => {
+                let anchor = ANCHOR.with(Cell::get);
+                let delta = if precedes(&anchor, &current) {
+                    current.relative_to(&anchor)
+                } else {
+                    current
+                };
+                let _ = delta.write_json(&mut buf);
+            }
+            None => {
+                let _ = buf.write_str("unavailable");
+            }
+        }
+        let _ = buf.write_str("\n");
+        let _ = std::io::stderr().write_all(buf.as_bytes());
+
+        IN_HOOK.with(|h| h.set(false));
+    }));
+}
@@ -0,0 +1,137 @@
+//! SVG plots of allocation statistics over time.
+//!
+//! `render_timeline` draws the live bytes of a series of snapshots, like the
+//! ones written by `SnapshotRecorder`, and `TimelinePlot` can add a second chart
+//! of the allocation rate below it. Axes are labeled in seconds and binary byte
+//! units.
+use crate::fmt::ByteSize;
+use crate::monitor::AllocInfo;
+use plotters::prelude::*;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// A snapshot of allocation statistics at a point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimelineEntry {
+    /// Time since the start of the timeline
+    pub timestamp: Duration,
+    pub info: AllocInfo,
+}
+
+/// Draws timelines of allocation statistics as SVG files.
+#[derive(Clone, Copy, Debug)]
+pub struct TimelinePlot {
+    width: u32,
+    height: u32,
+    alloc_rate: bool,
+}
+
+impl TimelinePlot {
+    pub const fn new() -> Self {
+        Self {
+            width: 1024,
+            height: 480,
+            alloc_rate: false,
+        }
+    }
+
+    /// Sets the size of the image, in pixels.
+    pub const fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Adds a chart of allocations per second, between each entry and the
+    /// previous one, below the chart of live bytes.
+    pub const fn alloc_rate(mut self, alloc_rate: bool) -> Self {
+        self.alloc_rate = alloc_rate;
+        self
+    }
+
+    /// Draws `entries`, which should be in order of time, to an SVG file at
+    /// `path`.
+    pub fn render(&self, entries: &[TimelineEntry], path: impl AsRef<Path>) -> io::Result<()> {
+        let root = SVGBackend::new(path.as_ref(), (self.width, self.height)).into_drawing_area();
+        self.draw(&root, entries).map_err(io::Error::other)?;
+        root.present().map_err(io::Error::other)
+    }
+
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, plotters::coord::Shift>,
+        entries: &[TimelineEntry],
+    ) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+        root.fill(&WHITE)?;
+        let (live_area, rate_area) = if self.alloc_rate {
+            let (top, bottom) = root.split_vertically(self.height / 2);
+            (top, Some(bottom))
+        } else {
+            (root.clone(), None)
+        };
+
+        let secs = |e: &TimelineEntry| e.timestamp.as_secs_f64();
+        let end = entries.last().map(secs).unwrap_or(0.0).max(f64::EPSILON);
+        let start = entries.first().map(secs).unwrap_or(0.0).min(end);
+
+        let live: Vec<(f64, f64)> = entries
+            .iter()
+            .map(|e| (secs(e), e.info.live_bytes() as f64))
+            .collect();
+        let max_live = live.iter().map(|p| p.1).fold(1.0, f64::max);
+        let mut chart = ChartBuilder::on(&live_area)
+            .caption("Live bytes", ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(80)
+            .build_cartesian_2d(start..end, 0.0..max_live * 1.05)?;
+        chart
+            .configure_mesh()
+            .x_desc("time")
+            .y_desc("live")
+            .x_label_formatter(&|s| format!("{:.1} s", s))
+            .y_label_formatter(&|b| ByteSize(*b as u128).to_string())
+            .draw()?;
+        chart.draw_series(LineSeries::new(live, &BLUE))?;
+
+        if let Some(rate_area) = rate_area {
+            let rate: Vec<(f64, f64)> = entries
+                .windows(2)
+                .map(|w| {
+                    let elapsed = (secs(&w[1]) - secs(&w[0])).max(f64::EPSILON);
+                    let allocs = w[1].info.alloc.saturating_sub(w[0].info.alloc);
+                    (secs(&w[1]), allocs as f64 / elapsed)
+                })
+                .collect();
+            let max_rate = rate.iter().map(|p| p.1).fold(1.0, f64::max);
+            let mut chart = ChartBuilder::on(&rate_area)
+                .caption("Allocation rate", ("sans-serif", 20))
+                .margin(10)
+                .x_label_area_size(30)
+                .y_label_area_size(80)
+                .build_cartesian_2d(start..end, 0.0..max_rate * 1.05)?;
+            chart
+                .configure_mesh()
+                .x_desc("time")
+                .y_desc("allocs/s")
+                .x_label_formatter(&|s| format!("{:.1} s", s))
+                .y_label_formatter(&|r| format!("{:.0}/s", r))
+                .draw()?;
+            chart.draw_series(LineSeries::new(rate, &RED))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for TimelinePlot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Draws the live bytes of `entries` over time to an SVG file at `path`, with
+/// the default size and no allocation rate chart.
+pub fn render_timeline(entries: &[TimelineEntry], path: impl AsRef<Path>) -> io::Result<()> {
+    TimelinePlot::new().render(entries, path)
+}
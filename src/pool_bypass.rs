@@ -0,0 +1,148 @@
+use crate::alloc::{suppress, AllocAction, AllocMonitor};
+use crate::callsite::current_location;
+use crate::describe::{MonitorDesc, Overhead};
+use crate::slots::Slots;
+use crate::tag::current_tag;
+use core::alloc::Layout;
+use core::panic::Location;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// An allocation of a pooled layout, passed to a `PoolBypassHandler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolBypass {
+    pub layout: Layout,
+    /// Tag of the allocating thread, from `with_tag`, or zero if it had none
+    pub tag: u32,
+    /// Location the allocation was attributed to, from `attributed` or
+    /// `trace_alloc!`, if any
+    pub location: Option<&'static Location<'static>>,
+}
+
+/// Called on every allocation of a pooled layout.
+pub type PoolBypassHandler = fn(PoolBypass);
+
+/// A monitor that counts allocations of layouts that are supposed to come from
+/// a pool, to check that the pools actually keep them away from the allocator.
+///
+/// The layouts are given to `new`, and an allocation counts against one only if
+/// its size and alignment are exactly the same. Reallocations don't count,
+/// since a pooled object doesn't grow. The handler set with `on_bypass`, if
+/// any, is called on every one of them with monitoring suppressed, with the
+/// thread's tag and location, to find the code that went around the pool.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{with_tag, InterAlloc, PoolBypass, PoolBypassMonitor};
+/// use std::alloc::System;
+/// use std::sync::atomic::{AtomicU32, Ordering};
+///
+/// struct Node([u64; 4]);
+/// struct Packet([u8; 1500]);
+///
+/// const PARSER: u32 = 7;
+/// static NODE_TAG: AtomicU32 = AtomicU32::new(0);
+///
+/// fn bypass(bypass: PoolBypass) {
+///     if bypass.layout == Layout::new::<Node>() {
+///         NODE_TAG.store(bypass.tag, Ordering::Relaxed);
+///     }
+/// }
+///
+/// static MONITOR: PoolBypassMonitor<2> =
+///     PoolBypassMonitor::new([Layout::new::<Node>(), Layout::new::<Packet>()]).on_bypass(bypass);
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, PoolBypassMonitor<2>> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// let before = MONITOR.total();
+/// // Same size as a Node, but not the same alignment.
+/// drop(std::hint::black_box(vec![0u8; 32]));
+/// drop(std::hint::black_box(vec![0u32; 8]));
+/// assert_eq!(MONITOR.total(), before);
+///
+/// with_tag(PARSER, || drop(std::hint::black_box(Box::new(Node([0; 4])))));
+/// drop(std::hint::black_box(Box::new(Packet([0; 1500]))));
+/// drop(std::hint::black_box(Box::new(Packet([0; 1500]))));
+/// assert_eq!(MONITOR.bypasses(Layout::new::<Node>()), Some(1));
+/// assert_eq!(MONITOR.bypasses(Layout::new::<Packet>()), Some(2));
+/// assert_eq!(MONITOR.bypasses(Layout::new::<u8>()), None);
+/// assert_eq!(MONITOR.total(), before + 3);
+/// assert_eq!(NODE_TAG.load(Ordering::Relaxed), PARSER);
+/// ```
+pub struct PoolBypassMonitor<const N: usize> {
+    layouts: [Layout; N],
+    bypasses: Slots<AtomicU64, N>,
+    handler: Option<PoolBypassHandler>,
+}
+
+impl<const N: usize> PoolBypassMonitor<N> {
+    /// A monitor of allocations of the pooled `layouts`.
+    pub const fn new(layouts: [Layout; N]) -> Self {
+        Self {
+            layouts,
+            bypasses: Slots::new([const { AtomicU64::new(0) }; N]),
+            handler: None,
+        }
+    }
+
+    /// Calls `handler` on every allocation of a pooled layout.
+    pub const fn on_bypass(mut self, handler: PoolBypassHandler) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    pub fn layouts(&self) -> &[Layout; N] {
+        &self.layouts
+    }
+
+    /// How many allocations of `layout` there were, or `None` if it isn't
+    /// pooled.
+    pub fn bypasses(&self, layout: Layout) -> Option<u64> {
+        let i = self.layouts.iter().position(|&pooled| pooled == layout)?;
+        Some(
+            self.bypasses
+                .get(i)
+                .map_or(0, |count| count.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// How many allocations of any pooled layout there were.
+    pub fn total(&self) -> u64 {
+        self.bypasses
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+impl<const N: usize> AllocMonitor for PoolBypassMonitor<N> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        if !matches!(action, AllocAction::Alloc | AllocAction::AllocZeroed) {
+            return;
+        }
+        let i = match self.layouts.iter().position(|&pooled| pooled == layout) {
+            Some(i) => i,
+            None => return,
+        };
+        if let Some(count) = self.bypasses.get(i) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(handler) = self.handler {
+            let bypass = PoolBypass {
+                layout,
+                tag: current_tag(),
+                location: current_location(),
+            };
+            suppress(|| handler(bypass));
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("PoolBypassMonitor", Overhead::Table).param("layouts", N as u64)
+    }
+}
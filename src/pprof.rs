@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::io;
+
+/// A sample of a heap profile, for `PprofWriter`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PprofSample<'a> {
+    /// Return addresses of the allocation stack, innermost first
+    pub frames: &'a [usize],
+    /// Blocks allocated at this stack that are still live
+    pub inuse_objects: i64,
+    /// Bytes allocated at this stack that are still live
+    pub inuse_bytes: i64,
+    /// Blocks allocated at this stack over the whole run
+    pub alloc_objects: i64,
+    /// Bytes allocated at this stack over the whole run
+    pub alloc_bytes: i64,
+}
+
+/// Minimal protobuf encoder, just enough for `profile.proto`.
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    /// A varint field. Zero values are skipped, like proto3 does.
+    fn uint(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, 0);
+            self.varint(value);
+        }
+    }
+
+    fn int(&mut self, field: u32, value: i64) {
+        self.uint(field, value as u64);
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, 2);
+        self.varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn message(&mut self, field: u32, build: impl FnOnce(&mut Encoder)) {
+        let mut inner = Encoder::default();
+        build(&mut inner);
+        self.bytes(field, &inner.buf);
+    }
+
+    fn packed(&mut self, field: u32, values: impl Iterator<Item = u64>) {
+        let mut inner = Encoder::default();
+        values.for_each(|v| inner.varint(v));
+        if !inner.buf.is_empty() {
+            self.bytes(field, &inner.buf);
+        }
+    }
+}
+
+/// A table of strings, where index 0 is always the empty string.
+struct Strings {
+    table: Vec<String>,
+    index: HashMap<String, i64>,
+}
+
+impl Strings {
+    fn new() -> Self {
+        let mut strings = Self {
+            table: Vec::new(),
+            index: HashMap::new(),
+        };
+        strings.get("");
+        strings
+    }
+
+    fn get(&mut self, s: &str) -> i64 {
+        if let Some(i) = self.index.get(s) {
+            return *i;
+        }
+        let i = self.table.len() as i64;
+        self.table.push(s.to_owned());
+        self.index.insert(s.to_owned(), i);
+        i
+    }
+}
+
+/// A function in the function table of a profile.
+struct Function {
+    name: i64,
+    filename: i64,
+}
+
+/// Writes heap profiles in pprof's `profile.proto` format, uncompressed. The
+/// output can be read by `go tool pprof` and other pprof tooling.
+///
+/// Each sample has the values `inuse_objects`, `inuse_bytes`, `alloc_objects`
+/// and `alloc_bytes`, in that order. Frame addresses are only symbolized when
+/// the profile is written, using `backtrace::resolve`, and each distinct
+/// address and function is only written once.
+#[derive(Clone, Copy, Debug)]
+pub struct PprofWriter {
+    symbolize: bool,
+}
+
+impl PprofWriter {
+    pub const fn new() -> Self {
+        Self { symbolize: true }
+    }
+
+    /// Sets whether frame addresses are resolved to functions. Locations are
+    /// written with just their address when this is off.
+    pub const fn symbolize(mut self, symbolize: bool) -> Self {
+        self.symbolize = symbolize;
+        self
+    }
+
+    /// Writes a profile made from `samples` to `out`.
+    pub fn write(&self, out: &mut impl io::Write, samples: &[PprofSample]) -> io::Result<()> {
+        let mut strings = Strings::new();
+        let mut profile = Encoder::default();
+
+        for (kind, unit) in [
+            ("inuse_objects", "count"),
+            ("inuse_bytes", "bytes"),
+            ("alloc_objects", "count"),
+            ("alloc_bytes", "bytes"),
+        ] {
+            let (kind, unit) = (strings.get(kind), strings.get(unit));
+            profile.message(1, |m| {
+                m.int(1, kind);
+                m.int(2, unit);
+            });
+        }
+
+        let mut locations: HashMap<usize, u64> = HashMap::new();
+        let mut location_order = Vec::new();
+        for sample in samples {
+            for addr in sample.frames {
+                if !locations.contains_key(addr) {
+                    location_order.push(*addr);
+                    locations.insert(*addr, location_order.len() as u64);
+                }
+            }
+            profile.message(2, |m| {
+                m.packed(1, sample.frames.iter().map(|a| locations[a]));
+                m.packed(
+                    2,
+                    [
+                        sample.inuse_objects,
+                        sample.inuse_bytes,
+                        sample.alloc_objects,
+                        sample.alloc_bytes,
+                    ]
+                    .iter()
+                    .map(|v| *v as u64),
+                );
+            });
+        }
+
+        let mut functions: Vec<Function> = Vec::new();
+        let mut function_ids: HashMap<(i64, i64), u64> = HashMap::new();
+        for (i, addr) in location_order.iter().enumerate() {
+            let mut lines = Vec::new();
+            if self.symbolize {
+                self.resolve(*addr, |name, filename, line| {
+                    let key = (strings.get(name), strings.get(filename));
+                    let id = *function_ids.entry(key).or_insert_with(|| {
+                        functions.push(Function {
+                            name: key.0,
+                            filename: key.1,
+                        });
+                        functions.len() as u64
+                    });
+                    lines.push((id, line));
+                });
+            }
+            profile.message(4, |m| {
+                m.uint(1, i as u64 + 1);
+                m.uint(3, *addr as u64);
+                for (function, line) in &lines {
+                    m.message(4, |l| {
+                        l.uint(1, *function);
+                        l.int(2, *line as i64);
+                    });
+                }
+            });
+        }
+
+        for (i, function) in functions.iter().enumerate() {
+            profile.message(5, |m| {
+                m.uint(1, i as u64 + 1);
+                m.int(2, function.name);
+                m.int(3, function.name);
+                m.int(4, function.filename);
+            });
+        }
+
+        for s in &strings.table {
+            profile.bytes(6, s.as_bytes());
+        }
+
+        out.write_all(&profile.buf)
+    }
+
+    /// Resolves a return address to the functions it's in, innermost inlined
+    /// function first.
+    fn resolve(&self, addr: usize, mut f: impl FnMut(&str, &str, u32)) {
+        // Return addresses point just past the call, which may already be the
+        // next line or function.
+        let addr = addr.saturating_sub(1) as *mut core::ffi::c_void;
+        backtrace::resolve(addr, |symbol| {
+            let name = symbol
+                .name()
+                .map(|n| format!("{:#}", n))
+                .unwrap_or_else(|| format!("{:#x}", addr as usize));
+            let filename = symbol
+                .filename()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+            f(&name, &filename, symbol.lineno().unwrap_or(0));
+        });
+    }
+}
+
+impl Default for PprofWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,326 @@
+use crate::histogram::{Bucketing, HistogramMonitor};
+use crate::monitor::{AllocInfo, StatsMonitor};
+use core::fmt;
+
+/// The `monitor` label of a sample, if there is one.
+struct MonitorLabel<'a>(Option<&'a str>);
+
+impl<'a> fmt::Display for MonitorLabel<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.0 {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        f.write_str("{monitor=\"")?;
+        for c in name.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                c => fmt::Write::write_char(f, c)?,
+            }
+        }
+        f.write_str("\"}")
+    }
+}
+
+/// A metric family of an `AllocInfo`, and how to get its value.
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    value: fn(&AllocInfo) -> u64,
+}
+
+const FAMILIES: [Family; 7] = [
+    Family {
+        name: "alloc_total",
+        kind: "counter",
+        help: "Number of calls to alloc.",
+        value: |i| i.alloc,
+    },
+    Family {
+        name: "dealloc_total",
+        kind: "counter",
+        help: "Number of calls to dealloc.",
+        value: |i| i.dealloc,
+    },
+    Family {
+        name: "realloc_total",
+        kind: "counter",
+        help: "Number of calls to realloc.",
+        value: |i| i.realloc,
+    },
+    Family {
+        name: "alloc_bytes_total",
+        kind: "counter",
+        help: "Total bytes allocated.",
+        value: |i| i.bytes_alloc,
+    },
+    Family {
+        name: "dealloc_bytes_total",
+        kind: "counter",
+        help: "Total bytes deallocated.",
+        value: |i| i.bytes_dealloc,
+    },
+    Family {
+        name: "live_bytes",
+        kind: "gauge",
+        help: "Bytes currently allocated.",
+        value: AllocInfo::live_bytes,
+    },
+    Family {
+        name: "peak_bytes",
+        kind: "gauge",
+        help: "Highest number of bytes live at once.",
+        value: |i| i.peak_bytes,
+    },
+];
+
+/// Writes every metric family in the Prometheus text exposition format, each
+/// with its `HELP` and `TYPE` lines once, followed by a sample for each of
+/// `samples`.
+fn write_families(
+    out: &mut impl fmt::Write,
+    prefix: &str,
+    samples: &[(MonitorLabel, AllocInfo)],
+) -> fmt::Result {
+    let sep = if prefix.is_empty() { "" } else { "_" };
+    for family in FAMILIES.iter() {
+        let name = family.name;
+        writeln!(out, "# HELP {}{}{} {}", prefix, sep, name, family.help)?;
+        writeln!(out, "# TYPE {}{}{} {}", prefix, sep, name, family.kind)?;
+        for (label, info) in samples {
+            let value = (family.value)(info);
+            writeln!(out, "{}{}{}{} {}", prefix, sep, name, label, value)?;
+        }
+    }
+    Ok(())
+}
+
+impl AllocInfo {
+    /// Renders this snapshot in the Prometheus text exposition format, with
+    /// every metric name prefixed by `prefix` and an underscore. An empty prefix
+    /// leaves the metric names bare.
+    ///
+    /// The cumulative fields are exposed as counters, and the difference between
+    /// bytes allocated and bytes deallocated is exposed as the `live_bytes` gauge,
+    /// next to the `peak_bytes` gauge.
+    ///
+    /// ```rust
+    /// use interloc::AllocInfo;
+    ///
+    /// let info = AllocInfo {
+    ///     alloc: 5,
+    ///     dealloc: 3,
+    ///     realloc: 1,
+    ///     bytes_alloc: 640,
+    ///     bytes_dealloc: 512,
+    ///     peak_bytes: 600,
+    ///     baseline_bytes: 0,
+    /// };
+    /// let mut out = String::new();
+    /// info.to_prometheus("app", &mut out).unwrap();
+    /// assert_eq!(
+    ///     out,
+    ///     "\
+    /// ## HELP app_alloc_total Number of calls to alloc.
+    /// ## TYPE app_alloc_total counter
+    /// app_alloc_total 5
+    /// ## HELP app_dealloc_total Number of calls to dealloc.
+    /// ## TYPE app_dealloc_total counter
+    /// app_dealloc_total 3
+    /// ## HELP app_realloc_total Number of calls to realloc.
+    /// ## TYPE app_realloc_total counter
+    /// app_realloc_total 1
+    /// ## HELP app_alloc_bytes_total Total bytes allocated.
+    /// ## TYPE app_alloc_bytes_total counter
+    /// app_alloc_bytes_total 640
+    /// ## HELP app_dealloc_bytes_total Total bytes deallocated.
+    /// ## TYPE app_dealloc_bytes_total counter
+    /// app_dealloc_bytes_total 512
+    /// ## HELP app_live_bytes Bytes currently allocated.
+    /// ## TYPE app_live_bytes gauge
+    /// app_live_bytes 128
+    /// ## HELP app_peak_bytes Highest number of bytes live at once.
+    /// ## TYPE app_peak_bytes gauge
+    /// app_peak_bytes 600
+    /// "
+    /// );
+    ///
+    /// out.clear();
+    /// AllocInfo::new().to_prometheus("", &mut out).unwrap();
+    /// assert!(out.starts_with("# HELP alloc_total Number of calls to alloc.\n"));
+    /// assert!(out.ends_with("\npeak_bytes 0\n"));
+    /// ```
+    pub fn to_prometheus(&self, prefix: &str, out: &mut impl fmt::Write) -> fmt::Result {
+        self.to_prometheus_labeled(prefix, None, out)
+    }
+
+    /// Like `to_prometheus`, but with a `monitor` label on every sample if
+    /// `monitor` is given, e.g. `alloc_total{monitor="arena"} 3`.
+    ///
+    /// Each call writes the `HELP` and `TYPE` lines of every family, so the
+    /// output of several calls can't be concatenated into one exposition. See
+    /// `StatsMonitor::write_prometheus` for exposing several monitors.
+    pub fn to_prometheus_labeled(
+        &self,
+        prefix: &str,
+        monitor: Option<&str>,
+        out: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        write_families(out, prefix, &[(MonitorLabel(monitor), *self)])
+    }
+}
+
+impl StatsMonitor {
+    /// Renders the monitor's statistics like `AllocInfo::to_prometheus`, labeled
+    /// with the monitor's name if it has one.
+    pub fn to_prometheus(&self, prefix: &str, out: &mut impl fmt::Write) -> fmt::Result {
+        self.info().to_prometheus_labeled(prefix, self.name(), out)
+    }
+
+    /// Renders several monitors in one exposition, like
+    /// `AllocInfo::to_prometheus`, with each family's `HELP` and `TYPE` lines
+    /// written once, followed by a sample for each monitor, labeled with the
+    /// name it's paired with. Each monitor is read once, so its samples are of
+    /// a single snapshot.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, StatsMonitor};
+    ///
+    /// let arena = StatsMonitor::new();
+    /// let cache = StatsMonitor::new();
+    /// let layout = Layout::from_size_align(64, 8).unwrap();
+    /// arena.monitor(layout, AllocAction::Alloc);
+    /// arena.monitor(layout, AllocAction::Alloc);
+    /// cache.monitor(layout, AllocAction::Alloc);
+    /// cache.monitor(layout, AllocAction::Dealloc { ptr: core::ptr::null_mut() });
+    ///
+    /// let mut out = String::new();
+    /// StatsMonitor::write_prometheus("app", &[("arena", &arena), ("cache", &cache)], &mut out)
+    ///     .unwrap();
+    /// let expected = [
+    ///     "# HELP app_alloc_total Number of calls to alloc.",
+    ///     "# TYPE app_alloc_total counter",
+    ///     "app_alloc_total{monitor=\"arena\"} 2",
+    ///     "app_alloc_total{monitor=\"cache\"} 1",
+    ///     "# HELP app_dealloc_total Number of calls to dealloc.",
+    ///     "# TYPE app_dealloc_total counter",
+    ///     "app_dealloc_total{monitor=\"arena\"} 0",
+    ///     "app_dealloc_total{monitor=\"cache\"} 1",
+    ///     "# HELP app_realloc_total Number of calls to realloc.",
+    ///     "# TYPE app_realloc_total counter",
+    ///     "app_realloc_total{monitor=\"arena\"} 0",
+    ///     "app_realloc_total{monitor=\"cache\"} 0",
+    ///     "# HELP app_alloc_bytes_total Total bytes allocated.",
+    ///     "# TYPE app_alloc_bytes_total counter",
+    ///     "app_alloc_bytes_total{monitor=\"arena\"} 128",
+    ///     "app_alloc_bytes_total{monitor=\"cache\"} 64",
+    ///     "# HELP app_dealloc_bytes_total Total bytes deallocated.",
+    ///     "# TYPE app_dealloc_bytes_total counter",
+    ///     "app_dealloc_bytes_total{monitor=\"arena\"} 0",
+    ///     "app_dealloc_bytes_total{monitor=\"cache\"} 64",
+    ///     "# HELP app_live_bytes Bytes currently allocated.",
+    ///     "# TYPE app_live_bytes gauge",
+    ///     "app_live_bytes{monitor=\"arena\"} 128",
+    ///     "app_live_bytes{monitor=\"cache\"} 0",
+    ///     "# HELP app_peak_bytes Highest number of bytes live at once.",
+    ///     "# TYPE app_peak_bytes gauge",
+    ///     "app_peak_bytes{monitor=\"arena\"} 128",
+    ///     "app_peak_bytes{monitor=\"cache\"} 64",
+    /// ];
+    /// assert_eq!(out.lines().collect::<Vec<_>>(), expected);
+    /// assert!(out.ends_with('\n'));
+    /// ```
+    pub fn write_prometheus(
+        prefix: &str,
+        monitors: &[(&str, &StatsMonitor)],
+        out: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        let samples: Vec<_> = monitors
+            .iter()
+            .map(|(name, monitor)| (MonitorLabel(Some(name)), monitor.info()))
+            .collect();
+        write_families(out, prefix, &samples)
+    }
+}
+
+impl<B: Bucketing> HistogramMonitor<B> {
+    /// Renders the histogram in the Prometheus text exposition format, as the
+    /// `alloc_size_bytes` histogram, prefixed like `AllocInfo::to_prometheus`.
+    /// Sizes are whole numbers of bytes, so each bucket's `le` bound is the
+    /// size before the next bucket starts.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, HistogramMonitor, LinearBuckets, LogBuckets};
+    ///
+    /// let log = HistogramMonitor::<LogBuckets<4>>::new();
+    /// let linear = HistogramMonitor::<LinearBuckets<1024, 512, 3>>::new();
+    /// for size in [0, 1, 3, 4, 1535, 1536, 2048] {
+    ///     let layout = Layout::from_size_align(size, 1).unwrap();
+    ///     log.monitor(layout, AllocAction::Alloc);
+    ///     linear.monitor(layout, AllocAction::Alloc);
+    /// }
+    ///
+    /// let mut out = String::new();
+    /// log.to_prometheus("app", &mut out).unwrap();
+    /// let buckets: Vec<&str> = out.lines().filter(|l| l.contains("_bucket")).collect();
+    /// assert_eq!(
+    ///     buckets,
+    ///     [
+    ///         "app_alloc_size_bytes_bucket{le=\"0\"} 1",
+    ///         "app_alloc_size_bytes_bucket{le=\"1\"} 2",
+    ///         "app_alloc_size_bytes_bucket{le=\"3\"} 3",
+    ///         "app_alloc_size_bytes_bucket{le=\"+Inf\"} 7",
+    ///     ]
+    /// );
+    /// assert!(out.contains("app_alloc_size_bytes_count 7\n"));
+    ///
+    /// out.clear();
+    /// linear.to_prometheus("", &mut out).unwrap();
+    /// assert!(out.contains("# TYPE alloc_size_bytes histogram\n"));
+    /// assert!(out.contains("alloc_size_bytes_bucket{le=\"1535\"} 5\n"));
+    /// assert!(out.contains("alloc_size_bytes_bucket{le=\"2047\"} 6\n"));
+    /// assert!(out.contains("alloc_size_bytes_bucket{le=\"+Inf\"} 7\n"));
+    /// assert!(out.contains("alloc_size_bytes_sum 5127\n"));
+    /// ```
+    pub fn to_prometheus(&self, prefix: &str, out: &mut impl fmt::Write) -> fmt::Result {
+        let sep = if prefix.is_empty() { "" } else { "_" };
+        let name = "alloc_size_bytes";
+        writeln!(
+            out,
+            "# HELP {}{}{} Sizes of allocations.",
+            prefix, sep, name
+        )?;
+        writeln!(out, "# TYPE {}{}{} histogram", prefix, sep, name)?;
+        let mut cumulative = 0;
+        for bucket in self.buckets() {
+            cumulative += bucket.count;
+            match bucket.end {
+                // A bucket that ends at 0 can't hold anything.
+                Some(0) => {}
+                Some(end) => writeln!(
+                    out,
+                    "{}{}{}_bucket{{le=\"{}\"}} {}",
+                    prefix,
+                    sep,
+                    name,
+                    end - 1,
+                    cumulative
+                )?,
+                None => writeln!(
+                    out,
+                    "{}{}{}_bucket{{le=\"+Inf\"}} {}",
+                    prefix, sep, name, cumulative
+                )?,
+            }
+        }
+        writeln!(out, "{}{}{}_sum {}", prefix, sep, name, self.bytes())?;
+        writeln!(out, "{}{}{}_count {}", prefix, sep, name, cumulative)
+    }
+}
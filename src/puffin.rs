@@ -0,0 +1,87 @@
+//! Allocation counts in puffin profiles.
+//!
+//! `scoped_alloc_stats!` measures what the current thread allocates until the
+//! end of the enclosing block, and reports it to puffin as a scope called
+//! `allocs` at the end of the enclosing puffin scope, with the counts as its data
+//! in JSON:
+//!
+//! ```rust,ignore
+//! fn update() {
+//!     puffin::profile_function!();
+//!     let _allocs = interloc::puffin::scoped_alloc_stats!();
+//!     // ...
+//! }
+//! ```
+//!
+//! Puffin only takes data when a scope starts, so the counts can't be put in
+//! the enclosing scope itself. When puffin's scopes are turned off, nothing is
+//! measured or reported.
+//!
+//! The counts come from `ThreadMonitor`, so the global allocator has to be an
+//! `InterAlloc` whose monitor includes one.
+use crate::bench::{start_measuring, stop_measuring};
+use crate::monitor::AllocInfo;
+use ::puffin::ThreadProfiler;
+use std::sync::OnceLock;
+
+pub use crate::scoped_alloc_stats;
+pub use ::puffin::ScopeId;
+
+/// Measures allocations until the end of the enclosing block, and reports them
+/// to puffin when dropped. See the module docs.
+#[macro_export]
+macro_rules! scoped_alloc_stats {
+    () => {{
+        static SCOPE_ID: ::std::sync::OnceLock<$crate::puffin::ScopeId> =
+            ::std::sync::OnceLock::new();
+        $crate::puffin::AllocStatsScope::new(&SCOPE_ID, module_path!(), file!(), line!())
+    }};
+}
+
+/// The guard returned by `scoped_alloc_stats!`.
+pub struct AllocStatsScope {
+    measuring: Option<(ScopeId, AllocInfo)>,
+}
+
+impl AllocStatsScope {
+    /// Starts measuring, if puffin's scopes are on, registering the `allocs`
+    /// scope for the given location in `id` the first time.
+    pub fn new(
+        id: &'static OnceLock<ScopeId>,
+        function: &'static str,
+        file: &'static str,
+        line: u32,
+    ) -> Self {
+        if !::puffin::are_scopes_on() {
+            return Self { measuring: None };
+        }
+        let id = *id.get_or_init(|| {
+            ThreadProfiler::call(|tp| {
+                tp.register_named_scope("allocs", function, ::puffin::short_file_name(file), line)
+            })
+        });
+        Self {
+            measuring: Some((id, start_measuring())),
+        }
+    }
+}
+
+impl Drop for AllocStatsScope {
+    fn drop(&mut self) {
+        let (id, start) = match self.measuring.take() {
+            Some(measuring) => measuring,
+            None => return,
+        };
+        let info = stop_measuring(&start);
+        if !::puffin::are_scopes_on() {
+            return;
+        }
+        let mut data = String::new();
+        if info.write_json(&mut data).is_ok() {
+            ThreadProfiler::call(|tp| {
+                let start = tp.begin_scope(id, &data);
+                tp.end_scope(start);
+            });
+        }
+    }
+}
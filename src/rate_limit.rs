@@ -0,0 +1,111 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::clock::{Clock, CoarseClock};
+use crate::describe::{ConfigReport, MonitorDesc, Overhead};
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+/// Forwards at most `K` events per period to `inner`, and counts the events it
+/// holds back.
+///
+/// This is a token bucket that holds up to `K` tokens and gains one every
+/// `period / K`, starting full: a burst of `K` events goes through at once, and
+/// after that events go through at the refill rate. It's kept as the time at
+/// which the bucket would be full again, so it takes a single atomic. Events are
+/// limited one by one, so the before and after actions of a call may not both
+/// be forwarded; it's meant for monitors that call a handler, rather than ones
+/// that keep counts.
+pub struct RateLimited<M, const K: u32, C = CoarseClock> {
+    inner: M,
+    clock: C,
+    interval: u64,
+    full_at: AtomicU64,
+    suppressed: AtomicU64,
+}
+
+impl<M, const K: u32> RateLimited<M, K> {
+    /// Limits `inner` to `K` events per `period`, timed by `CoarseClock`.
+    pub const fn new(inner: M, period: Duration) -> Self {
+        Self::with_clock(inner, period, CoarseClock)
+    }
+}
+
+impl<M, const K: u32, C> RateLimited<M, K, C> {
+    /// Limits `inner` to `K` events per `period`, timed by `clock`.
+    pub const fn with_clock(inner: M, period: Duration, clock: C) -> Self {
+        let interval = if K == 0 {
+            0
+        } else {
+            (period.as_nanos() / K as u128) as u64
+        };
+        Self {
+            inner,
+            clock,
+            interval,
+            full_at: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Number of events that weren't forwarded.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+}
+
+impl<M, const K: u32, C: Clock> RateLimited<M, K, C> {
+    /// Takes a token, if there is one.
+    fn take(&self) -> bool {
+        if K == 0 {
+            return false;
+        }
+        let now = self.clock.now_nanos();
+        // The bucket is empty once it's a whole capacity away from being full.
+        let capacity = self.interval.saturating_mul(K as u64);
+        let mut full_at = self.full_at.load(Ordering::Relaxed);
+        loop {
+            let start = full_at.max(now);
+            if start - now >= capacity && capacity != 0 {
+                return false;
+            }
+            match self.full_at.compare_exchange_weak(
+                full_at,
+                start.saturating_add(self.interval),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => full_at = current,
+            }
+        }
+    }
+}
+
+impl<M: AllocMonitor, const K: u32, C: Clock> AllocMonitor for RateLimited<M, K, C> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        if self.take() {
+            self.inner.monitor(layout, action);
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn on_first_event(&self) {
+        self.inner.on_first_event();
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("RateLimited", Overhead::Atomic)
+            .param("events", K as u64)
+            .param("period_ns", self.interval.saturating_mul(K as u64))
+    }
+
+    fn describe_inner(&self, report: &mut ConfigReport) {
+        report.add(&self.inner);
+    }
+}
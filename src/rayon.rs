@@ -0,0 +1,64 @@
+//! Allocations made by the threads of a rayon pool.
+//!
+//! The allocations of work run by rayon are spread over the threads of its
+//! pool, so `ThreadMonitor` on any one thread only sees part of them.
+//! `scope_with_stats` adds up what every thread of the pool allocated while a
+//! scope ran.
+//!
+//! Rayon doesn't say which thread ran which job, so this is every allocation
+//! made by the pool's threads between the start and the end of the scope,
+//! whatever it was for. It's exact when nothing else runs on the pool at the
+//! same time, and approximate otherwise: overlapping scopes, or other work that
+//! the pool steals while the scope runs, are counted too.
+use crate::bench::{start_measuring, stop_measuring};
+use crate::monitor::AllocInfo;
+use crate::thread_init::init_thread;
+
+/// A start handler for rayon pools that calls `init_thread` on each of their
+/// threads, so that `interloc` has nothing left to set up when they first
+/// allocate:
+///
+/// ```rust
+/// let pool = rayon::ThreadPoolBuilder::new()
+///     .num_threads(2)
+///     .start_handler(interloc::rayon::start_handler)
+///     .build()
+///     .unwrap();
+/// assert_eq!(pool.install(|| vec![1, 2, 3].len()), 3);
+/// ```
+pub fn start_handler(_index: usize) {
+    init_thread();
+}
+
+/// Runs `op` in a `rayon::scope` on the current pool, and returns what it
+/// returned along with what all the threads of the pool allocated while it ran,
+/// plus the calling thread if it isn't one of them. Threads of the pool that
+/// weren't started with `start_handler` are set up with `init_thread` first.
+///
+/// The counts come from `ThreadMonitor`, so the global allocator has to be an
+/// `InterAlloc` whose monitor includes one. `peak_bytes` is the sum of the
+/// peaks of every thread. Threads that free more than they allocate, as pool
+/// threads often do, don't see their live bytes go up, so it's only a rough
+/// guide.
+pub fn scope_with_stats<'scope, R: Send>(
+    op: impl FnOnce(&::rayon::Scope<'scope>) -> R + Send,
+) -> (R, AllocInfo) {
+    let starts = ::rayon::broadcast(|_| {
+        init_thread();
+        start_measuring()
+    });
+    let caller = ::rayon::current_thread_index()
+        .is_none()
+        .then(start_measuring);
+
+    let result = ::rayon::scope(op);
+
+    let caller = caller.map(|start| stop_measuring(&start));
+    let deltas = ::rayon::broadcast(|ctx| stop_measuring(&starts[ctx.index()]));
+    let threads = || deltas.iter().chain(caller.iter());
+    let mut total: AllocInfo = threads().sum();
+    // The threads ran at the same time, so unlike with `merge`, their peaks may
+    // have overlapped.
+    total.peak_bytes = threads().map(|delta| delta.peak_bytes).sum();
+    (result, total)
+}
@@ -0,0 +1,232 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::slots::Slots;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+
+thread_local! {
+    /// The block being reallocated on this thread, between the realloc action and
+    /// its result: its address and size.
+    static REALLOCATING: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+/// A range of addresses that a reallocation moved a block away from, from
+/// `ReallocMoveMonitor::invalidation`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Invalidation {
+    /// Address the block was at
+    pub ptr: usize,
+    /// Size of the block before it moved
+    pub size: usize,
+    /// How many blocks moved after this one, so 0 for the latest move
+    pub age: u64,
+}
+
+impl Invalidation {
+    /// Whether `addr` was in the block.
+    pub fn contains(&self, addr: usize) -> bool {
+        addr.wrapping_sub(self.ptr) < self.size
+    }
+}
+
+/// A slot of a `ReallocMoveMonitor`'s ring.
+struct MoveSlot {
+    /// One more than the position of the move in the slot, or 0 while it's
+    /// written or before it's first used
+    stamp: AtomicU64,
+    ptr: AtomicUsize,
+    size: AtomicUsize,
+}
+
+impl MoveSlot {
+    const fn new() -> Self {
+        Self {
+            stamp: AtomicU64::new(0),
+            ptr: AtomicUsize::new(0),
+            size: AtomicUsize::new(0),
+        }
+    }
+
+    fn write(&self, position: u64, ptr: usize, size: usize) {
+        self.stamp.store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.ptr.store(ptr, Ordering::Relaxed);
+        self.size.store(size, Ordering::Relaxed);
+        self.stamp.store(position + 1, Ordering::Release);
+    }
+
+    /// The move in the slot and its position, unless it's being written.
+    fn read(&self) -> Option<(u64, usize, usize)> {
+        let stamp = self.stamp.load(Ordering::Acquire);
+        if stamp == 0 {
+            return None;
+        }
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        let size = self.size.load(Ordering::Relaxed);
+        fence(Ordering::Acquire);
+        (self.stamp.load(Ordering::Relaxed) == stamp).then_some((stamp - 1, ptr, size))
+    }
+}
+
+/// A monitor that remembers where reallocations moved blocks away from, to
+/// catch pointers that outlived the block they pointed into, like a pointer
+/// into a `Vec` kept across a push that grew it.
+///
+/// When a reallocation moves a block, the range it used to take up is recorded
+/// in a ring of the last `N` moves, overwriting the oldest. Assertions can then
+/// check, with `was_recently_invalidated`, whether an address they're about to
+/// use was in one of them. Ranges stay in the ring until they age out, even if
+/// the allocator hands their memory out again, since a stale pointer into
+/// reused memory is the worst kind. Recording a move and looking one up never
+/// allocate or block; a lookup racing with moves that wrap around the ring may
+/// miss them.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{InterAlloc, ReallocMoveMonitor};
+/// use std::alloc::System;
+///
+/// static MONITOR: ReallocMoveMonitor = ReallocMoveMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, ReallocMoveMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// let mut v: Vec<u64> = Vec::with_capacity(4);
+/// v.extend([1, 2, 3, 4]);
+/// let interior = &v[2] as *const u64;
+/// assert!(!MONITOR.was_recently_invalidated(interior));
+///
+/// // Push until the allocator can't grow the block in place.
+/// let start = v.as_ptr();
+/// while v.as_ptr() == start {
+///     v.push(0);
+/// }
+/// assert!(MONITOR.was_recently_invalidated(interior));
+/// let moved = MONITOR.invalidation(interior).unwrap();
+/// assert_eq!(moved.ptr, start as usize);
+/// assert!(moved.contains(interior as usize));
+/// ```
+///
+/// Ranges age out after `N` more moves:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::testing::FakeAlloc;
+/// use interloc::{InterAlloc, ReallocMoveMonitor};
+///
+/// static MONITOR: ReallocMoveMonitor<2> = ReallocMoveMonitor::new();
+/// let alloc = InterAlloc {
+///     inner: FakeAlloc::<4096>::new(),
+///     monitor: &MONITOR,
+/// };
+///
+/// let layout = Layout::from_size_align(16, 8).unwrap();
+/// unsafe {
+///     let first = alloc.alloc(layout);
+///     // FakeAlloc always moves a block to grow it.
+///     let second = alloc.realloc(first, layout, 32);
+///     let info = MONITOR.invalidation(first.add(15)).unwrap();
+///     assert_eq!((info.size, info.age), (16, 0));
+///     // Just past the end.
+///     assert!(!MONITOR.was_recently_invalidated(first.add(16)));
+///     assert!(!MONITOR.was_recently_invalidated(second));
+///
+///     let layout = Layout::from_size_align(32, 8).unwrap();
+///     let third = alloc.realloc(second, layout, 64);
+///     assert_eq!(MONITOR.invalidation(first).unwrap().age, 1);
+///     let layout = Layout::from_size_align(64, 8).unwrap();
+///     alloc.realloc(third, layout, 128);
+///     assert!(!MONITOR.was_recently_invalidated(first));
+///     assert!(MONITOR.was_recently_invalidated(second));
+///     assert!(MONITOR.was_recently_invalidated(third));
+/// }
+/// assert_eq!(MONITOR.moves(), 3);
+/// ```
+pub struct ReallocMoveMonitor<const N: usize = 64> {
+    slots: Slots<MoveSlot, N>,
+    moves: AtomicU64,
+}
+
+impl<const N: usize> ReallocMoveMonitor<N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: Slots::new([const { MoveSlot::new() }; N]),
+            moves: AtomicU64::new(0),
+        }
+    }
+
+    /// How many reallocations moved a block.
+    pub fn moves(&self) -> u64 {
+        self.moves.load(Ordering::Relaxed)
+    }
+
+    /// Whether `ptr` was in a block that one of the last `N` moves moved away
+    /// from.
+    pub fn was_recently_invalidated<T>(&self, ptr: *const T) -> bool {
+        self.invalidation(ptr).is_some()
+    }
+
+    /// The latest of the last `N` moves that moved a block containing `ptr`
+    /// away, if any.
+    pub fn invalidation<T>(&self, ptr: *const T) -> Option<Invalidation> {
+        let addr = ptr as usize;
+        let moves = self.moves();
+        let mut latest: Option<Invalidation> = None;
+        for slot in &self.slots {
+            let (position, ptr, size) = match slot.read() {
+                Some(read) => read,
+                None => continue,
+            };
+            let found = Invalidation {
+                ptr,
+                size,
+                age: moves.saturating_sub(position + 1),
+            };
+            if found.contains(addr) && latest.is_none_or(|latest| found.age < latest.age) {
+                latest = Some(found);
+            }
+        }
+        latest
+    }
+
+    fn record(&self, ptr: usize, size: usize) {
+        if self.slots.is_empty() {
+            return;
+        }
+        let position = self.moves.fetch_add(1, Ordering::Relaxed);
+        self.slots[(position % N as u64) as usize].write(position, ptr, size);
+    }
+}
+
+impl<const N: usize> Default for ReallocMoveMonitor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> AllocMonitor for ReallocMoveMonitor<N> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        match action {
+            AllocAction::Realloc { ptr, .. } => {
+                let _ = REALLOCATING.try_with(|r| r.set((ptr as usize, layout.size())));
+            }
+            AllocAction::ReallocResult { ptr, .. } if !ptr.is_null() => {
+                let (old, size) = REALLOCATING.try_with(|r| r.get()).unwrap_or((0, 0));
+                if old != 0 && old != ptr as usize {
+                    self.record(old, size);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("ReallocMoveMonitor", Overhead::Atomic).param("capacity", N as u64)
+    }
+}
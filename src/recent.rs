@@ -0,0 +1,130 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::event::EventRecord;
+use crate::slots::Slots;
+use core::alloc::Layout;
+use core::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+
+/// A slot of the ring. `sequence` is `2 * index + 2` once the record with that
+/// index has been written, and odd while a record is being written.
+struct RingSlot {
+    sequence: AtomicU64,
+    words: [AtomicU64; 8],
+}
+
+impl RingSlot {
+    const fn new() -> Self {
+        Self {
+            sequence: AtomicU64::new(0),
+            words: [const { AtomicU64::new(0) }; 8],
+        }
+    }
+}
+
+/// A flight recorder keeping the last `N` events in a lock-free ring, so that
+/// recent allocator activity can be inspected after the fact.
+///
+/// Old events are overwritten as new ones come in. Each slot of the ring has its
+/// own sequence number, so `snapshot` never returns a torn record, even while
+/// other threads are writing. When two threads race for the same slot, the
+/// older event is dropped.
+pub struct RecentEventsMonitor<const N: usize> {
+    slots: Slots<RingSlot, N>,
+    next: AtomicUsize,
+}
+
+impl<const N: usize> RecentEventsMonitor<N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: Slots::new([const { RingSlot::new() }; N]),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Total number of events seen, including ones that were overwritten.
+    pub fn total(&self) -> usize {
+        self.next.load(Ordering::Relaxed)
+    }
+
+    /// Copies the newest events into `out`, oldest first by serial, returning how many
+    /// were copied. Up to `min(N, out.len())` events are copied; slots that are
+    /// being overwritten while the snapshot is taken are skipped.
+    pub fn snapshot(&self, out: &mut [EventRecord]) -> usize {
+        let end = self.next.load(Ordering::Acquire);
+        let want = out.len().min(self.slots.len()).min(end);
+        let mut len = 0;
+        for index in end - want..end {
+            if let Some(record) = self.read(index) {
+                out[len] = record;
+                len += 1;
+            }
+        }
+        // Slots are claimed in a slightly different order than serials are
+        // handed out when threads race.
+        out[..len].sort_unstable_by_key(|record| record.serial);
+        len
+    }
+
+    fn read(&self, index: usize) -> Option<EventRecord> {
+        let slot = &self.slots[index % N];
+        let expected = 2 * index as u64 + 2;
+        if slot.sequence.load(Ordering::Acquire) != expected {
+            return None;
+        }
+        let mut words = [0u64; 8];
+        for (word, atomic) in words.iter_mut().zip(&slot.words) {
+            *word = atomic.load(Ordering::Relaxed);
+        }
+        fence(Ordering::Acquire);
+        if slot.sequence.load(Ordering::Relaxed) != expected {
+            return None;
+        }
+        EventRecord::from_words(&words)
+    }
+
+    fn write(&self, record: &EventRecord) {
+        if self.slots.is_empty() {
+            return;
+        }
+        let index = self.next.fetch_add(1, Ordering::AcqRel);
+        let slot = &self.slots[index % N];
+        let writing = 2 * index as u64 + 1;
+        let mut current = slot.sequence.load(Ordering::Relaxed);
+        loop {
+            // Another writer has the slot, or a newer event is already in it.
+            if current % 2 != 0 || current > writing {
+                return;
+            }
+            match slot.sequence.compare_exchange_weak(
+                current,
+                writing,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+        fence(Ordering::Release);
+        for (atomic, word) in slot.words.iter().zip(record.words().iter()) {
+            atomic.store(*word, Ordering::Relaxed);
+        }
+        slot.sequence.store(writing + 1, Ordering::Release);
+    }
+}
+
+impl<const N: usize> Default for RecentEventsMonitor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> AllocMonitor for RecentEventsMonitor<N> {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        self.write(&EventRecord::new(layout, action));
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("RecentEventsMonitor", Overhead::Atomic).param("capacity", N as u64)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::alloc::realloc_via_alloc_copy;
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The byte the zones around blocks are filled with.
+const ZONE_BYTE: u8 = 0xfd;
+
+/// A block whose zones were overwritten, passed to a `RedzoneHandler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RedzoneViolation {
+    /// Address of the block
+    pub ptr: usize,
+    pub layout: Layout,
+    /// Whether the zone before the block was written to
+    pub underrun: bool,
+    /// Whether the zone after the block was written to
+    pub overrun: bool,
+}
+
+/// Called with every block whose zones were found overwritten.
+pub type RedzoneHandler = fn(RedzoneViolation);
+
+/// An allocator that surrounds every block it gets from `inner` with at least
+/// `ZONE` bytes of a known pattern on each side, and checks them when the block
+/// is freed or reallocated, to catch writes just past either end of a block.
+/// Blocks whose zones were overwritten are counted in `corrupted`, and passed to
+/// the handler of `on_corruption`, if any, before they're freed.
+///
+/// The zone before a block is rounded up to its alignment, so that it stays
+/// aligned. A reallocation can't be passed on to `inner`, which would leave the
+/// zone after the block in the wrong place, so it allocates a new block, copies
+/// the contents and frees the old one. When that fails, the old block is left
+/// as it was, zones included.
+///
+/// ```rust
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::RedzoneAlloc;
+/// use std::alloc::System;
+///
+/// let alloc = RedzoneAlloc::<_, 16>::new(System);
+/// let layout = Layout::from_size_align(10, 8).unwrap();
+/// unsafe {
+///     let ptr = alloc.alloc(layout);
+///     *ptr.add(9) = 1;
+///     let ptr = alloc.realloc(ptr, layout, 100);
+///     assert_eq!(*ptr.add(9), 1);
+///     alloc.dealloc(ptr, Layout::from_size_align(100, 8).unwrap());
+///     assert_eq!(alloc.corrupted(), 0);
+///
+///     // One byte too many.
+///     let ptr = alloc.alloc(layout);
+///     ptr.write_bytes(0, 11);
+///     alloc.dealloc(ptr, layout);
+/// }
+/// assert_eq!(alloc.corrupted(), 1);
+/// ```
+pub struct RedzoneAlloc<A, const ZONE: usize = 16> {
+    inner: A,
+    handler: Option<RedzoneHandler>,
+    corrupted: AtomicU64,
+}
+
+impl<A, const ZONE: usize> RedzoneAlloc<A, ZONE> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            handler: None,
+            corrupted: AtomicU64::new(0),
+        }
+    }
+
+    /// Calls `handler` with every block whose zones were overwritten.
+    pub const fn on_corruption(mut self, handler: RedzoneHandler) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    /// How many blocks were found with their zones overwritten.
+    pub fn corrupted(&self) -> u64 {
+        self.corrupted.load(Ordering::Relaxed)
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// The size of the zone before a block of `layout`, and the layout of the
+    /// block with its zones, if it's not too big.
+    fn outer(layout: Layout) -> Option<(usize, Layout)> {
+        let before = ZONE.checked_next_multiple_of(layout.align())?;
+        let size = before.checked_add(layout.size())?.checked_add(ZONE)?;
+        Some((before, Layout::from_size_align(size, layout.align()).ok()?))
+    }
+
+    /// Checks the zones around the block at `ptr`, and reports them if they
+    /// were overwritten.
+    unsafe fn check(&self, ptr: *mut u8, layout: Layout, before: usize) {
+        let intact = |start: *mut u8, len: usize| {
+            core::slice::from_raw_parts(start, len)
+                .iter()
+                .all(|&byte| byte == ZONE_BYTE)
+        };
+        let underrun = !intact(ptr.sub(before), before);
+        let overrun = !intact(ptr.add(layout.size()), ZONE);
+        if !underrun && !overrun {
+            return;
+        }
+        self.corrupted.fetch_add(1, Ordering::Relaxed);
+        if let Some(handler) = self.handler {
+            handler(RedzoneViolation {
+                ptr: ptr as usize,
+                layout,
+                underrun,
+                overrun,
+            });
+        }
+    }
+}
+
+impl<A: GlobalAlloc, const ZONE: usize> RedzoneAlloc<A, ZONE> {
+    /// Allocates a block with `alloc`, and fills its zones.
+    unsafe fn alloc_with(&self, layout: Layout, alloc: impl FnOnce(Layout) -> *mut u8) -> *mut u8 {
+        let (before, outer) = match Self::outer(layout) {
+            Some(outer) => outer,
+            None => return core::ptr::null_mut(),
+        };
+        let base = alloc(outer);
+        if base.is_null() {
+            return base;
+        }
+        base.write_bytes(ZONE_BYTE, before);
+        let ptr = base.add(before);
+        ptr.add(layout.size()).write_bytes(ZONE_BYTE, ZONE);
+        ptr
+    }
+}
+
+unsafe impl<A: GlobalAlloc, const ZONE: usize> GlobalAlloc for RedzoneAlloc<A, ZONE> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc_with(layout, |outer| self.inner.alloc(outer))
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.alloc_with(layout, |outer| self.inner.alloc_zeroed(outer))
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // The layout was checked when the block was allocated.
+        let (before, outer) = Self::outer(layout).unwrap();
+        self.check(ptr, layout, before);
+        self.inner.dealloc(ptr.sub(before), outer);
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        realloc_via_alloc_copy(self, ptr, layout, new_size)
+    }
+}
@@ -0,0 +1,293 @@
+use crate::monitor::{AllocDelta, AllocInfo};
+use core::cmp::Ordering;
+use core::fmt;
+
+/// A field of `AllocInfo`, for picking out one statistic by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AllocField {
+    Alloc,
+    Dealloc,
+    Realloc,
+    BytesAlloc,
+    BytesDealloc,
+    PeakBytes,
+}
+
+impl AllocField {
+    /// Every field of `AllocInfo` but `baseline_bytes`, in the order they're
+    /// declared.
+    pub const ALL: [AllocField; 6] = [
+        AllocField::Alloc,
+        AllocField::Dealloc,
+        AllocField::Realloc,
+        AllocField::BytesAlloc,
+        AllocField::BytesDealloc,
+        AllocField::PeakBytes,
+    ];
+
+    /// The fields that count as a cost when comparing statistics with
+    /// `AllocInfo::compare`: the number of calls of each kind, the bytes
+    /// allocated, and the peak. `bytes_dealloc` is left out, since every byte
+    /// freed was first counted as allocated.
+    pub const COST: [AllocField; 5] = [
+        AllocField::Alloc,
+        AllocField::Dealloc,
+        AllocField::Realloc,
+        AllocField::BytesAlloc,
+        AllocField::PeakBytes,
+    ];
+
+    /// The name of the field in `AllocInfo`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            AllocField::Alloc => "alloc",
+            AllocField::Dealloc => "dealloc",
+            AllocField::Realloc => "realloc",
+            AllocField::BytesAlloc => "bytes_alloc",
+            AllocField::BytesDealloc => "bytes_dealloc",
+            AllocField::PeakBytes => "peak_bytes",
+        }
+    }
+
+    /// Whether the field counts bytes rather than calls.
+    pub const fn is_bytes(self) -> bool {
+        matches!(
+            self,
+            AllocField::BytesAlloc | AllocField::BytesDealloc | AllocField::PeakBytes
+        )
+    }
+
+    /// The value of this field in `info`.
+    pub const fn of(self, info: &AllocInfo) -> u64 {
+        match self {
+            AllocField::Alloc => info.alloc,
+            AllocField::Dealloc => info.dealloc,
+            AllocField::Realloc => info.realloc,
+            AllocField::BytesAlloc => info.bytes_alloc,
+            AllocField::BytesDealloc => info.bytes_dealloc,
+            AllocField::PeakBytes => info.peak_bytes,
+        }
+    }
+
+    /// The value of this field in `delta`.
+    pub const fn of_delta(self, delta: &AllocDelta) -> i64 {
+        match self {
+            AllocField::Alloc => delta.alloc,
+            AllocField::Dealloc => delta.dealloc,
+            AllocField::Realloc => delta.realloc,
+            AllocField::BytesAlloc => delta.bytes_alloc,
+            AllocField::BytesDealloc => delta.bytes_dealloc,
+            AllocField::PeakBytes => delta.peak_bytes,
+        }
+    }
+}
+
+impl fmt::Display for AllocField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How one set of statistics compares to another, as returned by
+/// `AllocInfo::compare`. Only the fields in `AllocField::COST` are compared, and
+/// lower is better.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AllocComparison {
+    /// Every cost field is the same
+    Equal,
+    /// No cost field is higher, and at least one is lower
+    Better,
+    /// No cost field is lower, and at least one is higher
+    Worse,
+    /// Some cost fields are lower and some are higher
+    Mixed,
+}
+
+impl AllocComparison {
+    /// Classifies the per-field orderings returned by
+    /// `AllocInfo::compare_fields`.
+    pub fn from_fields(fields: &[(AllocField, Ordering)]) -> Self {
+        let lower = fields.iter().any(|f| f.1 == Ordering::Less);
+        let higher = fields.iter().any(|f| f.1 == Ordering::Greater);
+        match (lower, higher) {
+            (false, false) => AllocComparison::Equal,
+            (true, false) => AllocComparison::Better,
+            (false, true) => AllocComparison::Worse,
+            (true, true) => AllocComparison::Mixed,
+        }
+    }
+
+    /// The comparison from the other side, swapping better and worse.
+    pub const fn reverse(self) -> Self {
+        match self {
+            AllocComparison::Better => AllocComparison::Worse,
+            AllocComparison::Worse => AllocComparison::Better,
+            other => other,
+        }
+    }
+}
+
+/// The change in each field of `AllocInfo` as a percentage of a baseline, as
+/// returned by `AllocDelta::percent_of`. A field is `None` when it was zero in
+/// the baseline, since no percentage of zero describes the change.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AllocPercent {
+    pub alloc: Option<f64>,
+    pub dealloc: Option<f64>,
+    pub realloc: Option<f64>,
+    pub bytes_alloc: Option<f64>,
+    pub bytes_dealloc: Option<f64>,
+    pub peak_bytes: Option<f64>,
+}
+
+impl AllocPercent {
+    /// The percentage for `field`.
+    pub const fn get(&self, field: AllocField) -> Option<f64> {
+        match field {
+            AllocField::Alloc => self.alloc,
+            AllocField::Dealloc => self.dealloc,
+            AllocField::Realloc => self.realloc,
+            AllocField::BytesAlloc => self.bytes_alloc,
+            AllocField::BytesDealloc => self.bytes_dealloc,
+            AllocField::PeakBytes => self.peak_bytes,
+        }
+    }
+}
+
+impl fmt::Display for AllocPercent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, field) in AllocField::ALL.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            match self.get(*field) {
+                Some(percent) => write!(f, "{} {:+.1}%", field, percent)?,
+                None => write!(f, "{} n/a", field)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A field that grew by more than a `RegressionCheck` allows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegressionViolation {
+    pub field: AllocField,
+    /// How much the field grew, or `None` if it was zero in the baseline
+    pub percent: Option<f64>,
+    /// The most the field was allowed to grow, in percent
+    pub limit: f64,
+}
+
+impl fmt::Display for RegressionViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.percent {
+            Some(percent) => write!(
+                f,
+                "{} grew {:.1}% vs baseline (limit {:.1}%)",
+                self.field, percent, self.limit
+            ),
+            None => write!(f, "{} grew from zero in the baseline", self.field),
+        }
+    }
+}
+
+/// The outcome of a `RegressionCheck`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegressionReport {
+    /// Every field that grew by more than allowed, in the order of
+    /// `AllocField::ALL`
+    pub violations: Vec<RegressionViolation>,
+}
+
+impl RegressionReport {
+    /// Whether no field grew by more than allowed.
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for RegressionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.passed() {
+            return f.write_str("no allocation regressions");
+        }
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares allocation statistics against a baseline, failing if a field grew
+/// by more than a given percentage, e.g. as a CI gate.
+///
+/// Only fields given a limit are checked. A field grows too much when its
+/// percentage is strictly greater than its limit, so growing by exactly the limit
+/// passes. A field that was zero in the baseline has no percentage; it fails if
+/// it's nonzero now, however high its limit.
+///
+/// ```rust
+/// use interloc::{AllocField, AllocInfo, RegressionCheck};
+///
+/// let mut baseline = AllocInfo::new();
+/// baseline.alloc = 100;
+/// baseline.bytes_alloc = 4000;
+/// let mut current = baseline;
+/// current.alloc = 110;
+/// current.bytes_alloc = 4496;
+///
+/// let check = RegressionCheck::new()
+///     .max_percent(AllocField::Alloc, 10.0)
+///     .max_percent(AllocField::BytesAlloc, 10.0);
+/// let report = check.check(&baseline, &current);
+/// assert!(!report.passed());
+/// assert_eq!(report.violations.len(), 1);
+/// assert_eq!(
+///     report.to_string(),
+///     "bytes_alloc grew 12.4% vs baseline (limit 10.0%)"
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RegressionCheck {
+    limits: [Option<f64>; 6],
+}
+
+impl RegressionCheck {
+    /// A check with no limits, which always passes.
+    pub const fn new() -> Self {
+        Self { limits: [None; 6] }
+    }
+
+    /// Fails the check if `field` grows by more than `percent` percent.
+    pub const fn max_percent(mut self, field: AllocField, percent: f64) -> Self {
+        self.limits[field as usize] = Some(percent);
+        self
+    }
+
+    /// Checks how much `current` grew compared to `baseline`.
+    pub fn check(&self, baseline: &AllocInfo, current: &AllocInfo) -> RegressionReport {
+        let delta = current.delta_from(baseline);
+        let percent = delta.percent_of(baseline);
+        let violations = AllocField::ALL
+            .iter()
+            .filter_map(|&field| {
+                let limit = self.limits[field as usize]?;
+                let violation = RegressionViolation {
+                    field,
+                    percent: percent.get(field),
+                    limit,
+                };
+                let failed = match violation.percent {
+                    Some(percent) => percent > limit,
+                    None => field.of_delta(&delta) > 0,
+                };
+                failed.then_some(violation)
+            })
+            .collect();
+        RegressionReport { violations }
+    }
+}
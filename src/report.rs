@@ -0,0 +1,277 @@
+use crate::fmt::{ByteSize, ColorMode, FmtBuffer, Signed, GREEN, RED, RESET};
+use crate::monitor::{AllocDelta, AllocInfo, InfoSource};
+use core::cmp::Ordering;
+use core::fmt::{self, Write};
+
+/// The most columns a `Report` can hold.
+pub const MAX_REPORT_COLUMNS: usize = 8;
+
+/// A row of a report: the name of a field, whether it's a byte count, and how to
+/// read it out of an `AllocInfo` and out of the `AllocDelta` between two of them.
+type Row = (
+    &'static str,
+    bool,
+    fn(&AllocInfo) -> u64,
+    fn(&AllocDelta) -> i64,
+);
+
+const ROWS: [Row; 7] = [
+    ("alloc", false, |i| i.alloc, |d| d.alloc),
+    ("dealloc", false, |i| i.dealloc, |d| d.dealloc),
+    ("realloc", false, |i| i.realloc, |d| d.realloc),
+    ("bytes_alloc", true, |i| i.bytes_alloc, |d| d.bytes_alloc),
+    (
+        "bytes_dealloc",
+        true,
+        |i| i.bytes_dealloc,
+        |d| d.bytes_dealloc,
+    ),
+    (
+        "live_bytes",
+        true,
+        AllocInfo::live_bytes,
+        AllocDelta::net_bytes,
+    ),
+    ("peak_bytes", true, |i| i.peak_bytes, |d| d.peak_bytes),
+];
+
+/// Widest cell that a report renders.
+type Cell = FmtBuffer<32>;
+
+/// A table comparing several snapshots side by side, with one row per field of
+/// `AllocInfo` and one column per snapshot. Byte counts are shown in binary
+/// units. With color enabled, growth in the delta column is shown in red and
+/// reductions in green.
+///
+/// ```rust
+/// use interloc::{AllocInfo, Report};
+///
+/// let start = AllocInfo {
+///     alloc: 10,
+///     dealloc: 4,
+///     realloc: 1,
+///     bytes_alloc: 4096,
+///     bytes_dealloc: 1024,
+///     peak_bytes: 3072,
+///     baseline_bytes: 0,
+/// };
+/// let lines = |report: Report| report.to_string().lines().map(String::from).collect::<Vec<_>>();
+///
+/// assert_eq!(
+///     lines(Report::new().column("start", start)),
+///     [
+///         "                 start",
+///         "alloc               10",
+///         "dealloc              4",
+///         "realloc              1",
+///         "bytes_alloc    4.0 KiB",
+///         "bytes_dealloc  1.0 KiB",
+///         "live_bytes     3.0 KiB",
+///         "peak_bytes     3.0 KiB",
+///     ]
+/// );
+///
+/// let warm = AllocInfo {
+///     alloc: 250,
+///     dealloc: 200,
+///     realloc: 3,
+///     bytes_alloc: 2 << 20,
+///     bytes_dealloc: 1536 << 10,
+///     peak_bytes: 1 << 20,
+///     baseline_bytes: 0,
+/// };
+/// let report = Report::new()
+///     .column("start", start)
+///     .column("warm", warm)
+///     .diff_last_two();
+/// assert_eq!(
+///     lines(report),
+///     [
+///         "                 start       warm        delta",
+///         "alloc               10        250         +240",
+///         "dealloc              4        200         +196",
+///         "realloc              1          3           +2",
+///         "bytes_alloc    4.0 KiB    2.0 MiB     +2.0 MiB",
+///         "bytes_dealloc  1.0 KiB    1.5 MiB     +1.5 MiB",
+///         "live_bytes     3.0 KiB  512.0 KiB   +509.0 KiB",
+///         "peak_bytes     3.0 KiB    1.0 MiB  +1021.0 KiB",
+///     ]
+/// );
+///
+/// // Only the last two columns are compared, and shrinking is negative.
+/// let end = AllocInfo {
+///     alloc: 260,
+///     dealloc: 260,
+///     bytes_dealloc: 2 << 20,
+///     ..warm
+/// };
+/// let report = Report::new()
+///     .column("start", start)
+///     .column("warm", warm)
+///     .column("end", end)
+///     .diff_last_two();
+/// assert_eq!(
+///     lines(report),
+///     [
+///         "                 start       warm      end       delta",
+///         "alloc               10        250      260         +10",
+///         "dealloc              4        200      260         +60",
+///         "realloc              1          3        3           0",
+///         "bytes_alloc    4.0 KiB    2.0 MiB  2.0 MiB         0 B",
+///         "bytes_dealloc  1.0 KiB    1.5 MiB  2.0 MiB  +512.0 KiB",
+///         "live_bytes     3.0 KiB  512.0 KiB      0 B  -512.0 KiB",
+///         "peak_bytes     3.0 KiB    1.0 MiB  1.0 MiB         0 B",
+///     ]
+/// );
+///
+/// // Counters go down across a reset.
+/// let reset = AllocInfo { alloc: 3, ..AllocInfo::new() };
+/// let report = Report::new().column("end", end).column("reset", reset).diff_last_two();
+/// assert!(lines(report)[1].ends_with("  -257"));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Report<'a> {
+    columns: [(&'a str, AllocInfo); MAX_REPORT_COLUMNS],
+    len: usize,
+    diff: bool,
+    color: ColorMode,
+}
+
+impl<'a> Report<'a> {
+    pub const fn new() -> Self {
+        Self {
+            columns: [("", AllocInfo::new()); MAX_REPORT_COLUMNS],
+            len: 0,
+            diff: false,
+            color: ColorMode::Never,
+        }
+    }
+
+    /// Adds a column showing the statistics of `source` under the heading
+    /// `name`, either a snapshot or a monitor to take one of now.
+    ///
+    /// # Panics
+    /// Panics if the report already has `MAX_REPORT_COLUMNS` columns.
+    pub fn column(mut self, name: &'a str, source: impl InfoSource) -> Self {
+        assert!(
+            self.len < MAX_REPORT_COLUMNS,
+            "a report can have at most {} columns",
+            MAX_REPORT_COLUMNS
+        );
+        self.columns[self.len] = (name, source.info());
+        self.len += 1;
+        self
+    }
+
+    /// Adds a final `delta` column with the signed difference between the last
+    /// two columns. It's left out if the report has fewer than two columns.
+    pub fn diff_last_two(mut self) -> Self {
+        self.diff = true;
+        self
+    }
+
+    /// Sets whether the report is colored. Reports aren't colored by default.
+    pub fn color(mut self, mode: ColorMode) -> Self {
+        self.color = mode;
+        self
+    }
+
+    fn columns(&self) -> &[(&'a str, AllocInfo)] {
+        &self.columns[..self.len]
+    }
+
+    fn has_delta(&self) -> bool {
+        self.diff && self.len >= 2
+    }
+
+    /// The difference between the last two columns.
+    fn delta(&self) -> AllocDelta {
+        let new = &self.columns[self.len - 1].1;
+        new.delta_from(&self.columns[self.len - 2].1)
+    }
+
+    /// The escape code that the delta of row `row` should be colored with.
+    fn delta_color(&self, row: usize) -> Option<&'static str> {
+        match ROWS[row].3(&self.delta()).cmp(&0) {
+            Ordering::Greater => Some(RED),
+            Ordering::Less => Some(GREEN),
+            Ordering::Equal => None,
+        }
+    }
+
+    /// The cell for row `row` of column `col`, where the column one past the
+    /// snapshots is the delta.
+    fn cell(&self, row: usize, col: usize) -> Cell {
+        let (_, bytes, field, delta) = ROWS[row];
+        let mut cell = Cell::new();
+        // Every value fits in a cell, so these writes can't fail.
+        if col < self.len {
+            let value = field(&self.columns[col].1);
+            let _ = if bytes {
+                write!(cell, "{}", ByteSize(value as u128))
+            } else {
+                write!(cell, "{}", value)
+            };
+        } else {
+            let value = delta(&self.delta()) as i128;
+            let _ = write!(cell, "{}", Signed { value, bytes });
+        }
+        cell
+    }
+}
+
+impl<'a> Default for Report<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> fmt::Display for Report<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cols = self.len + self.has_delta() as usize;
+        let color = self.color.enabled();
+        let label_width = ROWS.iter().map(|r| r.0.len()).max().unwrap_or(0);
+
+        let mut widths = [0; MAX_REPORT_COLUMNS + 1];
+        for (col, width) in widths.iter_mut().enumerate().take(cols) {
+            *width = match self.columns().get(col) {
+                Some((name, _)) => name.chars().count(),
+                None => "delta".len(),
+            };
+            for row in 0..ROWS.len() {
+                *width = (*width).max(self.cell(row, col).as_str().chars().count());
+            }
+        }
+
+        write!(f, "{:w$}", "", w = label_width)?;
+        for (col, width) in widths.iter().enumerate().take(cols) {
+            let name = self.columns().get(col).map(|c| c.0).unwrap_or("delta");
+            write!(f, "  {:>w$}", name, w = width)?;
+        }
+        writeln!(f)?;
+
+        for (row, (label, ..)) in ROWS.iter().enumerate() {
+            write!(f, "{:w$}", label, w = label_width)?;
+            for (col, width) in widths.iter().enumerate().take(cols) {
+                let escape = if color && col == self.len {
+                    self.delta_color(row)
+                } else {
+                    None
+                };
+                match escape {
+                    Some(escape) => write!(
+                        f,
+                        "  {}{:>w$}{}",
+                        escape,
+                        self.cell(row, col),
+                        RESET,
+                        w = width
+                    )?,
+                    None => write!(f, "  {:>w$}", self.cell(row, col), w = width)?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
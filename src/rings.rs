@@ -0,0 +1,160 @@
+//! Per-thread rings of event records, shared by the monitors that hand events
+//! off to another thread.
+
+use crate::event::{thread_token, EventRecord};
+use crate::slots::Slots;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// A ring of records with a single producer, the thread that owns it, and a
+/// single consumer. Records are stored as atomic words, so a consumer racing
+/// with an overwrite never reads a torn record it then keeps.
+pub(crate) struct ThreadRing<const RECORDS: usize> {
+    owner: AtomicUsize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dropped: AtomicU64,
+    slots: [[AtomicU64; 8]; RECORDS],
+}
+
+impl<const RECORDS: usize> ThreadRing<RECORDS> {
+    const fn new() -> Self {
+        Self {
+            owner: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            slots: [const { [const { AtomicU64::new(0) }; 8] }; RECORDS],
+        }
+    }
+
+    /// The `thread_token` of the thread that owns this ring.
+    pub(crate) fn owner(&self) -> usize {
+        self.owner.load(Ordering::Relaxed)
+    }
+
+    /// Number of records this ring couldn't take.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn count_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of records waiting to be consumed.
+    pub(crate) fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        self.head.load(Ordering::Acquire).wrapping_sub(tail)
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len() >= RECORDS
+    }
+
+    /// Adds a record, or returns `false` if the ring is full. Only the owning
+    /// thread may call this.
+    pub(crate) fn push(&self, record: &EventRecord) -> bool {
+        if RECORDS == 0 || self.is_full() {
+            return false;
+        }
+        let head = self.head.load(Ordering::Relaxed);
+        for (slot, word) in self.slots[head % RECORDS].iter().zip(record.words().iter()) {
+            slot.store(*word, Ordering::Relaxed);
+        }
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Adds a record, making room by discarding the oldest one if the ring is
+    /// full. Returns whether a record was discarded. Only the owning thread may
+    /// call this.
+    pub(crate) fn push_overwrite(&self, record: &EventRecord) -> bool {
+        if RECORDS == 0 {
+            return false;
+        }
+        let mut overwrote = false;
+        while self.is_full() {
+            let tail = self.tail.load(Ordering::Acquire);
+            // Fails if the consumer took the record first, which makes room just
+            // as well.
+            overwrote |= self
+                .tail
+                .compare_exchange(
+                    tail,
+                    tail.wrapping_add(1),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok();
+        }
+        self.push(record);
+        overwrote
+    }
+
+    /// Takes the oldest record, if there is one.
+    pub(crate) fn pop(&self) -> Option<EventRecord> {
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            if self.head.load(Ordering::Acquire) == tail {
+                return None;
+            }
+            let mut words = [0u64; 8];
+            for (word, slot) in words.iter_mut().zip(self.slots[tail % RECORDS].iter()) {
+                *word = slot.load(Ordering::Relaxed);
+            }
+            // If the producer moved the tail past this record to overwrite it,
+            // the copy may be torn, so it's thrown away.
+            if self
+                .tail
+                .compare_exchange(
+                    tail,
+                    tail.wrapping_add(1),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+            {
+                return EventRecord::from_words(&words);
+            }
+        }
+    }
+}
+
+/// A fixed set of `ThreadRing`s, each claimed by the first thread that asks for
+/// one. Rings aren't given back when threads exit.
+pub(crate) struct ThreadRings<const THREADS: usize, const RECORDS: usize> {
+    rings: Slots<ThreadRing<RECORDS>, THREADS>,
+}
+
+impl<const THREADS: usize, const RECORDS: usize> ThreadRings<THREADS, RECORDS> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            rings: Slots::new([const { ThreadRing::new() }; THREADS]),
+        }
+    }
+
+    /// The ring owned by the current thread, claiming one if necessary.
+    pub(crate) fn ring(&self) -> Option<&ThreadRing<RECORDS>> {
+        if self.rings.is_empty() {
+            return None;
+        }
+        let token = thread_token();
+        let start = token % THREADS;
+        let rings = || (0..THREADS).map(|i| &self.rings[(start + i) % THREADS]);
+        if let Some(ring) = rings().find(|r| r.owner.load(Ordering::Relaxed) == token) {
+            return Some(ring);
+        }
+        rings().find(|r| {
+            r.owner
+                .compare_exchange(0, token, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        })
+    }
+
+    /// Every ring that has been claimed.
+    pub(crate) fn claimed(&self) -> impl Iterator<Item = &ThreadRing<RECORDS>> {
+        self.rings
+            .iter()
+            .filter(|r| r.owner.load(Ordering::Acquire) != 0)
+    }
+}
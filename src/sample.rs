@@ -0,0 +1,316 @@
+use crate::alloc::{AllocAction, AllocMonitor, AllocRel, GatingMonitor};
+use crate::describe::{ConfigReport, MonitorDesc, Overhead};
+use crate::event::thread_token;
+use crate::monitor::NoopMonitor;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// How a `SampleMonitor` picks the calls it forwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleMode {
+    /// Forward the first of every `n` calls made on each thread.
+    Every(usize),
+    /// Forward calls with a probability proportional to their size, on average
+    /// once every `n` bytes, like heap profilers do. Large allocations are
+    /// almost always sampled, and small ones rarely.
+    Bytes(usize),
+}
+
+impl SampleMode {
+    /// How many calls of `size` bytes each forwarded call stands for on average,
+    /// for scaling sampled counts and byte totals back up.
+    pub fn scale(&self, size: usize) -> f64 {
+        match *self {
+            SampleMode::Every(n) => n.max(1) as f64,
+            SampleMode::Bytes(n) => {
+                let p = 1.0 - (-(size as f64) / n.max(1) as f64).exp();
+                if p > 0.0 {
+                    1.0 / p
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+}
+
+/// How many `SampleMonitor`s can sample on each thread. See `SampleMonitor`.
+pub const SAMPLE_STATES: usize = 8;
+
+const STATES: usize = SAMPLE_STATES;
+
+/// Per-thread sampling state of one `SampleMonitor`.
+struct SampleState {
+    owner: Cell<usize>,
+    countdown: Cell<usize>,
+    bytes_left: Cell<u64>,
+    rng: Cell<u64>,
+    forwarding: Cell<bool>,
+}
+
+thread_local! {
+    static STATE: [SampleState; STATES] = const { [const { SampleState::new() }; STATES] };
+}
+
+/// Sets up the sampling state of the current thread, for `init_thread`.
+pub(crate) fn init_thread_locals() {
+    let _ = STATE.try_with(|_| ());
+}
+
+impl SampleState {
+    const fn new() -> Self {
+        Self {
+            owner: Cell::new(0),
+            countdown: Cell::new(0),
+            bytes_left: Cell::new(0),
+            rng: Cell::new(0),
+            forwarding: Cell::new(false),
+        }
+    }
+}
+
+/// The seed of the generator of every thread while it's isolated by
+/// `testing::isolate`.
+#[cfg(feature = "deterministic")]
+const ISOLATED_SEED: u64 = 0x853c_49e6_748f_ea9b;
+
+/// The sampling state of a thread, saved while it's isolated.
+#[cfg(feature = "deterministic")]
+pub(crate) struct SavedStates([(usize, usize, u64, u64, bool); STATES]);
+
+/// Resets the sampling state of the current thread, with the generator seeded
+/// by a fixed seed, and returns the state it had.
+#[cfg(feature = "deterministic")]
+pub(crate) fn isolate_states() -> Option<SavedStates> {
+    STATE
+        .try_with(|states| {
+            let mut saved = [(0, 0, 0, 0, false); STATES];
+            for (state, saved) in states.iter().zip(saved.iter_mut()) {
+                *saved = (
+                    state.owner.replace(0),
+                    state.countdown.replace(0),
+                    state.bytes_left.replace(0),
+                    state.rng.replace(ISOLATED_SEED),
+                    state.forwarding.replace(false),
+                );
+            }
+            SavedStates(saved)
+        })
+        .ok()
+}
+
+/// Puts back the sampling state returned by `isolate_states`.
+#[cfg(feature = "deterministic")]
+pub(crate) fn restore_states(saved: SavedStates) {
+    let _ = STATE.try_with(|states| {
+        for (state, saved) in states.iter().zip(saved.0.iter()) {
+            state.owner.set(saved.0);
+            state.countdown.set(saved.1);
+            state.bytes_left.set(saved.2);
+            state.rng.set(saved.3);
+            state.forwarding.set(saved.4);
+        }
+    });
+}
+
+impl SampleState {
+    /// A uniform random number in `(0, 1]`, from a xorshift64* generator seeded
+    /// by the thread token.
+    fn uniform(&self) -> f64 {
+        let mut x = self.rng.get();
+        if x == 0 {
+            x = (thread_token() as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        }
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.rng.set(x);
+        let bits = x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+        (bits + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Draws the number of bytes until the next sample, with mean `n`.
+    fn next_sample(&self, n: usize) -> u64 {
+        (-self.uniform().ln() * n.max(1) as f64) as u64
+    }
+
+    fn sample(&self, mode: SampleMode, size: usize) -> bool {
+        match mode {
+            SampleMode::Every(n) => {
+                let countdown = self.countdown.get();
+                if countdown == 0 {
+                    self.countdown.set(n.max(1) - 1);
+                    true
+                } else {
+                    self.countdown.set(countdown - 1);
+                    false
+                }
+            }
+            SampleMode::Bytes(n) => {
+                // The bytes until the next sample are exponentially distributed,
+                // so each call is sampled with probability 1 - exp(-size / n).
+                let mut left = self.bytes_left.get();
+                if left == 0 {
+                    left = self.next_sample(n);
+                }
+                if left > size as u64 {
+                    self.bytes_left.set(left - size as u64);
+                    return false;
+                }
+                self.bytes_left.set(self.next_sample(n));
+                true
+            }
+        }
+    }
+}
+
+/// Forwards only a sample of allocator calls to another monitor, to bound the
+/// overhead of expensive monitors like `BacktraceMonitor`.
+///
+/// Sampling is per call, so the before and after actions of a call are always
+/// forwarded together. Deallocations are sampled like allocations, by their
+/// size, independently of whether the allocation was sampled.
+///
+/// Sampling state is kept per thread, so that deciding is cheap and, in `Every`
+/// mode, deterministic for a given thread.
+///
+/// # Limits
+/// Each thread has room for the state of `SAMPLE_STATES` monitors, taken by
+/// the first ones that see a call on it, and kept for the life of the thread.
+/// On a thread that has no room left, a monitor forwards none of the calls,
+/// rather than sharing another monitor's state and losing track of which calls
+/// it forwarded. Those calls are counted by `unsampled`, and lower
+/// `effective_rate`, so they don't go unnoticed.
+///
+/// ```rust
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, GatingMonitor, SampleMode, SampleMonitor, SAMPLE_STATES};
+///
+/// let monitors: Vec<SampleMonitor> = (0..SAMPLE_STATES + 1)
+///     .map(|_| SampleMonitor::new(Default::default(), SampleMode::Every(1)))
+///     .collect();
+/// let layout = Layout::new::<u64>();
+/// std::thread::spawn(move || {
+///     for monitor in &monitors {
+///         monitor.accepts(layout, AllocAction::Alloc);
+///     }
+///     let last = &monitors[SAMPLE_STATES];
+///     assert!(!last.accepts(layout, AllocAction::Alloc));
+///     assert_eq!(last.unsampled(), 2);
+///     assert_eq!(last.effective_rate(), 0.0);
+///     assert_eq!(monitors[0].unsampled(), 0);
+/// })
+/// .join()
+/// .unwrap();
+/// ```
+///
+/// As a `GatingMonitor`, it accepts the calls it samples, so a
+/// `SampleMonitor<NoopMonitor>` can sample the later stages of a
+/// `PipelineMonitor`.
+pub struct SampleMonitor<M = NoopMonitor> {
+    inner: M,
+    mode: SampleMode,
+    seen: AtomicUsize,
+    forwarded: AtomicUsize,
+    unsampled: AtomicUsize,
+}
+
+impl<M> SampleMonitor<M> {
+    pub const fn new(inner: M, mode: SampleMode) -> Self {
+        Self {
+            inner,
+            mode,
+            seen: AtomicUsize::new(0),
+            forwarded: AtomicUsize::new(0),
+            unsampled: AtomicUsize::new(0),
+        }
+    }
+
+    /// The monitor that sampled calls are forwarded to.
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    pub fn mode(&self) -> SampleMode {
+        self.mode
+    }
+
+    /// How many calls of `size` bytes each forwarded call stands for.
+    pub fn scale(&self, size: usize) -> f64 {
+        self.mode.scale(size)
+    }
+
+    /// The fraction of calls forwarded so far, or 1 if there haven't been any.
+    pub fn effective_rate(&self) -> f64 {
+        let seen = self.seen.load(Ordering::Relaxed);
+        if seen == 0 {
+            return 1.0;
+        }
+        self.forwarded.load(Ordering::Relaxed) as f64 / seen as f64
+    }
+
+    /// How many calls weren't sampled, on threads that had no room left for
+    /// this monitor's state.
+    pub fn unsampled(&self) -> usize {
+        self.unsampled.load(Ordering::Relaxed)
+    }
+}
+
+impl<M> GatingMonitor for SampleMonitor<M> {
+    fn accepts(&self, layout: Layout, action: AllocAction) -> bool {
+        let forward = STATE.try_with(|states| {
+            let id = self as *const Self as usize;
+            let state = match states
+                .iter()
+                .find(|s| s.owner.get() == id || s.owner.get() == 0)
+            {
+                Some(state) => state,
+                None => {
+                    if action.relation() != AllocRel::After {
+                        self.seen.fetch_add(1, Ordering::Relaxed);
+                        self.unsampled.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return false;
+                }
+            };
+            state.owner.set(id);
+            if action.relation() == AllocRel::After {
+                return state.forwarding.replace(false);
+            }
+            let size = action.new_size().unwrap_or(layout.size());
+            let forward = state.sample(self.mode, size);
+            state.forwarding.set(forward);
+            self.seen.fetch_add(1, Ordering::Relaxed);
+            if forward {
+                self.forwarded.fetch_add(1, Ordering::Relaxed);
+            }
+            forward
+        });
+        forward.unwrap_or(false)
+    }
+}
+
+impl<M: AllocMonitor> AllocMonitor for SampleMonitor<M> {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        if self.accepts(layout, action) {
+            self.inner.monitor(layout, action);
+        }
+    }
+
+    fn on_first_event(&self) {
+        self.inner.on_first_event();
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        let desc = MonitorDesc::new("SampleMonitor", Overhead::Atomic);
+        match self.mode {
+            SampleMode::Every(n) => desc.param("every", n as u64),
+            SampleMode::Bytes(n) => desc.param("bytes", n as u64),
+        }
+    }
+
+    fn describe_inner(&self, report: &mut ConfigReport) {
+        report.add(&self.inner);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::contention::Contention;
+#[cfg(feature = "self-metrics")]
+use crate::contention::ContentionStats;
+use crate::sync::{fence, spin_loop, AtomicUsize, Ordering, UnsafeCell};
+
+/// A sequence lock: a value that is read by copying it out optimistically, and
+/// retrying if a write happened in the meantime. Readers never block writers,
+/// and writers only wait for each other.
+///
+/// The sequence is odd while a write is in progress, and changes with every
+/// write, so readers can tell when they may have copied a torn value.
+pub struct SeqLock<T: Copy> {
+    sequence: AtomicUsize,
+    value: UnsafeCell<T>,
+    contention: Contention,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    #[cfg(not(loom))]
+    pub const fn new(value: T) -> Self {
+        Self {
+            sequence: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+            contention: Contention::new(),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new(value: T) -> Self {
+        Self {
+            sequence: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+            contention: Contention::new(),
+        }
+    }
+
+    /// Copies the value out, retrying until no write happened during the copy.
+    #[inline]
+    pub fn read(&self) -> T {
+        let mut retries = 0;
+        loop {
+            if let Some(value) = self.try_read() {
+                if retries > 0 {
+                    self.contention.retried(retries);
+                }
+                return value;
+            }
+            retries += 1;
+            spin_loop();
+        }
+    }
+
+    /// Tries to copy the value out once, returning `None` if a write was in
+    /// progress or happened during the copy. This never blocks, so it can be
+    /// used from signal handlers, even if the handler interrupted a write.
+    #[cfg(not(any(miri, loom)))]
+    #[inline]
+    pub fn try_read(&self) -> Option<T> {
+        let before = self.sequence.load(Ordering::Acquire);
+        if before % 2 != 0 {
+            return None;
+        }
+        // The copy may race with a writer, in which case it's thrown away below.
+        let value = self
+            .value
+            .with(|value| unsafe { core::ptr::read_volatile(value) });
+        fence(Ordering::Acquire);
+        let after = self.sequence.load(Ordering::Relaxed);
+        if before == after {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Miri rightly reports the optimistic copy as a data race, and so would
+    /// loom, through its `UnsafeCell`, so under either readers take the write
+    /// side instead, like a spinlock. That means this can block. Loom still
+    /// checks that writers exclude each other and readers, and that what they
+    /// write is visible to the next writer or reader.
+    #[cfg(any(miri, loom))]
+    #[inline]
+    pub fn try_read(&self) -> Option<T> {
+        Some(self.update(|value| *value))
+    }
+
+    /// How often writers had to wait for each other, and reads had to start
+    /// over.
+    #[cfg(feature = "self-metrics")]
+    pub fn contention(&self) -> ContentionStats {
+        self.contention.stats()
+    }
+
+    /// Replaces the value.
+    #[inline]
+    pub fn write(&self, value: T) {
+        self.update(|v| *v = value);
+    }
+
+    /// Modifies the value in place. Updates from different threads are
+    /// serialized, so none of them are lost.
+    #[inline]
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut sequence = self.sequence.load(Ordering::Relaxed);
+        let mut loops = 0;
+        loop {
+            if sequence % 2 == 0 {
+                match self.sequence.compare_exchange(
+                    sequence,
+                    sequence.wrapping_add(1),
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => sequence = current,
+                }
+            } else {
+                spin_loop();
+                sequence = self.sequence.load(Ordering::Relaxed);
+            }
+            loops += 1;
+        }
+        if loops > 0 {
+            self.contention.waited(loops);
+        }
+        fence(Ordering::Release);
+        let result = self.value.with_mut(|ptr| {
+            let mut value = unsafe { core::ptr::read_volatile(ptr) };
+            let result = f(&mut value);
+            unsafe { core::ptr::write_volatile(ptr, value) };
+            result
+        });
+        self.sequence
+            .store(sequence.wrapping_add(2), Ordering::Release);
+        result
+    }
+}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
@@ -0,0 +1,141 @@
+//! Dumping allocation statistics on `SIGUSR1`.
+//!
+//! After calling `install_dump_handler`, sending the process `SIGUSR1` (e.g. with
+//! `kill -USR1 <pid>`) writes a line like the following to standard error:
+//!
+//! ```text
+//! interloc: {"alloc":10,"dealloc":4,"realloc":0,"bytes_alloc":1024,"bytes_dealloc":256,"peak_bytes":1024,"baseline_bytes":0}
+//! ```
+//!
+//! The handler only does async-signal-safe work: the snapshot is read with
+//! `StatsMonitor::try_info`, formatted into a buffer on the stack, and written with
+//! `write(2)`. If the signal interrupts a thread that is in the middle of
+//! updating the monitor, the handler writes `interloc: busy` instead of waiting.
+//!
+//! The handler can only be installed once per process. It replaces whatever
+//! handler was installed for `SIGUSR1` before it, and calls that handler after
+//! writing its own output, unless it was the default or ignore disposition.
+//! Handlers installed for `SIGUSR1` afterwards replace this one.
+use crate::fmt::FmtBuffer;
+use crate::monitor::StatsMonitor;
+use core::fmt::Write;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::io;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static MONITOR: AtomicPtr<StatsMonitor> = AtomicPtr::new(ptr::null_mut());
+static PREVIOUS: AtomicUsize = AtomicUsize::new(0);
+static PREVIOUS_SIGINFO: AtomicBool = AtomicBool::new(false);
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn errno() -> *mut libc::c_int {
+    libc::__errno_location()
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+unsafe fn errno() -> *mut libc::c_int {
+    libc::__error()
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+)))]
+unsafe fn errno() -> *mut libc::c_int {
+    ptr::null_mut()
+}
+
+extern "C" fn handle(signum: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let errno = unsafe { errno() };
+    let saved_errno = if errno.is_null() {
+        0
+    } else {
+        unsafe { *errno }
+    };
+
+    let mut buf = FmtBuffer::<256>::new();
+    let monitor = MONITOR.load(Ordering::Acquire);
+    let _ = buf.write_str("interloc: ");
+    match unsafe { monitor.as_ref() }.and_then(StatsMonitor::try_info) {
+        Some(info) => {
+            let _ = info.write_json(&mut buf);
+        }
+        None => {
+            let _ = buf.write_str("busy");
+        }
+    }
+    let _ = buf.write_str("\n");
+
+    let mut bytes = buf.as_bytes();
+    while !bytes.is_empty() {
+        let written =
+            unsafe { libc::write(libc::STDERR_FILENO, bytes.as_ptr() as *const _, bytes.len()) };
+        if written <= 0 {
+            break;
+        }
+        bytes = &bytes[written as usize..];
+    }
+
+    let previous = PREVIOUS.load(Ordering::Acquire);
+    if previous != libc::SIG_DFL && previous != libc::SIG_IGN {
+        if PREVIOUS_SIGINFO.load(Ordering::Acquire) {
+            let previous: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                unsafe { core::mem::transmute(previous) };
+            previous(signum, info, context);
+        } else {
+            let previous: extern "C" fn(libc::c_int) = unsafe { core::mem::transmute(previous) };
+            previous(signum);
+        }
+    }
+
+    if !errno.is_null() {
+        unsafe { *errno = saved_errno };
+    }
+}
+
+/// Installs a `SIGUSR1` handler that writes a snapshot of `monitor` to standard
+/// error. Returns an error of kind `AlreadyExists` if it has already been
+/// installed, or the OS error if the handler couldn't be registered.
+pub fn install_dump_handler(monitor: &'static StatsMonitor) -> io::Result<()> {
+    if INSTALLED.swap(true, Ordering::AcqRel) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "the interloc dump handler is already installed",
+        ));
+    }
+    MONITOR.store(monitor as *const _ as *mut _, Ordering::Release);
+
+    unsafe {
+        let mut action: libc::sigaction = core::mem::zeroed();
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) = handle;
+        action.sa_sigaction = handler as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        let mut previous: libc::sigaction = core::mem::zeroed();
+        // Block the signal while swapping handlers so the handler never sees a
+        // half-recorded previous handler.
+        let mut set: libc::sigset_t = core::mem::zeroed();
+        let mut old_set: libc::sigset_t = core::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGUSR1);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old_set);
+        let result = libc::sigaction(libc::SIGUSR1, &action, &mut previous);
+        let error = io::Error::last_os_error();
+        if result == 0 {
+            PREVIOUS_SIGINFO.store(previous.sa_flags & libc::SA_SIGINFO != 0, Ordering::Release);
+            PREVIOUS.store(previous.sa_sigaction, Ordering::Release);
+        }
+        libc::pthread_sigmask(libc::SIG_SETMASK, &old_set, ptr::null_mut());
+
+        if result != 0 {
+            INSTALLED.store(false, Ordering::Release);
+            return Err(error);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,392 @@
+use crate::slots::Slots;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+const EMPTY: u8 = 0;
+const WRITING: u8 = 1;
+const READY: u8 = 2;
+
+/// How many slots from its home slot a site can be stored in.
+const PROBE: usize = 16;
+
+/// A slot of a `SiteTable`.
+struct Slot<const DEPTH: usize> {
+    hash: AtomicU64,
+    state: AtomicU8,
+    len: AtomicUsize,
+    frames: [AtomicUsize; DEPTH],
+    count: AtomicUsize,
+    bytes: AtomicU64,
+    /// Set when the site is recorded, and cleared by eviction scans.
+    referenced: AtomicBool,
+}
+
+impl<const DEPTH: usize> Slot<DEPTH> {
+    const fn new() -> Self {
+        Self {
+            hash: AtomicU64::new(0),
+            state: AtomicU8::new(EMPTY),
+            len: AtomicUsize::new(0),
+            frames: [const { AtomicUsize::new(0) }; DEPTH],
+            count: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            referenced: AtomicBool::new(false),
+        }
+    }
+
+    fn write_frames(&self, frames: &[usize]) {
+        for (dst, src) in self.frames.iter().zip(frames) {
+            dst.store(*src, Ordering::Relaxed);
+        }
+        self.len.store(frames.len(), Ordering::Relaxed);
+    }
+
+    fn add(&self, bytes: usize) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.referenced.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A snapshot of one allocation site in a `SiteTable`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AllocSite {
+    /// Hash of the frame addresses, which identifies the site
+    pub hash: u64,
+    /// Return addresses of the stack, innermost first
+    pub frames: Vec<usize>,
+    /// Number of allocations recorded at this site
+    pub count: usize,
+    /// Bytes allocated at this site
+    pub bytes: u64,
+}
+
+/// The FNV-1a hash of nothing, to start `fnv1a` from.
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Adds `bytes` to the FNV-1a hash `hash`.
+pub(crate) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Hashes a stack of frame addresses with FNV-1a. Never returns zero, which marks
+/// empty slots.
+pub(crate) fn hash_frames(frames: &[usize]) -> u64 {
+    frames
+        .iter()
+        .fold(FNV_OFFSET, |hash, frame| {
+            fnv1a(hash, &(*frame as u64).to_le_bytes())
+        })
+        .max(1)
+}
+
+/// A fixed-capacity hash table of allocation sites, identified by up to `DEPTH`
+/// frame addresses, accumulating an allocation count and byte count per site.
+///
+/// Recording never allocates or blocks, so it can be done from inside a monitor.
+/// Each site can be stored in one of 16 slots near its hash. When those are all
+/// taken, a new site evicts the cold site with the fewest bytes, clock-style:
+/// sites recorded since the last eviction scan get a second chance. This keeps
+/// the sites with the most bytes in the table under pressure, at the cost of
+/// forgetting small ones; `evictions` and `evicted_bytes` bound the error of
+/// reports made from the table. Counts may also be lost when a site is recorded
+/// while it's being evicted, and allocations that can't claim a slot at all are
+/// counted in `dropped`.
+pub struct SiteTable<const SITES: usize, const DEPTH: usize> {
+    slots: Slots<Slot<DEPTH>, SITES>,
+    dropped: AtomicUsize,
+    evictions: AtomicUsize,
+    evicted_bytes: AtomicU64,
+}
+
+impl<const SITES: usize, const DEPTH: usize> SiteTable<SITES, DEPTH> {
+    pub const fn new() -> Self {
+        Self {
+            slots: Slots::new([const { Slot::new() }; SITES]),
+            dropped: AtomicUsize::new(0),
+            evictions: AtomicUsize::new(0),
+            evicted_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Records an allocation of `bytes` bytes at the site with the stack `frames`.
+    /// Frames past `DEPTH` are ignored.
+    pub fn record(&self, frames: &[usize], bytes: usize) {
+        let frames = &frames[..frames.len().min(DEPTH)];
+        let hash = hash_frames(frames);
+        if self.slots.is_empty() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let start = (hash % SITES as u64) as usize;
+        let window = PROBE.min(SITES);
+        for i in 0..window {
+            let slot = &self.slots[(start + i) % SITES];
+            let current = slot.hash.load(Ordering::Acquire);
+            let claimed = current == 0
+                && slot
+                    .hash
+                    .compare_exchange(0, hash, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok();
+            if claimed {
+                slot.state.store(WRITING, Ordering::Relaxed);
+                slot.write_frames(frames);
+                slot.state.store(READY, Ordering::Release);
+            } else if slot.hash.load(Ordering::Acquire) != hash {
+                continue;
+            }
+            slot.add(bytes);
+            return;
+        }
+        self.evict(start, window, hash, frames, bytes);
+    }
+
+    /// Replaces the coldest site with the fewest bytes in the window of `window`
+    /// slots from `start` with a new site.
+    fn evict(&self, start: usize, window: usize, hash: u64, frames: &[usize], bytes: usize) {
+        let mut victim: Option<&Slot<DEPTH>> = None;
+        for second_pass in [false, true] {
+            let mut lowest = u64::MAX;
+            for i in 0..window {
+                let slot = &self.slots[(start + i) % SITES];
+                if slot.state.load(Ordering::Acquire) != READY {
+                    continue;
+                }
+                if !second_pass && slot.referenced.swap(false, Ordering::Relaxed) {
+                    continue;
+                }
+                let slot_bytes = slot.bytes.load(Ordering::Relaxed);
+                if slot_bytes < lowest {
+                    lowest = slot_bytes;
+                    victim = Some(slot);
+                }
+            }
+            if victim.is_some() {
+                break;
+            }
+        }
+
+        let slot = match victim {
+            Some(slot)
+                if slot
+                    .state
+                    .compare_exchange(READY, WRITING, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok() =>
+            {
+                slot
+            }
+            _ => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        self.evicted_bytes
+            .fetch_add(slot.bytes.swap(0, Ordering::Relaxed), Ordering::Relaxed);
+        slot.count.store(0, Ordering::Relaxed);
+        slot.hash.store(hash, Ordering::Release);
+        slot.write_frames(frames);
+        slot.state.store(READY, Ordering::Release);
+        slot.add(bytes);
+    }
+
+    /// Number of allocations that weren't recorded because no slot could be
+    /// claimed for their site.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of sites evicted to make room for new ones.
+    pub fn evictions(&self) -> usize {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    /// Bytes that were recorded at evicted sites, and are missing from `sites`.
+    pub fn evicted_bytes(&self) -> u64 {
+        self.evicted_bytes.load(Ordering::Relaxed)
+    }
+
+    /// A snapshot of every site in the table, in no particular order. Sites that
+    /// are still being inserted are left out.
+    pub fn sites(&self) -> Vec<AllocSite> {
+        let mut sites: Vec<AllocSite> = self
+            .slots
+            .iter()
+            .filter(|slot| slot.state.load(Ordering::Acquire) == READY)
+            .map(|slot| {
+                let len = slot.len.load(Ordering::Relaxed);
+                AllocSite {
+                    hash: slot.hash.load(Ordering::Relaxed),
+                    frames: slot.frames[..len]
+                        .iter()
+                        .map(|f| f.load(Ordering::Relaxed))
+                        .collect(),
+                    count: slot.count.load(Ordering::Relaxed),
+                    bytes: slot.bytes.load(Ordering::Relaxed),
+                }
+            })
+            .collect();
+
+        // Racing evictions can store a site twice.
+        sites.sort_unstable_by_key(|site| site.hash);
+        sites.dedup_by(|dup, site| {
+            if dup.hash != site.hash {
+                return false;
+            }
+            site.count += dup.count;
+            site.bytes += dup.bytes;
+            true
+        });
+        sites
+    }
+
+    /// The `n` sites with the most bytes allocated, most first.
+    pub fn top_sites(&self, n: usize) -> Vec<AllocSite> {
+        let mut sites = self.sites();
+        sites.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.count.cmp(&a.count)));
+        sites.truncate(n);
+        sites
+    }
+}
+
+impl<const SITES: usize, const DEPTH: usize> Default for SiteTable<SITES, DEPTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Frames whose function names start with one of these are dropped from the
+/// innermost end of symbolized stacks, since they belong to the allocator path
+/// rather than the code doing the allocating.
+#[cfg(feature = "backtrace")]
+pub const DEFAULT_SKIP_PREFIXES: &[&str] = &[
+    "interloc::",
+    "<interloc::",
+    "backtrace::",
+    "__rust",
+    "__rdl",
+    "alloc::alloc::",
+    "<alloc::alloc::Global",
+    "std::alloc::",
+];
+
+/// A single symbolized frame of an allocation site.
+#[cfg(feature = "backtrace")]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SymbolizedFrame {
+    /// The return address the frame was resolved from
+    pub addr: usize,
+    /// Demangled function name, or the address if it couldn't be resolved
+    pub name: String,
+    pub filename: Option<String>,
+    pub line: Option<u32>,
+}
+
+/// An allocation site with its stack resolved to functions, from
+/// `SiteTable::symbolize`.
+#[cfg(feature = "backtrace")]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SymbolizedSite {
+    /// Innermost frame first, with inlined functions as frames of their own
+    pub frames: Vec<SymbolizedFrame>,
+    pub count: usize,
+    pub bytes: u64,
+}
+
+#[cfg(feature = "backtrace")]
+impl<const SITES: usize, const DEPTH: usize> SiteTable<SITES, DEPTH> {
+    /// Resolves the stacks of every site to functions, files and lines, most
+    /// bytes allocated first, dropping allocator frames with
+    /// `DEFAULT_SKIP_PREFIXES`.
+    ///
+    /// This is slow and allocates, so it must not be called from inside a
+    /// monitor. Allocations made while symbolizing aren't monitored.
+    pub fn symbolize(&self) -> Vec<SymbolizedSite> {
+        self.symbolize_with(DEFAULT_SKIP_PREFIXES)
+    }
+
+    /// Like `symbolize`, but drops innermost frames whose function names start
+    /// with any of `skip_prefixes` instead.
+    pub fn symbolize_with(&self, skip_prefixes: &[&str]) -> Vec<SymbolizedSite> {
+        crate::alloc::internal(|| {
+            self.top_sites(SITES)
+                .into_iter()
+                .map(|site| SymbolizedSite {
+                    frames: symbolize_frames(&site.frames, skip_prefixes),
+                    count: site.count,
+                    bytes: site.bytes,
+                })
+                .collect()
+        })
+    }
+}
+
+/// Resolves `addrs` to frames, expanding inlined functions and collapsing
+/// consecutive frames that resolve to the same place.
+#[cfg(feature = "backtrace")]
+fn symbolize_frames(addrs: &[usize], skip_prefixes: &[&str]) -> Vec<SymbolizedFrame> {
+    let mut frames: Vec<SymbolizedFrame> = Vec::new();
+    for addr in addrs {
+        let start = frames.len();
+        // Return addresses point just past the call, which may already be the
+        // next line or function.
+        let lookup = addr.saturating_sub(1) as *mut core::ffi::c_void;
+        backtrace::resolve(lookup, |symbol| {
+            frames.push(SymbolizedFrame {
+                addr: *addr,
+                name: symbol
+                    .name()
+                    .map(|n| format!("{:#}", n))
+                    .unwrap_or_else(|| format!("{:#x}", addr)),
+                filename: symbol.filename().map(|p| p.display().to_string()),
+                line: symbol.lineno(),
+            });
+        });
+        if frames.len() == start {
+            frames.push(SymbolizedFrame {
+                addr: *addr,
+                name: format!("{:#x}", addr),
+                filename: None,
+                line: None,
+            });
+        }
+    }
+
+    frames.dedup_by(|b, a| a.name == b.name && a.filename == b.filename && a.line == b.line);
+    let skip = frames
+        .iter()
+        .take_while(|f| skip_prefixes.iter().any(|p| f.name.starts_with(p)))
+        .count();
+    frames.drain(..skip);
+    frames
+}
+
+#[cfg(feature = "backtrace")]
+impl core::fmt::Display for SymbolizedFrame {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(filename) = &self.filename {
+            write!(f, " at {}", filename)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "backtrace")]
+impl core::fmt::Display for SymbolizedSite {
+    /// Writes a summary line with the count and bytes, followed by one indented
+    /// line per frame.
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        writeln!(f, "{} bytes in {} allocations", self.bytes, self.count)?;
+        for frame in &self.frames {
+            writeln!(f, "    {}", frame)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+//! The tables behind the monitors that keep one, which are compiled out with
+//! the `disabled` feature.
+#[cfg(feature = "disabled")]
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+/// A fixed table of `N` slots, which derefs to a slice of them. With the
+/// `disabled` feature it's zero-sized and always empty, so monitors check
+/// `is_empty` rather than `N` before hashing into it.
+#[cfg(not(feature = "disabled"))]
+pub(crate) struct Slots<T, const N: usize>([T; N]);
+
+#[cfg(feature = "disabled")]
+pub(crate) struct Slots<T, const N: usize>(PhantomData<[T; N]>);
+
+impl<T, const N: usize> Slots<T, N> {
+    #[cfg(not(feature = "disabled"))]
+    pub(crate) const fn new(slots: [T; N]) -> Self {
+        Self(slots)
+    }
+
+    #[cfg(feature = "disabled")]
+    pub(crate) const fn new(slots: [T; N]) -> Self {
+        core::mem::forget(slots);
+        Self(PhantomData)
+    }
+}
+
+impl<T, const N: usize> Deref for Slots<T, N> {
+    type Target = [T];
+
+    #[cfg(not(feature = "disabled"))]
+    #[inline(always)]
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+
+    #[cfg(feature = "disabled")]
+    #[inline(always)]
+    fn deref(&self) -> &[T] {
+        &[]
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a Slots<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, const N: usize> AsRef<[T]> for Slots<T, N> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
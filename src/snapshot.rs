@@ -0,0 +1,281 @@
+use crate::monitor::{Accounting, AllocInfo, InfoSource, StatsMonitor, ThreadMonitor};
+use core::fmt;
+
+/// A snapshot of a resettable monitor's statistics, stamped with how many times
+/// the monitor had been reset when it was taken, so that deltas across a reset
+/// are caught instead of coming out as garbage, and with how it counted bytes,
+/// so that deltas between monitors that count them differently are too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AllocSnapshot {
+    pub info: AllocInfo,
+    /// Bumped by every `reset`, `take` and `write_info` of the monitor
+    pub generation: u64,
+    pub accounting: Accounting,
+}
+
+impl AllocSnapshot {
+    pub const fn new() -> Self {
+        Self {
+            info: AllocInfo::new(),
+            generation: 0,
+            accounting: Accounting::Requested,
+        }
+    }
+
+    /// The allocations that happened between `origin` and `self`, as with
+    /// `AllocInfo::relative_to`, or an error if the monitor was reset in
+    /// between, `origin` counted bytes with another `Accounting`, or it's ahead
+    /// of `self`.
+    ///
+    /// ```rust
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, SnapshotError, ThreadMonitor};
+    ///
+    /// let monitor = ThreadMonitor::new();
+    /// let layout = Layout::from_size_align(100, 1).unwrap();
+    /// let origin = monitor.snapshot();
+    /// monitor.monitor(layout, AllocAction::Alloc);
+    /// assert_eq!(monitor.snapshot().delta_since(&origin).unwrap().alloc, 1);
+    ///
+    /// // The live bytes carried over make the end look like a plausible delta.
+    /// monitor.take();
+    /// let end = monitor.snapshot();
+    /// assert!(end.info.checked_relative_to(&origin.info).is_some());
+    /// assert_eq!(
+    ///     end.delta_since(&origin),
+    ///     Err(SnapshotError::GenerationMismatch {
+    ///         origin: origin.generation,
+    ///         current: origin.generation + 1,
+    ///     })
+    /// );
+    /// ```
+    pub fn delta_since(&self, origin: &Self) -> Result<AllocInfo, SnapshotError> {
+        if self.accounting != origin.accounting {
+            return Err(SnapshotError::AccountingMismatch {
+                origin: origin.accounting,
+                current: self.accounting,
+            });
+        }
+        if self.generation != origin.generation {
+            return Err(SnapshotError::GenerationMismatch {
+                origin: origin.generation,
+                current: self.generation,
+            });
+        }
+        self.info
+            .checked_relative_to(&origin.info)
+            .ok_or(SnapshotError::OriginAhead)
+    }
+}
+
+/// Why `AllocSnapshot::delta_since` couldn't compute a delta.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SnapshotError {
+    /// The monitor was reset between the snapshots.
+    GenerationMismatch { origin: u64, current: u64 },
+    /// The origin counted more than the later snapshot, e.g. because they came
+    /// from different monitors.
+    OriginAhead,
+    /// The snapshots came from monitors that count bytes differently.
+    AccountingMismatch {
+        origin: Accounting,
+        current: Accounting,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::GenerationMismatch { origin, current } => write!(
+                f,
+                "monitor was reset during the measurement (generation {} to {})",
+                origin, current
+            ),
+            SnapshotError::OriginAhead => f.write_str("origin snapshot is ahead of the end"),
+            SnapshotError::AccountingMismatch { origin, current } => write!(
+                f,
+                "snapshots count bytes differently ({:?} to {:?})",
+                origin, current
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// A monitor whose statistics can be reset, and so can only be subtracted from
+/// snapshots taken since the last reset.
+pub trait SnapshotSource: InfoSource {
+    /// The statistics as of now, with the monitor's generation.
+    fn snapshot(&self) -> AllocSnapshot;
+
+    /// Starts measuring the allocations counted by the monitor from now on.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, SnapshotError, SnapshotSource, StatsMonitor};
+    ///
+    /// let monitor = StatsMonitor::new();
+    /// let layout = Layout::from_size_align(100, 1).unwrap();
+    /// monitor.monitor(layout, AllocAction::Alloc);
+    ///
+    /// let measurement = monitor.start_measurement();
+    /// monitor.monitor(layout, AllocAction::Alloc);
+    /// assert_eq!(measurement.delta().unwrap().bytes_alloc, 100);
+    ///
+    /// // Without the generation, this would underflow, or panic in debug builds.
+    /// monitor.reset();
+    /// assert!(matches!(
+    ///     measurement.delta(),
+    ///     Err(SnapshotError::GenerationMismatch { origin: 0, current: 1 })
+    /// ));
+    /// ```
+    fn start_measurement(&self) -> Measurement<'_, Self> {
+        Measurement {
+            source: self,
+            start: self.snapshot(),
+        }
+    }
+
+    /// Starts following what the monitor counts, one poll at a time. See
+    /// `DeltaCursor`.
+    fn subscribe(&self) -> DeltaCursor<'_, Self> {
+        DeltaCursor {
+            source: self,
+            last: self.snapshot(),
+        }
+    }
+}
+
+impl<T: SnapshotSource + ?Sized> SnapshotSource for &T {
+    fn snapshot(&self) -> AllocSnapshot {
+        (**self).snapshot()
+    }
+}
+
+impl SnapshotSource for StatsMonitor {
+    fn snapshot(&self) -> AllocSnapshot {
+        StatsMonitor::snapshot(self)
+    }
+}
+
+impl StatsMonitor {
+    /// Starts following what the monitor counts, one poll at a time, as with
+    /// `SnapshotSource::subscribe`.
+    pub fn subscribe(&self) -> DeltaCursor<'_, Self> {
+        SnapshotSource::subscribe(self)
+    }
+}
+
+impl SnapshotSource for ThreadMonitor {
+    fn snapshot(&self) -> AllocSnapshot {
+        ThreadMonitor::snapshot(self)
+    }
+}
+
+/// A measurement of what a monitor counted since it was started with
+/// `SnapshotSource::start_measurement`.
+pub struct Measurement<'a, S: SnapshotSource + ?Sized> {
+    source: &'a S,
+    start: AllocSnapshot,
+}
+
+impl<'a, S: SnapshotSource + ?Sized> Measurement<'a, S> {
+    /// The snapshot taken at the start.
+    pub fn start(&self) -> AllocSnapshot {
+        self.start
+    }
+
+    /// What the monitor counted since the start, or an error if it was reset in
+    /// the meantime.
+    pub fn delta(&self) -> Result<AllocInfo, SnapshotError> {
+        self.source.snapshot().delta_since(&self.start)
+    }
+}
+
+/// What a `DeltaCursor` saw when it was polled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeltaPoll {
+    /// What the monitor counted since the last poll. The peak is the monitor's
+    /// own, since peaks can't be subtracted.
+    Delta(AllocInfo),
+    /// The monitor was reset since the last poll, so what it counted in between
+    /// is lost. The next poll counts from this one.
+    Reset,
+}
+
+/// Follows what a monitor counts, for dashboards that show it as it goes: each
+/// `poll` returns what was counted since the previous one, from
+/// `SnapshotSource::subscribe` or `StatsMonitor::subscribe`.
+///
+/// A cursor keeps the totals it last saw along with the monitor's generation,
+/// so a reset between two polls comes out as `DeltaPoll::Reset` rather than a
+/// delta that underflows or looks plausible. It's nothing more than that, so
+/// any number of cursors can follow the same monitor, each at its own pace,
+/// without the monitor keeping track of them.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocInfo, AllocMonitor, DeltaPoll, StatsMonitor};
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+///
+/// fn add(total: &mut AllocInfo, poll: DeltaPoll) {
+///     match poll {
+///         DeltaPoll::Delta(delta) => total.merge(&delta),
+///         DeltaPoll::Reset => panic!("not reset"),
+///     }
+/// }
+///
+/// let start = MONITOR.info();
+/// let mut fast = MONITOR.subscribe();
+/// let mut slow = MONITOR.subscribe();
+/// let (mut fast_total, mut slow_total) = (AllocInfo::new(), AllocInfo::new());
+/// for i in 1..=100 {
+///     let layout = Layout::from_size_align(i, 1).unwrap();
+///     MONITOR.monitor(layout, AllocAction::Alloc);
+///     add(&mut fast_total, fast.poll());
+///     if i % 7 == 0 {
+///         add(&mut slow_total, slow.poll());
+///     }
+/// }
+/// add(&mut slow_total, slow.poll());
+///
+/// let total = MONITOR.info().relative_to(&start);
+/// assert_eq!((total.alloc, total.bytes_alloc), (100, 5050));
+/// assert_eq!(fast_total, total);
+/// assert_eq!(slow_total, total);
+///
+/// // Both cursors see the reset, once, then carry on from it.
+/// MONITOR.reset();
+/// MONITOR.monitor(Layout::new::<u64>(), AllocAction::Alloc);
+/// assert_eq!(fast.poll(), DeltaPoll::Reset);
+/// assert_eq!(slow.poll(), DeltaPoll::Reset);
+/// MONITOR.monitor(Layout::new::<u64>(), AllocAction::Alloc);
+/// assert!(matches!(fast.poll(), DeltaPoll::Delta(delta) if delta.bytes_alloc == 8));
+/// ```
+pub struct DeltaCursor<'a, S: SnapshotSource + ?Sized> {
+    source: &'a S,
+    last: AllocSnapshot,
+}
+
+impl<'a, S: SnapshotSource + ?Sized> DeltaCursor<'a, S> {
+    /// What the monitor counted since the last poll, or since the cursor was
+    /// created, or `DeltaPoll::Reset` if it was reset in the meantime.
+    pub fn poll(&mut self) -> DeltaPoll {
+        let now = self.source.snapshot();
+        let delta = now.delta_since(&self.last);
+        self.last = now;
+        match delta {
+            Ok(delta) => DeltaPoll::Delta(delta),
+            Err(_) => DeltaPoll::Reset,
+        }
+    }
+
+    /// The snapshot taken by the last poll, or when the cursor was created.
+    pub fn last(&self) -> AllocSnapshot {
+        self.last
+    }
+}
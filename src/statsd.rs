@@ -0,0 +1,214 @@
+use crate::monitor::AllocInfo;
+use crate::snapshot::AllocSnapshot;
+use core::fmt::Write;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// Pushes allocation statistics to a statsd or DogStatsD server over UDP.
+///
+/// This type does its own allocations and socket IO, so it should be driven from
+/// a reporting thread owned by the user rather than from inside a monitor. Each
+/// call to `report` sends a single datagram containing one line per metric.
+///
+/// Cumulative fields are sent as counters (`|c`) containing the change since the
+/// previous report, since statsd counters are incremented by the value sent.
+/// Live and peak bytes are sent as gauges (`|g`).
+pub struct StatsdReporter {
+    socket: UdpSocket,
+    prefix: String,
+    tags: String,
+    buf: String,
+    last: AllocSnapshot,
+}
+
+impl StatsdReporter {
+    /// Creates a reporter sending to `addr`, with every metric name prefixed
+    /// by `prefix` and a period. The socket is bound to an ephemeral local port
+    /// up front, so reporting never needs to bind.
+    pub fn new(addr: impl ToSocketAddrs, prefix: &str) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send to"))?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.to_owned(),
+            tags: String::new(),
+            buf: String::new(),
+            last: AllocSnapshot::new(),
+        })
+    }
+
+    /// Attaches DogStatsD tags to every line sent by this reporter.
+    pub fn with_tags(mut self, tags: &[(&str, &str)]) -> Self {
+        self.tags.clear();
+        for (i, (key, value)) in tags.iter().enumerate() {
+            self.tags.push_str(if i == 0 { "|#" } else { "," });
+            self.tags.push_str(key);
+            if !value.is_empty() {
+                self.tags.push(':');
+                self.tags.push_str(value);
+            }
+        }
+        self
+    }
+
+    /// The local address of the socket used to send reports.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Formats `info` into the reporter's buffer and sends it as one datagram.
+    ///
+    /// If any counter of `info` is below the one last reported, the monitor was
+    /// reset or taken from since, so the counters are sent as they are: the
+    /// events since the reset, the only ones of the interval that are known. A
+    /// reset followed by more events than were counted before it can't be told
+    /// from growth, though; `report_snapshot` catches those too.
+    ///
+    /// ```rust
+    /// use interloc::{AllocInfo, StatsdReporter};
+    /// use std::collections::HashMap;
+    /// use std::net::UdpSocket;
+    ///
+    /// let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let mut reporter = StatsdReporter::new(server.local_addr().unwrap(), "app")
+    ///     .unwrap()
+    ///     .with_tags(&[("env", "test"), ("canary", "")]);
+    ///
+    /// let mut receive = || {
+    ///     let mut buf = [0; 1500];
+    ///     let len = server.recv(&mut buf).unwrap();
+    ///     let datagram = String::from_utf8(buf[..len].to_vec()).unwrap();
+    ///     let mut metrics = HashMap::new();
+    ///     for line in datagram.lines() {
+    ///         let (name, rest) = line.split_once(':').unwrap();
+    ///         let mut fields = rest.split('|');
+    ///         let value: u64 = fields.next().unwrap().parse().unwrap();
+    ///         let kind = fields.next().unwrap().to_owned();
+    ///         assert_eq!(fields.next(), Some("#env:test,canary"));
+    ///         metrics.insert(name.to_owned(), (value, kind));
+    ///     }
+    ///     metrics
+    /// };
+    ///
+    /// let mut info = AllocInfo::new();
+    /// info.alloc = 10;
+    /// info.bytes_alloc = 1000;
+    /// info.bytes_dealloc = 400;
+    /// info.peak_bytes = 800;
+    /// reporter.report(&info).unwrap();
+    /// let metrics = receive();
+    /// assert_eq!(metrics.len(), 7);
+    /// assert_eq!(metrics["app.alloc"], (10, "c".to_owned()));
+    /// assert_eq!(metrics["app.bytes_alloc"], (1000, "c".to_owned()));
+    /// assert_eq!(metrics["app.live_bytes"], (600, "g".to_owned()));
+    /// assert_eq!(metrics["app.peak_bytes"], (800, "g".to_owned()));
+    ///
+    /// // Counters are sent as the change since the last report.
+    /// info.alloc = 15;
+    /// reporter.report(&info).unwrap();
+    /// let metrics = receive();
+    /// assert_eq!(metrics["app.alloc"].0, 5);
+    /// assert_eq!(metrics["app.bytes_alloc"].0, 0);
+    ///
+    /// // After a reset, as the counts since it.
+    /// let mut reset = AllocInfo::new();
+    /// reset.alloc = 2;
+    /// reset.bytes_alloc = 64;
+    /// reporter.report(&reset).unwrap();
+    /// let metrics = receive();
+    /// assert_eq!(metrics["app.alloc"].0, 2);
+    /// assert_eq!(metrics["app.bytes_alloc"].0, 64);
+    /// reset.alloc = 3;
+    /// reporter.report(&reset).unwrap();
+    /// assert_eq!(receive()["app.alloc"].0, 1);
+    /// ```
+    pub fn report(&mut self, info: &AllocInfo) -> io::Result<()> {
+        let delta = info.checked_relative_to(&self.last.info).unwrap_or(*info);
+        self.send(info, &delta)?;
+        self.last.info = *info;
+        Ok(())
+    }
+
+    /// Like `report`, but with a snapshot of the monitor, which tells resets
+    /// apart by its generation: if it isn't the generation last reported, or the
+    /// monitor counts bytes differently, the counters are sent as they are.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use core::alloc::Layout;
+    /// use interloc::{AllocAction, AllocMonitor, StatsMonitor, StatsdReporter};
+    /// use std::net::UdpSocket;
+    ///
+    /// let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let mut reporter = StatsdReporter::new(server.local_addr().unwrap(), "app").unwrap();
+    /// let mut allocs = || {
+    ///     let mut buf = [0; 1500];
+    ///     let len = server.recv(&mut buf).unwrap();
+    ///     let datagram = String::from_utf8(buf[..len].to_vec()).unwrap();
+    ///     let line = datagram.lines().find(|l| l.starts_with("app.alloc:")).unwrap();
+    ///     line["app.alloc:".len()..line.len() - 2].parse::<u64>().unwrap()
+    /// };
+    ///
+    /// let monitor = StatsMonitor::new();
+    /// let layout = Layout::from_size_align(16, 8).unwrap();
+    /// for _ in 0..2 {
+    ///     monitor.monitor(layout, AllocAction::Alloc);
+    /// }
+    /// reporter.report_snapshot(&monitor.snapshot()).unwrap();
+    /// assert_eq!(allocs(), 2);
+    ///
+    /// // More allocations after a reset than before it still count from the
+    /// // reset.
+    /// monitor.reset();
+    /// for _ in 0..5 {
+    ///     monitor.monitor(layout, AllocAction::Alloc);
+    /// }
+    /// reporter.report_snapshot(&monitor.snapshot()).unwrap();
+    /// assert_eq!(allocs(), 5);
+    /// monitor.monitor(layout, AllocAction::Alloc);
+    /// reporter.report_snapshot(&monitor.snapshot()).unwrap();
+    /// assert_eq!(allocs(), 1);
+    /// ```
+    pub fn report_snapshot(&mut self, snapshot: &AllocSnapshot) -> io::Result<()> {
+        let delta = snapshot.delta_since(&self.last).unwrap_or(snapshot.info);
+        self.send(&snapshot.info, &delta)?;
+        self.last = *snapshot;
+        Ok(())
+    }
+
+    /// Sends the counters of `delta`, and the gauges of `info`.
+    fn send(&mut self, info: &AllocInfo, delta: &AllocInfo) -> io::Result<()> {
+        self.buf.clear();
+        self.line("alloc", delta.alloc, "c");
+        self.line("dealloc", delta.dealloc, "c");
+        self.line("realloc", delta.realloc, "c");
+        self.line("bytes_alloc", delta.bytes_alloc, "c");
+        self.line("bytes_dealloc", delta.bytes_dealloc, "c");
+        self.line("live_bytes", info.live_bytes(), "g");
+        self.line("peak_bytes", info.peak_bytes, "g");
+        self.buf.pop();
+
+        self.socket.send(self.buf.as_bytes())?;
+        Ok(())
+    }
+
+    /// Appends a single metric line to the buffer.
+    fn line(&mut self, name: &str, value: u64, kind: &str) {
+        let sep = if self.prefix.is_empty() { "" } else { "." };
+        // Writing to a String can't fail.
+        let _ = writeln!(
+            self.buf,
+            "{}{}{}:{}|{}{}",
+            self.prefix, sep, name, value, kind, self.tags
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use core::cell::Cell;
+
+thread_local! {
+    static TAG: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Restores the previous tag of the thread when `with_tag` returns or unwinds.
+struct TagGuard(u32);
+
+impl Drop for TagGuard {
+    fn drop(&mut self) {
+        let _ = TAG.try_with(|tag| tag.set(self.0));
+    }
+}
+
+/// Runs `f` with allocations on the current thread tagged `tag`, then puts the
+/// previous tag back. Tags are arbitrary nonzero numbers picked by the program,
+/// e.g. one per subsystem, recorded by `TrackingMonitor` and limited by
+/// `TagLimitAlloc`. Tag 0 means untagged, so `with_tag(0, f)` runs `f`
+/// untagged. Calls nest, and the innermost tag wins.
+///
+/// ```rust
+/// use interloc::{current_tag, with_tag};
+///
+/// const IMAGES: u32 = 1;
+/// const THUMBNAILS: u32 = 2;
+///
+/// assert_eq!(current_tag(), 0);
+/// with_tag(IMAGES, || {
+///     assert_eq!(current_tag(), IMAGES);
+///     with_tag(THUMBNAILS, || assert_eq!(current_tag(), THUMBNAILS));
+///     assert_eq!(current_tag(), IMAGES);
+/// });
+/// assert_eq!(current_tag(), 0);
+/// ```
+#[inline]
+pub fn with_tag<R>(tag: u32, f: impl FnOnce() -> R) -> R {
+    let _guard = TagGuard(TAG.with(|current| current.replace(tag)));
+    f()
+}
+
+/// The tag of the current thread's allocations, or 0 if they're untagged, or
+/// while the thread is exiting.
+#[inline]
+pub fn current_tag() -> u32 {
+    TAG.try_with(|tag| tag.get()).unwrap_or(0)
+}
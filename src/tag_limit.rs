@@ -0,0 +1,325 @@
+use crate::callsite::current_site;
+use crate::clock::Clock;
+use crate::live_bytes::LiveBytes;
+use crate::tag::current_tag;
+#[cfg(feature = "disabled")]
+use crate::tracking::{BlockTable, TrackSlot};
+use crate::tracking::{Tracked, TrackingMonitor};
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A tag's limit in a `TagLimitAlloc`, or an unused entry if the tag is 0.
+struct TagLimit {
+    tag: u32,
+    limit: u64,
+    live: LiveBytes,
+}
+
+impl TagLimit {
+    const fn new(tag: u32, limit: u64) -> Self {
+        Self {
+            tag,
+            limit,
+            live: LiveBytes::new(),
+        }
+    }
+}
+
+/// An allocator that limits the bytes live under each of up to `TAGS` tags, and
+/// passes allocations to `inner`, like `LimitAlloc` does for all of them. An
+/// allocation under a tag with a limit, set by `with_tag`, fails if it would
+/// leave more than the limit live under that tag.
+///
+/// Blocks are charged to the tag they were allocated under, and freeing one
+/// credits that tag, whichever tag the thread freeing it is under. To know which
+/// tag that was, blocks allocated under a limited tag are tracked in a table of
+/// `CAPACITY` blocks; when it's full, those allocations fail as well, and are
+/// counted in `tracking().overflowed()`. Reallocations keep the block's tag, and
+/// only need room for what it grows by.
+///
+/// Untagged allocations, and those under tags without a limit, aren't limited or
+/// tracked, and cost one scan of the `TAGS` limits. Frees and reallocations look
+/// the block up in the table, which is skipped while no block is tracked, and
+/// otherwise scans only as far as the blocks live at once have pushed each other
+/// from where their addresses hash to.
+///
+/// ```rust
+/// use interloc::{with_tag, TagLimitAlloc};
+/// use std::alloc::System;
+///
+/// const IMAGES: u32 = 1;
+///
+/// #[global_allocator]
+/// static GLOBAL: TagLimitAlloc<System> = TagLimitAlloc::new(System).limit(IMAGES, 1 << 20);
+///
+/// let decoded = with_tag(IMAGES, || {
+///     let mut decoded = Vec::<u8>::new();
+///     assert!(decoded.try_reserve_exact(2 << 20).is_err());
+///     decoded.try_reserve_exact(768 << 10).unwrap();
+///     decoded
+/// });
+/// assert_eq!(GLOBAL.live(IMAGES), 768 << 10);
+///
+/// // Untagged allocations don't count.
+/// let other = vec![0u8; 2 << 20];
+/// assert_eq!(GLOBAL.live(IMAGES), 768 << 10);
+///
+/// // Freeing credits the tag the block was allocated under.
+/// drop(decoded);
+/// assert_eq!(GLOBAL.live(IMAGES), 0);
+/// assert_eq!(GLOBAL.denied(), 1);
+/// # drop(other);
+/// ```
+///
+/// Each tag has a budget of its own:
+///
+/// ```rust
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::{with_tag, TagLimitAlloc};
+/// use std::alloc::System;
+///
+/// const IMAGES: u32 = 1;
+/// const AUDIO: u32 = 2;
+///
+/// let limited = TagLimitAlloc::<System, 2, 64>::new(System)
+///     .limit(IMAGES, 1000)
+///     .limit(AUDIO, 500);
+/// let layout = Layout::from_size_align(400, 8).unwrap();
+/// unsafe {
+///     let image = with_tag(IMAGES, || [limited.alloc(layout), limited.alloc(layout)]);
+///     assert!(with_tag(IMAGES, || limited.alloc(layout)).is_null());
+///
+///     // Images being out of room doesn't stop audio.
+///     let audio = with_tag(AUDIO, || limited.alloc(layout));
+///     assert!(!audio.is_null());
+///     assert!(with_tag(AUDIO, || limited.realloc(audio, layout, 600)).is_null());
+///     assert_eq!((limited.live(IMAGES), limited.live(AUDIO)), (800, 400));
+///
+///     // Frees go to the tag of the allocation, even under another tag, and
+///     // reallocations charge it too.
+///     with_tag(AUDIO, || limited.dealloc(image[0], layout));
+///     assert_eq!((limited.live(IMAGES), limited.live(AUDIO)), (400, 400));
+///     let grown = with_tag(AUDIO, || limited.realloc(image[1], layout, 1000));
+///     assert!(!grown.is_null());
+///     assert_eq!((limited.live(IMAGES), limited.live(AUDIO)), (1000, 400));
+///
+///     limited.dealloc(grown, Layout::from_size_align(1000, 8).unwrap());
+///     limited.dealloc(audio, layout);
+/// }
+/// assert_eq!((limited.live(IMAGES), limited.live(AUDIO)), (0, 0));
+/// assert_eq!((limited.peak(IMAGES), limited.peak(AUDIO)), (1000, 400));
+/// assert_eq!(limited.denied(), 2);
+/// ```
+pub struct TagLimitAlloc<A, const TAGS: usize = 8, const CAPACITY: usize = 4096> {
+    inner: A,
+    limits: [TagLimit; TAGS],
+    tracking: TrackingMonitor<CAPACITY>,
+    /// The blocks under limited tags, since `tracking` keeps no table with the
+    /// `disabled` feature
+    #[cfg(feature = "disabled")]
+    blocks: BlockTable<[TrackSlot; CAPACITY]>,
+    denied: AtomicU64,
+}
+
+impl<A, const TAGS: usize, const CAPACITY: usize> TagLimitAlloc<A, TAGS, CAPACITY> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            limits: [const { TagLimit::new(0, 0) }; TAGS],
+            tracking: TrackingMonitor::new(),
+            #[cfg(feature = "disabled")]
+            blocks: BlockTable::new([const { TrackSlot::new() }; CAPACITY]),
+            denied: AtomicU64::new(0),
+        }
+    }
+
+    /// Limits the bytes live under `tag` to `bytes`, replacing its limit if it
+    /// already had one. Panics if `tag` is 0, or if `TAGS` tags already have
+    /// limits.
+    pub const fn limit(mut self, tag: u32, bytes: u64) -> Self {
+        assert!(tag != 0, "tag 0 is untagged, and can't be limited");
+        let mut i = 0;
+        while i < TAGS {
+            if self.limits[i].tag == tag || self.limits[i].tag == 0 {
+                self.limits[i] = TagLimit::new(tag, bytes);
+                return self;
+            }
+            i += 1;
+        }
+        panic!("more tags limited than TAGS");
+    }
+
+    /// The limit of `tag`, if it has one.
+    pub fn limit_of(&self, tag: u32) -> Option<u64> {
+        Some(self.entry(tag)?.limit)
+    }
+
+    /// The bytes live under `tag`, or 0 if it has no limit.
+    pub fn live(&self, tag: u32) -> u64 {
+        self.entry(tag).map_or(0, |entry| entry.live.live())
+    }
+
+    /// The most bytes that were live under `tag` at once, or 0 if it has no
+    /// limit.
+    pub fn peak(&self, tag: u32) -> u64 {
+        self.entry(tag).map_or(0, |entry| entry.live.peak())
+    }
+
+    /// How many allocations and reallocations were failed, for a limit or
+    /// because the table of blocks was full.
+    pub fn denied(&self) -> u64 {
+        self.denied.load(Ordering::Relaxed)
+    }
+
+    /// The blocks allocated under limited tags. With the `disabled` feature it
+    /// keeps no table, and reports none, but limits are still enforced, with a
+    /// table kept apart from it.
+    pub fn tracking(&self) -> &TrackingMonitor<CAPACITY> {
+        &self.tracking
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    #[inline]
+    fn entry(&self, tag: u32) -> Option<&TagLimit> {
+        if tag == 0 {
+            return None;
+        }
+        self.limits.iter().find(|entry| entry.tag == tag)
+    }
+
+    /// The table the blocks under limited tags are tracked in.
+    #[cfg(not(feature = "disabled"))]
+    #[inline]
+    fn blocks(&self) -> &TrackingMonitor<CAPACITY> {
+        &self.tracking
+    }
+
+    #[cfg(feature = "disabled")]
+    #[inline]
+    fn blocks(&self) -> &BlockTable<[TrackSlot; CAPACITY]> {
+        &self.blocks
+    }
+
+    /// Stops tracking the block at `ptr`, if it was tracked. Blocks are only
+    /// tracked before they're handed out, so if none are, `ptr` isn't either.
+    #[inline]
+    fn untrack(&self, ptr: *mut u8) -> Option<Tracked> {
+        if self.blocks().live_blocks() == 0 {
+            return None;
+        }
+        self.blocks().remove(ptr as usize)
+    }
+
+    #[cold]
+    fn deny(&self) -> *mut u8 {
+        self.denied.fetch_add(1, Ordering::Relaxed);
+        core::ptr::null_mut()
+    }
+}
+
+impl<A: GlobalAlloc, const TAGS: usize, const CAPACITY: usize> TagLimitAlloc<A, TAGS, CAPACITY> {
+    /// Allocates with `alloc` under the limit of `entry`, and tracks the block.
+    unsafe fn alloc_tagged(
+        &self,
+        entry: &TagLimit,
+        layout: Layout,
+        alloc: impl FnOnce(Layout) -> *mut u8,
+    ) -> *mut u8 {
+        let size = layout.size() as u64;
+        if !entry.live.try_charge(size, entry.limit) {
+            return self.deny();
+        }
+        let ptr = alloc(layout);
+        if ptr.is_null() {
+            entry.live.credit(size);
+            return ptr;
+        }
+        let time = self.tracking.clock().now_nanos();
+        let tracked = Tracked::new(time, entry.tag, current_site());
+        if !self.blocks().insert(ptr as usize, layout, tracked) {
+            // Without its tag, freeing the block couldn't credit it.
+            self.inner.dealloc(ptr, layout);
+            entry.live.credit(size);
+            return self.deny();
+        }
+        ptr
+    }
+}
+
+unsafe impl<A: GlobalAlloc, const TAGS: usize, const CAPACITY: usize> GlobalAlloc
+    for TagLimitAlloc<A, TAGS, CAPACITY>
+{
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.entry(current_tag()) {
+            Some(entry) => self.alloc_tagged(entry, layout, |layout| self.inner.alloc(layout)),
+            None => self.inner.alloc(layout),
+        }
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        match self.entry(current_tag()) {
+            Some(entry) => {
+                self.alloc_tagged(entry, layout, |layout| self.inner.alloc_zeroed(layout))
+            }
+            None => self.inner.alloc_zeroed(layout),
+        }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Before the block is freed, so that its address can't be handed out and
+        // tracked again in the meantime.
+        let tracked = self.untrack(ptr);
+        self.inner.dealloc(ptr, layout);
+        if let Some(entry) = tracked.and_then(|tracked| self.entry(tracked.tag)) {
+            entry.live.credit(layout.size() as u64);
+        }
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let tracked = match self.untrack(ptr) {
+            Some(tracked) => tracked,
+            None => return self.inner.realloc(ptr, layout, new_size),
+        };
+        let entry = match self.entry(tracked.tag) {
+            Some(entry) => entry,
+            None => return self.inner.realloc(ptr, layout, new_size),
+        };
+        let old_size = layout.size();
+        let charge =
+            match entry
+                .live
+                .try_charge_realloc(old_size as u64, new_size as u64, entry.limit)
+            {
+                Some(charge) => charge,
+                None => {
+                    if !self.blocks().insert(ptr as usize, layout, tracked) {
+                        entry.live.credit(old_size as u64);
+                    }
+                    return self.deny();
+                }
+            };
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        let (block, layout) = if new_ptr.is_null() {
+            (ptr, layout)
+        } else {
+            // The new size fits, or the realloc wouldn't have succeeded.
+            (
+                new_ptr,
+                Layout::from_size_align_unchecked(new_size, layout.align()),
+            )
+        };
+        charge.finish(!new_ptr.is_null());
+        if !self.blocks().insert(block as usize, layout, tracked) {
+            // Another thread took the slot, and the block escapes the limit.
+            entry.live.credit(layout.size() as u64);
+        }
+        new_ptr
+    }
+}
@@ -0,0 +1,869 @@
+//! Support for tests of code that records allocations, and of monitors
+//! themselves.
+//!
+//! `RecordingMonitor` keeps every event it sees, so tests can check exactly
+//! what a monitor, or a wrapper around one, passed on. `FakeAlloc` is an
+//! allocator over a fixed arena that hands out the same addresses every run, so
+//! tests of monitors can drive an `InterAlloc` without touching the real heap.
+//! `exercise_global_alloc` checks that an allocator, or a wrapper around one,
+//! keeps the promises of `GlobalAlloc`.
+//!
+//! With the `deterministic` feature, `isolate` makes what monitors record
+//! repeatable across runs. Tests run in parallel by default, and some of what
+//! monitors record depends on state shared by the whole process, so the same
+//! test can record different things from one run to the next. `isolate` gives
+//! the current thread its own copy of that state for the duration of a closure:
+//!
+//! - Event serials, as recorded by `EventLogMonitor`, `EventQueueMonitor`,
+//!   `RecentEventsMonitor` and `TraceRecorder`, count from 0 on the isolated
+//!   thread, independently of every other thread.
+//! - The sampling state of `SampleMonitor`s starts over on the isolated thread,
+//!   with its random number generator seeded by a fixed seed, so `Bytes`
+//!   sampling makes the same decisions every time.
+//!
+//! Thread tokens aren't isolated, since monitors rely on them being unique, and
+//! neither are counters kept by monitors themselves. Only the calling thread is
+//! isolated: threads spawned inside the closure use the shared state as usual.
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+#[cfg(feature = "deterministic")]
+use crate::event::swap_isolated_serial;
+use crate::fmt::FmtBuffer;
+#[cfg(feature = "deterministic")]
+use crate::sample::{isolate_states, restore_states, SavedStates};
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::{Cell, UnsafeCell};
+use core::fmt::Write as _;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::io::Write as _;
+
+/// Restores the state of the thread when `isolate` returns or unwinds.
+#[cfg(feature = "deterministic")]
+struct Restore {
+    serial: Option<u64>,
+    states: Option<SavedStates>,
+}
+
+#[cfg(feature = "deterministic")]
+impl Drop for Restore {
+    fn drop(&mut self) {
+        swap_isolated_serial(self.serial);
+        if let Some(states) = self.states.take() {
+            restore_states(states);
+        }
+    }
+}
+
+/// Runs `f` with the current thread isolated from the state that other threads
+/// share, then puts the thread's state back the way it was. Calls can be nested,
+/// and each one starts over.
+///
+/// ```rust
+/// use interloc::{testing, AllocAction, EventRecord};
+/// use core::alloc::Layout;
+///
+/// let serials = testing::isolate(|| {
+///     let layout = Layout::new::<u64>();
+///     let first = EventRecord::new(layout, AllocAction::Alloc);
+///     let second = EventRecord::new(layout, AllocAction::Alloc);
+///     (first.serial, second.serial)
+/// });
+/// assert_eq!(serials, (0, 1));
+/// ```
+#[cfg(feature = "deterministic")]
+pub fn isolate<R>(f: impl FnOnce() -> R) -> R {
+    let _restore = Restore {
+        serial: swap_isolated_serial(Some(0)),
+        states: isolate_states(),
+    };
+    f()
+}
+
+/// An event seen by a `RecordingMonitor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordedEvent {
+    pub layout: Layout,
+    pub action: AllocAction,
+}
+
+/// A monitor that keeps the first `N` events it sees, in the order it saw them,
+/// as a reference for testing other monitors. It doesn't allocate, so it can be
+/// called from the global allocator; events past the first `N` are counted in
+/// `overflowed` instead.
+///
+/// Events are kept in the order their calls to `monitor` started. `events` stops
+/// at the first event that's still being stored by another thread, so it never
+/// has gaps, and it never blocks.
+///
+/// ```rust
+/// use core::alloc::Layout;
+/// use interloc::testing::{RecordedEvent, RecordingMonitor};
+/// use interloc::{AllocAction, AllocMonitor, SampleMode, SampleMonitor};
+///
+/// let sampled = SampleMonitor::new(RecordingMonitor::<8>::new(), SampleMode::Every(2));
+/// let layout = Layout::new::<u64>();
+/// for _ in 0..4 {
+///     sampled.monitor(layout, AllocAction::Alloc);
+/// }
+/// let events = sampled.inner().events();
+/// assert_eq!(events.len(), 2);
+/// assert_eq!(events[0], RecordedEvent { layout, action: AllocAction::Alloc });
+/// ```
+pub struct RecordingMonitor<const N: usize> {
+    events: [UnsafeCell<MaybeUninit<RecordedEvent>>; N],
+    /// Whether each slot has been written
+    ready: [AtomicBool; N],
+    /// Number of calls that have claimed a slot or overflowed
+    claimed: AtomicUsize,
+    /// Number of slots, from the start, that are known to have been written
+    written: AtomicUsize,
+    overflowed: AtomicUsize,
+}
+
+// Slots are only written once, by the call that claimed them, before they're
+// marked as ready.
+unsafe impl<const N: usize> Sync for RecordingMonitor<N> {}
+
+impl<const N: usize> RecordingMonitor<N> {
+    pub const fn new() -> Self {
+        Self {
+            events: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            ready: [const { AtomicBool::new(false) }; N],
+            claimed: AtomicUsize::new(0),
+            written: AtomicUsize::new(0),
+            overflowed: AtomicUsize::new(0),
+        }
+    }
+
+    /// The events recorded so far, oldest first.
+    pub fn events(&self) -> &[RecordedEvent] {
+        let len = self.publish();
+        // The first `len` slots are written and never change until `clear`,
+        // which takes `&mut self`.
+        unsafe { core::slice::from_raw_parts(self.events.as_ptr() as *const RecordedEvent, len) }
+    }
+
+    /// Number of events that weren't recorded because the buffer was full.
+    pub fn overflowed(&self) -> usize {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+
+    /// Forgets every event recorded so far, and resets `overflowed`.
+    pub fn clear(&mut self) {
+        for ready in self.ready.iter_mut() {
+            *ready.get_mut() = false;
+        }
+        *self.claimed.get_mut() = 0;
+        *self.written.get_mut() = 0;
+        *self.overflowed.get_mut() = 0;
+    }
+
+    /// Moves `written` past every slot that's ready, returning its new value.
+    fn publish(&self) -> usize {
+        let mut written = self.written.load(Ordering::Acquire);
+        while written < N && self.ready[written].load(Ordering::Acquire) {
+            written = match self.written.compare_exchange(
+                written,
+                written + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => written + 1,
+                Err(current) => current,
+            };
+        }
+        written
+    }
+}
+
+impl<const N: usize> Default for RecordingMonitor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> AllocMonitor for RecordingMonitor<N> {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let index = self.claimed.fetch_add(1, Ordering::Relaxed);
+        if index >= N {
+            self.claimed.fetch_sub(1, Ordering::Relaxed);
+            self.overflowed.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        unsafe { (*self.events[index].get()).write(RecordedEvent { layout, action }) };
+        self.ready[index].store(true, Ordering::Release);
+        self.publish();
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("RecordingMonitor", Overhead::Atomic).param("capacity", N as u64)
+    }
+}
+
+/// The arena of a `FakeAlloc`, aligned so that addresses in it are aligned the
+/// same way every run.
+#[repr(C, align(4096))]
+struct Arena<const N: usize>([MaybeUninit<u8>; N]);
+
+/// An allocator over a fixed arena of `N` bytes inside of it, for testing
+/// monitors without using the real heap.
+///
+/// Blocks are handed out one after the other and never reused, so the offset of
+/// every block from `base` is the same every run given the same calls. Freeing
+/// does nothing, and allocations fail, returning null, once the arena is used up.
+/// Reallocations always move the block.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use interloc::testing::FakeAlloc;
+/// use interloc::{InterAlloc, StatsMonitor};
+///
+/// static MONITOR: StatsMonitor = StatsMonitor::new();
+/// let alloc = InterAlloc {
+///     inner: FakeAlloc::<1024>::new(),
+///     monitor: &MONITOR,
+/// };
+///
+/// let layout = Layout::from_size_align(24, 8).unwrap();
+/// let first = unsafe { alloc.alloc(layout) };
+/// let second = unsafe { alloc.alloc(layout) };
+/// assert_eq!(alloc.inner.offset_of(first), Some(0));
+/// assert_eq!(alloc.inner.offset_of(second), Some(24));
+/// assert!(unsafe { alloc.alloc(Layout::new::<[u8; 1024]>()) }.is_null());
+/// assert_eq!(MONITOR.info().alloc, 3);
+/// ```
+pub struct FakeAlloc<const N: usize> {
+    arena: UnsafeCell<Arena<N>>,
+    used: AtomicUsize,
+}
+
+// Every block is handed out to a single caller, by claiming its range of the
+// arena atomically.
+unsafe impl<const N: usize> Sync for FakeAlloc<N> {}
+
+impl<const N: usize> FakeAlloc<N> {
+    pub const fn new() -> Self {
+        Self {
+            arena: UnsafeCell::new(Arena([MaybeUninit::uninit(); N])),
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// The start of the arena.
+    pub fn base(&self) -> *mut u8 {
+        self.arena.get() as *mut u8
+    }
+
+    /// Number of bytes of the arena used so far, including padding for
+    /// alignment.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// The offset of `ptr` from the start of the arena, or `None` if it isn't in
+    /// the arena.
+    pub fn offset_of(&self, ptr: *const u8) -> Option<usize> {
+        let offset = (ptr as usize).checked_sub(self.base() as usize)?;
+        (offset < N).then_some(offset)
+    }
+}
+
+impl<const N: usize> Default for FakeAlloc<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const N: usize> GlobalAlloc for FakeAlloc<N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let base = self.base() as usize;
+        let mut used = self.used.load(Ordering::Relaxed);
+        loop {
+            let start = match (base + used).checked_next_multiple_of(layout.align()) {
+                Some(start) => start - base,
+                None => return core::ptr::null_mut(),
+            };
+            let end = match start.checked_add(layout.size()) {
+                Some(end) if end <= N => end,
+                _ => return core::ptr::null_mut(),
+            };
+            match self
+                .used
+                .compare_exchange_weak(used, end, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return self.base().add(start),
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, _: *mut u8, _: Layout) {}
+}
+
+/// A check made by `exercise_global_alloc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConformanceCheck {
+    /// Blocks are aligned to the alignment of their layout, for every power of
+    /// two up to 4096, with sizes below, at and above the alignment
+    Alignment,
+    /// `alloc_zeroed` returns zeroed blocks, even where freed blocks that were
+    /// written to are likely to be reused
+    Zeroed,
+    /// `realloc` keeps the contents of a block up to the smaller of its old and
+    /// new sizes, growing and shrinking, and leaves the block untouched when it
+    /// fails
+    ReallocContents,
+    /// `realloc` keeps blocks aligned to the alignment of their layout
+    ReallocAlignment,
+    /// Blocks allocated, freed and reallocated in an interleaved order never
+    /// overlap, and keep what's written to them
+    Interleaved,
+}
+
+impl ConformanceCheck {
+    pub const fn name(self) -> &'static str {
+        match self {
+            ConformanceCheck::Alignment => "alignment",
+            ConformanceCheck::Zeroed => "zeroed",
+            ConformanceCheck::ReallocContents => "realloc_contents",
+            ConformanceCheck::ReallocAlignment => "realloc_alignment",
+            ConformanceCheck::Interleaved => "interleaved",
+        }
+    }
+}
+
+impl core::fmt::Display for ConformanceCheck {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A way in which an allocator failed a check of `exercise_global_alloc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConformanceFailure {
+    pub check: ConformanceCheck,
+    /// The layout of the block involved
+    pub layout: Layout,
+    /// What went wrong
+    pub detail: &'static str,
+}
+
+impl core::fmt::Display for ConformanceFailure {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{}: {} (size {}, align {})",
+            self.check,
+            self.detail,
+            self.layout.size(),
+            self.layout.align()
+        )
+    }
+}
+
+/// What `exercise_global_alloc` found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// Calls made to the allocator
+    pub calls: usize,
+    /// Calls that returned null. Allocators may fail, so these aren't failures,
+    /// but the checks that needed the block were skipped.
+    pub null_returns: usize,
+    /// Every failed check, in the order they were found
+    pub failures: Vec<ConformanceFailure>,
+}
+
+impl ConformanceReport {
+    /// Whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// One failure per line, or a summary if there are none.
+impl core::fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.passed() {
+            return write!(
+                f,
+                "all checks passed ({} calls, {} returned null)",
+                self.calls, self.null_returns
+            );
+        }
+        for (i, failure) in self.failures.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `alloc` through the checks of `ConformanceCheck`, for confidence that an
+/// allocator, or a wrapper around one, keeps the promises of `GlobalAlloc`.
+/// Every check runs even if earlier ones fail, and failures are collected
+/// rather than panicking.
+///
+/// The checks never allocate zero bytes, since `GlobalAlloc` makes that
+/// undefined behavior; the smallest blocks are one byte. Every block is freed
+/// before this returns. Bookkeeping goes through the global allocator, so this
+/// can check the global allocator too, as long as it's reentrant.
+///
+/// ```rust
+/// use interloc::testing::exercise_global_alloc;
+/// use interloc::{ArenaAlloc, InterAlloc, StatsMonitor};
+/// use std::alloc::System;
+///
+/// static STATS: StatsMonitor = StatsMonitor::new();
+/// static ARENA: ArenaAlloc<{ 1 << 20 }> = ArenaAlloc::new();
+/// static WRAPPED_SYSTEM: InterAlloc<System, StatsMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &STATS,
+/// };
+/// static WRAPPED_ARENA: InterAlloc<ArenaAlloc<{ 1 << 20 }>, StatsMonitor> = InterAlloc {
+///     inner: ArenaAlloc::new(),
+///     monitor: &STATS,
+/// };
+///
+/// let reports = [
+///     exercise_global_alloc(&System),
+///     exercise_global_alloc(&ARENA),
+///     exercise_global_alloc(&WRAPPED_SYSTEM),
+///     exercise_global_alloc(&WRAPPED_ARENA),
+/// ];
+/// for report in &reports {
+///     assert!(report.passed(), "{}", report);
+///     assert_eq!(report.null_returns, 0);
+/// }
+/// assert_eq!(STATS.info().live_bytes(), 0);
+/// assert_eq!(ARENA.used(), 0);
+/// ```
+pub fn exercise_global_alloc<A: GlobalAlloc + ?Sized>(alloc: &A) -> ConformanceReport {
+    let mut exerciser = Exerciser {
+        alloc,
+        report: ConformanceReport::default(),
+    };
+    exerciser.alignment();
+    exerciser.zeroed();
+    exerciser.realloc();
+    exerciser.interleaved();
+    exerciser.report
+}
+
+/// The byte at `index` of a block filled with the pattern `seed`.
+fn pattern(seed: usize, index: usize) -> u8 {
+    (seed.wrapping_mul(131).wrapping_add(index.wrapping_mul(7)) as u8) ^ 0x5a
+}
+
+fn is_aligned(ptr: *mut u8, align: usize) -> bool {
+    ptr as usize % align == 0
+}
+
+/// Sizes that are interesting for blocks aligned to `align`.
+fn sizes_for(align: usize) -> [usize; 7] {
+    [
+        1,
+        3,
+        33,
+        (align - 1).max(1),
+        align,
+        align + 1,
+        2 * align + 3,
+    ]
+}
+
+/// A block allocated by `Exerciser::interleaved`.
+#[derive(Clone, Copy)]
+struct Block {
+    ptr: *mut u8,
+    layout: Layout,
+    seed: usize,
+}
+
+struct Exerciser<'a, A: ?Sized> {
+    alloc: &'a A,
+    report: ConformanceReport,
+}
+
+impl<'a, A: GlobalAlloc + ?Sized> Exerciser<'a, A> {
+    fn fail(&mut self, check: ConformanceCheck, layout: Layout, detail: &'static str) {
+        self.report.failures.push(ConformanceFailure {
+            check,
+            layout,
+            detail,
+        });
+    }
+
+    fn returned(&mut self, ptr: *mut u8) -> Option<*mut u8> {
+        self.report.calls += 1;
+        if ptr.is_null() {
+            self.report.null_returns += 1;
+            return None;
+        }
+        Some(ptr)
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Option<*mut u8> {
+        let ptr = unsafe { self.alloc.alloc(layout) };
+        self.returned(ptr)
+    }
+
+    fn alloc_zeroed(&mut self, layout: Layout) -> Option<*mut u8> {
+        let ptr = unsafe { self.alloc.alloc_zeroed(layout) };
+        self.returned(ptr)
+    }
+
+    fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        self.report.calls += 1;
+        unsafe { self.alloc.dealloc(ptr, layout) };
+    }
+
+    fn fill(ptr: *mut u8, len: usize, seed: usize) {
+        for i in 0..len {
+            unsafe { *ptr.add(i) = pattern(seed, i) };
+        }
+    }
+
+    fn holds(ptr: *mut u8, len: usize, seed: usize) -> bool {
+        (0..len).all(|i| unsafe { *ptr.add(i) } == pattern(seed, i))
+    }
+
+    fn alignment(&mut self) {
+        for align in (0..=12).map(|shift| 1 << shift) {
+            for size in sizes_for(align) {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = match self.alloc(layout) {
+                    Some(ptr) => ptr,
+                    None => continue,
+                };
+                if !is_aligned(ptr, align) {
+                    self.fail(ConformanceCheck::Alignment, layout, "block isn't aligned");
+                } else {
+                    Self::fill(ptr, size, size);
+                }
+                self.dealloc(ptr, layout);
+            }
+        }
+    }
+
+    fn zeroed(&mut self) {
+        for align in [1, 8, 16, 64, 4096] {
+            for size in [1, 24, 100, 4096, 20000] {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                // Dirty a block of the same layout first, which is likely to be
+                // handed out again.
+                if let Some(ptr) = self.alloc(layout) {
+                    if is_aligned(ptr, align) {
+                        Self::fill(ptr, size, 1);
+                    }
+                    self.dealloc(ptr, layout);
+                }
+                let ptr = match self.alloc_zeroed(layout) {
+                    Some(ptr) => ptr,
+                    None => continue,
+                };
+                if !is_aligned(ptr, align) {
+                    self.fail(ConformanceCheck::Alignment, layout, "block isn't aligned");
+                } else if (0..size).any(|i| unsafe { *ptr.add(i) } != 0) {
+                    self.fail(ConformanceCheck::Zeroed, layout, "block isn't zeroed");
+                }
+                self.dealloc(ptr, layout);
+            }
+        }
+    }
+
+    fn realloc(&mut self) {
+        for align in [1, 8, 16, 64, 256, 4096] {
+            let mut layout = Layout::from_size_align(5, align).unwrap();
+            let mut ptr = match self.alloc(layout) {
+                Some(ptr) if is_aligned(ptr, align) => ptr,
+                Some(ptr) => {
+                    self.fail(ConformanceCheck::Alignment, layout, "block isn't aligned");
+                    self.dealloc(ptr, layout);
+                    continue;
+                }
+                None => continue,
+            };
+            Self::fill(ptr, layout.size(), align);
+            for new_size in [40, 1000, 17, 5000, 3, 64, 9000] {
+                let new_layout = Layout::from_size_align(new_size, align).unwrap();
+                let new_ptr = unsafe { self.alloc.realloc(ptr, layout, new_size) };
+                let new_ptr = match self.returned(new_ptr) {
+                    Some(new_ptr) => new_ptr,
+                    None => {
+                        if !Self::holds(ptr, layout.size(), align) {
+                            self.fail(
+                                ConformanceCheck::ReallocContents,
+                                layout,
+                                "failed realloc changed the block",
+                            );
+                        }
+                        continue;
+                    }
+                };
+                if !is_aligned(new_ptr, align) {
+                    self.fail(
+                        ConformanceCheck::ReallocAlignment,
+                        new_layout,
+                        "reallocated block isn't aligned",
+                    );
+                } else if !Self::holds(new_ptr, layout.size().min(new_size), align) {
+                    self.fail(
+                        ConformanceCheck::ReallocContents,
+                        new_layout,
+                        "reallocated block lost its contents",
+                    );
+                }
+                ptr = new_ptr;
+                layout = new_layout;
+                Self::fill(ptr, layout.size(), align);
+            }
+            self.dealloc(ptr, layout);
+        }
+    }
+
+    fn interleaved(&mut self) {
+        const BLOCKS: usize = 64;
+        let layout_for =
+            |i: usize| Layout::from_size_align((i * 37) % 700 + 1, 1 << (i % 7)).unwrap();
+        let mut blocks: Vec<Option<Block>> = vec![None; BLOCKS];
+        let allocate = |this: &mut Self, slot: &mut Option<Block>, layout: Layout, seed| {
+            *slot = this.alloc(layout).map(|ptr| {
+                if is_aligned(ptr, layout.align()) {
+                    Self::fill(ptr, layout.size(), seed);
+                } else {
+                    this.fail(ConformanceCheck::Alignment, layout, "block isn't aligned");
+                }
+                Block { ptr, layout, seed }
+            });
+        };
+
+        for (i, slot) in blocks.iter_mut().enumerate() {
+            allocate(self, slot, layout_for(i), i);
+        }
+        // Free every other block, fill the holes with blocks of other sizes, and
+        // move some of the rest.
+        for (i, slot) in blocks.iter_mut().enumerate().skip(1).step_by(2) {
+            if let Some(block) = slot.take() {
+                self.dealloc(block.ptr, block.layout);
+            }
+            allocate(self, slot, layout_for(i * 5 + 3), i + BLOCKS);
+        }
+        for slot in blocks.iter_mut().step_by(4) {
+            let block = match slot {
+                Some(block) => block,
+                None => continue,
+            };
+            let new_size = block.layout.size() * 3 + 1;
+            let new_ptr = unsafe { self.alloc.realloc(block.ptr, block.layout, new_size) };
+            if let Some(new_ptr) = self.returned(new_ptr) {
+                let new_layout = Layout::from_size_align(new_size, block.layout.align()).unwrap();
+                let kept = Self::holds(new_ptr, block.layout.size(), block.seed);
+                block.ptr = new_ptr;
+                block.layout = new_layout;
+                if kept {
+                    Self::fill(new_ptr, new_size, block.seed);
+                } else {
+                    self.fail(
+                        ConformanceCheck::ReallocContents,
+                        new_layout,
+                        "reallocated block lost its contents",
+                    );
+                    block.seed = usize::MAX;
+                }
+            }
+        }
+
+        let mut live: Vec<Block> = blocks.iter().flatten().copied().collect();
+        for block in &live {
+            let aligned = is_aligned(block.ptr, block.layout.align());
+            if aligned
+                && block.seed != usize::MAX
+                && !Self::holds(block.ptr, block.layout.size(), block.seed)
+            {
+                self.fail(
+                    ConformanceCheck::Interleaved,
+                    block.layout,
+                    "block was overwritten",
+                );
+            }
+        }
+        live.sort_unstable_by_key(|block| block.ptr as usize);
+        for pair in live.windows(2) {
+            if pair[0].ptr as usize + pair[0].layout.size() > pair[1].ptr as usize {
+                self.fail(
+                    ConformanceCheck::Interleaved,
+                    pair[1].layout,
+                    "blocks overlap",
+                );
+            }
+        }
+        for block in live {
+            self.dealloc(block.ptr, block.layout);
+        }
+    }
+}
+
+thread_local! {
+    /// Whether the current thread is inside a `NoReentryAlloc`.
+    static INSIDE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as inside a `NoReentryAlloc` until it's dropped.
+struct Inside;
+
+impl Inside {
+    #[inline]
+    fn enter(call: &str, layout: Layout) -> Self {
+        if INSIDE.try_with(|inside| inside.replace(true)) == Ok(true) {
+            reentered(call, layout);
+        }
+        Inside
+    }
+}
+
+impl Drop for Inside {
+    #[inline]
+    fn drop(&mut self) {
+        let _ = INSIDE.try_with(|inside| inside.set(false));
+    }
+}
+
+#[cold]
+fn reentered(call: &str, layout: Layout) -> ! {
+    let mut buf = FmtBuffer::<256>::new();
+    let _ = writeln!(
+        buf,
+        "interloc: {} of {} bytes from inside the allocator, aborting",
+        call,
+        layout.size()
+    );
+    let _ = std::io::stderr().write_all(buf.as_bytes());
+    std::process::abort();
+}
+
+/// An allocator that aborts the process when it's called from inside one of
+/// its own calls on the same thread, to check that the monitors of an
+/// `InterAlloc` never allocate. As the global allocator over an `InterAlloc`
+/// whose inner allocator doesn't call back into the global one, like `System`,
+/// a monitor that allocates, or lets std allocate on its behalf, e.g. to
+/// initialize a thread local, aborts with the size of the allocation on
+/// stderr. Monitors that allocate on purpose, with `suppress` or `internal`,
+/// abort as well. So does `ThreadRegistryMonitor` on platforms where std keeps
+/// thread-local destructors in a list it allocates, like Windows, since each
+/// thread registers one on its first event, unless it called `init_thread`.
+///
+/// Every monitor `interloc` promises won't allocate is run through it below,
+/// over a workload of allocations, reallocations and frees on several
+/// threads:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::time::Duration;
+/// use interloc::testing::{NoReentryAlloc, RecordingMonitor};
+/// use interloc::*;
+/// use std::alloc::{Layout, System};
+///
+/// static STATS: StatsMonitor = StatsMonitor::new();
+/// static THREAD: ThreadMonitor = ThreadMonitor::new();
+/// static REGISTRY: ThreadRegistryMonitor = ThreadRegistryMonitor::new();
+/// static LIVE: LiveBytes = LiveBytes::new();
+/// static TRACKING: TrackingMonitor<1024> = TrackingMonitor::new();
+/// static CALLSITES: CallsiteMonitor<16> = CallsiteMonitor::new();
+/// static ALIGN: AlignMonitor<16> = AlignMonitor::new();
+/// static RECORDING: RecordingMonitor<64> = RecordingMonitor::new();
+/// static SAMPLED: SampleMonitor<StatsMonitor> =
+///     SampleMonitor::new(StatsMonitor::new(), SampleMode::Bytes(4096));
+/// static LIMITED: RateLimited<StatsMonitor, 100> =
+///     RateLimited::new(StatsMonitor::new(), Duration::from_millis(10));
+/// static FILTERED: ThreadFilterMonitor<ThreadMonitor> = ThreadFilterMonitor::new(ThreadMonitor::new());
+/// static FIRST: FirstTimeMonitor<64> = FirstTimeMonitor::new();
+/// static HISTOGRAM: HistogramMonitor = HistogramMonitor::new();
+/// static MOVES: ReallocMoveMonitor = ReallocMoveMonitor::new();
+/// static POOLED: PoolBypassMonitor<1> = PoolBypassMonitor::new([Layout::new::<[u8; 64]>()]);
+/// static ROUTED: RouterMonitor<(StatsMonitor, StatsMonitor)> =
+///     RouterMonitor::new(&[256], (StatsMonitor::new(), StatsMonitor::new()));
+/// static PIPELINE: PipelineMonitor = PipelineMonitor::new(&[
+///     PipelineStage::Monitor(&ROUTED),
+///     PipelineStage::Monitor(&SAMPLED),
+///     PipelineStage::Monitor(&LIMITED),
+///     PipelineStage::Monitor(&FILTERED),
+/// ]);
+/// static MONITORS: SliceMonitor = SliceMonitor::new(&[
+///     &STATS, &THREAD, &REGISTRY, &LIVE, &TRACKING, &CALLSITES, &ALIGN, &RECORDING,
+///     &FIRST, &HISTOGRAM, &MOVES, &POOLED, &PIPELINE,
+/// ]);
+///
+/// #[global_allocator]
+/// static GLOBAL: NoReentryAlloc<InterAlloc<System, SliceMonitor>> =
+///     NoReentryAlloc::new(InterAlloc {
+///         inner: System,
+///         monitor: &MONITORS,
+///     });
+///
+/// fn workload() {
+///     let mut strings = Vec::new();
+///     for i in 0..100 {
+///         let mut s = trace_alloc! { String::with_capacity(i % 17) };
+///         s.push_str("allocation");
+///         strings.push(s);
+///     }
+///     strings.retain(|s| s.capacity() % 2 == 0);
+///     let zeroed = vec![0u64; 1000];
+///     let boxed: Vec<Box<[u8; 64]>> = (0..50).map(|_| Box::new([1; 64])).collect();
+///     let aligned = unsafe { std::alloc::alloc(Layout::from_size_align(64, 256).unwrap()) };
+///     unsafe { std::alloc::dealloc(aligned, Layout::from_size_align(64, 256).unwrap()) };
+///     drop((strings, zeroed, boxed));
+/// }
+///
+/// workload();
+/// std::thread::scope(|s| {
+///     for _ in 0..2 {
+///         s.spawn(workload);
+///     }
+/// });
+/// std::thread::spawn(workload).join().unwrap();
+/// assert!(STATS.info().alloc > 500);
+/// ```
+pub struct NoReentryAlloc<A> {
+    inner: A,
+}
+
+impl<A> NoReentryAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for NoReentryAlloc<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _inside = Inside::enter("alloc", layout);
+        self.inner.alloc(layout)
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let _inside = Inside::enter("alloc_zeroed", layout);
+        self.inner.alloc_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _inside = Inside::enter("dealloc", layout);
+        self.inner.dealloc(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _inside = Inside::enter("realloc", layout);
+        self.inner.realloc(ptr, layout, new_size)
+    }
+}
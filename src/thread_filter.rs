@@ -0,0 +1,169 @@
+use crate::alloc::{AllocAction, AllocMonitor, GatingMonitor};
+use crate::describe::{ConfigReport, MonitorDesc, Overhead};
+use crate::monitor::NoopMonitor;
+use core::alloc::Layout;
+use core::cell::Cell;
+
+/// Number of `ThreadFilterMonitor`s that can be switched on or off separately
+/// on each thread.
+const SWITCHES: usize = 8;
+
+/// Whether one `ThreadFilterMonitor` forwards events on the current thread.
+struct Switch {
+    owner: Cell<usize>,
+    enabled: Cell<bool>,
+}
+
+impl Switch {
+    const fn new() -> Self {
+        Self {
+            owner: Cell::new(0),
+            enabled: Cell::new(false),
+        }
+    }
+}
+
+thread_local! {
+    static SWITCH: [Switch; SWITCHES] = const { [const { Switch::new() }; SWITCHES] };
+}
+
+/// Sets up the switches of the current thread, for `init_thread`.
+pub(crate) fn init_thread_locals() {
+    let _ = SWITCH.try_with(|_| ());
+}
+
+/// Forwards events to an inner monitor only on the threads it's enabled on, so
+/// that an expensive monitor can watch a few threads without slowing down the
+/// rest.
+///
+/// Threads start out disabled, unless `enabled_by_default` says otherwise, and
+/// are switched with `enable_current_thread` and `disable_current_thread`. The
+/// switches are plain thread-local cells that don't allocate, so they can be
+/// flipped before a thread's first allocation. Each thread has switches for up
+/// to 8 filters; past that, the remaining filters share the last one.
+///
+/// As a `GatingMonitor`, it accepts events on enabled threads, so a
+/// `ThreadFilterMonitor<NoopMonitor>` can filter the later stages of a
+/// `PipelineMonitor`.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use interloc::{AllocAction, AllocMonitor, StatsMonitor, ThreadFilterMonitor};
+///
+/// static WORKERS: ThreadFilterMonitor<StatsMonitor> =
+///     ThreadFilterMonitor::new(StatsMonitor::new());
+///
+/// let layout = Layout::new::<u64>();
+/// let threads: Vec<_> = (0..8)
+///     .map(|i| {
+///         std::thread::spawn(move || {
+///             if i < 2 {
+///                 WORKERS.enable_current_thread();
+///             }
+///             for _ in 0..10 {
+///                 WORKERS.monitor(layout, AllocAction::Alloc);
+///             }
+///         })
+///     })
+///     .collect();
+/// for thread in threads {
+///     thread.join().unwrap();
+/// }
+/// assert_eq!(WORKERS.inner().info().alloc, 20);
+/// ```
+pub struct ThreadFilterMonitor<M = NoopMonitor> {
+    inner: M,
+    enabled_by_default: bool,
+}
+
+impl<M> ThreadFilterMonitor<M> {
+    pub const fn new(inner: M) -> Self {
+        Self {
+            inner,
+            enabled_by_default: false,
+        }
+    }
+
+    /// Sets whether threads that haven't been switched on or off forward
+    /// events.
+    pub const fn enabled_by_default(mut self, enabled: bool) -> Self {
+        self.enabled_by_default = enabled;
+        self
+    }
+
+    /// The monitor that events are forwarded to.
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Forwards events made on the current thread from now on.
+    pub fn enable_current_thread(&self) {
+        self.set_current_thread(true);
+    }
+
+    /// Stops forwarding events made on the current thread.
+    pub fn disable_current_thread(&self) {
+        self.set_current_thread(false);
+    }
+
+    /// Whether events made on the current thread are forwarded.
+    pub fn is_current_thread_enabled(&self) -> bool {
+        let id = self.id();
+        SWITCH
+            .try_with(|switches| {
+                switches
+                    .iter()
+                    .find(|s| s.owner.get() == id)
+                    .map(|s| s.enabled.get())
+            })
+            .ok()
+            .flatten()
+            .unwrap_or(self.enabled_by_default)
+    }
+
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn set_current_thread(&self, enabled: bool) {
+        let id = self.id();
+        let _ = SWITCH.try_with(|switches| {
+            let switch = switches
+                .iter()
+                .find(|s| s.owner.get() == id || s.owner.get() == 0)
+                .unwrap_or(&switches[SWITCHES - 1]);
+            switch.owner.set(id);
+            switch.enabled.set(enabled);
+        });
+    }
+}
+
+impl<M> GatingMonitor for ThreadFilterMonitor<M> {
+    #[inline]
+    fn accepts(&self, _: Layout, _: AllocAction) -> bool {
+        self.is_current_thread_enabled()
+    }
+}
+
+impl<M: AllocMonitor> AllocMonitor for ThreadFilterMonitor<M> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        if self.accepts(layout, action) {
+            self.inner.monitor(layout, action);
+        }
+    }
+
+    fn on_first_event(&self) {
+        self.inner.on_first_event();
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("ThreadFilterMonitor", Overhead::ThreadLocal)
+            .param("enabled_by_default", self.enabled_by_default as u64)
+    }
+
+    fn describe_inner(&self, report: &mut ConfigReport) {
+        report.add(&self.inner);
+    }
+}
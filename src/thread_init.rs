@@ -0,0 +1,72 @@
+use crate::alloc::is_suppressed;
+use crate::callsite::current_location;
+use crate::clock::CoarseClock;
+use crate::event::thread_token;
+use crate::monitor::ThreadMonitor;
+use crate::tag::current_tag;
+use crate::thread_registry::register_current_thread;
+
+/// Sets up everything `interloc` keeps for the current thread, so that none of
+/// it is done inside the allocator, on the thread's first allocation. Call it
+/// at the start of each thread, e.g. from a thread pool's start handler, like
+/// `interloc::rayon::start_handler` or tokio's `on_thread_start`. Calling it
+/// again does nothing.
+///
+/// Without it, all of this happens lazily the first time each piece is needed,
+/// which is usually the thread's first event:
+///
+/// - The thread gets its `thread_token`, and a `ThreadRegistryMonitor` slot,
+///   whose destructor is registered with std. On platforms where std keeps
+///   destructors in a list, like Windows, registering one allocates.
+/// - The thread locals of `ThreadMonitor`, `with_tag`, `attributed`,
+///   `SampleMonitor`, `ThreadFilterMonitor` and `suppress` are initialized. On
+///   most platforms they're initialized statically and need nothing, but
+///   where std emulates thread locals with OS keys, the first access to each
+///   one allocates.
+/// - `CoarseClock` is calibrated, which only happens once per process, and
+///   takes about a millisecond on x86.
+///
+/// None of it is a problem for correctness, since what allocates does so with
+/// monitoring suppressed, but it delays the first event, and trips
+/// `testing::NoReentryAlloc`. The rings of monitors like `TraceRecorder` are
+/// claimed per monitor on a thread's first record, and never allocate.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::testing::NoReentryAlloc;
+/// use interloc::{InterAlloc, SliceMonitor, ThreadMonitor, ThreadRegistryMonitor};
+/// use std::alloc::System;
+///
+/// static THREAD: ThreadMonitor = ThreadMonitor::new();
+/// static REGISTRY: ThreadRegistryMonitor = ThreadRegistryMonitor::new();
+/// static MONITORS: SliceMonitor = SliceMonitor::new(&[&THREAD, &REGISTRY]);
+///
+/// #[global_allocator]
+/// static GLOBAL: NoReentryAlloc<InterAlloc<System, SliceMonitor>> =
+///     NoReentryAlloc::new(InterAlloc {
+///         inner: System,
+///         monitor: &MONITORS,
+///     });
+///
+/// std::thread::spawn(|| {
+///     interloc::init_thread();
+///     let registered = REGISTRY.active_threads();
+///     // Nothing left to set up, so nothing allocates from inside the allocator.
+///     let first = std::hint::black_box(vec![0u8; 100]);
+///     assert_eq!(REGISTRY.active_threads(), registered);
+///     assert!(THREAD.info().bytes_alloc >= first.len() as u64);
+/// })
+/// .join()
+/// .unwrap();
+/// ```
+pub fn init_thread() {
+    thread_token();
+    register_current_thread();
+    let _ = ThreadMonitor::new().snapshot();
+    current_tag();
+    current_location();
+    crate::sample::init_thread_locals();
+    crate::thread_filter::init_thread_locals();
+    is_suppressed();
+    CoarseClock::calibrate();
+}
@@ -0,0 +1,699 @@
+use crate::alloc::{internal, AllocAction, AllocMonitor};
+use crate::counters::Counters;
+use crate::describe::{MonitorDesc, Overhead};
+use crate::event::thread_token;
+use crate::fmt::ByteSize;
+use crate::monitor::{AllocInfo, InfoSource};
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::fmt;
+use core::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+
+/// How many threads the registry keeps apart. Threads beyond these are counted
+/// in the aggregate entry.
+pub const REGISTRY_THREADS: usize = 256;
+
+/// How many bytes of a thread's name `name_current_thread` keeps.
+pub const THREAD_NAME_BYTES: usize = 32;
+
+/// The slot of a thread that hasn't had an event yet.
+const UNREGISTERED: usize = usize::MAX;
+/// The slot of a thread that found every slot taken, or that exited.
+const AGGREGATE: usize = usize::MAX - 1;
+
+/// Up to `THREAD_NAME_BYTES` of a thread's name, cut at a character boundary,
+/// as captured by `name_current_thread`. Displayed with `...` after it if it
+/// was cut.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ThreadName {
+    bytes: [u8; THREAD_NAME_BYTES],
+    len: u8,
+    truncated: bool,
+}
+
+impl ThreadName {
+    /// The start of `name` that fits.
+    pub fn new(name: &str) -> Self {
+        let mut len = name.len().min(THREAD_NAME_BYTES);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; THREAD_NAME_BYTES];
+        bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self {
+            bytes,
+            len: len as u8,
+            truncated: len < name.len(),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+
+    /// Whether the name was longer than `THREAD_NAME_BYTES`.
+    pub const fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl fmt::Debug for ThreadName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for ThreadName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())?;
+        if self.truncated {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
+/// The length of a `NameCell`'s name when it was cut.
+const TRUNCATED: usize = 1 << 8;
+
+/// A thread's name, written only by the thread, and read by copying it out
+/// like a `SeqLock`, but with atomics, so that it can live in a static.
+struct NameCell {
+    /// Odd while the name is being written
+    sequence: AtomicUsize,
+    /// The length of the name plus one, with `TRUNCATED` set if it was cut, or
+    /// 0 if there's none
+    len: AtomicUsize,
+    /// The hash of the whole name
+    hash: AtomicU64,
+    bytes: [AtomicU64; THREAD_NAME_BYTES / 8],
+}
+
+impl NameCell {
+    const fn new() -> Self {
+        Self {
+            sequence: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            hash: AtomicU64::new(0),
+            bytes: [const { AtomicU64::new(0) }; THREAD_NAME_BYTES / 8],
+        }
+    }
+
+    /// Only for the thread the cell belongs to.
+    fn set(&self, name: Option<&str>) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        match name {
+            Some(whole) => {
+                let name = ThreadName::new(whole);
+                for (word, chunk) in self.bytes.iter().zip(name.bytes.chunks(8)) {
+                    let mut bytes = [0; 8];
+                    bytes.copy_from_slice(chunk);
+                    word.store(u64::from_le_bytes(bytes), Ordering::Relaxed);
+                }
+                let truncated = if name.truncated { TRUNCATED } else { 0 };
+                self.len
+                    .store((name.len as usize + 1) | truncated, Ordering::Relaxed);
+                self.hash.store(hash_name(whole), Ordering::Relaxed);
+            }
+            None => self.len.store(0, Ordering::Relaxed),
+        }
+        self.sequence
+            .store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    /// The name and its hash, if there's a name.
+    fn get(&self) -> Option<(ThreadName, u64)> {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before % 2 == 0 {
+                let name = self.read();
+                fence(Ordering::Acquire);
+                if self.sequence.load(Ordering::Relaxed) == before {
+                    return name;
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn read(&self) -> Option<(ThreadName, u64)> {
+        let len = self.len.load(Ordering::Relaxed);
+        if len == 0 {
+            return None;
+        }
+        let mut name = ThreadName {
+            bytes: [0; THREAD_NAME_BYTES],
+            len: ((len & !TRUNCATED) - 1) as u8,
+            truncated: len & TRUNCATED != 0,
+        };
+        for (word, chunk) in self.bytes.iter().zip(name.bytes.chunks_mut(8)) {
+            chunk.copy_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+        }
+        Some((name, self.hash.load(Ordering::Relaxed)))
+    }
+}
+
+/// Hashes a thread's name with FNV-1a.
+fn hash_name(name: &str) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in name.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// The statistics of one registered thread.
+struct Slot {
+    /// The thread's token, or 0 while the slot is free
+    token: AtomicUsize,
+    info: Counters,
+    /// The reset generation `info` started in
+    generation: AtomicU64,
+    name: NameCell,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            token: AtomicUsize::new(0),
+            info: Counters::new(),
+            generation: AtomicU64::new(0),
+            name: NameCell::new(),
+        }
+    }
+
+    /// Whether `info` started in the current generation.
+    #[inline]
+    fn is_current(&self) -> bool {
+        self.generation.load(Ordering::Relaxed) == GENERATION.load(Ordering::Relaxed)
+    }
+
+    /// Applies the resets requested since `info` started, folding it into
+    /// `PREVIOUS` and starting over from the bytes still live. Only for the
+    /// thread the slot belongs to.
+    #[cold]
+    fn catch_up(&self) {
+        let generation = GENERATION.load(Ordering::Relaxed);
+        let taken = self.info.take();
+        let carried = taken.carried_over();
+        // The live bytes carry over in the slot, so they're taken out of what's
+        // folded into `PREVIOUS`, to only be counted once.
+        PREVIOUS.merge(&AllocInfo {
+            bytes_alloc: taken.baseline_bytes + taken.bytes_alloc - carried.baseline_bytes,
+            baseline_bytes: 0,
+            ..taken
+        });
+        self.info.merge(&carried);
+        self.generation.store(generation, Ordering::Relaxed);
+    }
+}
+
+static SLOTS: [Slot; REGISTRY_THREADS] = [const { Slot::new() }; REGISTRY_THREADS];
+/// Threads that exited, and threads that didn't get a slot.
+static RETIRED: Counters = Counters::new();
+/// `RETIRED` as of the last reset.
+static RETIRED_BASE: Counters = Counters::new();
+/// The highest peak of a thread that exited since the last reset.
+static RETIRED_PEAK: AtomicU64 = AtomicU64::new(0);
+/// What slots counted before the resets they caught up with.
+static PREVIOUS: Counters = Counters::new();
+/// Bumped by `ThreadRegistryMonitor::request_reset`.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+/// Threads that ever had an event.
+static SEEN: AtomicUsize = AtomicUsize::new(0);
+/// Threads that had an event and haven't exited.
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The index of the current thread's slot, `UNREGISTERED` or `AGGREGATE`.
+    static SLOT: Cell<usize> = const { Cell::new(UNREGISTERED) };
+    /// Retires the current thread when it exits. It's only touched once, when
+    /// the thread registers, since registering its destructor may allocate.
+    static EXIT: Exit = const { Exit };
+}
+
+/// Folds the thread's statistics into the aggregate when the thread exits.
+struct Exit;
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        let slot = SLOT.with(|slot| slot.replace(AGGREGATE));
+        internal(|| {
+            if let Some(slot) = SLOTS.get(slot) {
+                if !slot.is_current() {
+                    slot.catch_up();
+                }
+                let info = slot.info.take();
+                RETIRED.merge(&info);
+                RETIRED_PEAK.fetch_max(info.peak_bytes, Ordering::Relaxed);
+                slot.name.set(None);
+                slot.token.store(0, Ordering::Release);
+            }
+        });
+        ACTIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Claims a slot for the current thread, and arranges for it to be retired.
+#[cold]
+fn register() -> usize {
+    let token = thread_token();
+    let slot = SLOTS
+        .iter()
+        .position(|slot| {
+            slot.token
+                .compare_exchange(0, token, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })
+        .unwrap_or(AGGREGATE);
+    if let Some(claimed) = SLOTS.get(slot) {
+        claimed
+            .generation
+            .store(GENERATION.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+    SLOT.with(|s| s.set(slot));
+    SEEN.fetch_add(1, Ordering::Relaxed);
+    ACTIVE.fetch_add(1, Ordering::Relaxed);
+    // Without a destructor, e.g. while the thread's locals are being destroyed,
+    // the thread is never retired, and just stays active.
+    internal(|| {
+        let _ = EXIT.try_with(|_| ());
+    });
+    slot
+}
+
+/// Copies up to `THREAD_NAME_BYTES` of the current thread's name into its
+/// `ThreadRegistryMonitor` slot, for `ThreadEntry` to show, and registers the
+/// thread if it isn't yet. Reading the name may allocate, so the monitor doesn't
+/// do it; call this once at the start of each thread that should be named in
+/// reports, e.g. from a thread pool's start handler. An unnamed thread clears
+/// the name.
+///
+/// ```rust
+/// use interloc::{name_current_thread, InterAlloc, ThreadEntry, ThreadRegistryMonitor};
+/// use std::alloc::System;
+/// use std::sync::Barrier;
+/// use std::thread::Builder;
+///
+/// static MONITOR: ThreadRegistryMonitor = ThreadRegistryMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, ThreadRegistryMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// let names = [
+///     Some("worker-1"),
+///     Some("a-worker-with-a-name-too-long-to-keep"),
+///     None,
+/// ];
+/// let mut entries = vec![ThreadEntry::new(); 64];
+/// let barrier = Barrier::new(names.len() + 1);
+/// let lines: Vec<String> = std::thread::scope(|s| {
+///     let workers: Vec<_> = names
+///         .iter()
+///         .map(|name| {
+///             let builder = match name {
+///                 Some(name) => Builder::new().name(name.to_string()),
+///                 None => Builder::new(),
+///             };
+///             let barrier = &barrier;
+///             builder
+///                 .spawn_scoped(s, move || {
+///                     name_current_thread();
+///                     let block = std::hint::black_box(vec![0u8; 1024]);
+///                     barrier.wait();
+///                     barrier.wait();
+///                     drop(block);
+///                     interloc::thread_token()
+///                 })
+///                 .unwrap()
+///         })
+///         .collect();
+///     barrier.wait();
+///     let n = MONITOR.per_thread(&mut entries);
+///     barrier.wait();
+///     let tokens: Vec<usize> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+///     tokens
+///         .iter()
+///         .map(|token| {
+///             let entry = entries[..n].iter().find(|e| e.token == *token).unwrap();
+///             entry.to_string()
+///         })
+///         .collect()
+/// });
+///
+/// assert!(lines[0].starts_with("thread 'worker-1': live "));
+/// assert!(lines[1].starts_with("thread 'a-worker-with-a-name-too-long-to...': live "));
+/// assert!(lines[2].starts_with("thread #"));
+/// ```
+pub fn name_current_thread() {
+    let thread = std::thread::current();
+    if let Some(slot) = register_current_thread().and_then(|slot| SLOTS.get(slot)) {
+        slot.name.set(thread.name());
+    }
+}
+
+/// Registers the current thread if it isn't yet, returning its slot, or `None`
+/// while the thread is exiting.
+pub(crate) fn register_current_thread() -> Option<usize> {
+    match SLOT.try_with(|slot| slot.get()) {
+        Ok(UNREGISTERED) => Some(register()),
+        Ok(slot) => Some(slot),
+        Err(_) => None,
+    }
+}
+
+/// The statistics of one thread, or of every thread that exited, as filled in
+/// by `ThreadRegistryMonitor::per_thread`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ThreadEntry {
+    /// The thread's `thread_token`, or 0 for the aggregate entry
+    pub token: usize,
+    /// The thread's name, if it was captured with `name_current_thread`
+    pub name: Option<ThreadName>,
+    /// A hash of the thread's whole name, uncut
+    pub name_hash: Option<u64>,
+    /// What the thread allocated
+    pub info: AllocInfo,
+}
+
+impl ThreadEntry {
+    pub const fn new() -> Self {
+        Self {
+            token: 0,
+            name: None,
+            name_hash: None,
+            info: AllocInfo::new(),
+        }
+    }
+
+    /// Whether this is the entry for threads that exited, or that didn't fit.
+    pub const fn is_aggregate(&self) -> bool {
+        self.token == 0
+    }
+}
+
+/// One line, like `thread 'worker-3': live 1.0 KiB, peak 2.0 KiB, 12 allocs`,
+/// with `thread #7` for a thread without a name, and `exited threads` for the
+/// aggregate entry.
+impl fmt::Display for ThreadEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.is_aggregate(), &self.name) {
+            (true, _) => f.write_str("exited threads")?,
+            (false, Some(name)) => write!(f, "thread '{}'", name)?,
+            (false, None) => write!(f, "thread #{}", self.token)?,
+        }
+        write!(
+            f,
+            ": live {}, peak {}, {} allocs",
+            ByteSize(self.info.live_bytes() as u128),
+            ByteSize(self.info.peak_bytes as u128),
+            self.info.alloc
+        )
+    }
+}
+
+/// A monitor that keeps statistics for each thread, where other threads can
+/// read them, unlike `ThreadMonitor`'s, which only the thread itself can.
+///
+/// A thread gets one of `REGISTRY_THREADS` slots on its first event. When it
+/// exits, its statistics are folded into an aggregate entry and the slot is
+/// reused. Threads that find every slot taken are counted in the aggregate
+/// right away. Like `ThreadMonitor`, every `ThreadRegistryMonitor` shares the
+/// same statistics, since a thread's slot lives as long as the thread does,
+/// which may be longer than any one monitor. Entries only have the thread's
+/// name if the thread called `name_current_thread`.
+///
+/// The fields of an entry are read one at a time while its thread may be
+/// allocating, so they can be a few events apart. Statistics of a thread that's
+/// exiting may be counted twice for a moment.
+///
+/// `request_reset` starts the statistics over for every thread, for
+/// `total_since_reset`, while `info` keeps counting across resets.
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use interloc::{InterAlloc, ThreadEntry, ThreadRegistryMonitor};
+/// use std::alloc::System;
+/// use std::sync::Barrier;
+///
+/// static MONITOR: ThreadRegistryMonitor = ThreadRegistryMonitor::new();
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, ThreadRegistryMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// // Allocating registers the main thread, if it wasn't already.
+/// let mut running = vec![ThreadEntry::new(); 64];
+/// let seen = MONITOR.thread_count();
+/// let barrier = Barrier::new(5);
+/// let (n, tokens) = std::thread::scope(|s| {
+///     let workers: Vec<_> = (1..=4)
+///         .map(|i| {
+///             let barrier = &barrier;
+///             s.spawn(move || {
+///                 let block = std::hint::black_box(vec![0u8; 1000 * i]);
+///                 // Stay registered while the main thread looks.
+///                 barrier.wait();
+///                 barrier.wait();
+///                 drop(block);
+///                 interloc::thread_token()
+///             })
+///         })
+///         .collect::<Vec<_>>();
+///     barrier.wait();
+///     let n = MONITOR.per_thread(&mut running);
+///     barrier.wait();
+///     let tokens: Vec<usize> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+///     (n, tokens)
+/// });
+///
+/// assert_eq!(MONITOR.thread_count(), seen + 4);
+/// for (i, token) in tokens.iter().enumerate() {
+///     let entry = running[..n].iter().find(|e| e.token == *token).unwrap();
+///     assert_eq!(entry.info.bytes_alloc, 1000 * (i as u64 + 1));
+/// }
+///
+/// // The workers exited, so they're only in the aggregate entry now.
+/// assert_eq!(MONITOR.active_threads(), MONITOR.thread_count() - 4);
+/// let mut entries = [ThreadEntry::new(); 64];
+/// let n = MONITOR.per_thread(&mut entries);
+/// assert!(entries[..n].iter().all(|e| !tokens.contains(&e.token)));
+/// let retired = entries[n - 1];
+/// assert!(retired.is_aggregate());
+/// assert!(retired.info.bytes_alloc >= 10_000);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadRegistryMonitor;
+
+impl ThreadRegistryMonitor {
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    /// How many threads ever had an event, including ones that exited.
+    pub fn thread_count(&self) -> usize {
+        SEEN.load(Ordering::Relaxed)
+    }
+
+    /// How many threads had an event and haven't exited yet.
+    pub fn active_threads(&self) -> usize {
+        ACTIVE.load(Ordering::Relaxed)
+    }
+
+    /// How many times `request_reset` was called.
+    pub fn generation(&self) -> u64 {
+        GENERATION.load(Ordering::Relaxed)
+    }
+
+    /// Starts the statistics of every thread over, for `total_since_reset`.
+    /// Other threads' statistics can't be touched from here, so each thread
+    /// applies the reset itself, at its next event: what it counted before is
+    /// folded into the previous generations, and it starts over from the bytes
+    /// it still has live, as its baseline, as `ThreadMonitor::take` does.
+    ///
+    /// A thread that stays idle after the reset lags behind until it has an
+    /// event or exits. Until then, nothing it counted is in
+    /// `total_since_reset`, its `per_thread` entry still shows what it counted
+    /// before the reset, and the blocks it has live only show up once it
+    /// catches up. Threads that exit or call this while another thread does may
+    /// have a few events counted in the wrong generation.
+    ///
+    #[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+    #[cfg_attr(feature = "disabled", doc = "```ignore")]
+    /// use interloc::{InterAlloc, ThreadRegistryMonitor};
+    /// use std::alloc::System;
+    /// use std::sync::Barrier;
+    ///
+    /// static MONITOR: ThreadRegistryMonitor = ThreadRegistryMonitor::new();
+    ///
+    /// #[global_allocator]
+    /// static GLOBAL: InterAlloc<System, ThreadRegistryMonitor> = InterAlloc {
+    ///     inner: System,
+    ///     monitor: &MONITOR,
+    /// };
+    ///
+    /// let barrier = Barrier::new(3);
+    /// let totals = std::thread::scope(|s| {
+    ///     let barrier = &barrier;
+    ///     // Idle across the reset, then allocates once more.
+    ///     s.spawn(move || {
+    ///         let before = std::hint::black_box(vec![0u8; 1000]);
+    ///         barrier.wait();
+    ///         barrier.wait();
+    ///         barrier.wait();
+    ///         barrier.wait();
+    ///         let after = std::hint::black_box(vec![0u8; 500]);
+    ///         barrier.wait();
+    ///         barrier.wait();
+    ///         drop((before, after));
+    ///     });
+    ///     // Busy right after the reset.
+    ///     s.spawn(move || {
+    ///         let before = std::hint::black_box(vec![0u8; 2000]);
+    ///         barrier.wait();
+    ///         barrier.wait();
+    ///         let after = std::hint::black_box(vec![0u8; 3000]);
+    ///         barrier.wait();
+    ///         barrier.wait();
+    ///         barrier.wait();
+    ///         barrier.wait();
+    ///         drop((before, after));
+    ///     });
+    ///     barrier.wait();
+    ///     MONITOR.request_reset();
+    ///     let start = MONITOR.total_since_reset();
+    ///     barrier.wait();
+    ///     barrier.wait();
+    ///     let busy = MONITOR.total_since_reset();
+    ///     barrier.wait();
+    ///     barrier.wait();
+    ///     let both = MONITOR.total_since_reset();
+    ///     barrier.wait();
+    ///     [start, busy, both]
+    /// });
+    ///
+    /// // Nothing is counted until a thread catches up.
+    /// assert_eq!(totals[0].alloc, 0);
+    /// // The busy thread's live bytes carried over, and it allocated 3000 more.
+    /// // Its live bytes are a little under 2000, since a new thread frees some of
+    /// // what the thread that spawned it allocated for it.
+    /// assert_eq!((totals[1].alloc, totals[1].bytes_alloc), (1, 3000));
+    /// assert!((1900..=2000).contains(&totals[1].baseline_bytes));
+    /// // The idle thread's live bytes only count once it catches up.
+    /// assert_eq!((totals[2].alloc, totals[2].bytes_alloc), (2, 3500));
+    /// let idle = totals[2].baseline_bytes - totals[1].baseline_bytes;
+    /// assert!((900..=1000).contains(&idle));
+    /// assert_eq!(MONITOR.generation(), 1);
+    /// ```
+    pub fn request_reset(&self) {
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+        RETIRED_BASE.store(&RETIRED.read());
+        RETIRED_PEAK.store(0, Ordering::Relaxed);
+    }
+
+    /// What every thread counted since the last `request_reset`, as far as the
+    /// threads have caught up with it. The peak is the highest of any one
+    /// thread's since it caught up.
+    pub fn total_since_reset(&self) -> AllocInfo {
+        let mut info = RETIRED.read().relative_to(&RETIRED_BASE.read());
+        info.peak_bytes = RETIRED_PEAK.load(Ordering::Relaxed);
+        for slot in SLOTS.iter() {
+            if slot.token.load(Ordering::Acquire) != 0 && slot.is_current() {
+                info.merge(&slot.info.read());
+            }
+        }
+        info
+    }
+
+    /// Fills `entries` with the statistics of each registered thread, followed
+    /// by the aggregate entry if any thread exited or didn't get a slot, and
+    /// returns how many it filled. Threads that don't fit are counted in the
+    /// aggregate entry, so the entries always add up to `info`.
+    pub fn per_thread(&self, entries: &mut [ThreadEntry]) -> usize {
+        if entries.is_empty() {
+            return 0;
+        }
+        let mut aggregate = RETIRED.read();
+        let mut n = 0;
+        for slot in SLOTS.iter() {
+            let token = slot.token.load(Ordering::Acquire);
+            if token == 0 {
+                continue;
+            }
+            let info = slot.info.read();
+            if n < entries.len() {
+                let name = slot.name.get();
+                entries[n] = ThreadEntry {
+                    token,
+                    name: name.map(|(name, _)| name),
+                    name_hash: name.map(|(_, hash)| hash),
+                    info,
+                };
+                n += 1;
+            } else {
+                aggregate.merge(&info);
+            }
+        }
+        if aggregate == AllocInfo::new() {
+            return n;
+        }
+        if n == entries.len() {
+            n -= 1;
+            aggregate.merge(&entries[n].info);
+        }
+        entries[n] = ThreadEntry {
+            info: aggregate,
+            ..ThreadEntry::new()
+        };
+        n + 1
+    }
+}
+
+/// Every thread's statistics merged, across resets, so the peak is the highest
+/// of any one thread's.
+impl InfoSource for ThreadRegistryMonitor {
+    fn info(&self) -> AllocInfo {
+        let mut info = RETIRED.read();
+        info.merge(&PREVIOUS.read());
+        for slot in SLOTS.iter() {
+            if slot.token.load(Ordering::Acquire) != 0 {
+                info.merge(&slot.info.read());
+            }
+        }
+        info
+    }
+}
+
+impl AllocMonitor for ThreadRegistryMonitor {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        let slot = SLOT.try_with(|slot| slot.get()).unwrap_or(AGGREGATE);
+        let slot = if slot == UNREGISTERED {
+            register()
+        } else {
+            slot
+        };
+        match SLOTS.get(slot) {
+            Some(slot) => {
+                if !slot.is_current() {
+                    slot.catch_up();
+                }
+                slot.info.apply(layout, action)
+            }
+            None => RETIRED.add(layout, action),
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("ThreadRegistryMonitor", Overhead::ThreadLocal)
+    }
+}
@@ -0,0 +1,281 @@
+use crate::alloc::{ActionKind, AllocAction, AllocMonitor, EventMask};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::event::EventRecord;
+use crate::event_log::{EventLogMonitor, LogReader};
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Bytes at the start of every allocation trace.
+pub const TRACE_MAGIC: [u8; 8] = *b"ITRCv001";
+
+/// The events a trace is made of. Frees and the start of reallocations are
+/// recorded before the call, so that they're ordered before anything that could
+/// reuse the address; allocations and the end of reallocations are recorded
+/// after, once their address is known.
+const TRACE_MASK: EventMask = EventMask::NONE
+    .with(ActionKind::AllocResult)
+    .with(ActionKind::AllocZeroedResult)
+    .with(ActionKind::Dealloc)
+    .with(ActionKind::Realloc)
+    .with(ActionKind::ReallocResult);
+
+/// Records a trace of allocations that `TraceReplayer` can run again against
+/// another allocator.
+///
+/// A trace is an event log with its own header, holding only the events needed
+/// to replay it, so everything about `EventLogMonitor` applies: each of up to
+/// `THREADS` threads gets a buffer of `RECORDS` records, and the buffers need to
+/// be flushed regularly. Events that are dropped leave holes in the trace, which
+/// replay works around but which make it less faithful, so `dropped` should be
+/// checked after recording.
+pub struct TraceRecorder<const THREADS: usize, const RECORDS: usize> {
+    log: EventLogMonitor<THREADS, RECORDS>,
+}
+
+impl<const THREADS: usize, const RECORDS: usize> TraceRecorder<THREADS, RECORDS> {
+    pub const fn new() -> Self {
+        Self {
+            log: EventLogMonitor::new().mask(TRACE_MASK),
+        }
+    }
+
+    /// Number of events that couldn't be recorded.
+    pub fn dropped(&self) -> u64 {
+        self.log.dropped()
+    }
+
+    /// Writes the header that `TraceReplayer` expects at the start of a trace.
+    /// This should be called once per file, before the first flush.
+    pub fn write_header(&self, out: &mut impl io::Write) -> io::Result<()> {
+        out.write_all(&TRACE_MAGIC)
+    }
+
+    /// Drains every thread's buffer into `out`, returning the number of records
+    /// written.
+    pub fn flush(&self, out: &mut impl io::Write) -> io::Result<usize> {
+        self.log.flush(out)
+    }
+}
+
+impl<const THREADS: usize, const RECORDS: usize> Default for TraceRecorder<THREADS, RECORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const THREADS: usize, const RECORDS: usize> AllocMonitor for TraceRecorder<THREADS, RECORDS> {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        self.log.monitor(layout, action);
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("TraceRecorder", Overhead::ThreadLocal)
+            .param("threads", THREADS as u64)
+            .param("records", RECORDS as u64)
+    }
+}
+
+/// What happened when a trace was replayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// Allocations made, including zeroed ones
+    pub allocs: u64,
+    /// Blocks freed
+    pub deallocs: u64,
+    /// Blocks reallocated
+    pub reallocs: u64,
+    /// Events skipped because they refer to blocks the trace doesn't know
+    /// about, like frees of blocks allocated before recording started, or
+    /// because they record a failed call
+    pub skipped: u64,
+    /// Calls that failed during replay but not while recording
+    pub failed: u64,
+    /// Blocks still live at the end of the trace, which are freed after timing
+    /// stops
+    pub leaked: u64,
+    /// Most bytes live at once during replay
+    pub peak_bytes: u64,
+    /// Time spent in the allocator's functions
+    pub elapsed: Duration,
+}
+
+impl ReplayStats {
+    /// Number of calls made to the allocator, not counting the frees of leaked
+    /// blocks.
+    pub fn calls(&self) -> u64 {
+        self.allocs + self.deallocs + self.reallocs
+    }
+
+    /// Average time per call to the allocator, in nanoseconds.
+    pub fn ns_per_call(&self) -> f64 {
+        if self.calls() == 0 {
+            return 0.0;
+        }
+        self.elapsed.as_nanos() as f64 / self.calls() as f64
+    }
+}
+
+impl fmt::Display for ReplayStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "allocs:      {:>12}", self.allocs)?;
+        writeln!(f, "deallocs:    {:>12}", self.deallocs)?;
+        writeln!(f, "reallocs:    {:>12}", self.reallocs)?;
+        writeln!(f, "skipped:     {:>12}", self.skipped)?;
+        writeln!(f, "failed:      {:>12}", self.failed)?;
+        writeln!(f, "leaked:      {:>12}", self.leaked)?;
+        writeln!(f, "peak bytes:  {:>12}", self.peak_bytes)?;
+        write!(
+            f,
+            "time:        {:>12.1?} ({:.1} ns/call)",
+            self.elapsed,
+            self.ns_per_call()
+        )
+    }
+}
+
+/// A block allocated during replay.
+#[derive(Clone, Copy)]
+struct Block {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+/// Runs the allocations of a trace written by `TraceRecorder` against an
+/// allocator.
+///
+/// Replay is single threaded: the events of every thread are put in the order
+/// of their serial numbers and run one after the other. Recorded addresses are
+/// mapped to the blocks allocated during replay, and blocks are always freed and
+/// reallocated with the layout they were allocated with during replay, so holes
+/// in the trace never make the replay free memory incorrectly.
+///
+/// Note that replay allocates its own bookkeeping through the global allocator,
+/// so replaying against the global allocator itself skews the numbers a little.
+pub struct TraceReplayer;
+
+impl TraceReplayer {
+    /// Reads a trace from `trace` and replays it against `alloc`.
+    pub fn replay<A: GlobalAlloc>(trace: impl io::Read, alloc: &A) -> io::Result<ReplayStats> {
+        let reader = LogReader::with_magic(trace, &TRACE_MAGIC, "not an interloc trace")?;
+        let mut records = reader.collect::<io::Result<Vec<_>>>()?;
+        records.sort_unstable_by_key(|r| r.serial);
+        Ok(Self::replay_records(&records, alloc))
+    }
+
+    /// Replays `records`, which must already be in order, against `alloc`.
+    pub fn replay_records<A: GlobalAlloc>(records: &[EventRecord], alloc: &A) -> ReplayStats {
+        let mut stats = ReplayStats::default();
+        let mut live: HashMap<usize, Block> = HashMap::new();
+        // Blocks that are being reallocated, by the thread reallocating them,
+        // along with their recorded address.
+        let mut reallocating: HashMap<usize, (usize, Block)> = HashMap::new();
+        let mut live_bytes = 0u64;
+
+        for record in records {
+            match record.kind {
+                ActionKind::AllocResult | ActionKind::AllocZeroedResult => {
+                    let layout = match record.layout() {
+                        Some(layout) if layout.size() != 0 && record.ptr != 0 => layout,
+                        _ => {
+                            stats.skipped += 1;
+                            continue;
+                        }
+                    };
+                    let start = Instant::now();
+                    let ptr = unsafe {
+                        if record.kind == ActionKind::AllocZeroedResult {
+                            alloc.alloc_zeroed(layout)
+                        } else {
+                            alloc.alloc(layout)
+                        }
+                    };
+                    stats.elapsed += start.elapsed();
+                    if ptr.is_null() {
+                        stats.failed += 1;
+                        continue;
+                    }
+                    stats.allocs += 1;
+                    live_bytes += layout.size() as u64;
+                    // The free of the block that used to be at this address
+                    // wasn't recorded.
+                    if let Some(old) = live.insert(record.ptr, Block { ptr, layout }) {
+                        live_bytes -= old.layout.size() as u64;
+                        unsafe { alloc.dealloc(old.ptr, old.layout) };
+                    }
+                }
+                ActionKind::Dealloc => {
+                    let block = match live.remove(&record.ptr) {
+                        Some(block) => block,
+                        None => {
+                            stats.skipped += 1;
+                            continue;
+                        }
+                    };
+                    let start = Instant::now();
+                    unsafe { alloc.dealloc(block.ptr, block.layout) };
+                    stats.elapsed += start.elapsed();
+                    stats.deallocs += 1;
+                    live_bytes -= block.layout.size() as u64;
+                }
+                ActionKind::Realloc => match live.remove(&record.ptr) {
+                    Some(block) => {
+                        reallocating.insert(record.thread, (record.ptr, block));
+                    }
+                    None => stats.skipped += 1,
+                },
+                ActionKind::ReallocResult => {
+                    let (old_addr, block) = match reallocating.remove(&record.thread) {
+                        Some(pending) => pending,
+                        None => {
+                            stats.skipped += 1;
+                            continue;
+                        }
+                    };
+                    let new_layout = Layout::from_size_align(record.new_size, block.layout.align());
+                    let new_layout = match new_layout {
+                        Ok(layout) if layout.size() != 0 && record.ptr != 0 => layout,
+                        _ => {
+                            // The realloc failed while recording, so the block is
+                            // still where it was.
+                            live.insert(old_addr, block);
+                            stats.skipped += 1;
+                            continue;
+                        }
+                    };
+                    let start = Instant::now();
+                    let ptr = unsafe { alloc.realloc(block.ptr, block.layout, new_layout.size()) };
+                    stats.elapsed += start.elapsed();
+                    let new_block = if ptr.is_null() {
+                        stats.failed += 1;
+                        block
+                    } else {
+                        stats.reallocs += 1;
+                        live_bytes -= block.layout.size() as u64;
+                        live_bytes += new_layout.size() as u64;
+                        Block {
+                            ptr,
+                            layout: new_layout,
+                        }
+                    };
+                    if let Some(old) = live.insert(record.ptr, new_block) {
+                        live_bytes -= old.layout.size() as u64;
+                        unsafe { alloc.dealloc(old.ptr, old.layout) };
+                    }
+                }
+                ActionKind::Alloc | ActionKind::AllocZeroed | ActionKind::DeallocResult => {}
+            }
+            stats.peak_bytes = stats.peak_bytes.max(live_bytes);
+        }
+
+        let leftover = live.values().chain(reallocating.values().map(|(_, b)| b));
+        for block in leftover {
+            stats.leaked += 1;
+            unsafe { alloc.dealloc(block.ptr, block.layout) };
+        }
+        stats
+    }
+}
@@ -0,0 +1,404 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::callsite::current_site;
+use crate::clock::{Clock, CoarseClock};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::slots::Slots;
+use crate::tag::current_tag;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+const EMPTY: usize = 0;
+const TOMBSTONE: usize = 1;
+
+thread_local! {
+    /// The block being reallocated on this thread, between the realloc action and
+    /// its result: its address and what it was tracked with.
+    static REALLOCATING: Cell<(usize, Tracked)> = const { Cell::new((0, Tracked::new(0, 0, 0))) };
+}
+
+/// What a block is tracked with besides its address and layout: its allocation
+/// time, tag and site.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Tracked {
+    pub(crate) time: u64,
+    pub(crate) tag: u32,
+    pub(crate) site: u64,
+}
+
+impl Tracked {
+    pub(crate) const fn new(time: u64, tag: u32, site: u64) -> Self {
+        Self { time, tag, site }
+    }
+}
+
+/// A slot of a `TrackingMonitor`'s table, keyed by the block's address.
+pub(crate) struct TrackSlot {
+    ptr: AtomicUsize,
+    size: AtomicUsize,
+    align: AtomicUsize,
+    time: AtomicU64,
+    tag: AtomicU32,
+    site: AtomicU64,
+}
+
+impl TrackSlot {
+    pub(crate) const fn new() -> Self {
+        Self {
+            ptr: AtomicUsize::new(EMPTY),
+            size: AtomicUsize::new(0),
+            align: AtomicUsize::new(0),
+            time: AtomicU64::new(0),
+            tag: AtomicU32::new(0),
+            site: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A live allocation, from `TrackingMonitor`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LiveBlock {
+    /// Address of the block
+    pub ptr: usize,
+    pub size: usize,
+    pub align: usize,
+    /// How long ago the block was allocated. Reallocated blocks keep the age of
+    /// the original allocation.
+    pub age: Duration,
+    /// Tag the block was allocated under, or zero if it had none
+    pub tag: u32,
+    /// Hash of the location the block was allocated at, as set with
+    /// `attributed` or `trace_alloc!`, or zero if there was none. Reallocated
+    /// blocks keep the site of the original allocation.
+    pub site: u64,
+}
+
+/// Keeps track of every live allocation in a fixed-capacity open addressing
+/// table of `CAPACITY` blocks, keyed by address.
+///
+/// Each block is recorded with the tag it was allocated under, from `with_tag`,
+/// and the location it was allocated at, from `attributed`, which it keeps when
+/// it's reallocated.
+///
+/// Tracking never allocates or blocks. Allocations made while the table is full
+/// aren't tracked, and are counted in `overflowed` instead. Blocks are stored in
+/// the first free slot from the one their address hashes to, so looking a block
+/// up, tracked or not, scans at most `max_probe` slots past that one, which only
+/// grows with how many blocks are live at once, however many come and go.
+///
+/// Ages are timed by `C`, `CoarseClock` unless another is given to
+/// `with_clock`:
+///
+#[cfg_attr(not(feature = "disabled"), doc = "```rust")]
+#[cfg_attr(feature = "disabled", doc = "```ignore")]
+/// use core::alloc::Layout;
+/// use core::time::Duration;
+/// use interloc::{AllocAction, AllocMonitor, LiveBlock, ManualClock, TrackingMonitor};
+///
+/// static CLOCK: ManualClock = ManualClock::new();
+///
+/// let tracking = TrackingMonitor::<16, _>::with_clock(&CLOCK);
+/// let layout = Layout::from_size_align(64, 8).unwrap();
+/// let ptr = 0x1000 as *mut u8;
+/// tracking.monitor(layout, AllocAction::AllocResult { ptr });
+/// CLOCK.advance(1_500);
+///
+/// let mut blocks = [LiveBlock::default(); 1];
+/// assert_eq!(tracking.top_live(1, &mut blocks), 1);
+/// assert_eq!(blocks[0].age, Duration::from_nanos(1_500));
+/// ```
+pub struct TrackingMonitor<const CAPACITY: usize = 4096, C = CoarseClock> {
+    table: BlockTable<Slots<TrackSlot, CAPACITY>>,
+    name: Option<&'static str>,
+    clock: C,
+}
+
+impl<const CAPACITY: usize> TrackingMonitor<CAPACITY> {
+    pub const fn new() -> Self {
+        Self::with_clock(CoarseClock)
+    }
+
+    /// New instance of this monitor, labeled `name`, to tell it apart from
+    /// other monitors in the same process.
+    pub const fn named(name: &'static str) -> Self {
+        let mut monitor = Self::new();
+        monitor.name = Some(name);
+        monitor
+    }
+}
+
+impl<const CAPACITY: usize, C: Clock> TrackingMonitor<CAPACITY, C> {
+    /// New instance of this monitor, timing the ages of blocks by `clock`.
+    pub const fn with_clock(clock: C) -> Self {
+        Self {
+            table: BlockTable::new(Slots::new([const { TrackSlot::new() }; CAPACITY])),
+            name: None,
+            clock,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// The label given to `named`, if any.
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Number of blocks currently tracked.
+    pub fn live_blocks(&self) -> usize {
+        self.table.live_blocks()
+    }
+
+    /// Number of allocations that weren't tracked because the table was full.
+    pub fn overflowed(&self) -> usize {
+        self.table.overflowed.load(Ordering::Relaxed)
+    }
+
+    /// The furthest any block has been stored from the slot its address hashes
+    /// to, which bounds the slots a lookup scans.
+    pub fn max_probe(&self) -> usize {
+        self.table.max_probe.load(Ordering::Relaxed)
+    }
+
+    /// Finds the `n` largest live blocks and writes them to the start of `out`,
+    /// largest first, returning how many were written. At most `out.len()`
+    /// blocks are written.
+    ///
+    /// This scans the whole table without stopping other threads, so the result
+    /// is a racy snapshot: blocks allocated or freed during the scan may or may
+    /// not show up. It doesn't allocate.
+    pub fn top_live(&self, n: usize, out: &mut [LiveBlock]) -> usize {
+        let n = n.min(out.len());
+        if n == 0 {
+            return 0;
+        }
+        let heap = &mut out[..n];
+        let now = self.clock.now_nanos();
+        let mut len = 0;
+        for slot in self.table.slots.as_ref() {
+            let ptr = slot.ptr.load(Ordering::Acquire);
+            if ptr == EMPTY || ptr == TOMBSTONE {
+                continue;
+            }
+            let block = LiveBlock {
+                ptr,
+                size: slot.size.load(Ordering::Relaxed),
+                align: slot.align.load(Ordering::Relaxed),
+                age: Duration::from_nanos(now.saturating_sub(slot.time.load(Ordering::Relaxed))),
+                tag: slot.tag.load(Ordering::Relaxed),
+                site: slot.site.load(Ordering::Relaxed),
+            };
+            // `heap` is a min-heap by size of the largest blocks seen so far.
+            if len < n {
+                heap[len] = block;
+                sift_up(heap, len);
+                len += 1;
+            } else if block.size > heap[0].size {
+                heap[0] = block;
+                sift_down(&mut heap[..len], 0);
+            }
+        }
+        heap[..len].sort_unstable_by_key(|b| core::cmp::Reverse(b.size));
+        len
+    }
+
+    /// Calls `f` with the size of every tracked block, in a racy scan of the
+    /// whole table like that of `top_live`.
+    pub(crate) fn for_each_size(&self, mut f: impl FnMut(usize)) {
+        for slot in self.table.slots.as_ref() {
+            let ptr = slot.ptr.load(Ordering::Acquire);
+            if ptr != EMPTY && ptr != TOMBSTONE {
+                f(slot.size.load(Ordering::Relaxed));
+            }
+        }
+    }
+
+    /// Starts tracking a block, returning whether there was room for it.
+    pub(crate) fn insert(&self, ptr: usize, layout: Layout, tracked: Tracked) -> bool {
+        self.table.insert(ptr, layout, tracked)
+    }
+
+    /// Stops tracking the block at `ptr`, returning what it was tracked with if
+    /// it was tracked.
+    pub(crate) fn remove(&self, ptr: usize) -> Option<Tracked> {
+        self.table.remove(ptr)
+    }
+}
+
+/// The open addressing table behind a `TrackingMonitor`, in `S`: the monitor's
+/// `Slots`, or an array for `TagLimitAlloc`, which keeps its table with the
+/// `disabled` feature to go on enforcing limits.
+///
+/// Freed blocks leave tombstones, which new blocks are stored over, so the
+/// slots past a block's home that a lookup has to scan are only ever taken by
+/// blocks live at the same time, and `max_probe` keeps how many that's been at
+/// most; lookups stop there, rather than at the first empty slot, which churn
+/// leaves none of.
+pub(crate) struct BlockTable<S> {
+    slots: S,
+    live: AtomicUsize,
+    overflowed: AtomicUsize,
+    max_probe: AtomicUsize,
+}
+
+impl<S: AsRef<[TrackSlot]>> BlockTable<S> {
+    pub(crate) const fn new(slots: S) -> Self {
+        Self {
+            slots,
+            live: AtomicUsize::new(0),
+            overflowed: AtomicUsize::new(0),
+            max_probe: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of blocks currently tracked.
+    pub(crate) fn live_blocks(&self) -> usize {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    fn index(&self, ptr: usize) -> usize {
+        // Blocks are at least word-aligned, so the low bits carry no information.
+        (ptr >> 4).wrapping_mul(0x9e37_79b9) % self.slots.as_ref().len()
+    }
+
+    /// Starts tracking a block, returning whether there was room for it.
+    pub(crate) fn insert(&self, ptr: usize, layout: Layout, tracked: Tracked) -> bool {
+        let slots = self.slots.as_ref();
+        if !slots.is_empty() {
+            let start = self.index(ptr);
+            for i in 0..slots.len() {
+                let slot = &slots[(start + i) % slots.len()];
+                let current = slot.ptr.load(Ordering::Relaxed);
+                if current != EMPTY && current != TOMBSTONE {
+                    continue;
+                }
+                if slot
+                    .ptr
+                    .compare_exchange(current, ptr, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    // Readers may see the previous block's fields until these are
+                    // written, but the block can't be freed before then.
+                    slot.size.store(layout.size(), Ordering::Relaxed);
+                    slot.align.store(layout.align(), Ordering::Relaxed);
+                    slot.time.store(tracked.time, Ordering::Relaxed);
+                    slot.tag.store(tracked.tag, Ordering::Relaxed);
+                    slot.site.store(tracked.site, Ordering::Relaxed);
+                    // Before the block can be freed, and looked up, since that
+                    // only happens once it's been handed out.
+                    self.max_probe.fetch_max(i, Ordering::Relaxed);
+                    self.live.fetch_add(1, Ordering::Relaxed);
+                    return true;
+                }
+            }
+        }
+        self.overflowed.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    /// Stops tracking the block at `ptr`, returning what it was tracked with if
+    /// it was tracked.
+    pub(crate) fn remove(&self, ptr: usize) -> Option<Tracked> {
+        let slots = self.slots.as_ref();
+        if slots.is_empty() {
+            return None;
+        }
+        let start = self.index(ptr);
+        let probe = self.max_probe.load(Ordering::Relaxed);
+        for i in 0..=probe.min(slots.len() - 1) {
+            let slot = &slots[(start + i) % slots.len()];
+            let current = slot.ptr.load(Ordering::Acquire);
+            if current == EMPTY {
+                return None;
+            }
+            if current == ptr {
+                let tracked = Tracked::new(
+                    slot.time.load(Ordering::Relaxed),
+                    slot.tag.load(Ordering::Relaxed),
+                    slot.site.load(Ordering::Relaxed),
+                );
+                slot.ptr.store(TOMBSTONE, Ordering::Release);
+                self.live.fetch_sub(1, Ordering::Relaxed);
+                return Some(tracked);
+            }
+        }
+        None
+    }
+}
+
+/// Moves the block at `i` of the min-heap `heap` up to where it belongs.
+fn sift_up(heap: &mut [LiveBlock], mut i: usize) {
+    while i > 0 {
+        let parent = (i - 1) / 2;
+        if heap[parent].size <= heap[i].size {
+            break;
+        }
+        heap.swap(parent, i);
+        i = parent;
+    }
+}
+
+/// Moves the block at `i` of the min-heap `heap` down to where it belongs.
+fn sift_down(heap: &mut [LiveBlock], mut i: usize) {
+    loop {
+        let mut smallest = i;
+        for child in [2 * i + 1, 2 * i + 2] {
+            if child < heap.len() && heap[child].size < heap[smallest].size {
+                smallest = child;
+            }
+        }
+        if smallest == i {
+            break;
+        }
+        heap.swap(smallest, i);
+        i = smallest;
+    }
+}
+
+impl<const CAPACITY: usize> Default for TrackingMonitor<CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CAPACITY: usize, C: Clock> AllocMonitor for TrackingMonitor<CAPACITY, C> {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        use AllocAction::*;
+        match action {
+            AllocResult { ptr } | AllocZeroedResult { ptr } if !ptr.is_null() => {
+                let tracked = Tracked::new(self.clock.now_nanos(), current_tag(), current_site());
+                self.insert(ptr as usize, layout, tracked);
+            }
+            Dealloc { ptr } => {
+                self.remove(ptr as usize);
+            }
+            Realloc { ptr, .. } => {
+                let tracked = self.remove(ptr as usize).unwrap_or_else(|| {
+                    Tracked::new(self.clock.now_nanos(), current_tag(), current_site())
+                });
+                let _ = REALLOCATING.try_with(|r| r.set((ptr as usize, tracked)));
+            }
+            ReallocResult { ptr, new_size } => {
+                let (old, tracked) = REALLOCATING
+                    .try_with(|r| r.get())
+                    .unwrap_or((0, Tracked::new(0, 0, 0)));
+                if !ptr.is_null() {
+                    // The new size fits, or the realloc wouldn't have succeeded.
+                    let layout = Layout::from_size_align(new_size, layout.align()).unwrap();
+                    self.insert(ptr as usize, layout, tracked);
+                } else if old != 0 {
+                    // The old block is still live if the realloc failed.
+                    self.insert(old, layout, tracked);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("TrackingMonitor", Overhead::Table).param("capacity", CAPACITY as u64)
+    }
+}
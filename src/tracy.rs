@@ -0,0 +1,121 @@
+use crate::alloc::{internal, AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::ffi::c_void;
+use std::ffi::CStr;
+use tracy_client::{sys, Client};
+
+thread_local! {
+    /// The block being reallocated on this thread, so that it can be reported
+    /// as allocated again if the reallocation fails.
+    static REALLOCATING: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+/// Forwards allocations and frees to the Tracy profiler, so that they show up
+/// in its memory view. The Tracy client is started on the first event.
+///
+/// Allocations can be put in a named pool with `named`, which Tracy shows
+/// separately from the default pool. Every event with the same pool should
+/// come from monitors with the same name, so a block isn't freed from a pool
+/// it wasn't allocated in. Tracy can also capture the stack of every event, up
+/// to `callstack` frames, which is expensive.
+///
+/// Frees are reported before the call, and allocations after, so Tracy never
+/// sees an address allocated twice. The Tracy client allocates for itself, so
+/// monitoring is suppressed while it's called.
+#[derive(Clone, Copy, Debug)]
+pub struct TracyMonitor {
+    name: Option<&'static CStr>,
+    depth: u16,
+}
+
+impl TracyMonitor {
+    /// A monitor that reports to the default pool, without stacks.
+    pub const fn new() -> Self {
+        Self {
+            name: None,
+            depth: 0,
+        }
+    }
+
+    /// Reports to the pool called `name`.
+    pub const fn named(mut self, name: &'static CStr) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Captures up to `depth` frames of the stack of each event. Tracy supports
+    /// at most 62, which `depth` is capped to.
+    pub const fn callstack(mut self, depth: u16) -> Self {
+        self.depth = if depth < 62 { depth } else { 62 };
+        self
+    }
+
+    fn emit_alloc(&self, ptr: *mut u8, size: usize) {
+        let ptr = ptr as *const c_void;
+        let depth = self.depth as i32;
+        internal(|| unsafe {
+            Client::start();
+            match (self.name, depth) {
+                (None, 0) => sys::___tracy_emit_memory_alloc(ptr, size),
+                (None, _) => sys::___tracy_emit_memory_alloc_callstack(ptr, size, depth),
+                (Some(name), 0) => sys::___tracy_emit_memory_alloc_named(ptr, size, name.as_ptr()),
+                (Some(name), _) => {
+                    sys::___tracy_emit_memory_alloc_callstack_named(ptr, size, depth, name.as_ptr())
+                }
+            }
+        });
+    }
+
+    fn emit_free(&self, ptr: *mut u8) {
+        let ptr = ptr as *const c_void;
+        let depth = self.depth as i32;
+        internal(|| unsafe {
+            Client::start();
+            match (self.name, depth) {
+                (None, 0) => sys::___tracy_emit_memory_free(ptr),
+                (None, _) => sys::___tracy_emit_memory_free_callstack(ptr, depth),
+                (Some(name), 0) => sys::___tracy_emit_memory_free_named(ptr, name.as_ptr()),
+                (Some(name), _) => {
+                    sys::___tracy_emit_memory_free_callstack_named(ptr, depth, name.as_ptr())
+                }
+            }
+        });
+    }
+}
+
+impl Default for TracyMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AllocMonitor for TracyMonitor {
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        match action {
+            AllocAction::AllocResult { ptr } | AllocAction::AllocZeroedResult { ptr }
+                if !ptr.is_null() =>
+            {
+                self.emit_alloc(ptr, layout.size());
+            }
+            AllocAction::Dealloc { ptr } => self.emit_free(ptr),
+            AllocAction::Realloc { ptr, .. } => {
+                let _ = REALLOCATING.try_with(|r| r.set((ptr as usize, layout.size())));
+                self.emit_free(ptr);
+            }
+            AllocAction::ReallocResult { ptr, new_size } => {
+                if !ptr.is_null() {
+                    self.emit_alloc(ptr, new_size);
+                } else if let Ok((old, size)) = REALLOCATING.try_with(|r| r.get()) {
+                    self.emit_alloc(old as *mut u8, size);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("TracyMonitor", Overhead::Heavy).param("depth", self.depth as u64)
+    }
+}
@@ -0,0 +1,207 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use crate::fmt::ByteSize;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A function returning the size of the block that a pointer returned by the
+/// inner allocator really points to, like `malloc_usable_size`.
+pub type UsableSizeFn = unsafe fn(*const u8) -> usize;
+
+thread_local! {
+    /// The usable size of the block this thread is reallocating, measured
+    /// before the call, since the block may be gone after it.
+    static REALLOCATING: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe fn malloc_usable_size(ptr: *const u8) -> usize {
+    libc::malloc_usable_size(ptr as *mut libc::c_void)
+}
+
+/// Bytes requested from the allocator, next to the bytes it really handed out,
+/// as counted by `UsableSizeMonitor`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct UsableSizeInfo {
+    /// Number of blocks measured, from allocations and reallocations
+    pub blocks: u64,
+    /// Total bytes requested for those blocks
+    pub bytes_requested: u64,
+    /// Total usable bytes of those blocks
+    pub bytes_usable: u64,
+    /// Bytes requested for the blocks that are live now
+    pub live_requested: u64,
+    /// Usable bytes of the blocks that are live now
+    pub live_usable: u64,
+}
+
+impl UsableSizeInfo {
+    pub const fn new() -> Self {
+        Self {
+            blocks: 0,
+            bytes_requested: 0,
+            bytes_usable: 0,
+            live_requested: 0,
+            live_usable: 0,
+        }
+    }
+
+    /// Bytes handed out beyond those requested.
+    pub fn overhead_bytes(&self) -> u64 {
+        self.bytes_usable.saturating_sub(self.bytes_requested)
+    }
+
+    /// The overhead as a fraction of the bytes requested, or zero if nothing was
+    /// requested.
+    pub fn overhead_ratio(&self) -> f64 {
+        if self.bytes_requested == 0 {
+            return 0.0;
+        }
+        self.overhead_bytes() as f64 / self.bytes_requested as f64
+    }
+}
+
+impl fmt::Display for UsableSizeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "requested {}, usable {}, overhead {:.1}%",
+            ByteSize(self.bytes_requested as u128),
+            ByteSize(self.bytes_usable as u128),
+            self.overhead_ratio() * 100.0
+        )
+    }
+}
+
+/// Measures how much memory the inner allocator really hands out, by asking it
+/// for the usable size of every block it returns. `AllocInfo` counts the sizes
+/// that were requested, which allocators round up to their size classes, so the
+/// difference is memory that's used but not counted.
+///
+/// Asking for the usable size of a pointer that didn't come from the allocator
+/// being asked is undefined behavior, and nothing can check for it, so the
+/// constructors are unsafe: the inner allocator of the `InterAlloc` has to be the
+/// one that the function belongs to. `system` is right for `std::alloc::System`
+/// on Linux, which allocates with `malloc` and `posix_memalign`. For jemalloc,
+/// pass its usable size function to `with_fn`, e.g.
+/// `tikv_jemallocator::usable_size::<u8>`.
+///
+/// The live counters are only right if the monitor sees every call, so it
+/// shouldn't be behind a sampler or filter. The counters are updated separately,
+/// so a snapshot taken while other threads allocate can be off by the blocks
+/// being counted at the time.
+///
+/// ```rust
+/// use interloc::{InterAlloc, UsableSizeMonitor};
+/// use std::alloc::System;
+///
+/// static MONITOR: UsableSizeMonitor = unsafe { UsableSizeMonitor::system() };
+///
+/// #[global_allocator]
+/// static GLOBAL: InterAlloc<System, UsableSizeMonitor> = InterAlloc {
+///     inner: System,
+///     monitor: &MONITOR,
+/// };
+///
+/// for size in [1, 24, 100, 1000, 4097, 100_000] {
+///     let before = MONITOR.info();
+///     drop(std::hint::black_box(vec![0u8; size]));
+///     let info = MONITOR.info();
+///     assert_eq!(info.bytes_requested - before.bytes_requested, size as u64);
+///     assert!(info.bytes_usable - before.bytes_usable >= size as u64);
+/// }
+/// ```
+pub struct UsableSizeMonitor {
+    usable_size: UsableSizeFn,
+    blocks: AtomicU64,
+    bytes_requested: AtomicU64,
+    bytes_usable: AtomicU64,
+    live_requested: AtomicU64,
+    live_usable: AtomicU64,
+}
+
+impl UsableSizeMonitor {
+    /// Measures blocks with `malloc_usable_size`.
+    ///
+    /// # Safety
+    /// The inner allocator must allocate every block with `malloc` or one of its
+    /// relatives, like `std::alloc::System` does.
+    pub const unsafe fn system() -> Self {
+        Self::with_fn(malloc_usable_size)
+    }
+
+    /// Measures blocks with `usable_size`.
+    ///
+    /// # Safety
+    /// `usable_size` must be safe to call on every pointer that the inner
+    /// allocator returns.
+    pub const unsafe fn with_fn(usable_size: UsableSizeFn) -> Self {
+        Self {
+            usable_size,
+            blocks: AtomicU64::new(0),
+            bytes_requested: AtomicU64::new(0),
+            bytes_usable: AtomicU64::new(0),
+            live_requested: AtomicU64::new(0),
+            live_usable: AtomicU64::new(0),
+        }
+    }
+
+    pub fn info(&self) -> UsableSizeInfo {
+        UsableSizeInfo {
+            blocks: self.blocks.load(Ordering::Relaxed),
+            bytes_requested: self.bytes_requested.load(Ordering::Relaxed),
+            bytes_usable: self.bytes_usable.load(Ordering::Relaxed),
+            live_requested: self.live_requested.load(Ordering::Relaxed),
+            live_usable: self.live_usable.load(Ordering::Relaxed),
+        }
+    }
+
+    fn count(&self, ptr: *mut u8, requested: usize) {
+        let usable = unsafe { (self.usable_size)(ptr) };
+        self.blocks.fetch_add(1, Ordering::Relaxed);
+        self.bytes_requested
+            .fetch_add(requested as u64, Ordering::Relaxed);
+        self.bytes_usable
+            .fetch_add(usable as u64, Ordering::Relaxed);
+        self.live_requested
+            .fetch_add(requested as u64, Ordering::Relaxed);
+        self.live_usable.fetch_add(usable as u64, Ordering::Relaxed);
+    }
+
+    fn uncount(&self, requested: usize, usable: usize) {
+        self.live_requested
+            .fetch_sub(requested as u64, Ordering::Relaxed);
+        self.live_usable.fetch_sub(usable as u64, Ordering::Relaxed);
+    }
+}
+
+impl AllocMonitor for UsableSizeMonitor {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        match action {
+            AllocAction::AllocResult { ptr } | AllocAction::AllocZeroedResult { ptr }
+                if !ptr.is_null() =>
+            {
+                self.count(ptr, layout.size());
+            }
+            AllocAction::Dealloc { ptr } => {
+                self.uncount(layout.size(), unsafe { (self.usable_size)(ptr) });
+            }
+            AllocAction::Realloc { ptr, .. } => {
+                let usable = unsafe { (self.usable_size)(ptr) };
+                REALLOCATING.with(|r| r.set(usable));
+            }
+            // The old block is only gone if the call succeeded.
+            AllocAction::ReallocResult { ptr, new_size } if !ptr.is_null() => {
+                self.count(ptr, new_size);
+                self.uncount(layout.size(), REALLOCATING.with(Cell::get));
+            }
+            _ => {}
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("UsableSizeMonitor", Overhead::Atomic)
+    }
+}
@@ -0,0 +1,61 @@
+use crate::alloc::{AllocAction, AllocMonitor};
+use crate::describe::{MonitorDesc, Overhead};
+use core::alloc::Layout;
+use probe::probe_lazy;
+
+/// Fires USDT probes, in the SystemTap SDT format, on allocator calls, so that
+/// tools like bpftrace, SystemTap, perf and gdb can trace allocations in a
+/// running process without rebuilding it.
+///
+/// The probes are in the `interloc` provider:
+///
+/// | Probe     | Fired                      | Arguments                                   |
+/// |-----------|----------------------------|---------------------------------------------|
+/// | `alloc`   | after an allocation        | size, align, ptr                            |
+/// | `dealloc` | before a deallocation      | size, align, ptr                            |
+/// | `realloc` | after a reallocation       | new size, align, new ptr, old size          |
+///
+/// Failed allocations fire with a null pointer. Each probe is a single `nop`
+/// until a tracer attaches to it, and its arguments are only computed while one
+/// is attached. For example, to histogram allocation sizes:
+///
+/// ```sh
+/// bpftrace -p $PID -e 'usdt:*:interloc:alloc { @sizes = hist(arg0); }'
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UsdtMonitor;
+
+impl UsdtMonitor {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl AllocMonitor for UsdtMonitor {
+    #[inline]
+    fn monitor(&self, layout: Layout, action: AllocAction) {
+        match action {
+            AllocAction::AllocResult { ptr } | AllocAction::AllocZeroedResult { ptr } => {
+                probe_lazy!(interloc, alloc, layout.size(), layout.align(), ptr);
+            }
+            AllocAction::Dealloc { ptr } => {
+                probe_lazy!(interloc, dealloc, layout.size(), layout.align(), ptr);
+            }
+            AllocAction::ReallocResult { ptr, new_size } => {
+                probe_lazy!(
+                    interloc,
+                    realloc,
+                    new_size,
+                    layout.align(),
+                    ptr,
+                    layout.size()
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn describe(&self) -> MonitorDesc {
+        MonitorDesc::new("UsdtMonitor", Overhead::Free)
+    }
+}
@@ -0,0 +1,135 @@
+//! Checks that the `disabled` feature compiles monitoring out: the global
+//! `InterAlloc` never calls its monitor, `StatsMonitor` is zero-sized, and the
+//! monitors built on tables keep none of them, while `TagLimitAlloc` still
+//! enforces its limits. Run with
+//!
+//! ```text
+//! cargo test --features disabled --test disabled
+//! ```
+#![cfg(feature = "disabled")]
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use interloc::{
+    AlignMonitor, AllocAction, AllocMonitor, CallsiteMonitor, EventLogMonitor, EventQueueMonitor,
+    FirstTimeMonitor, HistogramMonitor, InterAlloc, LiveBlock, RecentEventsMonitor, SiteTable,
+    StatsMonitor, TrackingMonitor,
+};
+use std::alloc::System;
+
+/// Counts every call, including the first event hook.
+struct Calls(AtomicUsize);
+
+impl AllocMonitor for Calls {
+    fn monitor(&self, _: Layout, _: AllocAction) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_first_event(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+static CALLS: Calls = Calls(AtomicUsize::new(0));
+
+#[global_allocator]
+static GLOBAL: InterAlloc<System, Calls> = InterAlloc {
+    inner: System,
+    monitor: &CALLS,
+};
+
+#[test]
+fn the_monitor_is_never_called() {
+    let mut v = std::hint::black_box(vec![0u8; 100]);
+    v.resize(10_000, 1);
+    drop(v);
+    let layout = Layout::from_size_align(64, 64).unwrap();
+    unsafe {
+        let ptr = GLOBAL.alloc_zeroed(layout);
+        assert!(!ptr.is_null());
+        let ptr = GLOBAL.realloc(ptr, layout, 4096);
+        GLOBAL.dealloc(ptr, Layout::from_size_align(4096, 64).unwrap());
+    }
+    assert_eq!(CALLS.0.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn stats_monitor_is_zero_sized() {
+    assert_eq!(size_of::<StatsMonitor>(), 0);
+    static MONITOR: StatsMonitor = StatsMonitor::new();
+    MONITOR.monitor(Layout::new::<u64>(), AllocAction::Alloc);
+    assert_eq!(MONITOR.info(), interloc::AllocInfo::new());
+}
+
+#[test]
+fn monitors_drop_their_tables() {
+    // Only a few counters and settings are left, where the tables took
+    // kilobytes or more.
+    const MAX: usize = 64;
+    assert!(size_of::<TrackingMonitor<4096>>() <= MAX);
+    assert!(size_of::<SiteTable<1024, 32>>() <= MAX);
+    assert!(size_of::<CallsiteMonitor<1024>>() <= MAX);
+    assert!(size_of::<RecentEventsMonitor<1024>>() <= MAX);
+    assert!(size_of::<FirstTimeMonitor<1024>>() <= MAX);
+    assert!(size_of::<AlignMonitor<1024>>() <= MAX);
+    assert!(size_of::<interloc::ReallocMoveMonitor<1024>>() <= MAX);
+    assert!(size_of::<HistogramMonitor>() <= MAX);
+    assert!(size_of::<EventLogMonitor<64, 1024>>() <= MAX);
+    assert!(size_of::<EventQueueMonitor<64, 1024>>() <= MAX);
+}
+
+#[test]
+fn monitors_called_directly_report_empty_tables() {
+    let layout = Layout::from_size_align(256, 8).unwrap();
+    let ptr = 0x1000 as *mut u8;
+
+    let tracking = TrackingMonitor::<16>::new();
+    tracking.monitor(layout, AllocAction::AllocResult { ptr });
+    let mut out = [LiveBlock::default(); 4];
+    assert_eq!(tracking.top_live(4, &mut out), 0);
+    assert_eq!(tracking.live_blocks(), 0);
+
+    let sites = SiteTable::<16, 4>::new();
+    sites.record(&[0x1000, 0x2000], 256);
+    assert!(sites.sites().is_empty());
+
+    let histogram = HistogramMonitor::<interloc::LogBuckets>::new();
+    histogram.monitor(layout, AllocAction::Alloc);
+    assert_eq!(histogram.total(), 0);
+    assert_eq!(histogram.buckets().count(), 0);
+
+    let recent = RecentEventsMonitor::<16>::new();
+    recent.monitor(layout, AllocAction::Alloc);
+    let mut records = [interloc::EventRecord::new(layout, AllocAction::Alloc); 4];
+    assert_eq!(recent.snapshot(&mut records), 0);
+
+    let first = FirstTimeMonitor::<16>::new();
+    first.monitor(layout, AllocAction::Alloc);
+    assert_eq!(first.distinct(), 0);
+
+    let align = AlignMonitor::<16>::new();
+    align.monitor(layout.align_to(4096).unwrap(), AllocAction::Alloc);
+    assert_eq!(align.layouts().count(), 0);
+
+    let log = EventLogMonitor::<4, 16>::new();
+    log.monitor(layout, AllocAction::Alloc);
+    let mut out = Vec::new();
+    assert_eq!(log.flush(&mut out).unwrap(), 0);
+}
+
+#[test]
+fn tag_limits_are_still_enforced() {
+    const TAG: u32 = 7;
+    let limited = interloc::TagLimitAlloc::<System, 2, 16>::new(System).limit(TAG, 100);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    interloc::with_tag(TAG, || unsafe {
+        let ptr = limited.alloc(layout);
+        assert!(!ptr.is_null());
+        assert!(limited.alloc(layout).is_null());
+        limited.dealloc(ptr, layout);
+        let ptr = limited.alloc(layout);
+        assert!(!ptr.is_null());
+        limited.dealloc(ptr, layout);
+    });
+    assert_eq!((limited.live(TAG), limited.denied()), (0, 1));
+}
@@ -0,0 +1,80 @@
+//! Records events on several threads into an `EventLogMonitor` while another
+//! thread flushes it, and checks that the log read back has every event that
+//! wasn't dropped exactly once, in order for each thread.
+#![cfg(not(feature = "disabled"))]
+use core::alloc::Layout;
+use interloc::{AllocAction, AllocMonitor, EventLogMonitor, LogReader};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Barrier;
+
+const THREADS: usize = 4;
+const EVENTS: usize = if cfg!(miri) { 200 } else { 20_000 };
+
+static LOG: EventLogMonitor<THREADS, 256> = EventLogMonitor::new();
+
+#[test]
+fn concurrent_writers_and_flusher() {
+    let mut file = Vec::new();
+    LOG.write_header(&mut file).unwrap();
+    let done = AtomicBool::new(false);
+    let barrier = Barrier::new(THREADS + 1);
+
+    let flushed = std::thread::scope(|s| {
+        let writers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let barrier = &barrier;
+                s.spawn(move || {
+                    // Sizes tell the threads apart, and addresses the events of
+                    // one thread.
+                    let layout = Layout::from_size_align(thread + 1, 1).unwrap();
+                    barrier.wait();
+                    for i in 0..EVENTS {
+                        let ptr = (i + 1) as *mut u8;
+                        LOG.monitor(layout, AllocAction::AllocResult { ptr });
+                    }
+                })
+            })
+            .collect();
+        let flusher = s.spawn(|| {
+            barrier.wait();
+            let mut flushed = 0;
+            while !done.load(Ordering::Acquire) {
+                flushed += LOG.flush(&mut file).unwrap();
+            }
+            flushed + LOG.flush(&mut file).unwrap()
+        });
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Release);
+        flusher.join().unwrap()
+    });
+
+    let records: Vec<_> = LogReader::new(&file[..])
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(flushed > 0);
+    assert_eq!(records.len(), flushed);
+    assert_eq!(flushed as u64 + LOG.dropped(), (THREADS * EVENTS) as u64);
+
+    let mut last: HashMap<usize, usize> = HashMap::new();
+    let mut threads: HashMap<usize, usize> = HashMap::new();
+    for record in &records {
+        // Each thread's events come out in the order it recorded them.
+        let last = last.entry(record.size).or_insert(0);
+        assert!(record.ptr > *last, "{:?} after {}", record, last);
+        *last = record.ptr;
+        // And all of them were recorded on the same thread.
+        assert_eq!(
+            *threads.entry(record.size).or_insert(record.thread),
+            record.thread
+        );
+    }
+    assert_eq!(last.len(), THREADS);
+    let mut serials: Vec<u64> = records.iter().map(|r| r.serial).collect();
+    serials.sort_unstable();
+    serials.dedup();
+    assert_eq!(serials.len(), records.len());
+}
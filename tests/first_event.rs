@@ -0,0 +1,64 @@
+//! Releases many threads at once into the first events of monitors no
+//! `InterAlloc` has seen yet, and checks that each monitor's `on_first_event`
+//! hook runs exactly once, and returns before any thread's event reaches the
+//! monitor.
+#![cfg(not(any(loom, feature = "disabled")))]
+use core::alloc::{GlobalAlloc, Layout};
+use interloc::{AllocAction, AllocMonitor, ScopedInterAlloc};
+use std::alloc::System;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::Duration;
+
+const THREADS: usize = if cfg!(miri) { 4 } else { 16 };
+const ROUNDS: usize = if cfg!(miri) { 2 } else { 20 };
+
+#[derive(Default)]
+struct Slow {
+    hooks: AtomicUsize,
+    ready: AtomicBool,
+    early: AtomicUsize,
+    events: AtomicUsize,
+}
+
+impl AllocMonitor for Slow {
+    fn monitor(&self, _layout: Layout, _action: AllocAction) {
+        if !self.ready.load(Ordering::Acquire) {
+            self.early.fetch_add(1, Ordering::Relaxed);
+        }
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_first_event(&self) {
+        self.hooks.fetch_add(1, Ordering::Relaxed);
+        // Slow enough for the other threads to pile up behind it, allocating
+        // as it goes.
+        drop(vec![0u8; 1024]);
+        std::thread::sleep(Duration::from_millis(2));
+        self.ready.store(true, Ordering::Release);
+    }
+}
+
+#[test]
+fn first_event_races_on_fresh_monitors() {
+    // All live until the end, so that no two rounds share an address.
+    let monitors: Vec<Slow> = (0..ROUNDS).map(|_| Slow::default()).collect();
+    for monitor in &monitors {
+        let alloc = ScopedInterAlloc::new(System, monitor);
+        let barrier = Barrier::new(THREADS);
+        std::thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    let layout = Layout::from_size_align(64, 8).unwrap();
+                    barrier.wait();
+                    unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+                });
+            }
+        });
+        assert_eq!(monitor.hooks.load(Ordering::Relaxed), 1);
+        assert_eq!(monitor.early.load(Ordering::Relaxed), 0);
+        // An event before and after each of the two calls.
+        assert_eq!(monitor.events.load(Ordering::Relaxed), 4 * THREADS);
+    }
+    assert!(!interloc::first_event_overflowed());
+}
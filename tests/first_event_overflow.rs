@@ -0,0 +1,40 @@
+//! Sends events through more monitors than `InterAlloc` keeps track of, and
+//! checks that the hooks of the first 64 run once each, and that the rest are
+//! reported rather than skipped silently.
+#![cfg(not(any(loom, feature = "disabled")))]
+use core::alloc::{GlobalAlloc, Layout};
+use interloc::{AllocAction, AllocMonitor, ScopedInterAlloc};
+use std::alloc::System;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Default)]
+struct Hooked {
+    hooks: AtomicUsize,
+}
+
+impl AllocMonitor for Hooked {
+    fn monitor(&self, _layout: Layout, _action: AllocAction) {}
+
+    fn on_first_event(&self) {
+        self.hooks.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn first_event_overflow_is_reported() {
+    let monitors: Vec<Hooked> = (0..70).map(|_| Hooked::default()).collect();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    for _ in 0..2 {
+        for monitor in &monitors {
+            let alloc = ScopedInterAlloc::new(System, monitor);
+            unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+        }
+    }
+    let hooks: Vec<usize> = monitors
+        .iter()
+        .map(|monitor| monitor.hooks.load(Ordering::Relaxed))
+        .collect();
+    assert_eq!(hooks[..64], [1; 64]);
+    assert_eq!(hooks[64..], [0; 6]);
+    assert!(interloc::first_event_overflowed());
+}
@@ -0,0 +1,50 @@
+//! A test binary of its own, without the test harness, whose global allocator
+//! is declared with `interloc::global!` next to a real `main`: checks that the
+//! monitor sees the allocations of every thread from `main` on, and that
+//! library code finds it through `global_info` and `describe`.
+#[cfg(not(loom))]
+interloc::global!(
+    pub(crate) fn stats: std::alloc::System,
+    interloc::StatsMonitor = interloc::StatsMonitor::new()
+);
+
+#[cfg(not(loom))]
+fn main() {
+    let monitor: &'static interloc::StatsMonitor = stats();
+    let before = monitor.info();
+    let blocks: Vec<Vec<u8>> = (0..100).map(|i| vec![0; 1000 + i]).collect();
+    std::thread::spawn(|| drop(std::hint::black_box(vec![0u8; 5000])))
+        .join()
+        .unwrap();
+    let during = monitor.info().relative_to(&before);
+    drop(blocks);
+    let after = monitor.info().relative_to(&before);
+
+    if cfg!(feature = "disabled") {
+        assert_eq!(during, Default::default());
+        assert_eq!(interloc::global_info(), None);
+        assert!(interloc::describe().entries().is_empty());
+        return;
+    }
+
+    // The vector of blocks, the blocks and the thread's block, and whatever
+    // spawning the thread allocates.
+    assert!(during.alloc >= 102);
+    assert!(during.bytes_alloc >= 100 * 1000 + 5000);
+    assert_eq!(after.live_bytes(), during.live_bytes() - blocks_bytes());
+
+    let global = interloc::global_info().expect("registered by global!");
+    assert_eq!(global, monitor.info());
+    let report = interloc::describe();
+    assert_eq!(report.entries()[0].monitor.name, "StatsMonitor");
+    println!("{}", report);
+}
+
+/// What the vector of blocks and the blocks take.
+#[cfg(not(loom))]
+fn blocks_bytes() -> u64 {
+    (100 * core::mem::size_of::<Vec<u8>>() + (0..100).map(|i| 1000 + i).sum::<usize>()) as u64
+}
+
+#[cfg(loom)]
+fn main() {}
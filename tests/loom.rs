@@ -0,0 +1,152 @@
+//! Loom models of `SeqLock` and `StatsMonitor`, and of monitors called through
+//! `InterAlloc`, which has no fences around the inner allocator's calls by
+//! default. Run with
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --test loom
+//! ```
+#![cfg(loom)]
+use core::alloc::{GlobalAlloc, Layout};
+use interloc::{AllocAction, AllocInfo, AllocMonitor, InterAlloc, SeqLock, StatsMonitor};
+use loom::sync::atomic::{AtomicUsize, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+
+fn layout(size: usize) -> Layout {
+    Layout::from_size_align(size, 8).unwrap()
+}
+
+#[test]
+fn concurrent_updates_are_exact() {
+    loom::model(|| {
+        let monitor = Arc::new(StatsMonitor::new());
+        let threads: Vec<_> = [8, 16]
+            .iter()
+            .map(|&size| {
+                let monitor = monitor.clone();
+                thread::spawn(move || {
+                    monitor.monitor(layout(size), AllocAction::Alloc);
+                    let ptr = core::ptr::null_mut();
+                    monitor.monitor(layout(size), AllocAction::Dealloc { ptr });
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let info = monitor.info();
+        assert_eq!((info.alloc, info.dealloc, info.realloc), (2, 2, 0));
+        assert_eq!((info.bytes_alloc, info.bytes_dealloc), (24, 24));
+        // Either both blocks were live at once, or only the larger one.
+        assert!(info.peak_bytes == 24 || info.peak_bytes == 16, "{:?}", info);
+    });
+}
+
+#[test]
+fn reads_during_writes_are_never_torn() {
+    loom::model(|| {
+        let lock = Arc::new(SeqLock::new(AllocInfo::new()));
+        // Loom can't model more than one thread waiting for the lock, so a
+        // single writer makes both updates.
+        let writer = {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                for _ in 0..2 {
+                    lock.update(|info| {
+                        info.apply(layout(8), AllocAction::Alloc);
+                        let ptr = core::ptr::null_mut();
+                        let new_size = 24;
+                        info.apply(layout(8), AllocAction::Realloc { ptr, new_size });
+                    });
+                }
+            })
+        };
+
+        // Every update allocates 8 bytes and grows them to 24, so a copy that
+        // mixes the fields of two updates would show.
+        for _ in 0..2 {
+            let info = lock.read();
+            assert_eq!(info.alloc, info.realloc, "{:?}", info);
+            assert_eq!(info.bytes_alloc, 32 * info.alloc, "{:?}", info);
+            assert_eq!(info.bytes_dealloc, 8 * info.alloc, "{:?}", info);
+            assert_eq!(info.peak_bytes, info.live_bytes(), "{:?}", info);
+        }
+        writer.join().unwrap();
+        assert_eq!(lock.read().alloc, 2);
+    });
+}
+
+/// An inner allocator that hands out made up addresses, and counts the blocks
+/// it has out with loom's atomics, for the model to check against.
+struct Blocks {
+    taken: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for Blocks {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let block = self.taken.fetch_add(1, Ordering::Relaxed);
+        ((block + 1) * layout.align().max(64)) as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _: *mut u8, _: Layout) {
+        self.taken.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn monitors_see_calls_in_order_without_fences() {
+    loom::model(|| {
+        let monitor: &'static StatsMonitor = Box::leak(Box::new(StatsMonitor::new()));
+        let alloc = Arc::new(InterAlloc {
+            inner: Blocks {
+                taken: AtomicUsize::new(0),
+            },
+            monitor,
+        });
+        let block = Arc::new(AtomicUsize::new(0));
+
+        // One thread allocates a block and hands it to another, which frees it,
+        // through the program's own synchronization rather than the
+        // allocator's.
+        let allocating = {
+            let (alloc, block) = (alloc.clone(), block.clone());
+            thread::spawn(move || {
+                let ptr = unsafe { alloc.alloc(layout(32)) };
+                block.store(ptr as usize, Ordering::Release);
+            })
+        };
+        let freeing = {
+            let (alloc, block) = (alloc.clone(), block.clone());
+            thread::spawn(move || {
+                // Whatever it sees is consistent, in the middle of either call.
+                let info = monitor.info();
+                assert!(info.dealloc <= info.alloc, "{:?}", info);
+                assert_eq!(info.bytes_alloc, 32 * info.alloc);
+                let ptr = block.load(Ordering::Acquire);
+                if ptr == 0 {
+                    return;
+                }
+                unsafe { alloc.dealloc(ptr as *mut u8, layout(32)) };
+                // The allocation was counted before the block was handed over,
+                // so the monitor never sees the free first.
+                let info = monitor.info();
+                assert_eq!((info.alloc, info.dealloc), (1, 1), "{:?}", info);
+                assert_eq!(info.live_bytes(), 0);
+                assert_eq!(info.peak_bytes, 32);
+            })
+        };
+
+        allocating.join().unwrap();
+        freeing.join().unwrap();
+
+        let info = monitor.info();
+        assert_eq!(info.alloc, 1);
+        assert_eq!(info.bytes_alloc, 32);
+        assert_eq!(info.live_bytes(), 32 - 32 * info.dealloc);
+        assert_eq!(
+            info.dealloc,
+            1 - alloc.inner.taken.load(Ordering::Relaxed) as u64
+        );
+    });
+}
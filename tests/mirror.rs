@@ -0,0 +1,132 @@
+//! Mirrors a `MirrorMonitor` into a file and reads it back with `MirrorReader`,
+//! while the monitor is being written to, and after a writer died mid-update.
+#![cfg(all(unix, feature = "mirror"))]
+use core::alloc::Layout;
+use interloc::{AllocAction, AllocMonitor, MirrorMonitor, MirrorReader, MIRROR_MAGIC};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A path in the temporary directory that's removed when dropped.
+struct TempPath(PathBuf);
+
+impl TempPath {
+    fn new(name: &str) -> Self {
+        let name = format!("interloc-{}-{}", name, std::process::id());
+        Self(std::env::temp_dir().join(name))
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn alloc(monitor: &MirrorMonitor, size: usize) {
+    let layout = Layout::from_size_align(size, 8).unwrap();
+    monitor.monitor(layout, AllocAction::Alloc);
+}
+
+#[test]
+fn reads_what_was_written() {
+    static MONITOR: MirrorMonitor = MirrorMonitor::new();
+    let path = TempPath::new("mirror-read");
+    alloc(&MONITOR, 100);
+    MONITOR.create(&path.0).unwrap();
+
+    let reader = MirrorReader::open(&path.0).unwrap();
+    // Attaching publishes what was counted before.
+    assert_eq!(reader.read(1), Some(MONITOR.info()));
+    let sequence = reader.sequence();
+    assert_eq!(sequence % 2, 0);
+
+    alloc(&MONITOR, 200);
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    MONITOR.monitor(
+        layout,
+        AllocAction::Dealloc {
+            ptr: core::ptr::null_mut(),
+        },
+    );
+    let info = reader.read(1).unwrap();
+    assert_eq!(info, MONITOR.info());
+    assert_eq!((info.alloc, info.dealloc), (2, 1));
+    assert_eq!(
+        (info.bytes_alloc, info.bytes_dealloc, info.peak_bytes),
+        (300, 100, 300)
+    );
+    assert_eq!(reader.sequence(), sequence + 4);
+
+    let header = std::fs::read(&path.0).unwrap();
+    assert_eq!(header[..8], MIRROR_MAGIC.to_ne_bytes());
+}
+
+#[test]
+fn snapshots_are_never_torn() {
+    static MONITOR: MirrorMonitor = MirrorMonitor::new();
+    let path = TempPath::new("mirror-torn");
+    MONITOR.create(&path.0).unwrap();
+    let reader = MirrorReader::open(&path.0).unwrap();
+    let done = AtomicBool::new(false);
+    let updates = if cfg!(miri) { 100 } else { 200_000 };
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for _ in 0..updates {
+                alloc(&MONITOR, 8);
+            }
+            done.store(true, Ordering::Release);
+        });
+        let mut last = 0;
+        let mut reads = 0u64;
+        while !done.load(Ordering::Acquire) {
+            // Every update keeps the fields in step, so a snapshot mixing two
+            // updates would show.
+            if let Some(info) = reader.read(1000) {
+                assert_eq!(info.bytes_alloc, 8 * info.alloc, "{:?}", info);
+                assert_eq!(info.peak_bytes, info.bytes_alloc, "{:?}", info);
+                assert!(info.alloc >= last);
+                last = info.alloc;
+                reads += 1;
+            }
+        }
+        assert!(reads > 0);
+    });
+    assert_eq!(reader.read(1).unwrap().alloc, updates);
+}
+
+#[test]
+fn gives_up_on_a_writer_that_died_mid_update() {
+    static MONITOR: MirrorMonitor = MirrorMonitor::new();
+    let path = TempPath::new("mirror-dead");
+    MONITOR.create(&path.0).unwrap();
+    alloc(&MONITOR, 8);
+    let reader = MirrorReader::open(&path.0).unwrap();
+    let sequence = reader.sequence();
+
+    // An odd sequence counter, as a writer leaves it while updating.
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&path.0)
+        .unwrap();
+    file.seek(SeekFrom::Start(16)).unwrap();
+    file.write_all(&(sequence + 1).to_ne_bytes()).unwrap();
+    file.flush().unwrap();
+    assert_eq!(reader.read(100), None);
+
+    file.seek(SeekFrom::Start(16)).unwrap();
+    file.write_all(&(sequence + 2).to_ne_bytes()).unwrap();
+    file.flush().unwrap();
+    assert_eq!(reader.read(1).unwrap().alloc, 1);
+}
+
+#[test]
+fn rejects_other_files() {
+    let path = TempPath::new("mirror-other");
+    std::fs::write(&path.0, [0u8; 4096]).unwrap();
+    let error = MirrorReader::open(&path.0).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    std::fs::write(&path.0, [0u8; 8]).unwrap();
+    assert!(MirrorReader::open(&path.0).is_err());
+}
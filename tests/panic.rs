@@ -0,0 +1,93 @@
+//! Panics in a child with `panic::install`'s hook, and checks the summary it
+//! wrote to stderr, and that writing it didn't allocate.
+#![cfg(not(feature = "disabled"))]
+use interloc::{panic, InterAlloc, ThreadMonitor};
+use std::alloc::System;
+use std::cell::Cell;
+use std::process::Command;
+
+static THREAD: ThreadMonitor = ThreadMonitor::new();
+
+#[global_allocator]
+static GLOBAL: InterAlloc<System, ThreadMonitor> = InterAlloc {
+    inner: System,
+    monitor: &THREAD,
+};
+
+/// Set in the child, which panics.
+const CHILD: &str = "INTERLOC_PANIC_CHILD";
+
+thread_local! {
+    /// How many times the thread allocated when `panic::install`'s hook started
+    /// and when it returned.
+    static ALLOCS: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+fn allocs() -> u64 {
+    let info = THREAD.info();
+    info.alloc + info.realloc
+}
+
+#[test]
+fn summary_on_panic() {
+    if std::env::var_os(CHILD).is_some() {
+        // The hook that `install` chains after runs right before its summary,
+        // and the hook set after `install` right after it.
+        std::panic::set_hook(Box::new(|_| ALLOCS.with(|a| a.set((allocs(), 0)))));
+        panic::install(&THREAD);
+        let summary = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            summary(info);
+            ALLOCS.with(|a| a.set((a.get().0, allocs())));
+        }));
+
+        let before = vec![0u8; 1000];
+        panic::anchor();
+        let kept: Vec<u8> = Vec::with_capacity(100);
+        drop(before);
+        let count = kept.capacity();
+        let panicked = std::panic::catch_unwind(|| panic!("after {} bytes", count));
+        assert!(panicked.is_err());
+
+        let (start, end) = ALLOCS.with(Cell::get);
+        assert_eq!(end, start, "the summary allocated");
+        assert!(start > 0);
+        return;
+    }
+
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args([
+            "summary_on_panic",
+            "--exact",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let summaries: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.starts_with("interloc: "))
+        .collect();
+    assert_eq!(summaries.len(), 1, "{}", stderr);
+    let summary = summaries[0]
+        .strip_prefix("interloc: allocations since anchor: ")
+        .unwrap();
+    // The panic itself allocates too, differently across versions of std, so
+    // the counts are only checked against what the test did.
+    let field = |name: &str| -> u64 {
+        let key = format!("\"{}\":", name);
+        let start = summary.find(&key).unwrap() + key.len();
+        let len = summary[start..].find([',', '}']).unwrap();
+        summary[start..start + len].parse().unwrap()
+    };
+    assert!(summary.starts_with('{') && summary.ends_with('}'));
+    assert!(field("alloc") >= 1);
+    assert!(field("dealloc") >= 1);
+    assert!(field("bytes_alloc") >= 100);
+    assert!(field("bytes_dealloc") >= 1000);
+    // Allocated before the anchor, so not counted.
+    assert!(field("bytes_alloc") < 1000);
+}
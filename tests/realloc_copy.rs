@@ -0,0 +1,152 @@
+//! Resizes blocks filled with patterns up and down, over and over, through
+//! every allocator that reallocates by copying, in sequences drawn from a fixed
+//! generator. Checks that every reallocation keeps the contents up to the
+//! smaller of the two sizes, and that every one that fails leaves the block as
+//! it was.
+use core::alloc::{GlobalAlloc, Layout};
+use interloc::testing::exercise_global_alloc;
+use interloc::{ArenaAlloc, LimitAlloc, RedzoneAlloc};
+use std::alloc::System;
+
+const STEPS: usize = if cfg!(miri) { 200 } else { 20_000 };
+const BLOCKS: usize = 8;
+
+/// A linear congruential generator, so every run resizes the same way.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        ((self.0 >> 33) as usize) % n
+    }
+}
+
+fn pattern(seed: usize, i: usize) -> u8 {
+    (seed.wrapping_mul(31).wrapping_add(i) % 251) as u8
+}
+
+fn fill(ptr: *mut u8, len: usize, seed: usize) {
+    for i in 0..len {
+        unsafe { *ptr.add(i) = pattern(seed, i) };
+    }
+}
+
+fn holds(ptr: *mut u8, len: usize, seed: usize) -> bool {
+    (0..len).all(|i| unsafe { *ptr.add(i) } == pattern(seed, i))
+}
+
+#[derive(Clone, Copy)]
+struct Block {
+    ptr: *mut u8,
+    layout: Layout,
+    seed: usize,
+}
+
+/// What `resize_randomly` did.
+#[derive(Debug, Default)]
+struct Resizes {
+    grown: usize,
+    shrunk: usize,
+    failed: usize,
+}
+
+/// Keeps `BLOCKS` blocks of up to `max` bytes live in `alloc`, and resizes them
+/// at random, sometimes to `too_big`, which `alloc` can't allocate.
+fn resize_randomly<A: GlobalAlloc>(alloc: &A, max: usize, too_big: usize) -> Resizes {
+    let mut rng = Rng(0x00c0_ffee);
+    let mut resizes = Resizes::default();
+    let mut blocks: Vec<Block> = (0..BLOCKS)
+        .map(|seed| {
+            let layout = Layout::from_size_align(rng.below(max) + 1, 1 << rng.below(7)).unwrap();
+            let ptr = unsafe { alloc.alloc(layout) };
+            assert!(!ptr.is_null());
+            fill(ptr, layout.size(), seed);
+            Block { ptr, layout, seed }
+        })
+        .collect();
+
+    for step in 0..STEPS {
+        let block = &mut blocks[rng.below(BLOCKS)];
+        let new_size = if rng.below(16) == 0 {
+            too_big
+        } else {
+            rng.below(max) + 1
+        };
+        let (old, align) = (block.layout.size(), block.layout.align());
+        let new_ptr = unsafe { alloc.realloc(block.ptr, block.layout, new_size) };
+        if new_ptr.is_null() {
+            assert!(
+                holds(block.ptr, old, block.seed),
+                "a failed realloc from {} to {} bytes changed the block",
+                old,
+                new_size
+            );
+            resizes.failed += 1;
+            continue;
+        }
+        assert_eq!(new_ptr as usize % align, 0);
+        assert!(
+            holds(new_ptr, old.min(new_size), block.seed),
+            "a realloc from {} to {} bytes lost the contents",
+            old,
+            new_size
+        );
+        if new_size > old {
+            resizes.grown += 1;
+        } else if new_size < old {
+            resizes.shrunk += 1;
+        }
+        *block = Block {
+            ptr: new_ptr,
+            layout: Layout::from_size_align(new_size, align).unwrap(),
+            seed: BLOCKS + step,
+        };
+        fill(block.ptr, new_size, block.seed);
+    }
+
+    for block in blocks {
+        unsafe { alloc.dealloc(block.ptr, block.layout) };
+    }
+    assert!(resizes.grown > 0 && resizes.shrunk > 0 && resizes.failed > 0);
+    resizes
+}
+
+#[test]
+fn redzone() {
+    let alloc = RedzoneAlloc::<_, 16>::new(LimitAlloc::new(System, 64 * 1024));
+    resize_randomly(&alloc, 2048, 1 << 20);
+    assert_eq!(alloc.corrupted(), 0);
+    assert_eq!(alloc.inner().live(), 0);
+
+    // With no zones at all too.
+    let alloc = RedzoneAlloc::<_, 0>::new(LimitAlloc::new(System, 64 * 1024));
+    resize_randomly(&alloc, 2048, 1 << 20);
+    assert_eq!(alloc.inner().live(), 0);
+
+    let report = exercise_global_alloc(&RedzoneAlloc::<_, 16>::new(System));
+    assert!(report.passed(), "{}", report);
+}
+
+#[test]
+fn arena() {
+    // Small enough to run out, and to resize in place some of the time.
+    let alloc = ArenaAlloc::<{ 64 * 1024 }>::new();
+    resize_randomly(&alloc, 2048, 1 << 20);
+    assert_eq!(alloc.used(), 0);
+}
+
+#[cfg(all(unix, feature = "page-guard", not(miri)))]
+#[test]
+fn page_guard() {
+    let alloc = interloc::PageGuardAlloc::new();
+    // More than the address space, so it can never be mapped.
+    resize_randomly(&alloc, 3 * 4096, 1 << 50);
+    assert_eq!(alloc.mapped(), 0);
+
+    let report = exercise_global_alloc(&alloc);
+    assert!(report.passed(), "{}", report);
+    assert_eq!(alloc.mapped(), 0);
+}
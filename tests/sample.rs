@@ -0,0 +1,98 @@
+//! Samples a large synthetic workload with `SampleMonitor` in both modes, and
+//! checks that the counts and bytes scaled back up with `scale` estimate the
+//! whole workload.
+#![cfg(not(feature = "disabled"))]
+use core::alloc::Layout;
+use interloc::{AllocAction, AllocMonitor, SampleMode, SampleMonitor, StatsMonitor};
+
+const CALLS: usize = if cfg!(miri) { 2_000 } else { 200_000 };
+
+/// Sizes from 1 byte to 64 KiB, spread over orders of magnitude the way real
+/// workloads are, from a fixed linear congruential generator.
+fn sizes() -> impl Iterator<Item = usize> {
+    let mut x = 0x2545_f491_4f6c_dd1du64;
+    (0..CALLS).map(move |_| {
+        x = x.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        let bits = (x >> 33) as u32;
+        (((bits & 0xffff) as usize) >> ((bits >> 16) % 16)).max(1)
+    })
+}
+
+/// Runs the workload through `monitor` and returns the actual and estimated
+/// number of calls and bytes.
+fn run(monitor: &SampleMonitor<StatsMonitor>) -> ((f64, f64), (f64, f64)) {
+    let (mut calls, mut bytes) = (0.0, 0.0);
+    let (mut est_calls, mut est_bytes) = (0.0, 0.0);
+    for size in sizes() {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        let forwarded = monitor.inner().info().alloc;
+        monitor.monitor(layout, AllocAction::Alloc);
+        calls += 1.0;
+        bytes += size as f64;
+        if monitor.inner().info().alloc != forwarded {
+            est_calls += monitor.scale(size);
+            est_bytes += monitor.scale(size) * size as f64;
+        }
+    }
+    ((calls, est_calls), (bytes, est_bytes))
+}
+
+fn assert_close(actual: f64, estimate: f64, tolerance: f64) {
+    let error = (estimate - actual).abs() / actual;
+    assert!(
+        error < tolerance,
+        "estimated {} for {} ({:.1}% off)",
+        estimate,
+        actual,
+        error * 100.0
+    );
+}
+
+#[test]
+fn every_n_calls() {
+    let monitor = SampleMonitor::new(StatsMonitor::new(), SampleMode::Every(100));
+    let ((calls, est_calls), (bytes, est_bytes)) = run(&monitor);
+    // Exactly one in every 100 calls, the first one.
+    assert_eq!(monitor.inner().info().alloc as usize, CALLS / 100);
+    assert_eq!(monitor.effective_rate(), 0.01);
+    assert_eq!(est_calls, calls);
+    // Sizes vary a lot from call to call, so the bytes of 1 in 100 vary too.
+    let tolerance = if cfg!(miri) { 0.5 } else { 0.1 };
+    assert_close(bytes, est_bytes, tolerance);
+}
+
+#[test]
+fn proportional_to_bytes() {
+    let monitor = SampleMonitor::new(StatsMonitor::new(), SampleMode::Bytes(64 * 1024));
+    let ((calls, est_calls), (bytes, est_bytes)) = run(&monitor);
+    let info = monitor.inner().info();
+    // Each call is sampled with probability 1 - exp(-size / n).
+    let expected: f64 = sizes().map(|size| 1.0 / monitor.scale(size)).sum();
+    let tolerance = if cfg!(miri) { 0.5 } else { 0.05 };
+    assert_close(expected, info.alloc as f64, tolerance);
+    assert_close(bytes, est_bytes, tolerance);
+    // Calls are estimated less precisely, since each small call that's sampled
+    // stands for thousands.
+    assert_close(calls, est_calls, 4.0 * tolerance);
+    // Large allocations are sampled far more often than small ones, so the
+    // sampled calls are much larger than the average call.
+    let sampled_mean = info.bytes_alloc as f64 / info.alloc as f64;
+    assert!(sampled_mean > 4.0 * bytes / calls);
+    assert_close(
+        info.alloc as f64 / calls,
+        monitor.effective_rate(),
+        f64::EPSILON,
+    );
+    assert_eq!(monitor.unsampled(), 0);
+}
+
+#[test]
+fn huge_allocations_are_always_sampled() {
+    let monitor = SampleMonitor::new(StatsMonitor::new(), SampleMode::Bytes(4096));
+    let layout = Layout::from_size_align(1 << 20, 1).unwrap();
+    for _ in 0..1000 {
+        monitor.monitor(layout, AllocAction::Alloc);
+    }
+    assert_eq!(monitor.inner().info().alloc, 1000);
+    assert_eq!(monitor.scale(1 << 20), 1.0);
+}
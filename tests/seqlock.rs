@@ -0,0 +1,82 @@
+//! Updates an `AllocInfo` behind a `SeqLock` from several threads while others
+//! read it, and checks that no read mixes the words of two updates, and that no
+//! update is lost.
+#![cfg(not(loom))]
+use core::alloc::Layout;
+use interloc::{AllocAction, AllocInfo, SeqLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Barrier;
+
+const WRITERS: usize = 4;
+const READERS: usize = 4;
+const UPDATES: u64 = if cfg!(miri) { 100 } else { 100_000 };
+
+/// Holds for every `AllocInfo` the writers leave: one allocation of 8 bytes
+/// and one of 24 per update, and 8 of them freed again.
+fn check(info: &AllocInfo) {
+    assert_eq!(info.alloc, 2 * info.dealloc, "{:?}", info);
+    assert_eq!(info.bytes_alloc, 32 * info.dealloc, "{:?}", info);
+    assert_eq!(info.bytes_dealloc, 8 * info.dealloc, "{:?}", info);
+    // The peak was reached before the last update freed its 8 bytes.
+    let peak = if info.dealloc == 0 {
+        0
+    } else {
+        info.live_bytes() + 8
+    };
+    assert_eq!(info.peak_bytes, peak, "{:?}", info);
+    assert_eq!(info.realloc, 0, "{:?}", info);
+}
+
+#[test]
+fn reads_are_never_torn() {
+    static LOCK: SeqLock<AllocInfo> = SeqLock::new(AllocInfo::new());
+    let small = Layout::from_size_align(8, 8).unwrap();
+    let large = Layout::from_size_align(24, 8).unwrap();
+    let writing = AtomicUsize::new(WRITERS);
+    let barrier = Barrier::new(WRITERS + READERS);
+
+    std::thread::scope(|s| {
+        for _ in 0..WRITERS {
+            s.spawn(|| {
+                barrier.wait();
+                for _ in 0..UPDATES {
+                    LOCK.update(|info| {
+                        info.apply(small, AllocAction::Alloc);
+                        info.apply(large, AllocAction::Alloc);
+                        let ptr = core::ptr::null_mut();
+                        info.apply(small, AllocAction::Dealloc { ptr });
+                    });
+                }
+                writing.fetch_sub(1, Ordering::Release);
+            });
+        }
+        for reader in 0..READERS {
+            let (barrier, writing) = (&barrier, &writing);
+            s.spawn(move || {
+                barrier.wait();
+                let (mut last, mut reads) = (0, 0u64);
+                // At least one read each, even if the writers were all done
+                // before this thread got to run.
+                while writing.load(Ordering::Acquire) != 0 || reads == 0 {
+                    // Half the readers retry, and half give up on a torn copy.
+                    let info = if reader % 2 == 0 {
+                        LOCK.read()
+                    } else {
+                        match LOCK.try_read() {
+                            Some(info) => info,
+                            None => continue,
+                        }
+                    };
+                    check(&info);
+                    assert!(info.dealloc >= last);
+                    last = info.dealloc;
+                    reads += 1;
+                }
+            });
+        }
+    });
+
+    let info = LOCK.read();
+    check(&info);
+    assert_eq!(info.dealloc, WRITERS as u64 * UPDATES);
+}
@@ -0,0 +1,85 @@
+//! Sends `SIGUSR1` to a child that installed the dump handler, and checks what
+//! it wrote to stderr.
+#![cfg(all(unix, feature = "signal"))]
+use core::alloc::Layout;
+use interloc::signal::install_dump_handler;
+use interloc::{AllocAction, AllocMonitor, StatsMonitor};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+
+static MONITOR: StatsMonitor = StatsMonitor::new();
+
+/// Set in the child, which installs the handler and waits for stdin to close.
+const CHILD: &str = "INTERLOC_SIGNAL_CHILD";
+
+#[test]
+fn dumps_on_sigusr1() {
+    if std::env::var_os(CHILD).is_some() {
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        for _ in 0..3 {
+            MONITOR.monitor(layout, AllocAction::Alloc);
+        }
+        MONITOR.monitor(
+            layout,
+            AllocAction::Dealloc {
+                ptr: core::ptr::null_mut(),
+            },
+        );
+        install_dump_handler(&MONITOR).unwrap();
+        println!("ready");
+        // Blocks until the parent closes stdin, with the read restarted after
+        // the handler runs.
+        std::io::stdin().read_to_end(&mut Vec::new()).unwrap();
+        return;
+    }
+
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args([
+            "dumps_on_sigusr1",
+            "--exact",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(CHILD, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    // The harness prints the name of the test first, on the same line.
+    while !line.trim_end().ends_with("ready") {
+        line.clear();
+        assert_ne!(
+            stdout.read_line(&mut line).unwrap(),
+            0,
+            "child exited early"
+        );
+    }
+
+    let killed = Command::new("kill")
+        .args(["-USR1", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    // The signal is pending by now, so it's handled before the child sees the
+    // end of stdin.
+    drop(child.stdin.take());
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let dumps: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.starts_with("interloc: "))
+        .collect();
+    assert_eq!(
+        dumps,
+        [concat!(
+            r#"interloc: {"alloc":3,"dealloc":1,"realloc":0,"#,
+            r#""bytes_alloc":300,"bytes_dealloc":100,"peak_bytes":300,"#,
+            r#""baseline_bytes":0}"#,
+        )]
+    );
+}
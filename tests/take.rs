@@ -0,0 +1,64 @@
+//! Takes the statistics of a `StatsMonitor` over and over while several threads
+//! allocate and free through it, and checks that the phases add up to every
+//! call exactly once, with the live bytes carried from each phase to the next.
+#![cfg(not(any(loom, feature = "disabled")))]
+use core::alloc::Layout;
+use interloc::{AllocAction, AllocInfo, AllocMonitor, StatsMonitor};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Barrier;
+
+const THREADS: usize = 4;
+const ROUNDS: u64 = if cfg!(miri) { 50 } else { 50_000 };
+
+static MONITOR: StatsMonitor = StatsMonitor::new();
+
+#[test]
+fn take_races_allocating_threads() {
+    let running = AtomicUsize::new(THREADS);
+    let barrier = Barrier::new(THREADS + 1);
+    let mut phases = Vec::new();
+
+    std::thread::scope(|s| {
+        for thread in 0..THREADS {
+            let (running, barrier) = (&running, &barrier);
+            s.spawn(move || {
+                // Each round allocates two blocks and frees one, so every thread
+                // ends up with a block of each round live.
+                let size = 8 * (thread + 1);
+                let layout = Layout::from_size_align(size, 8).unwrap();
+                let ptr = core::ptr::null_mut();
+                barrier.wait();
+                for _ in 0..ROUNDS {
+                    MONITOR.monitor(layout, AllocAction::Alloc);
+                    MONITOR.monitor(layout, AllocAction::Alloc);
+                    MONITOR.monitor(layout, AllocAction::Dealloc { ptr });
+                }
+                running.fetch_sub(1, Ordering::Release);
+            });
+        }
+        barrier.wait();
+        while running.load(Ordering::Acquire) != 0 {
+            phases.push(MONITOR.take());
+        }
+    });
+    phases.push(MONITOR.take());
+
+    let sizes: u64 = (1..=THREADS as u64).map(|thread| 8 * thread).sum();
+    let total: AllocInfo = phases.iter().sum();
+    assert_eq!(total.alloc, ROUNDS * 2 * THREADS as u64);
+    assert_eq!(total.dealloc, ROUNDS * THREADS as u64);
+    assert_eq!(total.bytes_alloc, ROUNDS * 2 * sizes);
+    assert_eq!(total.bytes_dealloc, ROUNDS * sizes);
+
+    // Each phase starts from what the one before it left live.
+    let mut live = 0;
+    for phase in &phases {
+        assert_eq!(phase.baseline_bytes, live, "{:?}", phase);
+        assert!(phase.peak_bytes >= phase.live_bytes().max(live));
+        live = phase.live_bytes();
+    }
+    assert_eq!(live, ROUNDS * sizes);
+    let last = MONITOR.info();
+    assert_eq!((last.alloc, last.bytes_alloc), (0, 0));
+    assert_eq!(last.live_bytes(), ROUNDS * sizes);
+}
@@ -0,0 +1,67 @@
+//! Allocates and frees blocks through a `TrackingMonitor` over and over, at
+//! ever new addresses, with only a few live at once, so the freed blocks leave
+//! tombstones all over the table. Checks that lookups still only scan as far as
+//! the live blocks push each other, and that the blocks are all accounted for,
+//! with the sites they were allocated at.
+#![cfg(not(any(loom, feature = "disabled")))]
+use core::alloc::Layout;
+use interloc::{attributed, AllocAction, AllocMonitor, LiveBlock, TrackingMonitor};
+
+const ROUNDS: usize = if cfg!(miri) { 200 } else { 200_000 };
+const LIVE: usize = 8;
+
+/// A block address that no other round uses, without a real block behind it.
+fn block(round: usize) -> *mut u8 {
+    core::ptr::null_mut::<u8>().wrapping_add(0x1000 + 16 * round)
+}
+
+#[test]
+fn tombstones_dont_lengthen_lookups() {
+    let monitor = TrackingMonitor::<64>::new();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let mut live = [core::ptr::null_mut::<u8>(); LIVE];
+    let mut seed = 1u64;
+
+    for round in 0..ROUNDS {
+        // Frees a block drawn from a fixed generator, and puts a new one in
+        // its place.
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let i = (seed >> 33) as usize % LIVE;
+        if !live[i].is_null() {
+            monitor.monitor(layout, AllocAction::Dealloc { ptr: live[i] });
+            // Freeing a block a second time misses.
+            monitor.monitor(layout, AllocAction::Dealloc { ptr: live[i] });
+        }
+        // Every other block is attributed to this one site.
+        live[i] = block(round);
+        let alloc = || {
+            monitor.monitor(layout, AllocAction::Alloc);
+            monitor.monitor(layout, AllocAction::AllocResult { ptr: live[i] });
+        };
+        if round % 2 == 0 {
+            attributed(alloc);
+        } else {
+            alloc();
+        }
+        assert!(monitor.live_blocks() <= LIVE);
+    }
+
+    assert!(monitor.max_probe() < LIVE, "{}", monitor.max_probe());
+    assert_eq!(monitor.live_blocks(), LIVE);
+    let mut blocks = [LiveBlock::default(); LIVE + 1];
+    assert_eq!(monitor.top_live(LIVE + 1, &mut blocks), LIVE);
+    let site = blocks.iter().map(|block| block.site).max().unwrap();
+    assert_ne!(site, 0);
+    for block in &blocks[..LIVE] {
+        let round = (block.ptr - 0x1000) / 16;
+        assert!(live.iter().any(|ptr| ptr.addr() == block.ptr));
+        assert_eq!(block.site, if round % 2 == 0 { site } else { 0 });
+    }
+
+    for ptr in live {
+        monitor.monitor(layout, AllocAction::Dealloc { ptr });
+    }
+    assert_eq!((monitor.live_blocks(), monitor.overflowed()), (0, 0));
+}
@@ -0,0 +1,166 @@
+//! Drives every 64-bit counter past `u32::MAX`, where a `usize` counter would
+//! wrap on a 32-bit target. Counts that would take billions of calls to get
+//! there start just below it, and byte totals get there with blocks of 1 GiB,
+//! which are valid layouts on 32-bit targets too. To run it on one,
+//!
+//! ```text
+//! cargo +nightly miri test --target i686-unknown-linux-gnu --test wide_counters
+//! ```
+#![cfg(not(loom))]
+use core::alloc::Layout;
+use interloc::{AllocAction, AllocInfo, AllocMonitor, ThreadMonitor, ThreadRegistryMonitor};
+
+const GIB: u64 = 1 << 30;
+const NEAR: u64 = u32::MAX as u64 - 1;
+const PAST: u64 = u32::MAX as u64 + 2;
+
+fn gib() -> Layout {
+    Layout::from_size_align(GIB as usize, 1).unwrap()
+}
+
+/// Five blocks of 1 GiB, two of them freed and one grown to 2 GiB.
+fn drive(monitor: &impl AllocMonitor) {
+    let ptr = core::ptr::null_mut();
+    for _ in 0..5 {
+        monitor.monitor(gib(), AllocAction::Alloc);
+    }
+    for _ in 0..2 {
+        monitor.monitor(gib(), AllocAction::Dealloc { ptr });
+    }
+    let new_size = 2 * GIB as usize;
+    monitor.monitor(gib(), AllocAction::Realloc { ptr, new_size });
+}
+
+/// What `drive` adds up to, on top of `start`.
+fn driven(start: AllocInfo) -> AllocInfo {
+    AllocInfo {
+        alloc: start.alloc + 5,
+        dealloc: start.dealloc + 2,
+        realloc: start.realloc + 1,
+        bytes_alloc: start.bytes_alloc + 7 * GIB,
+        bytes_dealloc: start.bytes_dealloc + 3 * GIB,
+        peak_bytes: start.peak_bytes.max(start.live_bytes() + 5 * GIB),
+        ..start
+    }
+}
+
+fn near() -> AllocInfo {
+    AllocInfo {
+        alloc: NEAR,
+        dealloc: NEAR,
+        realloc: NEAR,
+        bytes_alloc: NEAR,
+        bytes_dealloc: NEAR,
+        peak_bytes: NEAR,
+        baseline_bytes: NEAR,
+    }
+}
+
+#[test]
+fn alloc_info() {
+    let mut info = near();
+    let ptr = core::ptr::null_mut();
+    for _ in 0..5 {
+        info.apply(gib(), AllocAction::Alloc);
+    }
+    for _ in 0..2 {
+        info.apply(gib(), AllocAction::Dealloc { ptr });
+    }
+    let new_size = 2 * GIB as usize;
+    info.apply(gib(), AllocAction::Realloc { ptr, new_size });
+    assert_eq!(info, driven(near()));
+    assert!(info.alloc > PAST - 2 && info.live_bytes() > 2 * NEAR);
+    let after = info.after_call(gib(), AllocAction::Alloc);
+    assert_eq!(after.bytes_alloc, info.bytes_alloc + GIB);
+
+    let delta = info.delta_from(&AllocInfo::new());
+    assert_eq!(delta.bytes_alloc as u64, info.bytes_alloc);
+    assert_eq!(
+        delta.net_bytes(),
+        (info.bytes_alloc - info.bytes_dealloc) as i64
+    );
+    assert_eq!(info.relative_to(&near()).bytes_alloc, 7 * GIB);
+    let total: AllocInfo = [info, info].iter().sum();
+    assert_eq!(total.alloc, 2 * (NEAR + 5));
+}
+
+#[cfg(not(feature = "disabled"))]
+#[test]
+fn stats_monitor() {
+    let monitor = interloc::StatsMonitor::new();
+    monitor.write_info(near());
+    drive(&monitor);
+    assert_eq!(monitor.info(), driven(near()));
+    let taken = monitor.take();
+    assert_eq!(taken, driven(near()));
+    assert_eq!(monitor.info().baseline_bytes, taken.live_bytes());
+    assert!(taken.live_bytes() > PAST);
+}
+
+#[test]
+fn thread_monitor() {
+    let monitor = ThreadMonitor::new();
+    monitor.write_info(near());
+    drive(&monitor);
+    assert_eq!(monitor.info(), driven(near()));
+}
+
+#[test]
+fn thread_registry_monitor() {
+    static MONITOR: ThreadRegistryMonitor = ThreadRegistryMonitor::new();
+    std::thread::spawn(|| drive(&MONITOR)).join().unwrap();
+    std::thread::spawn(|| drive(&MONITOR)).join().unwrap();
+    let info = interloc::InfoSource::info(&MONITOR);
+    assert_eq!(info.bytes_alloc, 14 * GIB);
+    assert_eq!(info.live_bytes(), 8 * GIB);
+    assert_eq!(info.peak_bytes, 5 * GIB);
+}
+
+#[cfg(all(unix, feature = "mirror"))]
+#[test]
+fn mirror_monitor() {
+    let monitor = interloc::MirrorMonitor::new();
+    for _ in 0..2 {
+        drive(&monitor);
+    }
+    let info = monitor.info();
+    assert_eq!(info.bytes_alloc, 14 * GIB);
+    assert_eq!(info.bytes_dealloc, 6 * GIB);
+    assert_eq!(info.peak_bytes, 9 * GIB);
+}
+
+#[cfg(not(feature = "disabled"))]
+#[test]
+fn site_totals() {
+    // Two sites in a table with room for one, so the first is evicted.
+    let table = interloc::SiteTable::<1, 1>::new();
+    for _ in 0..5 {
+        table.record(&[0x1000], GIB as usize);
+    }
+    let sites = table.sites();
+    assert_eq!(sites.len(), 1);
+    assert_eq!((sites[0].count, sites[0].bytes), (5, 5 * GIB));
+    for _ in 0..6 {
+        table.record(&[0x2000], GIB as usize);
+    }
+    assert_eq!(table.evictions(), 1);
+    assert_eq!(table.evicted_bytes(), 5 * GIB);
+    let sites = table.sites();
+    assert_eq!(sites.len(), 1);
+    assert_eq!(sites[0].frames, [0x2000]);
+    assert!(sites[0].bytes >= 5 * GIB);
+}
+
+#[cfg(not(feature = "disabled"))]
+#[test]
+fn callsite_totals() {
+    let monitor = interloc::CallsiteMonitor::<4>::new();
+    interloc::attributed(|| {
+        for _ in 0..5 {
+            monitor.monitor(gib(), AllocAction::Alloc);
+        }
+    });
+    let callsites = monitor.callsites();
+    assert_eq!(callsites.len(), 1);
+    assert_eq!((callsites[0].count, callsites[0].bytes), (5, 5 * GIB));
+}